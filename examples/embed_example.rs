@@ -5,6 +5,7 @@
 //!
 //! Run with: cargo run --example embed_example --features standalone
 
+#[cfg(feature = "standalone")]
 use rstherac25::*;
 
 #[cfg(feature = "standalone")]
@@ -51,8 +52,8 @@ async fn main() {
     println!("4. Completing data entry...");
     complete_data_entry(state.clone());
 
-    // Wait for setup to complete
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    // Wait for the setup test (the turntable move) to complete
+    wait_for(&state, |phase| phase == TPhase::SetupDone, 10).await;
     println!("   Current phase: {}", get_phase(&state));
     println!();
 
@@ -80,15 +81,20 @@ async fn main() {
     if can_treat(&state) {
         println!("   ✓ System is ready for treatment");
 
-        // Optionally start treatment
+        // Tick the pre-treatment checklist, then start treatment
+        check_all_checklist_items(state.clone());
         if handle_treat_command(state.clone()) {
             println!("   - Treatment started!");
 
-            // Wait a bit for treatment to progress
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+            // Wait for the treatment to finish, or to pause on a malfunction
+            wait_for(&state, |phase| !matches!(phase, TPhase::BeamReady | TPhase::PatientTreatment), 15).await;
 
             let s = state.read();
-            println!("   - Dose delivered: {:.1} cGy", s.dose_delivered);
+            println!("   - Phase: {}", s.phase);
+            println!("   - Dose delivered: {:.1}/{:.1} cGy", s.dose_delivered, s.dose_target);
+            if let Some(malfunction) = &s.last_malfunction {
+                println!("   - {}", malfunction);
+            }
         }
     } else {
         println!("   ✗ System is not ready for treatment");
@@ -105,6 +111,15 @@ async fn main() {
     println!("=== Example Complete ===");
 }
 
+/// Poll the phase until `done` holds, giving up after `timeout_secs`
+#[cfg(feature = "standalone")]
+async fn wait_for(state: &SharedTheracState, done: impl Fn(TPhase) -> bool, timeout_secs: u64) {
+    let deadline = tokio::time::Instant::now() + tokio::time::Duration::from_secs(timeout_secs);
+    while !done(get_phase(state)) && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+    }
+}
+
 #[cfg(not(feature = "standalone"))]
 fn main() {
     eprintln!("This example requires the 'standalone' feature.");
@@ -0,0 +1,191 @@
+//! Alarm hooks for embedders
+//!
+//! The simulator raises malfunctions and ends treatments without making a sound. Each
+//! alarm is queued on the state as it happens, and `dispatch_alarms` hands the queue to
+//! an `AlarmSink` once the state lock is released, so a kiosk can ring a bell or flash
+//! a light without patching the core or holding up the beam. The background tasks do
+//! this when spawned with `spawn_treatment_tasks_with_alarms`; a tick-driven frontend
+//! calls `dispatch_alarms` itself after each step. Alarms are not part of a saved session.
+
+use crate::locking::LockState;
+use crate::state::{Malfunction, SharedTheracState};
+use parking_lot::{Mutex, MutexGuard};
+use std::fmt;
+use std::io::{self, Write};
+#[cfg(feature = "standalone")]
+use std::sync::Arc;
+#[cfg(feature = "standalone")]
+use std::time::Duration;
+
+/// One alarm raised by the machine
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alarm {
+    /// A malfunction was raised
+    Malfunction(Malfunction),
+    /// The beam delivered the prescription and terminated
+    DoseComplete,
+}
+
+/// Receives the machine's alarms
+/// Called from whichever task dispatches them, with the state unlocked: a sink may take
+/// its time or read the state. Both methods do nothing unless overridden.
+pub trait AlarmSink: Send + Sync {
+    /// A malfunction was raised
+    fn on_malfunction(&self, _malfunction: &Malfunction) {}
+
+    /// The beam delivered the prescription and terminated
+    fn on_dose_complete(&self) {}
+}
+
+/// Hand every alarm queued since the last call to `sink`, oldest first
+/// The queue is taken under the write lock and the sink is called after it has been
+/// released. Returns how many alarms were dispatched.
+pub fn dispatch_alarms(state: &SharedTheracState, sink: &dyn AlarmSink) -> usize {
+    if state.read_state().pending_alarms.is_empty() {
+        return 0;
+    }
+    let alarms = state.write_state().take_alarms();
+    for alarm in &alarms {
+        match alarm {
+            Alarm::Malfunction(malfunction) => sink.on_malfunction(malfunction),
+            Alarm::DoseComplete => sink.on_dose_complete(),
+        }
+    }
+    alarms.len()
+}
+
+/// How often the alarm dispatcher looks for new alarms
+#[cfg(feature = "standalone")]
+pub const ALARM_POLL: Duration = Duration::from_millis(10);
+
+/// Alarm dispatcher task: passes alarms to `sink` as they are raised
+/// Keeps running while the simulation is paused; an alarm raised just before the pause
+/// still goes out.
+#[cfg(feature = "standalone")]
+pub async fn alarm_dispatcher(state: SharedTheracState, sink: Arc<dyn AlarmSink>) {
+    loop {
+        tokio::time::sleep(ALARM_POLL).await;
+        dispatch_alarms(&state, sink.as_ref());
+    }
+}
+
+/// Ignores every alarm
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoAlarmSink;
+
+impl AlarmSink for NoAlarmSink {}
+
+/// Writes one line per alarm, e.g. `ALARM: MALFUNCTION 54 - ...`
+pub struct LoggingAlarmSink<W = io::Stderr> {
+    out: Mutex<W>,
+}
+
+impl LoggingAlarmSink {
+    pub fn stderr() -> Self {
+        Self::new(io::stderr())
+    }
+}
+
+impl<W: Write + Send> LoggingAlarmSink<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: Mutex::new(out) }
+    }
+
+    /// What the alarms were written to
+    pub fn output(&self) -> MutexGuard<'_, W> {
+        self.out.lock()
+    }
+
+    fn write_line(&self, line: fmt::Arguments) {
+        // An alarm that cannot be written is not worth failing the pulse over
+        let _ = writeln!(self.out.lock(), "ALARM: {}", line);
+    }
+}
+
+impl<W: Write + Send> AlarmSink for LoggingAlarmSink<W> {
+    fn on_malfunction(&self, malfunction: &Malfunction) {
+        self.write_line(format_args!("MALFUNCTION {} - {}", malfunction.code(), malfunction.text));
+    }
+
+    fn on_dose_complete(&self) {
+        self.write_line(format_args!("dose complete"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::verify_and_fire;
+    use crate::state::{BeamEnergy, BeamType, CollimatorPosition, Meos, TPhase, TheracState};
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    fn treating_state() -> TheracState {
+        let mut s = TheracState::default();
+        s.config.random_fault_probability = 0.0;
+        s.config.dose_jitter = 0.0;
+        s.console_meos = Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::E10,
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        s.hardware_meos = s.console_meos;
+        s.phase = TPhase::PatientTreatment;
+        s
+    }
+
+    fn alarms(sink: &LoggingAlarmSink<Vec<u8>>) -> Vec<String> {
+        String::from_utf8(sink.output().clone()).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_sink_hears_malfunction_and_dose_complete() {
+        let sink = LoggingAlarmSink::new(Vec::new());
+        let mut s = treating_state();
+        s.dose_target = 0.001;
+        let meos = s.hardware_meos;
+        verify_and_fire(&mut s, meos, meos, 1.0);
+        assert_eq!(s.phase, TPhase::TerminateTreatment);
+        // Queued, not yet heard: the sink is only called once the lock is released
+        assert_eq!(s.pending_alarms, [Alarm::DoseComplete]);
+        assert!(alarms(&sink).is_empty());
+        let state = Arc::new(RwLock::new(s));
+        assert_eq!(dispatch_alarms(&state, &sink), 1);
+        assert_eq!(alarms(&sink), ["ALARM: dose complete"]);
+        assert_eq!(dispatch_alarms(&state, &sink), 0);
+
+        // The console moved on to X-ray, the hardware did not: MALFUNCTION 54
+        let mut s = treating_state();
+        let console = Meos { beam_type: BeamType::XRay, ..s.hardware_meos };
+        let hardware = s.hardware_meos;
+        verify_and_fire(&mut s, console, hardware, 1.0);
+        assert_eq!(s.phase, TPhase::PauseTreatment);
+        dispatch_alarms(&Arc::new(RwLock::new(s)), &sink);
+        let heard = alarms(&sink);
+        assert_eq!(heard.len(), 2);
+        assert!(heard[1].starts_with("ALARM: MALFUNCTION 54 - "), "{}", heard[1]);
+    }
+
+    #[test]
+    fn test_alarms_are_not_saved() {
+        let mut s = treating_state();
+        s.dose_target = 0.001;
+        let meos = s.hardware_meos;
+        verify_and_fire(&mut s, meos, meos, 1.0);
+        assert!(!s.pending_alarms.is_empty());
+        let restored = TheracState::from_json(&s.to_json().unwrap()).unwrap();
+        assert!(restored.pending_alarms.is_empty());
+    }
+
+    #[cfg(feature = "standalone")]
+    #[tokio::test(start_paused = true)]
+    async fn test_dispatcher_task_passes_alarms_on() {
+        let sink = Arc::new(LoggingAlarmSink::new(Vec::new()));
+        let state = Arc::new(RwLock::new(treating_state()));
+        let dispatcher = tokio::spawn(alarm_dispatcher(state.clone(), sink.clone()));
+        state.write().raise_alarm(Alarm::DoseComplete);
+        tokio::time::sleep(2 * ALARM_POLL).await;
+        assert_eq!(alarms(&sink), ["ALARM: dose complete"]);
+        dispatcher.abort();
+    }
+}
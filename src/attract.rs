@@ -0,0 +1,224 @@
+//! Attract mode: a hands-free demo loop for kiosks and conference booths
+//!
+//! Cycles through scripted scenes - a nominal treatment, a near miss the interlocks
+//! catch, and the East Texas overdose - with on-screen narration, resetting the
+//! machine between them. Console actions are played from a `ReplayBundle` against a
+//! machine whose background tasks are running. The overdose scene runs on the original's
+//! bending magnet timing, so its edit lands while the magnets are settling and goes unseen
+//! by the setup: that opens on cue, where a live race cannot be relied on to.
+
+use crate::locking::LockState;
+use crate::replay::{ReplayBundle, ReplayEvent, ReplayPlayer, TimedEvent};
+use crate::simulator::BENDING_MAGNET_SETTLE_TIME;
+use crate::state::{BeamEnergy, BeamType, SharedTheracState, TPhase};
+use std::time::Duration;
+
+/// How long a finished scene (and its consequences screen) stays up before the next
+pub const ATTRACT_SCENE_HOLD: Duration = Duration::from_secs(8);
+
+/// One scene of the loop
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttractScene {
+    pub title: &'static str,
+    /// Console actions and narration, timed from the start of the scene
+    pub script: ReplayBundle,
+    /// Bending magnet settle time the scene runs with (`SimConfig::bending_magnet_settle_ms`)
+    pub magnet_settle_ms: u64,
+}
+
+fn script(events: Vec<(u64, ReplayEvent)>) -> ReplayBundle {
+    ReplayBundle {
+        events: events.into_iter().map(|(at_ms, event)| TimedEvent { at_ms, event }).collect(),
+    }
+}
+
+fn narrate(text: &str) -> ReplayEvent {
+    ReplayEvent::Narrate(text.to_string())
+}
+
+/// The standard loop: nominal treatment, caught near miss, overdose
+pub fn attract_scenes() -> Vec<AttractScene> {
+    // Random faults pause most pulses; the operator keeps pressing proceed
+    let mut nominal = vec![
+        (0, narrate("A routine electron treatment: entries typed, checked, confirmed")),
+        (1_000, ReplayEvent::Mode(BeamType::Electron)),
+        (1_500, ReplayEvent::Energy(BeamEnergy::E10)),
+        (2_000, ReplayEvent::Gantry(0)),
+        (2_500, ReplayEvent::FieldSize(10.0, 10.0)),
+        (3_000, ReplayEvent::Dose(20.0)),
+        (3_500, ReplayEvent::CompleteDataEntry),
+        (6_000, ReplayEvent::Treat),
+        (6_500, narrate("Cryptic MALFUNCTION messages come and go - the operator proceeds past each")),
+    ];
+    nominal.extend((0..30).map(|i| (7_000 + i * 300, ReplayEvent::Resume)));
+
+    vec![
+        AttractScene {
+            title: "Nominal treatment",
+            script: script(nominal),
+            magnet_settle_ms: 0,
+        },
+        AttractScene {
+            title: "Near miss",
+            script: script(vec![
+                (0, narrate("X-ray entered by mistake, and data entry completed")),
+                (1_000, ReplayEvent::Mode(BeamType::XRay)),
+                (1_500, ReplayEvent::Gantry(0)),
+                (2_000, ReplayEvent::FieldSize(10.0, 10.0)),
+                (2_500, ReplayEvent::Dose(200.0)),
+                (3_000, ReplayEvent::CompleteDataEntry),
+                (5_000, narrate("The operator corrects the mode to electrons and fires at once")),
+                (5_500, ReplayEvent::Mode(BeamType::Electron)),
+                (6_000, ReplayEvent::Energy(BeamEnergy::E10)),
+                (6_500, ReplayEvent::Treat),
+                (7_500, narrate("Caught: the edit was never confirmed, so the beam stays off")),
+            ]),
+            magnet_settle_ms: 0,
+        },
+        AttractScene {
+            title: "Overdose",
+            script: script(vec![
+                (0, narrate("Tyler, Texas, March 1986: the same correction, made while the magnets were settling")),
+                (1_000, ReplayEvent::Mode(BeamType::XRay)),
+                (1_500, ReplayEvent::CompleteDataEntry),
+                (4_500, ReplayEvent::Mode(BeamType::Electron)),
+                (5_000, narrate("Corrected to electrons three seconds later; the operator waits for the setup")),
+                (13_500, ReplayEvent::Treat),
+                (14_000, narrate("The setup never sees the edit. The beam fires without its flatness filter")),
+            ]),
+            magnet_settle_ms: BENDING_MAGNET_SETTLE_TIME.as_millis() as u64,
+        },
+    ]
+}
+
+/// Drives the machine through the scenes in a loop
+/// The caller supplies elapsed wall-clock time, as with `ReplayPlayer`.
+pub struct AttractMode {
+    scenes: Vec<AttractScene>,
+    current: usize,
+    player: ReplayPlayer,
+    started: bool,
+    held: Duration,
+}
+
+impl AttractMode {
+    /// Loop through `scenes`, which must not be empty
+    pub fn new(scenes: Vec<AttractScene>) -> Self {
+        assert!(!scenes.is_empty(), "attract mode needs at least one scene");
+        let player = ReplayPlayer::new(scenes[0].script.clone());
+        Self {
+            scenes,
+            current: 0,
+            player,
+            started: false,
+            held: Duration::ZERO,
+        }
+    }
+
+    pub fn scene(&self) -> &AttractScene {
+        &self.scenes[self.current]
+    }
+
+    /// Latest narration of the current scene
+    pub fn narration(&self) -> Option<&str> {
+        self.player.applied_events().iter().rev().find_map(|timed| match &timed.event {
+            ReplayEvent::Narrate(text) => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Play the current scene on by `elapsed`; once it has been held for
+    /// `ATTRACT_SCENE_HOLD`, reset the machine and start the next one.
+    /// Returns true when a scene starts: screens left over from the last one
+    /// (the consequences screen in particular) should be dismissed.
+    pub fn tick(&mut self, state: &SharedTheracState, elapsed: Duration) -> bool {
+        if !self.started {
+            self.started = true;
+            self.start_scene(state);
+            return true;
+        }
+        if !self.player.is_finished() {
+            self.player.advance(state, elapsed);
+            return false;
+        }
+
+        self.held += elapsed;
+        if self.held < ATTRACT_SCENE_HOLD {
+            return false;
+        }
+        self.current = (self.current + 1) % self.scenes.len();
+        self.start_scene(state);
+        true
+    }
+
+    fn start_scene(&mut self, state: &SharedTheracState) {
+        self.player = ReplayPlayer::new(self.scene().script.clone());
+        self.held = Duration::ZERO;
+
+        let title = self.scene().title;
+        let mut s = state.write_state();
+        s.config.bending_magnet_settle_ms = self.scene().magnet_settle_ms;
+        s.reset();
+        s.phase = TPhase::DataEntry;
+        s.add_log(format!("[SCENARIO] Attract mode: {}", title));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_therac_state;
+    #[cfg(feature = "standalone")]
+    use crate::state::TreatmentOutcome;
+
+    #[cfg(feature = "standalone")]
+    #[tokio::test(start_paused = true)]
+    async fn test_overdose_screen_is_dismissed_and_the_loop_continues() {
+        use crate::simulator::{cleanup_tasks, spawn_treatment_tasks};
+
+        let scenes = attract_scenes();
+        let overdose = scenes.iter().find(|s| s.title == "Overdose").unwrap().clone();
+        let nominal = scenes[0].clone();
+        let mut attract = AttractMode::new(vec![overdose, nominal]);
+        let state = create_therac_state();
+        let tasks = spawn_treatment_tasks(state.clone());
+        let frame = Duration::from_millis(100);
+
+        assert!(attract.tick(&state, frame));
+        assert_eq!(attract.scene().title, "Overdose");
+        while !attract.tick(&state, frame) && state.read().last_outcome.is_none() {
+            tokio::time::sleep(frame).await;
+        }
+        assert!(matches!(state.read().last_outcome, Some(TreatmentOutcome::Overdose { .. })), "{:?}", state.read().last_outcome);
+
+        // The consequences screen stays up for the hold, then the next scene starts
+        let mut held = Duration::ZERO;
+        let mut narration = String::new();
+        while !attract.tick(&state, frame) {
+            held += frame;
+            narration = attract.narration().unwrap_or_default().to_string();
+            tokio::time::sleep(frame).await;
+        }
+        cleanup_tasks(tasks);
+        assert!(narration.contains("flatness filter"));
+        assert!(held >= ATTRACT_SCENE_HOLD - frame);
+        assert_eq!(attract.scene().title, "Nominal treatment");
+        let s = state.read();
+        assert_eq!(s.last_outcome, None);
+        assert_eq!(s.config.bending_magnet_settle_ms, 0);
+        assert_eq!(s.dose_delivered, 0.0);
+    }
+
+    #[test]
+    fn test_scenes_loop_back_to_the_first() {
+        let mut attract = AttractMode::new(attract_scenes());
+        let state = create_therac_state();
+        let mut titles = Vec::new();
+        for _ in 0..200 {
+            if attract.tick(&state, Duration::from_secs(1)) {
+                titles.push(attract.scene().title);
+            }
+        }
+        assert_eq!(&titles[..4], ["Nominal treatment", "Near miss", "Overdose", "Nominal treatment"]);
+    }
+}
@@ -11,7 +11,9 @@
 use rstherac25::*;
 use rstherac25::simulator::*;
 use rstherac25::tui::TuiApp;
-use rstherac25::tui_authentic::AuthenticTuiApp;
+use rstherac25::attract::{attract_scenes, AttractMode};
+use rstherac25::tui_authentic::{AuthenticTuiApp, ColorCapability, KeystrokeReplay};
+use rstherac25::cast::CastFile;
 use std::sync::Arc;
 use parking_lot::RwLock;
 
@@ -19,7 +21,87 @@ use parking_lot::RwLock;
 async fn main() -> anyhow::Result<()> {
     // Check for command-line arguments
     let args: Vec<String> = std::env::args().collect();
-    let use_authentic = args.iter().any(|arg| arg == "--authentic" || arg == "-a");
+    if let Some(i) = args.iter().position(|arg| arg == "--emit-schema") {
+        let name = args.get(i + 1)
+            .ok_or_else(|| anyhow::anyhow!("--emit-schema needs a type: {}", schema::SCHEMA_TYPES.join(", ")))?;
+        let json = schema::schema_json(name)
+            .ok_or_else(|| anyhow::anyhow!("no schema for '{}'; try {}", name, schema::SCHEMA_TYPES.join(", ")))?;
+        println!("{}", json);
+        return Ok(());
+    }
+    let speed = match args.iter().position(|arg| arg == "--speed") {
+        Some(i) => args.get(i + 1)
+            .ok_or_else(|| anyhow::anyhow!("--speed needs a number"))?
+            .parse::<f32>()
+            .ok()
+            .filter(|speed| speed.is_finite() && *speed > 0.0)
+            .ok_or_else(|| anyhow::anyhow!("--speed must be a positive number"))?,
+        None => 1.0,
+    };
+    // A keystroke file from --keylog, replayed on the authentic console
+    let keystroke_replay = match args.iter().position(|arg| arg == "--replay") {
+        Some(i) => {
+            let path = args.get(i + 1)
+                .ok_or_else(|| anyhow::anyhow!("--replay needs a file path"))?;
+            Some(KeystrokeReplay::parse(&std::fs::read_to_string(path)?, speed)?)
+        }
+        None => None,
+    };
+    let use_authentic = keystroke_replay.is_some() || args.iter().any(|arg| arg == "--authentic" || arg == "-a");
+    // A SimConfig TOML file; the switches below turn settings on over it
+    let mut config = match args.iter().position(|arg| arg == "--config") {
+        Some(i) => {
+            let path = args.get(i + 1)
+                .ok_or_else(|| anyhow::anyhow!("--config needs a file path"))?;
+            SimConfig::from_toml_path(path)
+                .map_err(|error| anyhow::anyhow!("cannot load config '{}': {}", path, error))?
+        }
+        None => SimConfig::default(),
+    };
+    config.harm_reporting_only |= args.iter().any(|arg| arg == "--harm-reporting-only");
+    let color = if args.iter().any(|arg| arg == "--mono") {
+        ColorCapability::Monochrome
+    } else {
+        ColorCapability::detect()
+    };
+    config.phantom_operator |= args.iter().any(|arg| arg == "--phantom-operator");
+    config.hardware_watchdog |= args.iter().any(|arg| arg == "--watchdog");
+    config.safe_mode |= args.iter().any(|arg| arg == "--safe");
+    let attract = args.iter().any(|arg| arg == "--attract");
+    let replay_bundle = match args.iter().position(|arg| arg == "--replay-bundle") {
+        Some(i) => {
+            let path = args.get(i + 1)
+                .ok_or_else(|| anyhow::anyhow!("--replay-bundle needs a file path"))?;
+            Some(ReplayBundle::from_json(&std::fs::read_to_string(path)?)?)
+        }
+        None => None,
+    };
+    let messages = match args.iter().position(|arg| arg == "--messages") {
+        Some(i) => {
+            let path = args.get(i + 1)
+                .ok_or_else(|| anyhow::anyhow!("--messages needs a file path"))?;
+            Some(Messages::from_json(&std::fs::read_to_string(path)?)?)
+        }
+        None => None,
+    };
+    let seed = match args.iter().position(|arg| arg == "--seed") {
+        Some(i) => Some(args.get(i + 1)
+            .ok_or_else(|| anyhow::anyhow!("--seed needs a number"))?
+            .parse::<u64>()?),
+        None => None,
+    };
+    let record_path = match args.iter().position(|arg| arg == "--record") {
+        Some(i) => Some(args.get(i + 1)
+            .ok_or_else(|| anyhow::anyhow!("--record needs a file path"))?
+            .clone()),
+        None => None,
+    };
+    let keylog_path = match args.iter().position(|arg| arg == "--keylog") {
+        Some(i) => Some(args.get(i + 1)
+            .ok_or_else(|| anyhow::anyhow!("--keylog needs a file path"))?
+            .clone()),
+        None => None,
+    };
 
     // Print warning
     println!("\n╔═══════════════════════════════════════════════════════════════════════╗");
@@ -36,6 +118,12 @@ async fn main() -> anyhow::Result<()> {
     println!("║                                                                        ║");
     println!("║  Based on Nancy Leveson's analysis: \"Medical Devices: The Therac-25\"  ║");
     println!("║                                                                        ║");
+    println!("║  {:<69}║", if config.safe_mode {
+        "Mode: SAFE - race removed (--safe)"
+    } else {
+        "Mode: ORIGINAL - race condition present (--safe to fix)"
+    });
+    println!("║                                                                        ║");
     println!("╚═══════════════════════════════════════════════════════════════════════╝\n");
 
     if use_authentic {
@@ -46,8 +134,18 @@ async fn main() -> anyhow::Result<()> {
     }
 
     // Create shared state
-    let state = Arc::new(RwLock::new(TheracState::new()));
-    state.write().add_log("System initialized".to_string());
+    let state = Arc::new(RwLock::new(match seed {
+        Some(seed) => TheracState::with_seed(seed),
+        None => TheracState::new(),
+    }));
+    {
+        let mut s = state.write();
+        s.config = config;
+        if let Some(messages) = messages {
+            s.messages = Arc::new(messages);
+        }
+        s.add_log("System initialized".to_string());
+    }
 
     // Start concurrent tasks
     let state_clone1 = state.clone();
@@ -61,15 +159,61 @@ async fn main() -> anyhow::Result<()> {
         housekeeper(state_clone2).await;
     });
 
+    let state_clone3 = state.clone();
+    tokio::spawn(async move {
+        phantom_operator(state_clone3).await;
+    });
+
+    let state_clone4 = state.clone();
+    tokio::spawn(async move {
+        hardware_watchdog(state_clone4).await;
+    });
+
+    let state_clone5 = state.clone();
+    tokio::spawn(async move {
+        bending_magnet_settler(state_clone5).await;
+    });
+
+    // The consoles send their commands through this channel rather than locking the state
+    let commands = spawn_command_processor(state.clone());
+
     // Give tasks time to start
     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
 
+    let recorder = match record_path {
+        Some(path) => {
+            let (width, height) = crossterm::terminal::size()?;
+            Some(CastFile::create(path, width, height)?)
+        }
+        None => None,
+    };
+
     // Run TUI
     if use_authentic {
-        let mut app = AuthenticTuiApp::new(state.clone());
+        let mut app = AuthenticTuiApp::new(state.clone())
+            .with_color_capability(color)
+            .with_command_sender(commands);
+        if let Some(recorder) = recorder {
+            app = app.with_recording(recorder);
+        }
+        if let Some(path) = keylog_path {
+            app = app.with_keystroke_log(path);
+        }
+        if let Some(replay) = keystroke_replay {
+            app = app.with_replay(replay);
+        }
         app.run()?;
     } else {
-        let mut app = TuiApp::new(state.clone());
+        let mut app = TuiApp::new(state.clone()).with_command_sender(commands);
+        if attract {
+            app = app.with_attract(AttractMode::new(attract_scenes()));
+        }
+        if let Some(bundle) = replay_bundle {
+            app = app.with_replay(bundle);
+        }
+        if let Some(recorder) = recorder {
+            app = app.with_recording(recorder);
+        }
         app.run().await?;
     }
 
@@ -0,0 +1,148 @@
+//! Terminal recordings in asciinema v2 format
+//!
+//! A `.cast` file is a JSON header line followed by one JSON array per event:
+//! `[seconds, "o", output]` for terminal output and `[seconds, "r", "WxH"]` for a
+//! resize. The recorder writes the text of every rendered frame that differs from
+//! the previous one, so the file plays back in any asciinema player without the
+//! simulator. Colours and styles are not recorded.
+
+use ratatui::buffer::Buffer;
+use serde_json::json;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::Instant;
+use unicode_width::UnicodeWidthStr;
+
+/// Writes rendered frames to an asciinema v2 cast
+pub struct CastRecorder<W: Write> {
+    out: W,
+    started: Instant,
+    size: (u16, u16),
+    last_frame: Option<String>,
+}
+
+/// Recorder writing to a file, as used by the TUIs
+pub type CastFile = CastRecorder<BufWriter<File>>;
+
+impl CastFile {
+    /// Create (or truncate) a `.cast` file for a terminal of `width`×`height`
+    pub fn create(path: impl AsRef<Path>, width: u16, height: u16) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), width, height)
+    }
+}
+
+impl<W: Write> CastRecorder<W> {
+    /// Start a recording on `out`, writing the header immediately
+    pub fn new(mut out: W, width: u16, height: u16) -> io::Result<Self> {
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": chrono::Utc::now().timestamp(),
+            "title": "Therac-25 simulator",
+        });
+        writeln!(out, "{}", header)?;
+        Ok(Self {
+            out,
+            started: Instant::now(),
+            size: (width, height),
+            last_frame: None,
+        })
+    }
+
+    /// Record a rendered frame, timestamped from the start of the recording
+    /// Frames identical to the previous one are skipped.
+    pub fn record(&mut self, buffer: &Buffer) -> io::Result<()> {
+        let at = self.started.elapsed().as_secs_f64();
+
+        let size = (buffer.area.width, buffer.area.height);
+        if size != self.size {
+            self.size = size;
+            self.last_frame = None;
+            self.write_event(at, "r", &format!("{}x{}", size.0, size.1))?;
+        }
+
+        let frame = frame_text(buffer);
+        if self.last_frame.as_ref() == Some(&frame) {
+            return Ok(());
+        }
+        // Home the cursor and repaint every row; each row fills the full width
+        self.write_event(at, "o", &format!("\x1b[H{}", frame))?;
+        self.last_frame = Some(frame);
+        Ok(())
+    }
+
+    fn write_event(&mut self, at: f64, kind: &str, data: &str) -> io::Result<()> {
+        writeln!(self.out, "{}", json!([at, kind, data]))?;
+        // Flush per frame so a crash still leaves a playable recording
+        self.out.flush()
+    }
+
+    /// Finish the recording and return the writer
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+/// Plain text of a buffer, rows separated by CR LF
+/// Cells hidden behind a wide character are skipped so columns stay aligned.
+fn frame_text(buffer: &Buffer) -> String {
+    let width = buffer.area.width as usize;
+    let mut rows = Vec::with_capacity(buffer.area.height as usize);
+    for row in buffer.content.chunks(width.max(1)) {
+        let mut line = String::with_capacity(width);
+        let mut hidden = 0;
+        for cell in row {
+            if hidden > 0 {
+                hidden -= 1;
+                continue;
+            }
+            line.push_str(cell.symbol());
+            hidden = cell.symbol().width().saturating_sub(1);
+        }
+        rows.push(line);
+    }
+    rows.join("\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::layout::Rect;
+    use ratatui::style::Style;
+
+    fn buffer(width: u16, height: u16, text: &str) -> Buffer {
+        let mut buffer = Buffer::empty(Rect::new(0, 0, width, height));
+        buffer.set_string(0, 0, text, Style::default());
+        buffer
+    }
+
+    #[test]
+    fn test_cast_records_changed_frames_and_resizes() {
+        let mut recorder = CastRecorder::new(Vec::new(), 4, 2).unwrap();
+        recorder.record(&buffer(4, 2, "ab")).unwrap();
+        recorder.record(&buffer(4, 2, "ab")).unwrap();
+        recorder.record(&buffer(3, 1, "c")).unwrap();
+
+        let cast = String::from_utf8(recorder.into_inner()).unwrap();
+        let lines: Vec<serde_json::Value> = cast
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 4, "{}", cast);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 4);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "\x1b[Hab  \r\n    ");
+        assert_eq!(lines[2][1], "r");
+        assert_eq!(lines[2][2], "3x1");
+        assert_eq!(lines[3][2], "\x1b[Hc  ");
+    }
+
+    #[test]
+    fn test_wide_characters_keep_columns_aligned() {
+        assert_eq!(frame_text(&buffer(4, 1, "日x")), "日x ");
+    }
+}
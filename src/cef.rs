@@ -0,0 +1,159 @@
+//! Common Event Format (CEF) export of incidents
+//!
+//! For a security/monitoring lesson: every malfunction in `TheracState::incidents`
+//! becomes one syslog-style line a SIEM can ingest, so the fault stream of a
+//! safety-critical device can be watched the way a SOC watches a firewall.
+//!
+//! ```text
+//! Oct 16 14:03:07 therac25 CEF:0|rstherac25|Therac25|0.1.0|UNSAFE-BEAM|Unsafe beam fired|10|rt=... msg=...
+//! ```
+//!
+//! Header fields escape `\` and `|`; extension values escape `\`, `=` and line breaks,
+//! as the CEF specification requires.
+
+use crate::state::{Incident, MalfunctionKind};
+
+/// Host name written into the syslog prefix
+pub const CEF_HOST: &str = "therac25";
+
+/// Signature ID, name and severity (0-10) of a kind of malfunction
+pub fn signature(kind: &MalfunctionKind) -> (String, &'static str, u8) {
+    let (id, severity) = match kind {
+        MalfunctionKind::EditInProgress => ("EDIT-IN-PROGRESS".to_string(), 5),
+        MalfunctionKind::ParameterMismatch => ("MALFUNCTION-54".to_string(), 6),
+        MalfunctionKind::UnsafeBeam { .. } => ("UNSAFE-BEAM".to_string(), 10),
+        MalfunctionKind::CollimatorTimeout => ("COLLIMATOR-TIMEOUT".to_string(), 7),
+        MalfunctionKind::MagnetNotSettled => ("MAGNET-NOT-SETTLED".to_string(), 6),
+        MalfunctionKind::EnergyNotSettled => ("ENERGY-NOT-SETTLED".to_string(), 6),
+        MalfunctionKind::CollimatorStuck => ("COLLIMATOR-STUCK".to_string(), 7),
+        MalfunctionKind::RandomFault(number) => (format!("MALFUNCTION-{}", number), 3),
+    };
+    (id, kind.name(), severity)
+}
+
+/// Escape a header field: backslash and pipe
+pub fn escape_header(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// Escape an extension value: backslash, equals sign and line breaks
+pub fn escape_extension(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// One incident as a syslog-prefixed CEF line (without the trailing newline)
+pub fn incident_line(incident: &Incident) -> String {
+    let at = chrono::DateTime::parse_from_rfc3339(&incident.at).ok();
+    let (signature_id, name, severity) = signature(&incident.kind);
+
+    let mut extension = vec![
+        ("rt", at.map_or_else(|| incident.at.clone(), |at| at.timestamp_millis().to_string())),
+        ("msg", incident.message.clone()),
+        ("cfp1", format!("{:.1}", incident.dose_delivered)),
+        ("cfp1Label", "doseDeliveredCgy".to_string()),
+        ("cfp2", format!("{:.1}", incident.dose_target)),
+        ("cfp2Label", "doseTargetCgy".to_string()),
+    ];
+    if let MalfunctionKind::UnsafeBeam { dose_multiplier, dose_this_pulse } = incident.kind {
+        extension.extend([
+            ("cfp3", format!("{:.1}", dose_multiplier)),
+            ("cfp3Label", "doseMultiplier".to_string()),
+            ("cfp4", format!("{:.1}", dose_this_pulse)),
+            ("cfp4Label", "pulseDoseCgy".to_string()),
+        ]);
+    }
+    if let Some(operator) = &incident.operator_id {
+        extension.push(("suser", operator.clone()));
+    }
+    let extension: Vec<String> = extension
+        .into_iter()
+        .map(|(key, value)| format!("{}={}", key, escape_extension(&value)))
+        .collect();
+
+    format!(
+        "{} {} CEF:0|rstherac25|Therac25|{}|{}|{}|{}|{}",
+        at.map_or_else(|| incident.at.clone(), |at| at.format("%b %e %H:%M:%S").to_string()),
+        CEF_HOST,
+        escape_header(env!("CARGO_PKG_VERSION")),
+        escape_header(&signature_id),
+        escape_header(name),
+        severity,
+        extension.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::TheracState;
+
+    /// Split on the separator where it is not escaped, unescaping as we go
+    fn split_unescaped(text: &str, separator: char, limit: usize) -> Vec<String> {
+        let mut fields = vec![String::new()];
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('n') => fields.last_mut().unwrap().push('\n'),
+                    Some(escaped) => fields.last_mut().unwrap().push(escaped),
+                    None => {}
+                },
+                c if c == separator && fields.len() < limit => fields.push(String::new()),
+                c => fields.last_mut().unwrap().push(c),
+            }
+        }
+        fields
+    }
+
+    #[test]
+    fn test_cef_lines_follow_the_spec() {
+        let mut s = TheracState {
+            operator_id: Some("op7".to_string()),
+            dose_target: 200.0,
+            ..TheracState::default()
+        };
+        s.report_malfunction(MalfunctionKind::ParameterMismatch, "MALFUNCTION 54 - Console: a|b\\c".to_string());
+        s.dose_delivered = 8000.0;
+        s.report_malfunction(
+            MalfunctionKind::UnsafeBeam { dose_multiplier: 100.0, dose_this_pulse: 8000.0 },
+            "dose=8000\nLETHAL".to_string(),
+        );
+
+        let export = s.export_incidents_cef();
+        let lines: Vec<&str> = export.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in &lines {
+            let (prefix, cef) = line.split_once("CEF:").unwrap();
+            assert!(prefix.ends_with(" therac25 "), "syslog prefix: {:?}", prefix);
+            let header = split_unescaped(cef, '|', 8);
+            assert_eq!(header.len(), 8, "{}", line);
+            assert_eq!(&header[..4], ["0", "rstherac25", "Therac25", env!("CARGO_PKG_VERSION")]);
+        }
+
+        let mismatch = split_unescaped(lines[0].split_once("CEF:").unwrap().1, '|', 8);
+        assert_eq!(mismatch[4], "MALFUNCTION-54");
+        assert_eq!(mismatch[6], "6");
+        assert!(lines[0].contains("msg=MALFUNCTION 54 - Console: a|b\\\\c "));
+        assert!(mismatch[7].ends_with("suser=op7"));
+
+        let overdose = split_unescaped(lines[1].split_once("CEF:").unwrap().1, '|', 8);
+        assert_eq!((overdose[4].as_str(), overdose[6].as_str()), ("UNSAFE-BEAM", "10"));
+        // Escaped '=' and line breaks keep the extension one line of key=value pairs
+        assert!(lines[1].contains("msg=dose\\=8000\\nLETHAL "));
+        for field in ["cfp1=8000.0", "cfp2=200.0", "cfp3=100.0", "cfp3Label=doseMultiplier", "cfp4=8000.0"] {
+            assert!(lines[1].contains(field), "missing {}", field);
+        }
+    }
+
+    #[test]
+    fn test_header_escaping() {
+        assert_eq!(escape_header("a|b\\c"), "a\\|b\\\\c");
+        assert_eq!(escape_extension("a=b\\c\r\nd"), "a\\=b\\\\c\\nd");
+        assert_eq!(signature(&MalfunctionKind::RandomFault(37)).0, "MALFUNCTION-37");
+    }
+}
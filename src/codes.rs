@@ -0,0 +1,118 @@
+//! Malfunction codes and what they meant
+//!
+//! The console reported faults as "MALFUNCTION" and a number from 1 to 64, the analog/digital
+//! channel the fault was read on, and the operator's manual did not explain them
+//! (Leveson & Turner, "An Investigation of the Therac-25 Accidents", IEEE Computer, 1993).
+//! Only a few are on record with a meaning. This table keeps those, with their source,
+//! and gives the other channels the simulator raises a short description of its own; the
+//! source of each entry says which it is.
+
+use rand::Rng;
+
+/// One malfunction number, with its short description and where it comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MalfunctionCode {
+    /// Shown as "MALFUNCTION n"
+    pub code: u32,
+    /// The short description, as in the manual's code list
+    pub description: &'static str,
+    /// Where the number and description come from
+    pub source: &'static str,
+    /// A hardware fault that can come up at random during treatment
+    pub random: bool,
+}
+
+const fn code(code: u32, description: &'static str, source: &'static str, random: bool) -> MalfunctionCode {
+    MalfunctionCode { code, description, source, random }
+}
+
+/// Every code the simulator raises, lowest first
+pub static MALFUNCTION_CODES: [MalfunctionCode; 8] = [
+    code(12, "H-TILT - horizontal beam tilt out of tolerance",
+        "Leveson & Turner 1993: Hamilton, Ontario, July 1985, shut down with an H-tilt error \
+         and 'no dose' shown. The channel number is not on record; 12 is the simulator's",
+        true),
+    code(13, "V-TILT - vertical beam tilt out of tolerance",
+        "Simulator: the vertical counterpart of H-TILT, not itself on record",
+        true),
+    code(26, "BEAM FIRED UNSAFE - hardware not configured for the beam type",
+        "Simulator: raised when a pulse fires with the turntable wrong for the mode, so an \
+         overdose stands apart from a caught mismatch. The original's overdoses showed 54",
+        false),
+    code(31, "BEAM CURRENT - accelerator current outside its window",
+        "Simulator: an accelerator channel, not on record",
+        true),
+    code(38, "TARGET POSITION - X-ray target readback disagrees with the mode",
+        "Simulator: a turntable channel, not on record",
+        true),
+    code(47, "GANTRY POSITION - gantry angle readback disagrees with the entry",
+        "Simulator: a gantry channel, not on record",
+        true),
+    code(53, "DOSE INPUT 1 - dose monitor 1 reading out of tolerance",
+        "Simulator: the first of the two dose monitoring channels, by analogy with 54",
+        true),
+    code(54, "DOSE INPUT 2 - dose monitor 2 read too high or too low",
+        "Leveson & Turner 1993: East Texas Cancer Center, March and April 1986. The manual \
+         called it a 'dose input 2' error; AECL explained it as a dose too high or too low. \
+         The simulator also shows it for interlocks with no number of their own",
+        false),
+];
+
+/// The code table entry for `code`, if it is one the simulator raises
+pub fn malfunction_code(code: u32) -> Option<&'static MalfunctionCode> {
+    MALFUNCTION_CODES.iter().find(|entry| entry.code == code)
+}
+
+/// Short description of `code`, if it has one
+pub fn describe_code(code: u32) -> Option<&'static str> {
+    malfunction_code(code).map(|entry| entry.description)
+}
+
+/// Draw the code for a random hardware fault
+pub fn random_fault_code<R: Rng>(rng: &mut R) -> &'static MalfunctionCode {
+    let candidates: Vec<_> = MALFUNCTION_CODES.iter().filter(|entry| entry.random).collect();
+    candidates[rng.gen_range(0..candidates.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::MalfunctionKind;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_every_code_is_described_and_sourced() {
+        for entry in &MALFUNCTION_CODES {
+            assert!(!entry.description.trim().is_empty(), "MALFUNCTION {}", entry.code);
+            assert!(!entry.source.trim().is_empty(), "MALFUNCTION {}", entry.code);
+            assert!((1..=64).contains(&entry.code), "MALFUNCTION {} is not a channel", entry.code);
+            assert_eq!(malfunction_code(entry.code), Some(entry));
+        }
+        assert!(MALFUNCTION_CODES.windows(2).all(|pair| pair[0].code < pair[1].code));
+        assert_eq!(describe_code(99), None);
+
+        // Every interlock's number is in the table
+        for kind in [
+            MalfunctionKind::EditInProgress,
+            MalfunctionKind::ParameterMismatch,
+            MalfunctionKind::UnsafeBeam { dose_multiplier: 100.0, dose_this_pulse: 1.0 },
+            MalfunctionKind::CollimatorTimeout,
+            MalfunctionKind::MagnetNotSettled,
+            MalfunctionKind::EnergyNotSettled,
+            MalfunctionKind::CollimatorStuck,
+        ] {
+            assert!(describe_code(kind.code()).is_some(), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn test_random_faults_draw_from_the_table() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut drawn: Vec<u32> = (0..200).map(|_| random_fault_code(&mut rng).code).collect();
+        drawn.sort();
+        drawn.dedup();
+        let expected: Vec<u32> = MALFUNCTION_CODES.iter().filter(|e| e.random).map(|e| e.code).collect();
+        assert_eq!(drawn, expected);
+    }
+}
@@ -0,0 +1,463 @@
+//! Simulator configuration
+//!
+//! Behaviour switches that deviate from (or stay faithful to) the original machine.
+//! Defaults always match the real Therac-25.
+
+use crate::simulator::{COLLIMATOR_TRANSIT_TIME, ENERGY_SETTLE_TIME, PARAM_SYNC_TIME};
+use crate::state::{CollimatorPosition, Meos};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Configuration carried in `TheracState::config`
+/// Survives a reset; only the operator/integrator changes it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default)]
+pub struct SimConfig {
+    /// Selecting X-ray forces 25 MeV and skips the energy field, as on the real console.
+    /// When false, X-ray energy is entered manually like electron energy.
+    pub auto_max_energy_on_xray: bool,
+    /// Refuse to start treatment until every pre-treatment checklist item is ticked.
+    /// The original machine had no such checklist.
+    pub require_checklist: bool,
+    /// Refuse to complete data entry while console entries differ from the prescription
+    /// and the operator has not acknowledged the differences (`verify`). No read-back
+    /// step existed on the original machine.
+    pub require_readback: bool,
+    /// Shot-to-shot dose variation as a fraction of the nominal pulse dose (0.05 = ±5%).
+    /// Zero delivers exactly the modelled dose every pulse.
+    pub dose_jitter: f32,
+    /// Concentrate the pulse dose in small fields, inversely to the field area
+    /// (a 5x5 cm field receives 4x the dose of a 10x10 cm one). Off by default, so
+    /// only the dose rate changes the dose per pulse.
+    pub field_size_scales_dose: bool,
+    /// Malfunction dismissals per session that raise a NORMALIZED DEVIANCE warning.
+    /// Zero disables the warning.
+    pub normalized_deviance_threshold: u32,
+    /// Enter on an empty entry field copies the prescribed value (the expert shortcut
+    /// behind the historical hazard). When false, every value has to be typed.
+    pub auto_copy_enabled: bool,
+    /// Extra transit time (ms) when the turntable is told to reverse mid-move.
+    /// The mechanism has to stop and overcome its own play before heading back.
+    pub collimator_hysteresis_ms: u32,
+    /// Time (ms) for one turntable move on a new machine: how long the race window
+    /// stays open after a mode edit. Read by the housekeeper at the start of each move.
+    pub collimator_move_ms: u64,
+    /// Time (ms) for the housekeeper to bring gantry, field size and the other treatment
+    /// params in line with the console.
+    pub param_sync_ms: u64,
+    /// Hold (ms) between beam-on and the first pulse, during which a stop aborts
+    /// with no dose. Zero fires at once, as the original did.
+    pub beam_hold_ms: u32,
+    /// Pulse repetition frequency (Hz) at `REFERENCE_DOSE_RATE`: pulses are spaced in
+    /// simulated time, and a higher dose rate pulses proportionally faster instead of
+    /// making each pulse bigger. Zero fires a pulse on every monitor pass and puts the
+    /// dose rate into the pulse size.
+    pub pulse_repetition_hz: f64,
+    /// Chance (0.0-1.0) that one verification reading is glitched: stale or inverted.
+    /// Zero gives a perfect sensor.
+    pub sensor_glitch_probability: f32,
+    /// Chance (0.0-1.0) that a pulse is stopped by a random hardware fault instead of
+    /// firing. The default is the original's `RANDOM_FAULT_PROBABILITY`; zero leaves only
+    /// the race and the safety interlocks to stop a pulse.
+    pub random_fault_probability: f64,
+    /// Chance (0.0-1.0) that a turntable move jams mid-rotation instead of completing,
+    /// leaving the collimator `Stuck` until an operator frees it. Zero never jams.
+    pub collimator_stick_probability: f32,
+    /// Verify against the majority of three sensor readings instead of a single one.
+    /// The original trusted one reading.
+    pub sensor_majority_vote: bool,
+    /// Frame overdose outcomes as a simulation: the model is unchanged, but displays
+    /// carry `HARM_REPORTING_BANNER` and label doses as hypothetical.
+    pub harm_reporting_only: bool,
+    /// Average growth of `TheracState::wear_factor` per treatment (0.01 = 1% slower
+    /// mechanics each treatment, jittered). Zero keeps the machine as new.
+    pub wear_per_treatment: f32,
+    /// Upper bound on `TheracState::wear_factor`; a machine this worn is overdue for service.
+    pub max_wear_factor: f32,
+    /// Treatments allowed between recalibrations; once reached, treatment is refused with
+    /// RECALIBRATION REQUIRED until the machine is recalibrated. Zero never asks.
+    pub recalibration_interval: u32,
+    /// Log a `LOCK WAIT` diagnostic when a beam pulse or collimator sync waits at least
+    /// this long (ms) for the write lock. Zero disables the diagnostic.
+    pub lock_wait_log_ms: u32,
+    /// Run a simulated second operator who now and then reaches over and nudges a
+    /// console entry: a third writer racing the monitor and housekeeper.
+    pub phantom_operator: bool,
+    /// Fire pulses through `zap_the_specimen_safe`, which checks the hardware and fires
+    /// under one lock acquisition: the race condition fixed. The original raced.
+    pub safe_mode: bool,
+    /// Run an independent watchdog that trips COLLIMATOR TIMEOUT and inhibits the beam
+    /// when the turntable is still moving after twice its longest legitimate move.
+    /// The original had no such check.
+    pub hardware_watchdog: bool,
+    /// Time (ms) the bending magnets take to settle after the beam type switches; a pulse
+    /// triggered before then raises BENDING MAGNET NOT SETTLED. The real magnets took about
+    /// 8 s (`BENDING_MAGNET_SETTLE_TIME`). Zero settles them at once.
+    pub bending_magnet_settle_ms: u64,
+    /// Time (ms) the energy system (klystron and accelerator) takes to settle after the
+    /// console energy goes up; a pulse triggered before then raises ENERGY NOT SETTLED.
+    /// Zero settles it at once.
+    pub energy_settle_ms: u64,
+    /// Largest dose (cGy) shown for an overdose; above it displays read `>N` and flag a
+    /// LETHAL OVERDOSE. Only the reporting is clamped: the dose model and recorded doses
+    /// are unchanged. Zero shows the raw figures.
+    pub reported_dose_cap: f64,
+    /// Hardware dose interlock (cGy): a pulse that would take the delivered dose past it
+    /// is never fired, and the treatment is terminated. The original had no such
+    /// interlock; zero leaves it out.
+    pub max_safe_dose_cgy: f64,
+    /// Order in which the housekeeper brings hardware aspects in line with the console.
+    /// The default moves the turntable first and sets beam type and energy on arrival.
+    pub sync_order: SyncOrder,
+}
+
+/// One hardware aspect the housekeeper syncs to the console
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum SyncAspect {
+    /// Turntable position (flatness filter in or out)
+    Collimator,
+    BeamType,
+    Energy,
+    /// Gantry angle, field size and the other treatment params
+    Params,
+}
+
+/// Housekeeper sync priority, first aspect first
+/// Aspects ordered before `Collimator` are synced before the turntable starts to move,
+/// so the hardware passes through a transient with them already changed: energy first,
+/// for instance, gives a high-energy beam with the filter still set for the old mode.
+/// Aspects after it are synced when the turntable arrives. Aspects left out are synced
+/// last, in the default order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(transparent)]
+pub struct SyncOrder(pub Vec<SyncAspect>);
+
+impl Default for SyncOrder {
+    fn default() -> Self {
+        SyncOrder(vec![SyncAspect::Collimator, SyncAspect::BeamType, SyncAspect::Energy, SyncAspect::Params])
+    }
+}
+
+impl SyncOrder {
+    /// Parse aspect names separated by commas or spaces, e.g. "energy, collimator"
+    /// (case-insensitive; `beam` or `beamtype` for the beam type). `None` on an unknown name.
+    pub fn parse(text: &str) -> Option<Self> {
+        text.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|name| !name.is_empty())
+            .map(|name| match name.to_lowercase().as_str() {
+                "collimator" => Some(SyncAspect::Collimator),
+                "beam" | "beamtype" => Some(SyncAspect::BeamType),
+                "energy" => Some(SyncAspect::Energy),
+                "params" => Some(SyncAspect::Params),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(SyncOrder)
+    }
+
+    /// Every aspect once, in priority order
+    pub fn aspects(&self) -> Vec<SyncAspect> {
+        let mut aspects: Vec<SyncAspect> = Vec::new();
+        for aspect in self.0.iter().chain(SyncOrder::default().0.iter()) {
+            if !aspects.contains(aspect) {
+                aspects.push(*aspect);
+            }
+        }
+        aspects
+    }
+
+    /// Aspects synced before the turntable starts to move
+    pub fn before_collimator(&self) -> Vec<SyncAspect> {
+        self.aspects().into_iter().take_while(|a| *a != SyncAspect::Collimator).collect()
+    }
+
+    /// Hardware MEOS while the turntable moves from `hardware` towards `console`
+    pub fn transient(&self, console: Meos, hardware: Meos) -> Meos {
+        let mut transient = Meos { collimator: CollimatorPosition::Transitioning, ..hardware };
+        for aspect in self.before_collimator() {
+            match aspect {
+                SyncAspect::BeamType => transient.beam_type = console.beam_type,
+                SyncAspect::Energy => transient.beam_energy = console.beam_energy,
+                SyncAspect::Collimator | SyncAspect::Params => {}
+            }
+        }
+        transient
+    }
+}
+
+/// Banner shown on outcome and malfunction displays when `harm_reporting_only` is set
+pub const HARM_REPORTING_BANNER: &str = "NO ACTUAL HARM — SIMULATION";
+
+/// Display cap (cGy) matching the documented incidents: ~100 Gy, the lower end of the
+/// doses estimated for the Therac-25 accidents
+pub const DOCUMENTED_INCIDENT_DOSE: f64 = 10_000.0;
+
+/// Chance of a random hardware fault per pulse on the original: a draw from 12-53
+/// faulted above 22, 31 times in 42
+pub const RANDOM_FAULT_PROBABILITY: f64 = 31.0 / 42.0;
+
+/// Slowest pulse repetition frequency `validate` accepts: one pulse every 100 s
+pub const MIN_PULSE_REPETITION_HZ: f64 = 0.01;
+
+/// Fastest pulse repetition frequency `validate` accepts
+pub const MAX_PULSE_REPETITION_HZ: f64 = 1000.0;
+
+impl SimConfig {
+    /// One turntable move on a new machine (`collimator_move_ms`)
+    pub fn collimator_move_time(&self) -> Duration {
+        Duration::from_millis(self.collimator_move_ms)
+    }
+
+    /// Bending magnet settle time (`bending_magnet_settle_ms`)
+    pub fn bending_magnet_settle_time(&self) -> Duration {
+        Duration::from_millis(self.bending_magnet_settle_ms)
+    }
+
+    /// Energy system settle time (`energy_settle_ms`)
+    pub fn energy_settle_time(&self) -> Duration {
+        Duration::from_millis(self.energy_settle_ms)
+    }
+
+    /// One param sync on a new machine (`param_sync_ms`)
+    pub fn param_sync_time(&self) -> Duration {
+        Duration::from_millis(self.param_sync_ms)
+    }
+
+    /// Appended to dose figures on outcome displays: " (hypothetical)" in
+    /// harm-reporting-only mode, empty otherwise
+    pub fn dose_label(&self) -> &'static str {
+        if self.harm_reporting_only {
+            " (hypothetical)"
+        } else {
+            ""
+        }
+    }
+
+    /// Whether `dose` (cGy) is above `reported_dose_cap`
+    pub fn exceeds_reported_cap(&self, dose: f64) -> bool {
+        self.reported_dose_cap > 0.0 && dose > self.reported_dose_cap
+    }
+
+    /// Whether delivering `dose` (cGy) in total would trip the `max_safe_dose_cgy` interlock
+    pub fn exceeds_safe_dose(&self, dose: f64) -> bool {
+        self.max_safe_dose_cgy > 0.0 && dose > self.max_safe_dose_cgy
+    }
+
+    /// `dose` (cGy) as displayed: to one decimal, or `>N` above `reported_dose_cap`
+    pub fn reported_dose(&self, dose: f64) -> String {
+        if self.exceeds_reported_cap(dose) {
+            format!(">{:.0}", self.reported_dose_cap)
+        } else {
+            format!("{:.1}", dose)
+        }
+    }
+
+    /// Check that every number is finite and in range
+    /// A probability or jitter outside 0.0-1.0, a negative dose, or a pulse rate so low
+    /// that the pulse spacing does not fit a `Duration` would otherwise panic or misbehave
+    /// deep inside a running treatment. The message names the offending field.
+    pub fn validate(&self) -> Result<(), String> {
+        let fractions = [
+            ("dose_jitter", self.dose_jitter as f64),
+            ("sensor_glitch_probability", self.sensor_glitch_probability as f64),
+            ("random_fault_probability", self.random_fault_probability),
+            ("collimator_stick_probability", self.collimator_stick_probability as f64),
+        ];
+        for (name, value) in fractions {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{} must be between 0.0 and 1.0, not {}", name, value));
+            }
+        }
+        let non_negative = [
+            ("wear_per_treatment", self.wear_per_treatment as f64),
+            ("reported_dose_cap", self.reported_dose_cap),
+            ("max_safe_dose_cgy", self.max_safe_dose_cgy),
+        ];
+        for (name, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must be a finite number of at least 0, not {}", name, value));
+            }
+        }
+        if !(self.max_wear_factor.is_finite() && self.max_wear_factor >= 1.0) {
+            return Err(format!("max_wear_factor must be a finite number of at least 1, not {}", self.max_wear_factor));
+        }
+        let hz = self.pulse_repetition_hz;
+        if !(hz == 0.0 || (MIN_PULSE_REPETITION_HZ..=MAX_PULSE_REPETITION_HZ).contains(&hz)) {
+            return Err(format!(
+                "pulse_repetition_hz must be 0 or between {} and {}, not {}",
+                MIN_PULSE_REPETITION_HZ, MAX_PULSE_REPETITION_HZ, hz
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read a configuration from TOML; fields left out keep their defaults
+    /// A configuration that parses but fails `validate` is rejected as well.
+    #[cfg(feature = "config-files")]
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        let config: Self = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate().map_err(ConfigError::Invalid)?;
+        Ok(config)
+    }
+
+    /// The configuration as TOML, every field included
+    #[cfg(feature = "config-files")]
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string_pretty(self)
+    }
+
+    /// Load a configuration file written by hand or with `to_toml_path`
+    /// A file that is not valid TOML, has a field of the wrong type, or fails `validate`
+    /// is `InvalidData`.
+    #[cfg(all(feature = "config-files", not(target_arch = "wasm32")))]
+    pub fn from_toml_path(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    /// Save the configuration to a file as TOML
+    #[cfg(all(feature = "config-files", not(target_arch = "wasm32")))]
+    pub fn to_toml_path(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        let text = self.to_toml()
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?;
+        std::fs::write(path, text)
+    }
+}
+
+/// Why `SimConfig::from_toml` refused a configuration
+#[cfg(feature = "config-files")]
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Not valid TOML, or a field of the wrong type
+    Parse(toml::de::Error),
+    /// Parsed, but `SimConfig::validate` rejected a value
+    Invalid(String),
+}
+
+#[cfg(feature = "config-files")]
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Parse(error) => write!(f, "{}", error),
+            ConfigError::Invalid(reason) => write!(f, "invalid configuration: {}", reason),
+        }
+    }
+}
+
+#[cfg(feature = "config-files")]
+impl std::error::Error for ConfigError {}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            auto_max_energy_on_xray: true,
+            require_checklist: false,
+            require_readback: false,
+            dose_jitter: 0.0,
+            field_size_scales_dose: false,
+            normalized_deviance_threshold: 5,
+            auto_copy_enabled: true,
+            collimator_hysteresis_ms: 400,
+            collimator_move_ms: COLLIMATOR_TRANSIT_TIME.as_millis() as u64,
+            param_sync_ms: PARAM_SYNC_TIME.as_millis() as u64,
+            beam_hold_ms: 0,
+            pulse_repetition_hz: 0.0,
+            sensor_glitch_probability: 0.0,
+            random_fault_probability: RANDOM_FAULT_PROBABILITY,
+            collimator_stick_probability: 0.0,
+            sensor_majority_vote: false,
+            harm_reporting_only: false,
+            wear_per_treatment: 0.0,
+            max_wear_factor: 1.5,
+            recalibration_interval: 0,
+            lock_wait_log_ms: 0,
+            phantom_operator: false,
+            safe_mode: false,
+            hardware_watchdog: false,
+            bending_magnet_settle_ms: 0,
+            energy_settle_ms: ENERGY_SETTLE_TIME.as_millis() as u64,
+            reported_dose_cap: 0.0,
+            max_safe_dose_cgy: 0.0,
+            sync_order: SyncOrder::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_rejects_out_of_range_values() {
+        assert_eq!(SimConfig::default().validate(), Ok(()));
+        assert!(SimConfig { pulse_repetition_hz: 50.0, dose_jitter: 1.0, ..SimConfig::default() }.validate().is_ok());
+
+        let invalid = [
+            SimConfig { collimator_stick_probability: f32::NAN, ..SimConfig::default() },
+            SimConfig { random_fault_probability: 1.5, ..SimConfig::default() },
+            SimConfig { dose_jitter: -0.1, ..SimConfig::default() },
+            SimConfig { pulse_repetition_hz: 1e-300, ..SimConfig::default() },
+            SimConfig { pulse_repetition_hz: f64::INFINITY, ..SimConfig::default() },
+            SimConfig { pulse_repetition_hz: -10.0, ..SimConfig::default() },
+            SimConfig { max_safe_dose_cgy: f64::NAN, ..SimConfig::default() },
+            SimConfig { max_wear_factor: 0.5, ..SimConfig::default() },
+        ];
+        for config in invalid {
+            assert!(config.validate().is_err(), "{:?} should be rejected", config);
+        }
+        let reason = SimConfig { collimator_stick_probability: f32::NAN, ..SimConfig::default() }
+            .validate()
+            .unwrap_err();
+        assert!(reason.contains("collimator_stick_probability"));
+    }
+
+    #[cfg(feature = "config-files")]
+    #[test]
+    fn test_toml_round_trip() {
+        let config = SimConfig {
+            safe_mode: true,
+            collimator_move_ms: 2500,
+            random_fault_probability: 0.0,
+            dose_jitter: 0.05,
+            energy_settle_ms: 0,
+            sync_order: SyncOrder(vec![SyncAspect::Energy, SyncAspect::Collimator]),
+            ..SimConfig::default()
+        };
+        let path = std::env::temp_dir().join(format!("rstherac25-config-{}.toml", std::process::id()));
+        config.to_toml_path(&path).unwrap();
+        let restored = SimConfig::from_toml_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.unwrap(), config);
+
+        let error = SimConfig::from_toml_path(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[cfg(feature = "config-files")]
+    #[test]
+    fn test_toml_missing_fields_keep_their_defaults() {
+        let config = SimConfig::from_toml("safe_mode = true\ncollimator_move_ms = 2500\n").unwrap();
+        assert_eq!(config, SimConfig { safe_mode: true, collimator_move_ms: 2500, ..SimConfig::default() });
+        assert_eq!(SimConfig::from_toml("").unwrap(), SimConfig::default());
+        assert!(SimConfig::from_toml("safe_mode = \"yes\"").is_err());
+
+        let classroom = SimConfig::from_toml(include_str!("../examples/config/classroom.toml")).unwrap();
+        assert_eq!(classroom.collimator_move_ms, 2000);
+        assert_eq!(classroom.sync_order.before_collimator(), [SyncAspect::Energy]);
+        assert!(!classroom.safe_mode);
+    }
+
+    #[cfg(feature = "config-files")]
+    #[test]
+    fn test_toml_rejects_invalid_values() {
+        let error = SimConfig::from_toml("collimator_stick_probability = nan\n").unwrap_err();
+        assert!(matches!(error, ConfigError::Invalid(_)));
+        assert!(SimConfig::from_toml("pulse_repetition_hz = 1e-300\n").is_err());
+        assert!(matches!(SimConfig::from_toml("safe_mode = \"yes\"").unwrap_err(), ConfigError::Parse(_)));
+    }
+}
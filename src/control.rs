@@ -0,0 +1,447 @@
+//! Command channel between a frontend and the machine
+//!
+//! A frontend that holds a `CommandSender` never locks the state to change it: it sends
+//! `ControlMessage`s, and the processor task from `spawn_command_processor` applies them
+//! in the order they were sent. The sender is cheap to clone and to move to another
+//! thread, so a network listener or a test harness can drive the machine the same way
+//! the consoles do. Without a runtime, `apply_message` applies one message directly.
+//!
+//! Settings and operator actions travel the same way as the entries, so a toggle sent
+//! after an entry is applied after it. Toggles flip the value the state holds when the
+//! message is applied, not the one the frontend last saw.
+
+use crate::config::{SyncOrder, DOCUMENTED_INCIDENT_DOSE, RANDOM_FAULT_PROBABILITY};
+use crate::input::{
+    acknowledge_differences, copy_prescription_to_console, handle_console_entries,
+    handle_dose_input, handle_energy_input, handle_field_size_input, handle_gantry_input,
+    handle_mode_input, handle_patient_name_input, login_operator, logout_operator, verify_entry,
+    ConsoleEntries,
+};
+use crate::locking::LockState;
+use crate::prescription::load_prescription;
+use crate::simulator::{
+    apply_command, force_race_window, pause_simulation, resume_simulation, Command,
+    BENDING_MAGNET_SETTLE_TIME,
+};
+use crate::state::{BeamEnergy, BeamType, LogLevel, SharedTheracState, TheracState};
+
+#[cfg(feature = "standalone")]
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+
+/// One operator action, as sent down the channel
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlMessage {
+    /// A command prompt entry
+    Command(Command),
+    PatientName(String),
+    Mode(BeamType),
+    Energy(BeamEnergy),
+    Gantry(u16),
+    /// Field size in cm, X then Y
+    FieldSize(f32, f32),
+    /// Dose target in cGy; one that is not positive is rejected
+    Dose(f64),
+    /// Make the named library prescription the reference
+    LoadPrescription(String),
+    /// Several entries confirmed at once; an edit only if one of them changed
+    Entries(ConsoleEntries),
+    /// Pre-set the console entries from the prescription
+    CopyPrescription,
+    /// Read the console entries back against the prescription; the differences are left
+    /// in `TheracState::last_readback` for the frontend to show
+    Verify,
+    /// Acknowledge the differences found at the read-back as intended
+    AcknowledgeDifferences,
+    /// Log in with an operator ID (kept as typed)
+    Login(String),
+    Logout,
+    /// Change a machine setting
+    Setting(Setting),
+    /// Fault injection: jam the turntable mid-move, or let it go again
+    ToggleJam,
+    /// Free a turntable stuck mid-rotation
+    FreeCollimator,
+    /// Service the machine: wear back to zero
+    Service,
+    Recalibrate,
+    /// Freeze the background tasks, or unfreeze them if they are frozen
+    ToggleFreeze,
+    /// Open the race window and turn the beam on (`force_race_window`)
+    ForceRace,
+    /// Write the event log to a CSV file
+    #[cfg(not(target_arch = "wasm32"))]
+    WriteLogCsv(String),
+    /// Log this session's statistics for every operator who has logged in
+    LogOperatorStats,
+    /// Log the outcome of the session so far (`TheracState::summary`)
+    LogSummary,
+    /// A line for the event log, e.g. a usage hint or an entry that did not parse
+    Log(LogLevel, String),
+}
+
+/// A machine setting, as changed from a console's command line
+/// The toggles switch a feature off, or on at the value the analytical console uses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Setting {
+    /// Whether the pre-treatment checklist gates treatment
+    ToggleChecklist,
+    /// A `BEAM_HOLD_MS` last-chance hold between beam-on and the first pulse
+    ToggleBeamHold,
+    /// `WEAR_PER_TREATMENT` wear from each treatment
+    ToggleWear,
+    /// LOCK WAIT diagnostics from `LOCK_WAIT_LOG_MS`
+    ToggleLockWait,
+    /// The race-free pulse; refused during treatment
+    ToggleSafeMode,
+    /// A second operator who nudges console entries at random
+    TogglePhantom,
+    /// Capping displayed overdoses at `DOCUMENTED_INCIDENT_DOSE`
+    ToggleDoseCap,
+    /// The independent watchdog that catches a stuck turntable
+    ToggleWatchdog,
+    /// The real bending magnet settle time, or instant magnets
+    ToggleMagnets,
+    /// Random hardware faults at `RANDOM_FAULT_PROBABILITY`
+    ToggleFaults,
+    /// Copy from the prescription on Enter
+    ToggleAutoCopy,
+    /// Requiring a verified read-back before data entry can complete
+    ToggleReadback,
+    /// Treatments allowed between recalibrations; 0 never asks
+    RecalibrationInterval(u32),
+    /// Hardware dose interlock in cGy; 0 leaves it out
+    DoseInterlock(f64),
+    /// Pulses per second at the reference dose rate; 0 pulses every monitor pass
+    PulseRepetitionHz(f64),
+    /// Turntable move time and, optionally, param sync time (ms); refused during treatment
+    Timing { move_ms: u64, param_sync_ms: Option<u64> },
+    /// Order of the housekeeper's sync steps
+    SyncOrder(SyncOrder),
+}
+
+/// Beam-on hold switched on by `Setting::ToggleBeamHold` (ms)
+pub const BEAM_HOLD_MS: u32 = 3000;
+
+/// Write-lock wait (ms) logged once `Setting::ToggleLockWait` switches diagnostics on
+pub const LOCK_WAIT_LOG_MS: u32 = 5;
+
+/// Wear per treatment switched on by `Setting::ToggleWear`
+pub const WEAR_PER_TREATMENT: f32 = 0.02;
+
+impl From<Command> for ControlMessage {
+    fn from(command: Command) -> Self {
+        ControlMessage::Command(command)
+    }
+}
+
+impl From<Setting> for ControlMessage {
+    fn from(setting: Setting) -> Self {
+        ControlMessage::Setting(setting)
+    }
+}
+
+/// Apply one message to the machine, as the processor task does
+/// Returns false if it was refused (see `apply_command`, `handle_dose_input`,
+/// `load_prescription` and `apply_setting`) or had nothing to act on; parameter entries
+/// are otherwise always taken.
+pub fn apply_message(state: SharedTheracState, message: ControlMessage) -> bool {
+    match message {
+        ControlMessage::Command(command) => return apply_command(state, command),
+        ControlMessage::PatientName(name) => handle_patient_name_input(state, &name),
+        ControlMessage::Mode(mode) => handle_mode_input(state, mode),
+        ControlMessage::Energy(energy) => handle_energy_input(state, energy),
+        ControlMessage::Gantry(angle) => handle_gantry_input(state, angle),
+        ControlMessage::FieldSize(x, y) => handle_field_size_input(state, x, y),
+        ControlMessage::Dose(dose) => return handle_dose_input(state, dose),
+        ControlMessage::LoadPrescription(name) => return load_prescription(state, &name),
+        ControlMessage::Entries(entries) => handle_console_entries(state, entries),
+        ControlMessage::CopyPrescription => copy_prescription_to_console(state),
+        ControlMessage::Verify => {
+            verify_entry(state);
+        }
+        ControlMessage::AcknowledgeDifferences => return acknowledge_differences(state),
+        ControlMessage::Login(id) => return login_operator(state, &id),
+        ControlMessage::Logout => return logout_operator(state),
+        ControlMessage::Setting(setting) => return apply_setting(&mut state.write_state(), setting),
+        ControlMessage::ToggleJam => {
+            let mut s = state.write_state();
+            s.collimator_jammed = !s.collimator_jammed;
+            let jammed = s.collimator_jammed;
+            s.add_log(format!("[OPERATOR] Collimator turntable {}", if jammed { "jammed" } else { "freed" }));
+        }
+        ControlMessage::FreeCollimator => {
+            let mut s = state.write_state();
+            if !s.free_stuck_collimator() {
+                s.add_log("[OPERATOR] Collimator is not stuck".to_string());
+                return false;
+            }
+        }
+        ControlMessage::Service => state.write_state().reset_wear(),
+        ControlMessage::Recalibrate => state.write_state().recalibrate(),
+        ControlMessage::ToggleFreeze => {
+            if !pause_simulation(state.clone()) {
+                resume_simulation(state);
+            }
+        }
+        ControlMessage::ForceRace => force_race_window(state),
+        #[cfg(not(target_arch = "wasm32"))]
+        ControlMessage::WriteLogCsv(path) => {
+            let mut s = state.write_state();
+            match s.write_log_csv(&path) {
+                Ok(()) => s.add_log(format!("[OPERATOR] Event log written to {} as CSV", path)),
+                Err(e) => {
+                    s.add_log_with_level(LogLevel::Warn, format!("[OPERATOR] Could not write {}: {}", path, e));
+                    return false;
+                }
+            }
+        }
+        ControlMessage::LogOperatorStats => {
+            let mut s = state.write_state();
+            let lines: Vec<String> = s
+                .operator_stats
+                .iter()
+                .map(|(id, stats)| format!(
+                    "[OPERATOR] {}: {} treatments, {} overdoses, {} near misses",
+                    id, stats.treatments, stats.overdoses, stats.near_misses
+                ))
+                .collect();
+            if lines.is_empty() {
+                s.add_log("[OPERATOR] No operator has logged in this session".to_string());
+            }
+            for line in lines {
+                s.add_log(line);
+            }
+        }
+        ControlMessage::LogSummary => {
+            let mut s = state.write_state();
+            let summary = s.summary().to_string();
+            for line in summary.lines() {
+                s.add_log(format!("[OPERATOR] {}", line.trim()));
+            }
+        }
+        ControlMessage::Log(level, line) => state.write_state().add_log_with_level(level, line),
+    }
+    true
+}
+
+/// Change a machine setting and log it
+/// Returns false if it was refused: safe mode and the timing cannot change during
+/// treatment (`TheracState::config_change_allowed`).
+pub fn apply_setting(s: &mut TheracState, setting: Setting) -> bool {
+    match setting {
+        Setting::ToggleChecklist => {
+            s.config.require_checklist = !s.config.require_checklist;
+            let required = s.config.require_checklist;
+            s.add_log(format!(
+                "[OPERATOR] Pre-treatment checklist {}",
+                if required { "required" } else { "not required" }
+            ));
+        }
+        Setting::ToggleBeamHold => {
+            s.config.beam_hold_ms = if s.config.beam_hold_ms == 0 { BEAM_HOLD_MS } else { 0 };
+            let hold = s.config.beam_hold_ms;
+            s.add_log(format!("[OPERATOR] Beam-on hold set to {} ms", hold));
+        }
+        Setting::ToggleWear => {
+            s.config.wear_per_treatment = if s.config.wear_per_treatment == 0.0 { WEAR_PER_TREATMENT } else { 0.0 };
+            let wear = s.config.wear_per_treatment;
+            s.add_log(format!("[OPERATOR] Machine wear set to {:.0}% per treatment", wear * 100.0));
+        }
+        Setting::ToggleLockWait => {
+            s.config.lock_wait_log_ms = if s.config.lock_wait_log_ms == 0 { LOCK_WAIT_LOG_MS } else { 0 };
+            let threshold = s.config.lock_wait_log_ms;
+            s.add_log(format!("[OPERATOR] Lock wait diagnostics {}", if threshold == 0 {
+                "off".to_string()
+            } else {
+                format!("at {} ms", threshold)
+            }));
+        }
+        Setting::ToggleSafeMode => {
+            let enabled = !s.config.safe_mode;
+            return s.set_safe_mode(enabled);
+        }
+        Setting::TogglePhantom => {
+            s.config.phantom_operator = !s.config.phantom_operator;
+            let enabled = s.config.phantom_operator;
+            s.add_log(format!(
+                "[OPERATOR] Phantom operator {}",
+                if enabled { "at the console" } else { "gone" }
+            ));
+        }
+        Setting::ToggleDoseCap => {
+            s.config.reported_dose_cap = if s.config.reported_dose_cap == 0.0 { DOCUMENTED_INCIDENT_DOSE } else { 0.0 };
+            let cap = s.config.reported_dose_cap;
+            s.add_log(format!("[OPERATOR] Displayed overdose cap {}", if cap == 0.0 {
+                "off".to_string()
+            } else {
+                format!("at {:.0} cGy", cap)
+            }));
+        }
+        Setting::ToggleWatchdog => {
+            s.config.hardware_watchdog = !s.config.hardware_watchdog;
+            let enabled = s.config.hardware_watchdog;
+            s.add_log(format!("[OPERATOR] Hardware watchdog {}", if enabled { "armed" } else { "off" }));
+        }
+        Setting::ToggleMagnets => {
+            s.config.bending_magnet_settle_ms = if s.config.bending_magnet_settle_ms == 0 {
+                BENDING_MAGNET_SETTLE_TIME.as_millis() as u64
+            } else {
+                0
+            };
+            let settle_ms = s.config.bending_magnet_settle_ms;
+            s.add_log(format!("[OPERATOR] Bending magnet settle time {} ms", settle_ms));
+        }
+        Setting::ToggleFaults => {
+            s.config.random_fault_probability = if s.config.random_fault_probability == 0.0 { RANDOM_FAULT_PROBABILITY } else { 0.0 };
+            let probability = s.config.random_fault_probability;
+            s.add_log(format!("[OPERATOR] Random fault probability set to {:.2}", probability));
+        }
+        Setting::ToggleAutoCopy => {
+            s.config.auto_copy_enabled = !s.config.auto_copy_enabled;
+            let enabled = s.config.auto_copy_enabled;
+            s.add_log(format!(
+                "[OPERATOR] Copy from prescription on ENTER {}",
+                if enabled { "enabled" } else { "disabled - type every value" }
+            ));
+        }
+        Setting::ToggleReadback => {
+            s.config.require_readback = !s.config.require_readback;
+            let required = s.config.require_readback;
+            s.add_log(format!(
+                "[OPERATOR] Read-back verification {}",
+                if required { "required" } else { "not required" }
+            ));
+        }
+        Setting::RecalibrationInterval(interval) => {
+            s.config.recalibration_interval = interval;
+            s.add_log(format!("[OPERATOR] Recalibration every {} treatments", interval));
+        }
+        Setting::DoseInterlock(cgy) => {
+            s.config.max_safe_dose_cgy = cgy;
+            if cgy == 0.0 {
+                s.add_log("[OPERATOR] Dose interlock off".to_string());
+            } else {
+                s.add_log(format!("[OPERATOR] Dose interlock at {:.1} cGy", cgy));
+            }
+        }
+        Setting::PulseRepetitionHz(hz) => {
+            s.config.pulse_repetition_hz = hz;
+            s.add_log(format!("[OPERATOR] Pulse repetition frequency {} Hz", hz));
+        }
+        // Through the setters, which refuse a change during treatment
+        Setting::Timing { move_ms, param_sync_ms } => {
+            return s.set_collimator_move_ms(move_ms)
+                && param_sync_ms.is_none_or(|param_ms| s.set_param_sync_ms(param_ms));
+        }
+        Setting::SyncOrder(order) => {
+            s.config.sync_order = order;
+            let aspects = s.config.sync_order.aspects();
+            s.add_log(format!("[OPERATOR] Housekeeper sync order: {:?}", aspects));
+        }
+    }
+    true
+}
+
+/// Sends operator actions to a processor task
+/// Sending never blocks, and the message is applied later, so the result of the action
+/// is only seen in the state; `send` only reports whether the processor is still there.
+#[cfg(feature = "standalone")]
+#[derive(Debug, Clone)]
+pub struct CommandSender {
+    tx: UnboundedSender<ControlMessage>,
+}
+
+#[cfg(feature = "standalone")]
+impl CommandSender {
+    /// Queue a message; false if the processor task has gone
+    pub fn send(&self, message: impl Into<ControlMessage>) -> bool {
+        self.tx.send(message.into()).is_ok()
+    }
+
+    /// Whether the processor task has gone, so nothing sent will be applied
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
+
+/// Spawn a task applying every message sent to the returned sender to `state`
+/// The task ends once every clone of the sender has been dropped.
+#[cfg(feature = "standalone")]
+pub fn spawn_command_processor(state: SharedTheracState) -> CommandSender {
+    let (tx, mut rx) = unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(message) = rx.recv().await {
+            apply_message(state.clone(), message);
+        }
+    });
+    CommandSender { tx }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_therac_state;
+    use crate::state::TPhase;
+
+    #[test]
+    fn test_apply_message_reports_refusals() {
+        let state = create_therac_state();
+        assert!(apply_message(state.clone(), ControlMessage::Gantry(45)));
+        assert!(apply_message(state.clone(), ControlMessage::Dose(150.0)));
+        assert!(!apply_message(state.clone(), ControlMessage::Dose(-1.0)));
+        assert!(!apply_message(state.clone(), ControlMessage::LoadPrescription("Knee X-ray".to_string())));
+        // Nothing to acknowledge
+        assert!(!apply_message(state.clone(), Command::Acknowledge.into()));
+
+        let s = state.read();
+        assert_eq!(s.console_params.gantry_angle, 45);
+        assert_eq!(s.dose_target, 150.0);
+        assert_eq!(s.phase, TPhase::Reset);
+    }
+
+    #[test]
+    fn test_settings_toggle_the_value_held_when_applied() {
+        let state = create_therac_state();
+        assert!(apply_message(state.clone(), Setting::ToggleBeamHold.into()));
+        assert!(apply_message(state.clone(), Setting::ToggleBeamHold.into()));
+        assert!(apply_message(state.clone(), Setting::ToggleWear.into()));
+        assert_eq!(state.read().config.beam_hold_ms, 0);
+        assert_eq!(state.read().config.wear_per_treatment, WEAR_PER_TREATMENT);
+
+        // The timing and safe mode are refused during treatment
+        let timing = Setting::Timing { move_ms: 2000, param_sync_ms: Some(50) };
+        state.write().phase = TPhase::PatientTreatment;
+        assert!(!apply_message(state.clone(), timing.clone().into()));
+        assert!(!apply_message(state.clone(), Setting::ToggleSafeMode.into()));
+        state.write().phase = TPhase::DataEntry;
+        assert!(apply_message(state.clone(), timing.into()));
+        assert_eq!((state.read().config.collimator_move_ms, state.read().config.param_sync_ms), (2000, 50));
+
+        // Nothing stuck to free
+        assert!(!apply_message(state.clone(), ControlMessage::FreeCollimator));
+    }
+
+    #[cfg(feature = "standalone")]
+    #[tokio::test]
+    async fn test_processor_applies_messages_in_order() {
+        let state = create_therac_state();
+        state.write().phase = TPhase::DataEntry;
+        let sender = spawn_command_processor(state.clone());
+        assert!(sender.send(ControlMessage::Mode(BeamType::Electron)));
+        assert!(sender.send(ControlMessage::Energy(BeamEnergy::E10)));
+        assert!(sender.send(ControlMessage::Dose(120.0)));
+        assert!(sender.send(ControlMessage::Dose(80.0)));
+        assert!(sender.clone().send(Command::Proceed));
+
+        // Sent, but not applied until the processor gets a turn
+        assert_eq!(state.read().dose_target, crate::state::DEFAULT_DOSE_TARGET);
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        let s = state.read();
+        assert_eq!(s.console_meos.beam_type, BeamType::Electron);
+        assert_eq!(s.console_meos.beam_energy, BeamEnergy::E10);
+        assert_eq!(s.dose_target, 80.0);
+        assert!(s.data_entry_complete);
+    }
+}
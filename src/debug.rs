@@ -0,0 +1,114 @@
+//! Test and debug utilities
+//!
+//! Write the hardware directly, bypassing the housekeeper, to set up one particular
+//! mismatch in a test or demo without racing the housekeeper for it. No operator could
+//! do this on the machine: every override is logged as a `[DIAGNOSTIC]` warning.
+
+use crate::locking::LockState;
+use crate::state::{LogLevel, Meos, SharedTheracState, TreatmentParams};
+
+/// Write the hardware MEOS directly, bypassing the housekeeper
+/// Any configuration is accepted, unsafe ones included. Nothing syncs it back while a
+/// treatment is under way (the housekeeper stands still in PatientTreatment). Otherwise
+/// the housekeeper moves the turntable back in line with the console on its next pass,
+/// unless the override leaves it `Transitioning` with no move to finish, or `Stuck`
+/// until an operator frees it.
+pub fn set_hardware_meos(state: SharedTheracState, meos: Meos) {
+    let mut s = state.write_state();
+    s.hardware_meos = meos;
+    s.add_log_with_level(LogLevel::Warn, format!(
+        "[DIAGNOSTIC] Hardware MEOS overridden: {} @ {} with collimator {}",
+        meos.beam_type, meos.beam_energy, meos.collimator
+    ));
+}
+
+/// Write the hardware treatment parameters directly, bypassing the housekeeper's param
+/// sync; logged like `set_hardware_meos`. The housekeeper syncs them back to the
+/// console on its next pass outside PatientTreatment.
+pub fn set_hardware_params(state: SharedTheracState, params: TreatmentParams) {
+    let mut s = state.write_state();
+    s.hardware_params = params;
+    s.add_log_with_level(LogLevel::Warn, format!(
+        "[DIAGNOSTIC] Hardware params overridden: gantry {}°, collimator {}°, field {}×{} cm, {:.0} cGy/min",
+        params.gantry_angle, params.collimator_angle, params.field_size_x, params.field_size_y, params.dose_rate
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::zap_the_specimen;
+    use crate::state::{BeamEnergy, BeamType, CollimatorPosition, MalfunctionKind, TPhase, TheracState};
+    use crate::tick::housekeeper_tick;
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    const CONSOLE: Meos = Meos {
+        beam_type: BeamType::Electron,
+        beam_energy: BeamEnergy::E10,
+        collimator: CollimatorPosition::OutOfPosition,
+    };
+
+    fn electron_state(phase: TPhase) -> SharedTheracState {
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        {
+            let mut s = state.write();
+            s.phase = phase;
+            s.console_meos = CONSOLE;
+            s.hardware_meos = CONSOLE;
+            s.hardware_params = s.console_params;
+            s.config.energy_settle_ms = 0;
+        }
+        state
+    }
+
+    #[tokio::test]
+    async fn test_hardware_override_is_logged_and_caught_by_the_zap() {
+        let state = electron_state(TPhase::SetupDone);
+        let hardware = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, ..CONSOLE };
+        set_hardware_meos(state.clone(), hardware);
+        let params = TreatmentParams { gantry_angle: 270, ..state.read().console_params };
+        set_hardware_params(state.clone(), params);
+        {
+            let s = state.read();
+            assert_eq!(s.hardware_meos, hardware);
+            assert_eq!(s.hardware_params, params);
+            let diagnostics: Vec<_> = s.log.iter().filter(|e| e.message.starts_with("[DIAGNOSTIC]")).collect();
+            assert_eq!(diagnostics.len(), 2);
+            assert!(diagnostics.iter().all(|e| e.level == LogLevel::Warn));
+        }
+
+        state.write().phase = TPhase::PatientTreatment;
+        zap_the_specimen(state.clone()).await;
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PauseTreatment);
+        assert_eq!(s.dose_delivered, 0.0);
+        assert_eq!(s.last_malfunction.as_ref().unwrap().kind, MalfunctionKind::ParameterMismatch);
+    }
+
+    #[test]
+    fn test_housekeeper_syncs_an_override_back_outside_treatment() {
+        let hardware = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, ..CONSOLE };
+
+        // During treatment the override stays
+        let state = electron_state(TPhase::PatientTreatment);
+        set_hardware_meos(state.clone(), hardware);
+        for _ in 0..1000 {
+            housekeeper_tick(&state, Duration::from_millis(10));
+        }
+        assert_eq!(state.read().hardware_meos, hardware);
+
+        // In data entry the housekeeper brings the hardware back to the console
+        let state = electron_state(TPhase::DataEntry);
+        set_hardware_meos(state.clone(), hardware);
+        let params = TreatmentParams { gantry_angle: 270, ..state.read().console_params };
+        set_hardware_params(state.clone(), params);
+        for _ in 0..1000 {
+            housekeeper_tick(&state, Duration::from_millis(10));
+        }
+        let s = state.read();
+        assert_eq!(s.hardware_meos, CONSOLE);
+        assert_eq!(s.hardware_params, s.console_params);
+    }
+}
@@ -0,0 +1,201 @@
+//! Fleet of independent Therac-25 machines
+//!
+//! For classroom use: every student drives their own machine, while a shared
+//! display shows all of them at once. Each machine is an ordinary
+//! `SharedTheracState`, so everything in `input` and `simulator` works on it
+//! unchanged; the fleet only adds addressing by ID and fleet-wide aggregation.
+
+use crate::state::*;
+use crate::locking::LockState;
+use crate::create_therac_state;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[cfg(feature = "standalone")]
+use crate::simulator::{spawn_treatment_tasks, cleanup_tasks, TheracTaskHandles};
+
+/// Identifies one machine in a fleet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct MachineId(pub u32);
+
+impl fmt::Display for MachineId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Machine {}", self.0)
+    }
+}
+
+/// Totals across every machine in a fleet
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FleetStats {
+    /// Number of machines in the fleet
+    pub machines: usize,
+    /// Machines currently delivering beam
+    pub treating: usize,
+    /// Machines paused by an interlock, malfunction or the operator
+    pub paused: usize,
+    /// Malfunctions reported across the fleet
+    pub total_malfunctions: u32,
+    /// Unflattened X-ray pulses fired across the fleet
+    pub total_overdoses: u32,
+    /// Mismatches caught before the beam fired, across the fleet
+    pub total_near_misses: u32,
+    /// Dose delivered across the fleet (cGy)
+    pub total_dose_delivered: f64,
+}
+
+/// A set of machines addressed by `MachineId`
+#[derive(Default)]
+pub struct Fleet {
+    machines: Vec<(MachineId, SharedTheracState)>,
+}
+
+impl Fleet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a fleet of `count` fresh machines numbered from 1
+    pub fn with_machines(count: u32) -> Self {
+        let mut fleet = Self::new();
+        for id in 1..=count {
+            fleet.add_machine(MachineId(id));
+        }
+        fleet
+    }
+
+    /// Add a fresh machine under `id` and return its state
+    /// If `id` is already in use, the existing machine is returned instead
+    pub fn add_machine(&mut self, id: MachineId) -> SharedTheracState {
+        if let Some(state) = self.get(id) {
+            return state;
+        }
+        let state = create_therac_state();
+        self.machines.push((id, state.clone()));
+        state
+    }
+
+    /// State of the machine with `id`, for routing operator input to it
+    pub fn get(&self, id: MachineId) -> Option<SharedTheracState> {
+        self.machines
+            .iter()
+            .find(|(machine_id, _)| *machine_id == id)
+            .map(|(_, state)| state.clone())
+    }
+
+    /// All machines, in the order they were added
+    pub fn machines(&self) -> &[(MachineId, SharedTheracState)] {
+        &self.machines
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    /// Aggregate statistics across the fleet
+    pub fn stats(&self) -> FleetStats {
+        let mut stats = FleetStats {
+            machines: self.machines.len(),
+            ..FleetStats::default()
+        };
+        for (_, state) in &self.machines {
+            let s = state.read_state();
+            match s.phase {
+                TPhase::PatientTreatment => stats.treating += 1,
+                TPhase::PauseTreatment => stats.paused += 1,
+                _ => {}
+            }
+            stats.total_malfunctions += s.malfunction_count;
+            stats.total_overdoses += s.overdose_count;
+            stats.total_near_misses += s.near_misses;
+            stats.total_dose_delivered += s.dose_delivered;
+        }
+        stats
+    }
+
+    /// Machines that have fired at least one unflattened X-ray pulse
+    pub fn overdosed_machines(&self) -> Vec<MachineId> {
+        self.machines
+            .iter()
+            .filter(|(_, state)| state.read_state().overdose_count > 0)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Spawn the treatment monitor and housekeeper for every machine
+    #[cfg(feature = "standalone")]
+    pub fn spawn_all(&self) -> Vec<(MachineId, TheracTaskHandles)> {
+        self.machines
+            .iter()
+            .map(|(id, state)| (*id, spawn_treatment_tasks(state.clone())))
+            .collect()
+    }
+
+    /// Stop tasks previously returned by `spawn_all`
+    #[cfg(feature = "standalone")]
+    pub fn cleanup_all(handles: Vec<(MachineId, TheracTaskHandles)>) {
+        for (_, handle) in handles {
+            cleanup_tasks(handle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machines_are_independent() {
+        let fleet = Fleet::with_machines(3);
+        assert_eq!(fleet.len(), 3);
+
+        fleet.get(MachineId(2)).unwrap().write().phase = TPhase::PatientTreatment;
+
+        assert_eq!(fleet.get(MachineId(1)).unwrap().read().phase, TPhase::Reset);
+        assert_eq!(fleet.get(MachineId(2)).unwrap().read().phase, TPhase::PatientTreatment);
+        assert!(fleet.get(MachineId(4)).is_none());
+    }
+
+    #[test]
+    fn test_add_machine_reuses_existing_id() {
+        let mut fleet = Fleet::new();
+        let first = fleet.add_machine(MachineId(7));
+        first.write().dose_delivered = 12.0;
+
+        let again = fleet.add_machine(MachineId(7));
+
+        assert_eq!(fleet.len(), 1);
+        assert_eq!(again.read().dose_delivered, 12.0);
+    }
+
+    #[test]
+    fn test_stats_and_overdoses() {
+        let fleet = Fleet::with_machines(3);
+        {
+            let state = fleet.get(MachineId(1)).unwrap();
+            let mut s = state.write();
+            s.phase = TPhase::PatientTreatment;
+            s.dose_delivered = 50.0;
+        }
+        {
+            let state = fleet.get(MachineId(3)).unwrap();
+            let mut s = state.write();
+            s.phase = TPhase::PauseTreatment;
+            s.dose_delivered = 800.0;
+            s.malfunction_count = 2;
+            s.overdose_count = 1;
+        }
+
+        let stats = fleet.stats();
+        assert_eq!(stats.machines, 3);
+        assert_eq!(stats.treating, 1);
+        assert_eq!(stats.paused, 1);
+        assert_eq!(stats.total_malfunctions, 2);
+        assert_eq!(stats.total_overdoses, 1);
+        assert_eq!(stats.total_dose_delivered, 850.0);
+        assert_eq!(fleet.overdosed_machines(), vec![MachineId(3)]);
+    }
+}
@@ -3,7 +3,12 @@
 //! This module provides utility functions for handling user input and
 //! updating the simulator state.
 
-use crate::state::{SharedTheracState, BeamType, BeamEnergy, TPhase};
+use crate::config::SimConfig;
+use crate::locking::LockState;
+use crate::state::{
+    SharedTheracState, TheracState, BeamType, BeamEnergy, ChecklistItem, LogLevel, Meos,
+    OverrideKind, PrescriptionDifference, TPhase, valid_operator_id,
+};
 
 /// Input field identifier for data entry
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -16,70 +21,305 @@ pub enum InputField {
     Command,
 }
 
+impl InputField {
+    /// Next field in entry order, wrapping from Command back to Mode
+    pub fn next(self) -> Self {
+        match self {
+            InputField::Mode => InputField::Energy,
+            InputField::Energy => InputField::Gantry,
+            InputField::Gantry => InputField::FieldSize,
+            InputField::FieldSize => InputField::Dose,
+            InputField::Dose => InputField::Command,
+            InputField::Command => InputField::Mode,
+        }
+    }
+
+    /// Previous field in entry order, wrapping from Mode back to Command
+    pub fn prev(self) -> Self {
+        match self {
+            InputField::Mode => InputField::Command,
+            InputField::Energy => InputField::Mode,
+            InputField::Gantry => InputField::Energy,
+            InputField::FieldSize => InputField::Gantry,
+            InputField::Dose => InputField::FieldSize,
+            InputField::Command => InputField::Dose,
+        }
+    }
+}
+
+/// Handle patient name entry (recorded on the treatment summary)
+pub fn handle_patient_name_input(state: SharedTheracState, name: &str) {
+    let mut s = state.write_state();
+    let name = name.trim().to_string();
+    s.add_log(format!("[CONSOLE] Patient name set to {}", name));
+    s.patient_name = name;
+}
+
 /// Handle mode selection (X-ray or Electron)
 pub fn handle_mode_input(state: SharedTheracState, mode: BeamType) {
-    let mut s = state.write();
+    let mut s = state.write_state();
     s.console_meos.beam_type = mode;
 
     // Auto-set energy for X-ray mode (as per real Therac-25)
-    if mode == BeamType::XRay {
-        s.console_meos.beam_energy = BeamEnergy::E25;
+    if mode == BeamType::XRay && s.config.auto_max_energy_on_xray {
+        s.set_console_energy(BeamEnergy::E25);
+        s.mark_console_edit();
+        s.add_log(format!("[CONSOLE] Mode set to {}, energy auto-set to {}", mode, BeamEnergy::E25));
+    } else {
+        s.mark_console_edit();
+        s.add_log(format!("[CONSOLE] Mode set to {}", mode));
     }
+}
 
-    s.add_log(format!("[CONSOLE] Mode set to {:?}", mode));
+/// Field the cursor moves to after the mode has been entered
+/// X-ray skips the energy field when its energy is forced to 25 MeV
+pub fn field_after_mode(config: &SimConfig, mode: BeamType) -> InputField {
+    if mode == BeamType::XRay && config.auto_max_energy_on_xray {
+        InputField::Gantry
+    } else {
+        InputField::Energy
+    }
+}
+
+/// Warning for a console energy that does not suit the mode, or `None`
+/// X-ray mode is only treated at 25 MeV (the energy it auto-sets), so a lower energy is
+/// a manual downgrade. It is still accepted: the warning only has to be seen and logged.
+pub fn energy_mode_warning(meos: &Meos) -> Option<String> {
+    (meos.beam_type == BeamType::XRay && meos.beam_energy != BeamEnergy::E25).then(|| {
+        format!("WARNING: X-Ray mode at {} - X-ray treatments run at {}", meos.beam_energy, BeamEnergy::E25)
+    })
 }
 
 /// Handle energy selection
 pub fn handle_energy_input(state: SharedTheracState, energy: BeamEnergy) {
-    let mut s = state.write();
-    s.console_meos.beam_energy = energy;
+    let mut s = state.write_state();
+    s.set_console_energy(energy);
+    s.mark_console_edit();
     s.add_log(format!("[CONSOLE] Energy set to {}", energy));
+    // Dangerous but allowed: the operator is warned and may carry on
+    if let Some(warning) = energy_mode_warning(&s.console_meos) {
+        s.add_log_with_level(LogLevel::Warn, format!("[CONSOLE] {}", warning));
+    }
 }
 
 /// Handle gantry angle input
 pub fn handle_gantry_input(state: SharedTheracState, angle: u16) {
-    let mut s = state.write();
+    let mut s = state.write_state();
     s.console_params.gantry_angle = angle;
+    s.mark_console_edit();
     s.add_log(format!("[CONSOLE] Gantry angle set to {} deg", angle));
 }
 
+/// Prescribed value offered by the Enter-to-copy shortcut on an empty entry field
+/// `None` when `config.auto_copy_enabled` is off: Enter on an empty field then does
+/// nothing and the operator has to type the value.
+pub fn copy_from_prescription<T>(
+    state: &SharedTheracState,
+    read: impl FnOnce(&TheracState) -> T,
+) -> Option<T> {
+    let s = state.read_state();
+    s.config.auto_copy_enabled.then(|| read(&s))
+}
+
+/// Parse a field size entry in cm: "10x15" (or "10×15") is a rectangular field,
+/// a single number is an equivalent square and sets both sides
+pub fn parse_field_size(entry: &str) -> Option<(f32, f32)> {
+    let side = |text: &str| {
+        text.trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|size| size.is_finite() && *size >= 0.0)
+    };
+    let mut parts = entry.split(['x', 'X', '×']);
+    let x = side(parts.next()?)?;
+    let y = match parts.next() {
+        Some(text) => side(text)?,
+        None => x,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((x, y))
+}
+
 /// Handle field size input
 pub fn handle_field_size_input(state: SharedTheracState, x: f32, y: f32) {
-    let mut s = state.write();
+    let mut s = state.write_state();
     s.console_params.field_size_x = x;
     s.console_params.field_size_y = y;
-    s.add_log(format!("[CONSOLE] Field size set to {}x{} cm", x, y));
+    s.mark_console_edit();
+    let equivalent = s.console_params.equivalent_square();
+    s.add_log(format!("[CONSOLE] Field size set to {}x{} cm (equivalent square {:.1} cm)", x, y, equivalent));
+}
+
+/// Several console entries confirmed at once, as the authentic console sends its whole
+/// screen on PROCEED or TREAT; entries left `None` keep their value
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConsoleEntries {
+    pub energy: Option<BeamEnergy>,
+    pub dose_rate: Option<f32>,
+    pub gantry_angle: Option<u16>,
+    pub collimator_angle: Option<u16>,
+    pub field_size_x: Option<f32>,
+    pub field_size_y: Option<f32>,
+    pub monitor_units: Option<f64>,
+    pub dose_target: Option<f64>,
+}
+
+/// Apply several console entries under one lock
+/// Only an actual change counts as an edit the hardware must catch up with, and the dose
+/// target and monitor units go through their setters only when they change, so
+/// re-confirming an unchanged screen neither flags an edit nor logs anything.
+pub fn handle_console_entries(state: SharedTheracState, entries: ConsoleEntries) {
+    let mut s = state.write_state();
+    let before = (s.console_meos, s.console_params, s.dose_target, s.monitor_units);
+
+    if let Some(rate) = entries.dose_rate {
+        s.console_params.dose_rate = rate;
+    }
+    if let Some(angle) = entries.gantry_angle {
+        s.console_params.gantry_angle = angle;
+    }
+    if let Some(angle) = entries.collimator_angle {
+        s.console_params.collimator_angle = angle;
+    }
+    if let Some(size) = entries.field_size_x {
+        s.console_params.field_size_x = size;
+    }
+    if let Some(size) = entries.field_size_y {
+        s.console_params.field_size_y = size;
+    }
+    // Monitor units, not the dose, are what the beam terminates on
+    if let Some(monitor_units) = entries.monitor_units.filter(|&mu| mu != s.monitor_units) {
+        s.set_monitor_units(monitor_units);
+    }
+    if let Some(dose) = entries.dose_target.filter(|&dose| dose != s.dose_target) {
+        s.set_dose_target(dose);
+    }
+    if let Some(energy) = entries.energy {
+        s.set_console_energy(energy);
+    }
+
+    if (s.console_meos, s.console_params, s.dose_target, s.monitor_units) != before {
+        s.mark_console_edit();
+    }
+}
+
+/// Pre-set the console entries from the prescription, so Enter on each field confirms it
+/// Not an operator edit: used right after a reset, before anything has been entered.
+pub fn copy_prescription_to_console(state: SharedTheracState) {
+    let mut s = state.write_state();
+    s.console_meos = s.reference_meos;
+    s.console_params = s.reference_params;
 }
 
 /// Handle dose target input
-pub fn handle_dose_input(state: SharedTheracState, dose: f64) {
-    let mut s = state.write();
-    s.dose_target = dose;
-    s.add_log(format!("[CONSOLE] Dose target set to {} cGy", dose));
+/// A target that is not positive is rejected with a logged error; returns whether it was set.
+pub fn handle_dose_input(state: SharedTheracState, dose: f64) -> bool {
+    let mut s = state.write_state();
+    let accepted = s.set_dose_target(dose);
+    if accepted {
+        s.mark_console_edit();
+    }
+    accepted
 }
 
 /// Handle treat command - start treatment
+/// Goes through `start_treatment`, so the checklist and recalibration gates apply;
+/// returns whether treatment started.
 pub fn handle_treat_command(state: SharedTheracState) -> bool {
-    let mut s = state.write();
-    if s.phase == TPhase::SetupDone {
-        s.phase = TPhase::PatientTreatment;
-        s.add_log("[OPERATOR] Treatment started".to_string());
-        true
-    } else {
-        s.add_log("[OPERATOR] Cannot start treatment - setup not complete".to_string());
-        false
+    if get_phase(&state) != TPhase::SetupDone {
+        state.write_state().add_log_with_level(LogLevel::Warn, "[OPERATOR] Cannot start treatment - setup not complete".to_string());
+        return false;
     }
+    crate::simulator::start_treatment(state)
+}
+
+/// Toggle one pre-treatment checklist item
+pub fn toggle_checklist_item(state: SharedTheracState, item: ChecklistItem) {
+    let mut s = state.write_state();
+    let checked = s.checklist.toggle(item);
+    s.add_log(format!(
+        "[OPERATOR] Checklist: {} {}",
+        item,
+        if checked { "checked" } else { "unchecked" }
+    ));
+}
+
+/// Tick every checklist item at once
+/// Satisfies the gate exactly like checking each item, which is the point:
+/// a checklist that can be ticked blindly verifies nothing.
+pub fn check_all_checklist_items(state: SharedTheracState) {
+    let mut s = state.write_state();
+    s.checklist.check_all();
+    s.add_log("[OPERATOR] Checklist: all items checked at once - nothing was actually inspected".to_string());
+    s.record_override(OverrideKind::ChecklistBulkChecked, "no item was inspected".to_string());
+}
+
+/// Dismiss the current malfunction message without resuming treatment
+/// Recorded as an override; returns false if there was nothing to dismiss.
+pub fn dismiss_malfunction(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    match s.last_malfunction.take() {
+        Some(malfunction) => {
+            s.record_override(OverrideKind::MalfunctionDismissed, malfunction.message());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Log an operator in; everything they do from now on is attributed to them
+/// Replaces whoever was logged in. Returns false if `id` is not a usable ID.
+pub fn login_operator(state: SharedTheracState, id: &str) -> bool {
+    let mut s = state.write_state();
+    if !valid_operator_id(id) {
+        s.add_log_with_level(LogLevel::Warn, format!("[OPERATOR] Login refused - invalid operator ID '{}'", id));
+        return false;
+    }
+    s.operator_id = Some(id.to_string());
+    s.operator_stats.entry(id.to_string()).or_default();
+    s.add_log(format!("[OPERATOR] {} logged in", id));
+    true
+}
+
+/// Log the current operator out; returns false if nobody was logged in
+pub fn logout_operator(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    let Some(id) = s.operator_id.clone() else {
+        return false;
+    };
+    s.add_log(format!("[OPERATOR] {} logged out", id));
+    s.operator_id = None;
+    true
 }
 
 /// Handle reset command
 pub fn handle_reset_command(state: SharedTheracState) {
-    let mut s = state.write();
+    let mut s = state.write_state();
     s.reset();
 }
 
+/// Handle new prescription command
+/// Replaces only the prescription, leaving console entries and dose progress intact,
+/// so the operator's entries may no longer match what is prescribed.
+/// Refused while a treatment is under way, paused on a malfunction included, so a
+/// resumed treatment never delivers against a prescription it did not start with.
+pub fn handle_new_prescription_command(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    if s.treatment_under_way() {
+        s.add_log_with_level(LogLevel::Warn, "[OPERATOR] Cannot change prescription during treatment".to_string());
+        false
+    } else {
+        s.generate_new_reference();
+        s.add_log("[OPERATOR] Prescription replaced - console entries NOT re-verified".to_string());
+        true
+    }
+}
+
 /// Handle setup test command
 pub fn handle_setup_test_command(state: SharedTheracState) {
-    let mut s = state.write();
+    let mut s = state.write_state();
     if s.phase == TPhase::DataEntry && s.data_entry_complete {
         s.phase = TPhase::SetupTest;
         s.add_log("[OPERATOR] Setup test initiated".to_string());
@@ -88,29 +328,82 @@ pub fn handle_setup_test_command(state: SharedTheracState) {
 
 /// Check if data entry is complete
 pub fn is_data_entry_complete(state: SharedTheracState) -> bool {
-    let s = state.read();
+    let s = state.read_state();
     s.data_entry_complete
 }
 
 /// Check if currently in a state that can start treatment
 pub fn can_treat(state: &SharedTheracState) -> bool {
-    let s = state.read();
+    let s = state.read_state();
     s.phase == TPhase::SetupDone
 }
 
 /// Get current phase
 pub fn get_phase(state: &SharedTheracState) -> TPhase {
-    state.read().phase
+    state.read_state().phase
+}
+
+/// Read back the console entries against the prescription
+/// Logs every difference (or that the entry matches) and returns them, for a side-by-side
+/// display; they are also left in `last_readback` for a console that sent the read-back
+/// down the command channel. Differences then have to be acknowledged before data entry can be completed
+/// when `config.require_readback` is set.
+pub fn verify_entry(state: SharedTheracState) -> Vec<PrescriptionDifference> {
+    let mut s = state.write_state();
+    let differences = s.prescription_differences();
+    if differences.is_empty() {
+        s.add_log("[CONSOLE] Read-back: entry matches prescription".to_string());
+    }
+    for d in &differences {
+        s.add_log(format!(
+            "[CONSOLE] Read-back: {} entered {}, prescribed {}",
+            d.field, d.entered, d.prescribed
+        ));
+    }
+    s.last_readback = Some(differences.clone());
+    differences
+}
+
+/// Acknowledge the current differences from the prescription as intended
+/// Returns false if there was nothing to acknowledge.
+pub fn acknowledge_differences(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    let differences = s.prescription_differences();
+    if differences.is_empty() {
+        return false;
+    }
+    s.add_log(format!(
+        "[OPERATOR] Acknowledged {} difference(s) from prescription",
+        differences.len()
+    ));
+    s.acknowledged_differences = Some(differences);
+    true
 }
 
 /// Mark data entry as complete
+/// Refused while `readback_satisfied` is false.
+/// Completing again after an edit made past data entry re-runs the setup test,
+/// giving the hardware a chance to catch up with the edit before treatment.
 pub fn complete_data_entry(state: SharedTheracState) {
-    let mut s = state.write();
+    let mut s = state.write_state();
+    let reconfirming = s.editing_taking_place
+        && matches!(s.phase, TPhase::SetupTest | TPhase::SetupDone | TPhase::PauseTreatment);
+    if (s.phase == TPhase::DataEntry || reconfirming) && !s.readback_satisfied() {
+        s.add_log_with_level(LogLevel::Warn, "[CONSOLE] Cannot complete data entry - entries differ from prescription, verify and acknowledge".to_string());
+        return;
+    }
     if s.phase == TPhase::DataEntry {
         s.data_entry_complete = true;
         s.editing_taking_place = false;
+        s.missed_edit_setup = None;
         // DO NOT copy console settings to hardware here - let the housekeeper do it
         // This is part of the race condition design
         s.add_log("[CONSOLE] Data entry complete".to_string());
+    } else if reconfirming {
+        s.phase = TPhase::DataEntry;
+        s.data_entry_complete = true;
+        s.editing_taking_place = false;
+        s.missed_edit_setup = None;
+        s.add_log("[CONSOLE] Edits re-confirmed, repeating setup test".to_string());
     }
 }
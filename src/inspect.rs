@@ -0,0 +1,337 @@
+//! Detailed text report of a `TheracState`
+//!
+//! `TheracState::inspect` dumps everything that decides what the next pulse does: the
+//! three MEOS and parameter sets side by side, every interlock flag with what its value
+//! means, dose, pending syncs and the latest incidents. Each part is annotated safe or
+//! dangerous, so a support ticket or a post-mortem can answer "why did this happen"
+//! without reading the code.
+
+use crate::simulator::{overdose_factor, time_until_safe_to_treat};
+use crate::state::{BeamType, CollimatorPosition, Meos, TheracState, TreatmentParams};
+use std::fmt::Write;
+
+/// Incidents listed at the end of the report, most recent last
+pub const INSPECT_INCIDENTS: usize = 5;
+
+/// Why `meos` is safe or dangerous to fire
+fn meos_assessment(meos: &Meos) -> String {
+    if meos.is_safe() {
+        return "safe".to_string();
+    }
+    let reason = match (meos.beam_type, meos.collimator) {
+        (BeamType::Undefined, _) => "no beam type".to_string(),
+        (_, CollimatorPosition::Transitioning) => "turntable moving".to_string(),
+        (BeamType::XRay, _) => "X-ray without the flatness filter".to_string(),
+        (BeamType::Electron, _) => "electrons through the flatness filter".to_string(),
+    };
+    let factor = overdose_factor(meos);
+    if factor > 1.0 {
+        format!("DANGEROUS - {} ({:.1}x dose)", reason, factor)
+    } else {
+        format!("DANGEROUS - {}", reason)
+    }
+}
+
+fn meos_row(label: &str, meos: &Meos) -> String {
+    format!(
+        "  {:<13} {:<9} {:<7} {:<12} {}\n",
+        label,
+        meos.beam_type.to_string(),
+        meos.beam_energy.to_string(),
+        meos.collimator.to_string(),
+        meos_assessment(meos)
+    )
+}
+
+fn params_row(label: &str, params: &TreatmentParams) -> String {
+    format!(
+        "  {:<13} {:>5}°  {:>5}°  {:<12} {} cGy/min\n",
+        label,
+        params.gantry_angle,
+        params.collimator_angle,
+        format!("{}×{} cm", params.field_size_x, params.field_size_y),
+        params.dose_rate
+    )
+}
+
+/// Names of the MEOS aspects that differ
+fn meos_differences(a: &Meos, b: &Meos) -> Vec<&'static str> {
+    let mut differences = Vec::new();
+    if a.beam_type != b.beam_type {
+        differences.push("mode");
+    }
+    if a.beam_energy != b.beam_energy {
+        differences.push("energy");
+    }
+    if a.collimator != b.collimator {
+        differences.push("collimator");
+    }
+    differences
+}
+
+/// Names of the treatment parameters that differ
+fn params_differences(a: &TreatmentParams, b: &TreatmentParams) -> Vec<&'static str> {
+    let mut differences = Vec::new();
+    if a.gantry_angle != b.gantry_angle {
+        differences.push("gantry");
+    }
+    if a.collimator_angle != b.collimator_angle {
+        differences.push("collimator angle");
+    }
+    if a.field_size_x != b.field_size_x || a.field_size_y != b.field_size_y {
+        differences.push("field size");
+    }
+    if a.dose_rate != b.dose_rate {
+        differences.push("dose rate");
+    }
+    differences
+}
+
+fn flag_row(name: &str, value: impl std::fmt::Display, meaning: &str) -> String {
+    format!("  {:<22} {:<6} {}\n", name, value.to_string(), meaning)
+}
+
+/// The full report for `state`
+pub fn report(state: &TheracState) -> String {
+    let mut out = String::new();
+    out.push_str("=== THERAC-25 STATE INSPECTION ===\n\n");
+
+    out.push_str("PHASE\n");
+    let _ = writeln!(out, "  {} ({:?})", state.phase, state.phase);
+    if let Some(operator) = &state.operator_id {
+        let _ = writeln!(out, "  Operator: {}", operator);
+    }
+
+    out.push_str("\nBEAM CONFIGURATION (MEOS)\n");
+    let _ = writeln!(out, "  {:<13} {:<9} {:<7} {:<12} Assessment", "", "Mode", "Energy", "Collimator");
+    out.push_str(&meos_row("Prescription", &state.reference_meos));
+    out.push_str(&meos_row("Console", &state.console_meos));
+    out.push_str(&meos_row("Hardware", &state.hardware_meos));
+    let mismatch = meos_differences(&state.console_meos, &state.hardware_meos);
+    if mismatch.is_empty() {
+        out.push_str("  Console vs hardware: match\n");
+    } else {
+        let _ = writeln!(
+            out,
+            "  Console vs hardware: MISMATCH ({}) - MALFUNCTION 54 if verified now",
+            mismatch.join(", ")
+        );
+    }
+    let off_script = meos_differences(&state.console_meos, &state.reference_meos);
+    if !off_script.is_empty() {
+        let _ = writeln!(out, "  Console vs prescription: differs ({})", off_script.join(", "));
+    }
+
+    out.push_str("\nTREATMENT PARAMETERS\n");
+    let _ = writeln!(out, "  {:<13} {:>6}  {:>6}  {:<12} Dose rate", "", "Gantry", "Coll.", "Field");
+    out.push_str(&params_row("Prescription", &state.reference_params));
+    out.push_str(&params_row("Console", &state.console_params));
+    out.push_str(&params_row("Hardware", &state.hardware_params));
+    let unsynced = params_differences(&state.console_params, &state.hardware_params);
+    if unsynced.is_empty() {
+        out.push_str("  Console vs hardware: synced\n");
+    } else {
+        let _ = writeln!(out, "  Console vs hardware: NOT SYNCED ({})", unsynced.join(", "));
+    }
+
+    out.push_str("\nFLAGS\n");
+    out.push_str(&flag_row(
+        "data_entry_complete",
+        state.data_entry_complete,
+        if state.data_entry_complete { "entries confirmed; setup may run" } else { "entries not yet confirmed" },
+    ));
+    out.push_str(&flag_row(
+        "editing_taking_place",
+        state.editing_taking_place,
+        if state.editing_taking_place {
+            "DANGEROUS - console edited after confirmation; next pulse raises EDIT IN PROGRESS"
+        } else {
+            "no unconfirmed edits"
+        },
+    ));
+    out.push_str(&flag_row(
+        "f_small",
+        state.f_small,
+        if state.f_small { "collimator verification failed" } else { "collimator verification clear" },
+    ));
+    let class3_meaning = match state.class3 {
+        0 => "no setup test passes counted (also what an 8-bit wrap leaves)".to_string(),
+        passes => format!("{} setup test passes counted", passes),
+    };
+    out.push_str(&flag_row("class3", state.class3, &class3_meaning));
+    out.push_str(&flag_row(
+        "class3_ignore",
+        state.class3_ignore,
+        if state.class3_ignore { "DANGEROUS - Class3 verification skipped" } else { "Class3 verification honoured" },
+    ));
+    let magnet_meaning = if state.bending_magnet_flag {
+        format!(
+            "bending magnets settling ({} ms left); a pulse now raises BENDING MAGNET NOT SETTLED",
+            state.bending_magnet_settle_remaining_ms
+        )
+    } else {
+        "bending magnets idle".to_string()
+    };
+    out.push_str(&flag_row("bending_magnet_flag", state.bending_magnet_flag, &magnet_meaning));
+    out.push_str(&flag_row(
+        "reset_pending",
+        state.reset_pending,
+        if state.reset_pending { "reset requested" } else { "no reset requested" },
+    ));
+    out.push_str(&flag_row(
+        "collimator_jammed",
+        state.collimator_jammed,
+        if state.collimator_jammed { "DANGEROUS - turntable stuck (injected fault)" } else { "turntable free" },
+    ));
+    out.push_str(&flag_row(
+        "collimator_timeout",
+        state.collimator_timeout,
+        if state.collimator_timeout { "COLLIMATOR TIMEOUT latched; beam inhibited" } else { "watchdog not tripped" },
+    ));
+
+    out.push_str("\nDOSE\n");
+    let _ = writeln!(
+        out,
+        "  Delivered {:.1} of {:.1} cGy target (prescribed {:.1} cGy)",
+        state.dose_delivered, state.dose_target, state.reference_dose_target
+    );
+    if state.dose_delivered > state.dose_target && state.dose_target > 0.0 {
+        let _ = writeln!(
+            out,
+            "  DANGEROUS - {:.1}x the target delivered",
+            state.dose_delivered / state.dose_target
+        );
+    }
+    let _ = writeln!(
+        out,
+        "  Malfunctions {}  |  Unflattened pulses {}  |  Near misses {}",
+        state.malfunction_count, state.overdose_count, state.near_misses
+    );
+
+    out.push_str("\nPENDING SYNCS\n");
+    if state.hardware_meos.collimator == CollimatorPosition::Transitioning {
+        let _ = writeln!(out, "  Turntable moving: {} ms left", state.collimator_transit_remaining_ms);
+    }
+    match time_until_safe_to_treat(state) {
+        Some(wait) => {
+            let _ = writeln!(out, "  Hardware catches up in {:.1}s - treating now races the housekeeper", wait.as_secs_f64());
+        }
+        None => out.push_str("  Nothing pending\n"),
+    }
+
+    out.push_str("\nRECENT INCIDENTS\n");
+    if state.incidents.is_empty() {
+        out.push_str("  None\n");
+    }
+    let skip = state.incidents.len().saturating_sub(INSPECT_INCIDENTS);
+    for incident in &state.incidents[skip..] {
+        let _ = writeln!(out, "  {}  {}", incident.at, incident.message);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{BeamEnergy, Incident, MalfunctionKind, TPhase};
+
+    /// The East Texas race, caught mid-move: the operator has corrected X-ray to
+    /// electrons and confirmed, while the turntable is still swinging out
+    fn mid_race_state() -> TheracState {
+        let params = TreatmentParams {
+            gantry_angle: 90,
+            collimator_angle: 0,
+            field_size_x: 10.0,
+            field_size_y: 15.0,
+            dose_rate: 300.0,
+        };
+        TheracState {
+            phase: TPhase::SetupDone,
+            reference_meos: Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::OutOfPosition,
+            },
+            console_meos: Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::OutOfPosition,
+            },
+            hardware_meos: Meos {
+                beam_type: BeamType::XRay,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::Transitioning,
+            },
+            reference_params: params,
+            console_params: params,
+            hardware_params: TreatmentParams { gantry_angle: 0, ..params },
+            reference_dose_target: 180.0,
+            dose_target: 180.0,
+            data_entry_complete: true,
+            editing_taking_place: true,
+            collimator_transit_remaining_ms: 450,
+            malfunction_count: 1,
+            near_misses: 1,
+            incidents: vec![Incident {
+                kind: MalfunctionKind::RandomFault(31),
+                message: "MALFUNCTION 31 - Random hardware fault".to_string(),
+                at: "2026-10-16T09:14:02+00:00".to_string(),
+                dose_delivered: 0.0,
+                dose_target: 180.0,
+                operator_id: None,
+                phase: TPhase::PatientTreatment,
+                console_meos: Meos::default(),
+                hardware_meos: Meos::default(),
+            }],
+            ..TheracState::default()
+        }
+    }
+
+    #[test]
+    fn test_inspect_mid_race_snapshot() {
+        let expected = "\
+=== THERAC-25 STATE INSPECTION ===
+
+PHASE
+  Setup Done (SetupDone)
+
+BEAM CONFIGURATION (MEOS)
+                Mode      Energy  Collimator   Assessment
+  Prescription  Electron  25 MeV  Out          safe
+  Console       Electron  25 MeV  Out          safe
+  Hardware      X-Ray     25 MeV  Moving...    DANGEROUS - turntable moving
+  Console vs hardware: MISMATCH (mode, collimator) - MALFUNCTION 54 if verified now
+
+TREATMENT PARAMETERS
+                Gantry   Coll.  Field        Dose rate
+  Prescription     90°      0°  10×15 cm     300 cGy/min
+  Console          90°      0°  10×15 cm     300 cGy/min
+  Hardware          0°      0°  10×15 cm     300 cGy/min
+  Console vs hardware: NOT SYNCED (gantry)
+
+FLAGS
+  data_entry_complete    true   entries confirmed; setup may run
+  editing_taking_place   true   DANGEROUS - console edited after confirmation; next pulse raises EDIT IN PROGRESS
+  f_small                false  collimator verification clear
+  class3                 0      no setup test passes counted (also what an 8-bit wrap leaves)
+  class3_ignore          false  Class3 verification honoured
+  bending_magnet_flag    false  bending magnets idle
+  reset_pending          false  no reset requested
+  collimator_jammed      false  turntable free
+  collimator_timeout     false  watchdog not tripped
+
+DOSE
+  Delivered 0.0 of 180.0 cGy target (prescribed 180.0 cGy)
+  Malfunctions 1  |  Unflattened pulses 0  |  Near misses 1
+
+PENDING SYNCS
+  Turntable moving: 450 ms left
+  Hardware catches up in 0.7s - treating now races the housekeeper
+
+RECENT INCIDENTS
+  2026-10-16T09:14:02+00:00  MALFUNCTION 31 - Random hardware fault
+";
+        assert_eq!(mid_race_state().inspect(), expected);
+    }
+}
@@ -48,8 +48,27 @@ use parking_lot::RwLock;
 
 // Re-export core modules
 pub mod state;
+pub mod config;
+pub mod locking;
 pub mod simulator;
+pub mod tick;
 pub mod input;
+pub mod fleet;
+pub mod scenario;
+pub mod replay;
+pub mod attract;
+pub mod sensor;
+pub mod cef;
+pub mod inspect;
+pub mod snapshot;
+pub mod messages;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod alarm;
+pub mod prescription;
+pub mod control;
+pub mod codes;
+pub mod debug;
 
 // Optional rendering module (only with "tui-render" feature)
 #[cfg(feature = "tui-render")]
@@ -62,6 +81,9 @@ pub mod tui;
 #[cfg(feature = "standalone")]
 pub mod tui_authentic;
 
+#[cfg(feature = "standalone")]
+pub mod cast;
+
 // WASM module
 #[cfg(feature = "wasm")]
 pub mod wasm;
@@ -69,21 +91,64 @@ pub mod wasm;
 // Re-export commonly used types from state module
 pub use state::{
     TheracState, SharedTheracState, TPhase, BeamType, BeamEnergy,
-    CollimatorPosition, Meos, TreatmentParams,
+    CollimatorPosition, Meos, TreatmentParams, TreatmentOutcome, Checklist, ChecklistItem,
+    OverrideEvent, OverrideKind, TreatmentSummary, SessionSummary, StateDiff, LogSource, LogLevel, LogEntry, PrescriptionDifference,
+    OperatorStats, Incident, MalfunctionKind, Malfunction, MeosDiff, MeosField, SessionRng,
 };
 
+pub use config::{SimConfig, SyncAspect, SyncOrder, HARM_REPORTING_BANNER, DOCUMENTED_INCIDENT_DOSE, RANDOM_FAULT_PROBABILITY};
+pub use messages::{MessageKey, Messages};
+pub use locking::{LockState, StateGuard};
+pub use snapshot::TheracSnapshot;
+pub use alarm::{Alarm, AlarmSink, NoAlarmSink, LoggingAlarmSink, dispatch_alarms};
+pub use codes::{MalfunctionCode, MALFUNCTION_CODES, malfunction_code, describe_code};
+
 // Re-export simulator functions
+pub use simulator::{
+    run_treatment_to_completion, clear_malfunction, classify_delivery, DeliveryClass,
+    time_until_safe_to_treat, emergency_off, phantom_nudge, CollimatorWatchdog,
+    pause_simulation, resume_simulation, is_simulation_paused, force_race_window,
+    Command, UnknownCommand, apply_command,
+};
+
+pub use tick::{step, monitor_tick, housekeeper_tick, TickProgress};
+
 #[cfg(feature = "standalone")]
 pub use simulator::{
-    spawn_treatment_tasks, cleanup_tasks, TheracTaskHandles,
-    treatment_monitor, housekeeper,
+    spawn_treatment_tasks, spawn_treatment_tasks_with_alarms, cleanup_tasks, TheracTaskHandles,
+    treatment_monitor, housekeeper, phantom_operator, hardware_watchdog, bending_magnet_settler,
 };
 
+// Re-export scenarios
+pub use scenario::{historical_edit_sequence, HISTORICAL_EDIT_WINDOW, Scenario, ScenarioResult, ScenarioStep, SCENARIO_WAIT_LIMIT};
+
+#[cfg(feature = "standalone")]
+pub use scenario::run_scenario;
+
+// Re-export replay types
+pub use replay::{ReplayBundle, ReplayEvent, ReplayPlayer, SessionFingerprint, TimedEvent};
+
+// Re-export the command channel
+pub use control::{ControlMessage, Setting, apply_message, apply_setting};
+
+#[cfg(feature = "standalone")]
+pub use control::{CommandSender, spawn_command_processor};
+
+// Re-export the prescription library
+pub use prescription::{Prescription, PRESCRIPTIONS, list_prescriptions, find_prescription, load_prescription};
+
+// Re-export fleet types
+pub use fleet::{Fleet, FleetStats, MachineId};
+
 // Re-export input helpers
 pub use input::{
-    InputField, handle_mode_input, handle_energy_input, handle_gantry_input,
-    handle_field_size_input, handle_dose_input, handle_treat_command,
-    handle_reset_command, handle_setup_test_command, is_data_entry_complete,
+    InputField, handle_patient_name_input, handle_mode_input, handle_energy_input,
+    handle_gantry_input, copy_from_prescription, handle_field_size_input, parse_field_size,
+    ConsoleEntries, handle_console_entries, copy_prescription_to_console,
+    handle_dose_input, handle_treat_command,
+    handle_reset_command, handle_new_prescription_command, handle_setup_test_command,
+    field_after_mode, energy_mode_warning, toggle_checklist_item, check_all_checklist_items, dismiss_malfunction,
+    is_data_entry_complete, verify_entry, acknowledge_differences, login_operator, logout_operator,
     can_treat, get_phase, complete_data_entry,
 };
 
@@ -115,4 +180,177 @@ mod tests {
         state.write().phase = TPhase::SetupDone;
         assert!(can_treat(&state));
     }
+
+    #[test]
+    fn test_non_positive_dose_targets_are_rejected() {
+        let state = create_therac_state();
+        assert!(handle_dose_input(state.clone(), 180.0));
+        state.write().editing_taking_place = false;
+
+        for dose in [0.0, -50.0, f64::NAN] {
+            assert!(!handle_dose_input(state.clone(), dose));
+            let s = state.read();
+            assert_eq!(s.dose_target, 180.0);
+            assert!(!s.editing_taking_place);
+            let refusal = s.log.last().unwrap();
+            assert!(refusal.message.contains("rejected - must be positive"));
+            assert_eq!(refusal.level, LogLevel::Warn);
+        }
+    }
+
+    #[test]
+    fn test_new_prescription_keeps_console_entries() {
+        let state = create_therac_state();
+        handle_mode_input(state.clone(), BeamType::XRay);
+        handle_gantry_input(state.clone(), 123);
+        state.write().dose_delivered = 42.0;
+
+        assert!(handle_new_prescription_command(state.clone()));
+
+        let s = state.read();
+        assert_eq!(s.console_meos.beam_type, BeamType::XRay);
+        assert_eq!(s.console_meos.beam_energy, BeamEnergy::E25);
+        assert_eq!(s.console_params.gantry_angle, 123);
+        assert_eq!(s.dose_delivered, 42.0);
+    }
+
+    #[test]
+    fn test_xray_energy_field_depends_on_auto_max_energy() {
+        let state = create_therac_state();
+        handle_energy_input(state.clone(), BeamEnergy::E10);
+        handle_mode_input(state.clone(), BeamType::XRay);
+        assert_eq!(state.read().console_meos.beam_energy, BeamEnergy::E25);
+        assert_eq!(field_after_mode(&state.read().config, BeamType::XRay), InputField::Gantry);
+
+        state.write().config.auto_max_energy_on_xray = false;
+        handle_energy_input(state.clone(), BeamEnergy::E10);
+        handle_mode_input(state.clone(), BeamType::XRay);
+        assert_eq!(state.read().console_meos.beam_energy, BeamEnergy::E10);
+        assert_eq!(field_after_mode(&state.read().config, BeamType::XRay), InputField::Energy);
+        assert_eq!(field_after_mode(&state.read().config, BeamType::Electron), InputField::Energy);
+    }
+
+    #[test]
+    fn test_energy_mode_warning() {
+        let meos = |beam_type, beam_energy| Meos { beam_type, beam_energy, collimator: CollimatorPosition::OutOfPosition };
+        assert_eq!(energy_mode_warning(&meos(BeamType::XRay, BeamEnergy::E25)), None);
+        assert_eq!(energy_mode_warning(&meos(BeamType::Electron, BeamEnergy::E10)), None);
+        let warning = energy_mode_warning(&meos(BeamType::XRay, BeamEnergy::E10)).unwrap();
+        assert_eq!(warning, "WARNING: X-Ray mode at 10 MeV - X-ray treatments run at 25 MeV");
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        handle_mode_input(state.clone(), BeamType::XRay);
+        handle_energy_input(state.clone(), BeamEnergy::E10);
+        let logged = state.read().log.last().cloned().unwrap();
+        assert_eq!((logged.level, logged.message), (LogLevel::Warn, format!("[CONSOLE] {}", warning)));
+        assert!(energy_mode_warning(&meos(BeamType::XRay, BeamEnergy::EnergyMeV(24.5))).is_some());
+    }
+
+    #[test]
+    fn test_input_field_navigation_round_trips() {
+        let mut field = InputField::Mode;
+        let mut visited = Vec::new();
+        for _ in 0..6 {
+            visited.push(field);
+            assert_eq!(field.next().prev(), field);
+            field = field.next();
+        }
+        assert_eq!(field, InputField::Mode);
+        assert_eq!(visited.last(), Some(&InputField::Command));
+        assert_eq!(InputField::Mode.prev(), InputField::Command);
+    }
+
+    #[test]
+    fn test_new_prescription_refused_during_treatment() {
+        let state = create_therac_state();
+        state.write().phase = TPhase::PatientTreatment;
+        let before = state.read().reference_params;
+
+        assert!(!handle_new_prescription_command(state.clone()));
+        assert_eq!(state.read().reference_params, before);
+    }
+
+    #[test]
+    fn test_new_prescription_refused_while_treatment_paused() {
+        let state = create_therac_state();
+        {
+            let mut s = state.write();
+            s.phase = TPhase::SetupDone;
+            s.beam_on();
+            s.phase = TPhase::PauseTreatment;
+        }
+        let before = state.read().reference_params;
+        assert!(!handle_new_prescription_command(state.clone()));
+        assert_eq!(state.read().reference_params, before);
+        assert!(state.read().log.last().unwrap().message.contains("Cannot change prescription"));
+
+        // Once the treatment is over a new prescription is accepted again
+        state.write().reset();
+        assert!(handle_new_prescription_command(state.clone()));
+    }
+
+    #[test]
+    fn test_readback_blocks_until_differences_acknowledged() {
+        let state = create_therac_state();
+        {
+            let mut s = state.write();
+            s.config.require_readback = true;
+            s.phase = TPhase::DataEntry;
+            s.console_meos = s.reference_meos;
+            s.console_params = s.reference_params;
+            s.dose_target = s.reference_dose_target;
+        }
+        // Entry matches the prescription: nothing to acknowledge
+        assert!(verify_entry(state.clone()).is_empty());
+        assert!(!acknowledge_differences(state.clone()));
+        complete_data_entry(state.clone());
+        assert!(state.read().data_entry_complete);
+
+        let state = create_therac_state();
+        {
+            let mut s = state.write();
+            s.config.require_readback = true;
+            s.phase = TPhase::DataEntry;
+            s.console_meos = s.reference_meos;
+            s.console_params = s.reference_params;
+            s.dose_target = s.reference_dose_target + 50.0;
+        }
+        let differences = verify_entry(state.clone());
+        assert_eq!(differences.len(), 1);
+        assert_eq!(differences[0].field, "Dose");
+        complete_data_entry(state.clone());
+        assert!(!state.read().data_entry_complete);
+
+        assert!(acknowledge_differences(state.clone()));
+        // A further edit invalidates the acknowledgment
+        let other_angle = (state.read().reference_params.gantry_angle + 1) % 360;
+        handle_gantry_input(state.clone(), other_angle);
+        complete_data_entry(state.clone());
+        assert!(!state.read().data_entry_complete);
+
+        assert!(acknowledge_differences(state.clone()));
+        complete_data_entry(state.clone());
+        assert!(state.read().data_entry_complete);
+    }
+
+    #[test]
+    fn test_copy_from_prescription_follows_config() {
+        let state = create_therac_state();
+        let prescribed = state.read().reference_dose_target;
+        assert_eq!(copy_from_prescription(&state, |s| s.reference_dose_target), Some(prescribed));
+
+        state.write().config.auto_copy_enabled = false;
+        assert_eq!(copy_from_prescription(&state, |s| s.reference_dose_target), None);
+    }
+
+    #[test]
+    fn test_parse_field_size() {
+        assert_eq!(parse_field_size("10x15"), Some((10.0, 15.0)));
+        assert_eq!(parse_field_size("7.5×20"), Some((7.5, 20.0)));
+        // A single number is an equivalent square
+        assert_eq!(parse_field_size("12"), Some((12.0, 12.0)));
+        assert_eq!(parse_field_size(""), None);
+        assert_eq!(parse_field_size("10x"), None);
+        assert_eq!(parse_field_size("10x15x20"), None);
+        assert_eq!(parse_field_size("-5"), None);
+    }
 }
@@ -0,0 +1,173 @@
+//! Lock acquisition discipline for the shared state
+//!
+//! The simulator is about a concurrency bug, so its own locking has to be beyond
+//! suspicion. The rules:
+//!
+//! 1. A thread holds at most one guard on a `SharedTheracState` at a time - of any
+//!    machine, read or write. Take what you need, drop it, then lock again.
+//! 2. A guard is never held across an `.await` (the guards are `!Send`, so a spawned
+//!    task that tries will not compile).
+//! 3. Any lock added later is acquired only while no state guard is held.
+//!
+//! With one lock per thread there is no acquisition order to get wrong, so no
+//! deadlock is possible. `LockState` enforces rule 1: a nested acquisition panics
+//! with a clear message instead of deadlocking. Library code locks through it;
+//! the deliberate check-then-act race in `zap_the_specimen` is two separate,
+//! correctly ordered acquisitions and is unaffected.
+//!
+//! The hot paths - a beam pulse and a collimator sync - take the write lock through
+//! `write_state_logged`, which measures the wait and logs a `LOCK WAIT` diagnostic
+//! when it reaches `config.lock_wait_log_ms`. That makes contention between the
+//! monitor and housekeeper tasks visible instead of just slow.
+
+use crate::state::{LogLevel, SharedTheracState, TheracState};
+use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+use std::cell::Cell;
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, Instant};
+
+/// How long each attempt of a contended timed acquisition blocks before retrying
+const LOCK_WAIT_POLL: Duration = Duration::from_millis(1);
+
+thread_local! {
+    static STATE_LOCK_HELD: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as holding a state guard for as long as it lives
+struct HeldMarker;
+
+impl HeldMarker {
+    fn acquire() -> Self {
+        STATE_LOCK_HELD.with(|held| {
+            assert!(
+                !held.get(),
+                "nested state lock acquisition: drop the current guard before locking again"
+            );
+            held.set(true);
+        });
+        HeldMarker
+    }
+}
+
+impl Drop for HeldMarker {
+    fn drop(&mut self) {
+        STATE_LOCK_HELD.with(|held| held.set(false));
+    }
+}
+
+/// A read or write guard on the state, tracked by the lock discipline
+pub struct StateGuard<G> {
+    // Field order matters: the lock is released before the marker is cleared
+    guard: G,
+    _held: HeldMarker,
+}
+
+impl<G: Deref<Target = TheracState>> Deref for StateGuard<G> {
+    type Target = TheracState;
+
+    fn deref(&self) -> &TheracState {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut<Target = TheracState>> DerefMut for StateGuard<G> {
+    fn deref_mut(&mut self) -> &mut TheracState {
+        &mut self.guard
+    }
+}
+
+/// Disciplined access to a shared state
+pub trait LockState {
+    /// Read guard; panics if this thread already holds a state guard
+    fn read_state(&self) -> StateGuard<RwLockReadGuard<'_, TheracState>>;
+    /// Write guard; panics if this thread already holds a state guard
+    fn write_state(&self) -> StateGuard<RwLockWriteGuard<'_, TheracState>>;
+    /// Write guard and how long this thread waited for it
+    fn write_state_timed(&self) -> (StateGuard<RwLockWriteGuard<'_, TheracState>>, Duration);
+
+    /// Write guard for a hot path; logs "`tag` LOCK WAIT {ms} ms" when the wait
+    /// reaches `config.lock_wait_log_ms`
+    fn write_state_logged(&self, tag: &str) -> StateGuard<RwLockWriteGuard<'_, TheracState>> {
+        let (mut s, waited) = self.write_state_timed();
+        let threshold = s.config.lock_wait_log_ms;
+        if threshold > 0 && waited >= Duration::from_millis(threshold as u64) {
+            s.add_log_with_level(LogLevel::Warn, format!("{} LOCK WAIT {} ms", tag, waited.as_millis()));
+        }
+        s
+    }
+}
+
+impl LockState for SharedTheracState {
+    fn read_state(&self) -> StateGuard<RwLockReadGuard<'_, TheracState>> {
+        let held = HeldMarker::acquire();
+        StateGuard { guard: self.read(), _held: held }
+    }
+
+    fn write_state(&self) -> StateGuard<RwLockWriteGuard<'_, TheracState>> {
+        let held = HeldMarker::acquire();
+        StateGuard { guard: self.write(), _held: held }
+    }
+
+    fn write_state_timed(&self) -> (StateGuard<RwLockWriteGuard<'_, TheracState>>, Duration) {
+        let held = HeldMarker::acquire();
+        if let Some(guard) = self.try_write() {
+            return (StateGuard { guard, _held: held }, Duration::ZERO);
+        }
+        // Only a contended acquisition reads the clock
+        let started = Instant::now();
+        loop {
+            if let Some(guard) = self.try_write_for(LOCK_WAIT_POLL) {
+                return (StateGuard { guard, _held: held }, started.elapsed());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_therac_state;
+
+    #[test]
+    fn test_sequential_acquisition_is_allowed() {
+        let state = create_therac_state();
+        state.write_state().dose_delivered = 5.0;
+        assert_eq!(state.read_state().dose_delivered, 5.0);
+        let other = create_therac_state();
+        assert_eq!(other.read_state().dose_delivered, 0.0);
+    }
+
+    #[test]
+    fn test_contended_write_logs_lock_wait() {
+        let state = create_therac_state();
+        state.write().config.lock_wait_log_ms = 10;
+
+        // Uncontended: no diagnostic
+        drop(state.write_state_logged("[MONITOR]"));
+        assert!(!state.read().log.iter().any(|m| m.message.contains("LOCK WAIT")));
+
+        // Another thread sits on the lock for 50 ms
+        let holder = state.clone();
+        let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let _s = holder.write_state();
+            locked_tx.send(()).unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+        });
+        locked_rx.recv().unwrap();
+        drop(state.write_state_logged("[MONITOR]"));
+        handle.join().unwrap();
+
+        let s = state.read();
+        let entry = s.log.iter().find(|m| m.message.contains("LOCK WAIT")).expect("no LOCK WAIT logged");
+        assert!(entry.message.starts_with("[MONITOR] LOCK WAIT"), "{}", entry);
+    }
+
+    #[test]
+    #[should_panic(expected = "nested state lock acquisition")]
+    fn test_nested_acquisition_panics() {
+        let state = create_therac_state();
+        let _outer = state.read_state();
+        let _inner = state.write_state();
+    }
+}
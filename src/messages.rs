@@ -0,0 +1,248 @@
+//! Translatable message table
+//!
+//! Log and UI text that an operator reads - malfunction messages, phase names and
+//! the help screen - is looked up by `MessageKey` instead of being written inline.
+//! A `Messages` table holds translations loaded from JSON; any key it does not
+//! translate falls back to the English text, so a partial translation is usable.
+//!
+//! Templates use positional placeholders (`{0}`, `{1}`, ...) so a translation can
+//! reorder them. Malfunction codes such as `MALFUNCTION 54` should be kept as-is:
+//! operators looked them up in the manual by number.
+
+use crate::state::TPhase;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// Identifies one translatable message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MessageKey {
+    PhaseReset,
+    PhaseDataEntry,
+    PhaseSetupTest,
+    PhaseSetupDone,
+    PhaseBeamReady,
+    PhasePatientTreatment,
+    PhasePauseTreatment,
+    PhaseTerminateTreatment,
+    PhaseEmergencyStop,
+    PhaseDateTimeIdChanges,
+    /// {0}: occurrence number
+    MalfunctionEditInProgress,
+    /// {0}: occurrence number, {1}/{2}: console beam type/collimator,
+    /// {3}/{4}: hardware beam type/collimator
+    Malfunction54,
+    /// {0}: dose multiplier, {1}: dose this pulse, {2}: total delivered, {3}: target
+    MalfunctionUnsafeBeam,
+    /// {0}: dose display cap in cGy
+    LethalOverdose,
+    /// {0}: how long the turntable had been moving, in ms
+    CollimatorTimeout,
+    /// {0}: occurrence number, {1}: time left until the magnets settle, in ms
+    MagnetNotSettled,
+    /// {0}: occurrence number, {1}: energy entered, {2}: time left until it settles, in ms
+    EnergyNotSettled,
+    /// {0}: occurrence number
+    CollimatorStuck,
+    /// {0}: malfunction number
+    MalfunctionRandomFault,
+    HelpTitle,
+    HelpBody,
+    HelpRaceTitle,
+    HelpRaceBody,
+    HelpClose,
+    CommandBar,
+}
+
+impl MessageKey {
+    /// Key for the name of `phase`
+    pub fn phase(phase: TPhase) -> Self {
+        match phase {
+            TPhase::Reset => MessageKey::PhaseReset,
+            TPhase::DataEntry => MessageKey::PhaseDataEntry,
+            TPhase::SetupTest => MessageKey::PhaseSetupTest,
+            TPhase::SetupDone => MessageKey::PhaseSetupDone,
+            TPhase::BeamReady => MessageKey::PhaseBeamReady,
+            TPhase::PatientTreatment => MessageKey::PhasePatientTreatment,
+            TPhase::PauseTreatment => MessageKey::PhasePauseTreatment,
+            TPhase::TerminateTreatment => MessageKey::PhaseTerminateTreatment,
+            TPhase::EmergencyStop => MessageKey::PhaseEmergencyStop,
+            TPhase::DateTimeIdChanges => MessageKey::PhaseDateTimeIdChanges,
+        }
+    }
+
+    /// The English text, used whenever a table has no translation
+    pub fn english(self) -> &'static str {
+        match self {
+            MessageKey::PhaseReset => "Reset",
+            MessageKey::PhaseDataEntry => "Data Entry",
+            MessageKey::PhaseSetupTest => "Setup Test",
+            MessageKey::PhaseSetupDone => "Setup Done",
+            MessageKey::PhaseBeamReady => "Beam Ready",
+            MessageKey::PhasePatientTreatment => "Patient Treatment",
+            MessageKey::PhasePauseTreatment => "Paused",
+            MessageKey::PhaseTerminateTreatment => "Terminated",
+            MessageKey::PhaseEmergencyStop => "EMERGENCY STOP",
+            MessageKey::PhaseDateTimeIdChanges => "Date/Time/ID Changes",
+            MessageKey::MalfunctionEditInProgress => "EDIT IN PROGRESS (occurrence #{0}) - Console changed after data entry was completed, hardware not synced to latest edit",
+            MessageKey::Malfunction54 => "MALFUNCTION 54 - Parameter mismatch (occurrence #{0}) - Console: {1}/{2}, Hardware: {3}/{4}",
+            MessageKey::MalfunctionUnsafeBeam => "CRITICAL SAFETY VIOLATION! Beam fired with unsafe configuration! Dose multiplier: {0}x - Delivered {1} cGy this pulse (total: {2}/{3} cGy)",
+            MessageKey::LethalOverdose => "LETHAL OVERDOSE - shown capped at {0} cGy, the scale of the documented Therac-25 incidents",
+            MessageKey::CollimatorStuck => "COLLIMATOR STUCK (occurrence #{0}) - turntable jammed mid-rotation, beam inhibited",
+            MessageKey::MalfunctionRandomFault => "MALFUNCTION {0} - Random hardware fault",
+            MessageKey::CollimatorTimeout => "COLLIMATOR TIMEOUT - beam inhibited (turntable moving for {0} ms)",
+            MessageKey::MagnetNotSettled => "BENDING MAGNET NOT SETTLED (occurrence #{0}) - beam triggered {1} ms before the magnets settled",
+            MessageKey::EnergyNotSettled => "ENERGY NOT SETTLED (occurrence #{0}) - beam triggered {2} ms before the energy system settled at {1}",
+            MessageKey::HelpTitle => "THERAC-25 SIMULATOR - HELP",
+            MessageKey::HelpBody => HELP_BODY,
+            MessageKey::HelpRaceTitle => "THE RACE CONDITION:",
+            MessageKey::HelpRaceBody => HELP_RACE_BODY,
+            MessageKey::HelpClose => "Press any key to close help...",
+            MessageKey::CommandBar => "Commands: (t)reat | (r)eset | (p)roceed | (n)ew Rx | (s)top | (c)ontinue | (q)uit  |  F1=Help  |  F12=EMERGENCY OFF",
+        }
+    }
+}
+
+const HELP_BODY: &str = "\
+DATA ENTRY WORKFLOW:
+  1. Enter Mode: X (X-ray) or E (Electron)
+     - X-ray automatically sets energy to 25 MeV and skips to Gantry
+  2. Enter Energy: 5, 10, 15, 20, or 25 (MeV)
+  3. Enter Gantry Angle: 0-360 degrees
+  4. Enter Field Size: Type X value, press 'x', type Y value
+     - Example: 10x15 for 10cm × 15cm field
+  5. Enter Dose: target dose in cGy (centigray)
+  6. Enter Command at prompt
+
+COPYING PRESCRIPTION VALUES:
+  - Press ENTER on any numeric field to copy from prescription
+  - This simulates the quick-entry workflow that led to real accidents
+  - Quick entry was convenient but dangerous!
+
+FIELD NAVIGATION:
+  - Press ENTER to advance to next field
+  - Press ESC to return to Mode entry
+  - Up/Down or Tab/Shift+Tab move between fields without entering
+  - Backspace to delete characters
+
+COMMANDS:
+  t, treat    - Complete entry and start treatment immediately
+  r, reset    - Reset system and generate new prescription
+  p, proceed  - Complete data entry and move to setup phase
+  n, new      - Replace prescription only (console entries are kept)
+  library     - Pick a named prescription from the reference library
+  login <id>  - Log in as operator <id>; logout - log out
+  operators   - Log treatments, overdoses and near misses per operator
+  summary     - Log the session outcome: dose, malfunctions by kind, final phase
+  csv <path>  - Write the event log to a CSV file (timestamp,level,message)
+  sync <order> - Housekeeper sync order, e.g. sync energy collimator
+  timing <ms> [<ms>] - Turntable move and param sync times (default 800 200)
+  prf <Hz>    - Space pulses at this rate, scaled by dose rate (0 = every pass)
+  interlock <cGy> - Terminate before any pulse takes the dose past this (0 = off)
+  cap         - Cap displayed overdoses at 10000 cGy (LETHAL OVERDOSE)
+  sweep       - Chart per-pulse dose for every energy/mode/filter
+  verify      - Read entries back against the prescription ('y' acknowledges)
+  readback    - Require a verified read-back before data entry completes
+  safemode    - Toggle the race-free pulse (check and fire under one lock)
+  watchdog    - Toggle the hardware watchdog (COLLIMATOR TIMEOUT)
+  race        - Force the race window and turn the beam on (next pulse unsafe)
+  jam         - Jam (or free) the collimator turntable
+  free        - Free a turntable stuck mid-rotation (COLLIMATOR STUCK)
+  faults      - Toggle random hardware faults (off leaves only the race)
+  freeze      - Pause (or resume) the monitor, housekeeper and other tasks
+  magnets     - Toggle the 8 s bending magnet settle time (MAGNET NOT SETTLED)
+  phantom     - Toggle a colleague who nudges console entries at random
+  lockwait    - Log write-lock waits of 5 ms or more (LOCK WAIT)
+  checklist   - Require the pre-treatment checklist (F2-F5 tick, F6 all)
+  wear        - Toggle machine wear; service - reset it to a new machine
+  recal <n>   - Require recalibration every <n> treatments (0 = never)
+  recalibrate - Recalibrate the machine (also resets wear)
+  s, stop     - Pause current treatment
+  c, continue - Resume paused treatment
+  q, quit     - Exit simulator
+  F12         - EMERGENCY OFF: cut the beam now; only a reset recovers";
+
+const HELP_RACE_BODY: &str = "\
+The original Therac-25 bug occurred when operators:
+  1. Entered X-ray mode (auto-sets to 25 MeV high energy)
+  2. Noticed mistake, quickly changed to Electron mode
+  3. Started treatment before hardware collimator sync completed
+  4. Result: High-energy beam without flatness filter = 100x overdose
+
+TO TRIGGER THE BUG:
+  - Type X (X-ray mode), then immediately press Backspace
+  - Type E (Electron mode), fill in parameters quickly
+  - Use 't' command to treat before hardware finishes syncing
+  - Watch for MALFUNCTION 54 or CRITICAL SAFETY VIOLATION!";
+
+/// A translation table; untranslated keys fall back to English
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Messages {
+    translations: HashMap<MessageKey, String>,
+}
+
+impl Messages {
+    /// Load a translation: a JSON object from key names to text,
+    /// e.g. `{"PhasePauseTreatment": "En pausa"}`
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+
+    /// Translate (or replace) one message
+    pub fn set(&mut self, key: MessageKey, text: impl Into<String>) {
+        self.translations.insert(key, text.into());
+    }
+
+    /// Text for `key`, in English if this table has no translation
+    pub fn get(&self, key: MessageKey) -> &str {
+        self.translations
+            .get(&key)
+            .map_or_else(|| key.english(), String::as_str)
+    }
+
+    /// Text for `key` with `{0}`, `{1}`, ... replaced by `args`
+    pub fn format(&self, key: MessageKey, args: &[&dyn Display]) -> String {
+        let mut text = self.get(key).to_string();
+        for (i, arg) in args.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", i), &arg.to_string());
+        }
+        text
+    }
+
+    /// Display name of `phase`
+    pub fn phase(&self, phase: TPhase) -> &str {
+        self.get(MessageKey::phase(phase))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_untranslated_keys_fall_back_to_english() {
+        let messages = Messages::from_json(r#"{"PhasePauseTreatment": "En pausa"}"#).unwrap();
+        assert_eq!(messages.phase(TPhase::PauseTreatment), "En pausa");
+        assert_eq!(messages.phase(TPhase::SetupDone), "Setup Done");
+        assert_eq!(
+            messages.format(MessageKey::MalfunctionRandomFault, &[&42]),
+            "MALFUNCTION 42 - Random hardware fault"
+        );
+    }
+
+    #[test]
+    fn test_translations_can_reorder_placeholders() {
+        let mut messages = Messages::default();
+        messages.set(MessageKey::Malfunction54, "{3}/{4} != {1}/{2} (#{0}) MALFUNCTION 54");
+        let text = messages.format(MessageKey::Malfunction54, &[&1, &"X", &"IN", &"E", &"OUT"]);
+        assert_eq!(text, "E/OUT != X/IN (#1) MALFUNCTION 54");
+    }
+
+    #[test]
+    fn test_example_translation_loads() {
+        let messages = Messages::from_json(include_str!("../examples/messages/es.json")).unwrap();
+        assert_ne!(messages.get(MessageKey::HelpTitle), MessageKey::HelpTitle.english());
+        assert!(messages.get(MessageKey::Malfunction54).contains("MALFUNCTION 54"));
+    }
+}
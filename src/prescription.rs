@@ -0,0 +1,128 @@
+//! Reference prescriptions
+//!
+//! Prescriptions are normally drawn at random (`TheracState::generate_new_reference`).
+//! For structured exercises an instructor can load one of these named ones instead, so
+//! every student starts from the same plan. A reset draws a random one again.
+
+use crate::locking::LockState;
+use crate::state::{BeamEnergy, BeamType, CollimatorPosition, LogLevel, Meos, SharedTheracState, TreatmentParams};
+
+/// A named treatment plan
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prescription {
+    pub name: &'static str,
+    pub meos: Meos,
+    pub params: TreatmentParams,
+    /// Dose to deliver (cGy)
+    pub dose: f64,
+}
+
+const fn xray(gantry_angle: u16, field_size_x: f32, field_size_y: f32, dose_rate: f32) -> (Meos, TreatmentParams) {
+    (
+        Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::InPosition },
+        TreatmentParams { gantry_angle, collimator_angle: 0, field_size_x, field_size_y, dose_rate },
+    )
+}
+
+const fn electron(beam_energy: BeamEnergy, gantry_angle: u16, field_size_x: f32, field_size_y: f32, dose_rate: f32) -> (Meos, TreatmentParams) {
+    (
+        Meos { beam_type: BeamType::Electron, beam_energy, collimator: CollimatorPosition::OutOfPosition },
+        TreatmentParams { gantry_angle, collimator_angle: 0, field_size_x, field_size_y, dose_rate },
+    )
+}
+
+const fn prescription(name: &'static str, (meos, params): (Meos, TreatmentParams), dose: f64) -> Prescription {
+    Prescription { name, meos, params, dose }
+}
+
+/// The library, in the order the pickers list it
+pub static PRESCRIPTIONS: [Prescription; 6] = [
+    prescription("Whole brain X-ray 25 MV", xray(90, 15.0, 12.0, 100.0), 200.0),
+    prescription("Pelvis X-ray 25 MV", xray(180, 15.0, 15.0, 100.0), 180.0),
+    prescription("Lung X-ray 25 MV", xray(0, 10.0, 12.0, 110.0), 200.0),
+    prescription("Electron boost 10 MeV", electron(BeamEnergy::E10, 0, 8.0, 8.0, 150.0), 250.0),
+    prescription("Chest wall electron 15 MeV", electron(BeamEnergy::E15, 30, 12.0, 18.0, 150.0), 180.0),
+    // The East Texas Cancer Center treatment of March 1986: 180 rad of 22 MeV
+    // electrons to a 10x17 cm field on the upper back
+    prescription("Upper back electron 22 MeV", electron(BeamEnergy::EnergyMeV(22.0), 0, 10.0, 17.0, 150.0), 180.0),
+];
+
+/// Names of every prescription in the library
+pub fn list_prescriptions() -> Vec<&'static str> {
+    PRESCRIPTIONS.iter().map(|p| p.name).collect()
+}
+
+/// The prescription called `name`, ignoring case
+pub fn find_prescription(name: &str) -> Option<&'static Prescription> {
+    PRESCRIPTIONS.iter().find(|p| p.name.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Make the named prescription the reference, in place of the random one
+/// Like `handle_new_prescription_command`, console entries are left as they are and it
+/// is refused while a treatment is under way. Returns false if refused or not found.
+pub fn load_prescription(state: SharedTheracState, name: &str) -> bool {
+    let mut s = state.write_state();
+    let Some(prescription) = find_prescription(name) else {
+        s.add_log(format!("[OPERATOR] No prescription called '{}' in the library", name));
+        return false;
+    };
+    if s.treatment_under_way() {
+        s.add_log_with_level(LogLevel::Warn, "[OPERATOR] Cannot change prescription during treatment".to_string());
+        return false;
+    }
+    s.set_reference(prescription.meos, prescription.params, prescription.dose);
+    s.add_log(format!("[OPERATOR] Prescription loaded from the library: {}", prescription.name));
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_therac_state;
+    use crate::state::TPhase;
+
+    #[test]
+    fn test_library_prescriptions_are_consistent() {
+        for p in &PRESCRIPTIONS {
+            assert!(p.meos.is_safe(), "{}: collimator does not match the beam type", p.name);
+            assert!(BeamEnergy::RANGE_MEV.contains(&p.meos.beam_energy.mev()), "{}", p.name);
+            assert_eq!(crate::input::energy_mode_warning(&p.meos), None, "{}", p.name);
+            assert!(crate::state::valid_dose_target(p.dose), "{}", p.name);
+            assert!(p.params.dose_rate > 0.0 && p.params.gantry_angle < 360, "{}", p.name);
+            assert_eq!(find_prescription(&p.name.to_uppercase()), Some(p));
+        }
+        let mut names = list_prescriptions();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), PRESCRIPTIONS.len());
+    }
+
+    #[test]
+    fn test_load_prescription_sets_the_reference() {
+        let state = create_therac_state();
+        let entered = state.read().console_meos;
+        assert!(load_prescription(state.clone(), "electron boost 10 mev"));
+        {
+            let s = state.read();
+            assert_eq!(s.reference_meos, PRESCRIPTIONS[3].meos);
+            assert_eq!(s.reference_params, PRESCRIPTIONS[3].params);
+            assert_eq!(s.reference_dose_target, 250.0);
+            assert_eq!(s.console_meos, entered);
+        }
+
+        assert!(!load_prescription(state.clone(), "Knee X-ray"));
+        state.write().phase = TPhase::PatientTreatment;
+        assert!(!load_prescription(state.clone(), "Pelvis X-ray 25 MV"));
+        assert_eq!(state.read().reference_dose_target, 250.0);
+
+        // Paused on a malfunction, the treatment is still open
+        {
+            let mut s = state.write();
+            s.phase = TPhase::SetupDone;
+            s.beam_on();
+            s.phase = TPhase::PauseTreatment;
+        }
+        assert!(!load_prescription(state.clone(), "Pelvis X-ray 25 MV"));
+        assert_eq!(state.read().reference_dose_target, 250.0);
+    }
+}
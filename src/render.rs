@@ -14,7 +14,9 @@ use ratatui::{
     widgets::{Block, Borders, BorderType, Paragraph, List, ListItem, Gauge},
 };
 
-use crate::state::{SharedTheracState, TPhase, BeamType};
+use crate::state::{SharedTheracState, TPhase, LogLevel, LogSource, MeosField};
+use crate::simulator::treatment_depth_cm;
+use crate::config::HARM_REPORTING_BANNER;
 
 /// Render the Therac-25 interface to a ratatui Frame
 /// This can be called from an external TUI application
@@ -33,7 +35,7 @@ pub fn render_therac25(frame: &mut Frame, state: &SharedTheracState) {
 
     render_header(frame, chunks[0]);
     render_main_area(frame, chunks[1], &state_guard);
-    render_footer(frame, chunks[2], &state_guard);
+    render_footer(frame, chunks[2]);
 }
 
 #[cfg(feature = "tui-render")]
@@ -120,7 +122,12 @@ fn render_reference_prescription(frame: &mut Frame, area: Rect, state: &crate::s
         Line::from(vec![
             Span::raw("Field: "),
             Span::styled(
-                format!("{}x{} cm", state.reference_params.field_size_x, state.reference_params.field_size_y),
+                format!(
+                    "{}x{} cm (eq. sq. {:.1})",
+                    state.reference_params.field_size_x,
+                    state.reference_params.field_size_y,
+                    state.reference_params.equivalent_square()
+                ),
                 Style::default().fg(Color::Cyan),
             ),
         ]),
@@ -143,19 +150,20 @@ fn render_reference_prescription(frame: &mut Frame, area: Rect, state: &crate::s
 
 #[cfg(feature = "tui-render")]
 fn render_console_settings(frame: &mut Frame, area: Rect, state: &crate::state::TheracState) {
+    let diff = state.meos_diff();
     let content = vec![
         Line::from(vec![
             Span::raw("Mode: "),
             Span::styled(
                 format!("{}", state.console_meos.beam_type),
-                Style::default().fg(Color::Yellow),
+                mismatch_style(Color::Yellow, diff.console_hardware_differ(MeosField::BeamType)),
             ),
         ]),
         Line::from(vec![
             Span::raw("Energy: "),
             Span::styled(
                 format!("{}", state.console_meos.beam_energy),
-                Style::default().fg(Color::Yellow),
+                mismatch_style(Color::Yellow, diff.console_hardware_differ(MeosField::BeamEnergy)),
             ),
         ]),
         Line::from(vec![
@@ -168,7 +176,12 @@ fn render_console_settings(frame: &mut Frame, area: Rect, state: &crate::state::
         Line::from(vec![
             Span::raw("Field: "),
             Span::styled(
-                format!("{}x{} cm", state.console_params.field_size_x, state.console_params.field_size_y),
+                format!(
+                    "{}x{} cm (eq. sq. {:.1})",
+                    state.console_params.field_size_x,
+                    state.console_params.field_size_y,
+                    state.console_params.equivalent_square()
+                ),
                 Style::default().fg(Color::Yellow),
             ),
         ]),
@@ -197,26 +210,35 @@ fn render_hardware_status(frame: &mut Frame, area: Rect, state: &crate::state::T
         Color::Red
     };
 
+    let diff = state.meos_diff();
     let content = vec![
         Line::from(vec![
             Span::raw("Mode: "),
             Span::styled(
                 format!("{}", state.hardware_meos.beam_type),
-                Style::default().fg(Color::Green),
+                mismatch_style(Color::Green, diff.console_hardware_differ(MeosField::BeamType)),
             ),
         ]),
         Line::from(vec![
             Span::raw("Energy: "),
             Span::styled(
                 format!("{}", state.hardware_meos.beam_energy),
-                Style::default().fg(Color::Green),
+                mismatch_style(Color::Green, diff.console_hardware_differ(MeosField::BeamEnergy)),
             ),
         ]),
         Line::from(vec![
             Span::raw("Collimator: "),
             Span::styled(
                 format!("{}", state.hardware_meos.collimator),
-                Style::default().fg(collimator_color).add_modifier(Modifier::BOLD),
+                mismatch_style(collimator_color, diff.console_hardware_differ(MeosField::Collimator))
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Depth: "),
+            Span::styled(
+                format!("{:.1} cm", treatment_depth_cm(&state.hardware_meos)),
+                Style::default().fg(collimator_color),
             ),
         ]),
         Line::from(vec![
@@ -226,6 +248,14 @@ fn render_hardware_status(frame: &mut Frame, area: Rect, state: &crate::state::T
                 Style::default().fg(collimator_color).add_modifier(Modifier::BOLD),
             ),
         ]),
+        Line::from(vec![
+            Span::raw("Sync: "),
+            if diff.sync_pending {
+                Span::styled("PENDING", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
+            } else {
+                Span::styled("OK", Style::default().fg(Color::Green))
+            },
+        ]),
     ];
 
     let block = Paragraph::new(content)
@@ -236,22 +266,41 @@ fn render_hardware_status(frame: &mut Frame, area: Rect, state: &crate::state::T
     frame.render_widget(block, area);
 }
 
+/// A MEOS field in its panel's colour, or red and bold when the console and hardware
+/// disagree on it, so the divergence during a sync window stands out in both panels
+#[cfg(feature = "tui-render")]
+fn mismatch_style(color: Color, differs: bool) -> Style {
+    if differs {
+        Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+    } else {
+        Style::default().fg(color)
+    }
+}
+
 #[cfg(feature = "tui-render")]
 fn render_treatment_phase(frame: &mut Frame, area: Rect, state: &crate::state::TheracState) {
     let phase_color = match state.phase {
         TPhase::PatientTreatment => Color::Green,
-        TPhase::PauseTreatment | TPhase::TerminateTreatment => Color::Red,
+        TPhase::PauseTreatment | TPhase::TerminateTreatment | TPhase::EmergencyStop => Color::Red,
         _ => Color::Yellow,
     };
 
-    let content = Paragraph::new(vec![
+    let mut lines = vec![
         Line::from(vec![
             Span::styled(
-                format!("{}", state.phase),
+                state.messages.phase(state.phase).to_string(),
                 Style::default().fg(phase_color).add_modifier(Modifier::BOLD),
             ),
         ]),
-    ])
+    ];
+    if state.phase == TPhase::BeamReady {
+        lines.push(Line::from(format!(
+            "Beam on in {:.1}s",
+            state.beam_hold_remaining_ms as f32 / 1000.0
+        )));
+    }
+
+    let content = Paragraph::new(lines)
     .alignment(Alignment::Center)
     .block(Block::default()
         .title("Treatment Phase")
@@ -262,17 +311,24 @@ fn render_treatment_phase(frame: &mut Frame, area: Rect, state: &crate::state::T
 
 #[cfg(feature = "tui-render")]
 fn render_dose_delivery(frame: &mut Frame, area: Rect, state: &crate::state::TheracState) {
-    let progress = if state.dose_target > 0.0 {
-        (state.dose_delivered / state.dose_target).min(1.0)
-    } else {
-        0.0
-    };
+    let percent = state.dose_percent_uncapped();
 
     let gauge = Gauge::default()
         .block(Block::default().title("Dose Progress").borders(Borders::ALL))
         .gauge_style(Style::default().fg(Color::Cyan).bg(Color::Black))
-        .percent((progress * 100.0) as u16)
-        .label(format!("{:.1}/{:.1} cGy", state.dose_delivered, state.dose_target));
+        .percent(percent.min(100.0) as u16);
+    // A full bar hides how far past the target an overdose went; the label does not.
+    // The usual overshoot of the last pulse is not an overdose.
+    let gauge = if crate::simulator::dose_past_target(state) {
+        gauge
+            .gauge_style(Style::default().fg(Color::Red).bg(Color::Black))
+            .label(Span::styled(
+                format!("{:.1}/{:.1} cGy - {:.0}% OF TARGET", state.dose_delivered, state.dose_target, percent),
+                Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD | Modifier::SLOW_BLINK),
+            ))
+    } else {
+        gauge.label(format!("{:.1}/{:.1} cGy", state.dose_delivered, state.dose_target))
+    };
 
     frame.render_widget(gauge, area);
 }
@@ -288,10 +344,16 @@ fn render_status(frame: &mut Frame, area: Rect, state: &crate::state::TheracStat
                 Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
             ),
             Span::styled(
-                malfunction,
+                malfunction.text.as_str(),
                 Style::default().fg(Color::Red),
             ),
         ]));
+        if state.config.harm_reporting_only {
+            lines.push(Line::from(Span::styled(
+                HARM_REPORTING_BANNER,
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+        }
     }
 
     if !state.treatment_outcome.is_empty() {
@@ -304,7 +366,19 @@ fn render_status(frame: &mut Frame, area: Rect, state: &crate::state::TheracStat
         ]));
     }
 
-    lines.push(Line::from(format!("Malfunctions: {}", state.malfunction_count)));
+    lines.push(Line::from(format!(
+        "Malfunctions: {}  Overrides: {}  Near misses: {}",
+        state.malfunction_count,
+        state.overrides.len(),
+        state.near_misses
+    )));
+
+    if state.normalized_deviance_warnings > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("NORMALIZED DEVIANCE: {} dismissals", state.malfunction_dismissals),
+            Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD),
+        )));
+    }
 
     let block = Paragraph::new(lines)
         .block(Block::default()
@@ -320,7 +394,18 @@ fn render_log(frame: &mut Frame, area: Rect, state: &crate::state::TheracState)
         .rev()
         .take(area.height.saturating_sub(2) as usize)
         .rev()
-        .map(|entry| ListItem::new(entry.clone()))
+        .map(|entry| {
+            let style = match entry.level {
+                LogLevel::Critical => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                LogLevel::Warn => Style::default().fg(Color::Red),
+                LogLevel::Info => Style::default().fg(match entry.source() {
+                    LogSource::Console | LogSource::Operator => Color::Yellow,
+                    LogSource::Housekeeper => Color::Cyan,
+                    LogSource::Monitor | LogSource::Other => Color::White,
+                }),
+            };
+            ListItem::new(entry.to_string()).style(style)
+        })
         .collect();
 
     let list = List::new(log_items)
@@ -333,7 +418,7 @@ fn render_log(frame: &mut Frame, area: Rect, state: &crate::state::TheracState)
 }
 
 #[cfg(feature = "tui-render")]
-fn render_footer(frame: &mut Frame, area: Rect, state: &crate::state::TheracState) {
+fn render_footer(frame: &mut Frame, area: Rect) {
     let help_text = "F1: Help | Ctrl+C: Quit | Commands: TREAT, RESET, SETUP";
     let footer = Paragraph::new(help_text)
         .style(Style::default().fg(Color::Gray))
@@ -341,3 +426,73 @@ fn render_footer(frame: &mut Frame, area: Rect, state: &crate::state::TheracStat
         .block(Block::default().borders(Borders::ALL));
     frame.render_widget(footer, area);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{BeamEnergy, BeamType, CollimatorPosition, MalfunctionKind, Meos};
+    use ratatui::{backend::TestBackend, buffer::Buffer, Terminal};
+
+    #[test]
+    fn test_render_therac25_draws_the_malfunction() {
+        let state = crate::create_therac_state();
+        state.write().report_malfunction(
+            MalfunctionKind::ParameterMismatch,
+            "MALFUNCTION 54 - Parameter mismatch".to_string(),
+        );
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 50)).unwrap();
+        let buffer = terminal.draw(|f| render_therac25(f, &state)).unwrap().buffer.clone();
+        let text: String = buffer.content.iter().map(|cell| cell.symbol()).collect();
+
+        assert!(text.contains("THERAC-25 RADIATION THERAPY SIMULATOR"));
+        assert!(text.contains("MALFUNCTION 54 - Parameter mismatch"));
+    }
+
+    /// Style of the value after `label`, where `label` and `value` are first drawn
+    fn style_of(buffer: &Buffer, label: &str, value: &str) -> (Color, bool) {
+        let needle = format!("{}{}", label, value);
+        let width = buffer.area.width as usize;
+        let symbols: Vec<&str> = buffer.content.iter().map(|cell| cell.symbol()).collect();
+        let at = (0..buffer.content.len())
+            .find(|&i| i % width + needle.len() <= width && symbols[i..i + needle.len()].concat() == needle)
+            .unwrap_or_else(|| panic!("'{}' not drawn", needle));
+        let cell = &buffer.content[at + label.len()];
+        (cell.fg, cell.modifier.contains(Modifier::BOLD))
+    }
+
+    #[test]
+    fn test_console_hardware_mismatch_renders_bold_red_in_both_panels() {
+        let state = crate::create_therac_state();
+        {
+            let mut s = state.write();
+            s.console_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::InPosition,
+            };
+            // Mid-sync: the hardware is still in X-ray mode at the same energy
+            s.hardware_meos = Meos { beam_type: BeamType::XRay, ..s.console_meos };
+            // Nothing in the reference panel reads the same
+            s.reference_meos = Meos { beam_type: BeamType::Undefined, beam_energy: BeamEnergy::E5, ..s.console_meos };
+        }
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 50)).unwrap();
+        let buffer = terminal.draw(|f| render_therac25(f, &state)).unwrap().buffer.clone();
+
+        assert_eq!(style_of(&buffer, "Mode: ", "Electron"), (Color::Red, true));
+        assert_eq!(style_of(&buffer, "Mode: ", "X-Ray"), (Color::Red, true));
+        // The energy agrees: the console's stays yellow
+        assert_eq!(style_of(&buffer, "Energy: ", "25 MeV"), (Color::Yellow, false));
+        assert_eq!(style_of(&buffer, "Collimator: ", "In Position"), (Color::Green, true));
+
+        // A safe hardware collimator the console disagrees with is still a mismatch
+        {
+            let mut s = state.write();
+            s.console_meos = Meos { collimator: CollimatorPosition::OutOfPosition, ..s.hardware_meos };
+        }
+        let buffer = terminal.draw(|f| render_therac25(f, &state)).unwrap().buffer.clone();
+
+        assert_eq!(style_of(&buffer, "Collimator: ", "In Position"), (Color::Red, true));
+    }
+}
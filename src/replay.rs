@@ -0,0 +1,343 @@
+//! Recorded operator sessions
+//!
+//! A `ReplayBundle` is the operator side of a session: every console action with the
+//! time it happened, relative to the start of the recording. Replaying it against a
+//! machine whose background tasks are running reproduces the session, including the
+//! timing that decides whether the race window opens.
+
+use crate::input::*;
+use crate::locking::LockState;
+use crate::simulator::{resume_treatment, start_treatment, stop_treatment};
+use crate::state::{BeamEnergy, BeamType, LogSource, SharedTheracState, TheracState};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// One operator action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum ReplayEvent {
+    Mode(BeamType),
+    Energy(BeamEnergy),
+    Gantry(u16),
+    FieldSize(f32, f32),
+    Dose(f64),
+    CompleteDataEntry,
+    Treat,
+    Stop,
+    Resume,
+    Reset,
+    NewPrescription,
+    /// On-screen commentary for a scripted session; logged, changes nothing
+    Narrate(String),
+}
+
+/// An operator action and when it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct TimedEvent {
+    /// Milliseconds since the start of the recording
+    pub at_ms: u64,
+    pub event: ReplayEvent,
+}
+
+/// A recorded session, events in chronological order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct ReplayBundle {
+    pub events: Vec<TimedEvent>,
+}
+
+impl ReplayBundle {
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let mut bundle: Self = serde_json::from_str(json)?;
+        bundle.events.sort_by_key(|e| e.at_ms);
+        Ok(bundle)
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Time of the last event
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.events.last().map_or(0, |e| e.at_ms))
+    }
+}
+
+/// Apply one recorded action through the same helpers the operator interfaces use
+pub fn apply_event(state: &SharedTheracState, event: &ReplayEvent) {
+    match *event {
+        ReplayEvent::Mode(mode) => handle_mode_input(state.clone(), mode),
+        ReplayEvent::Energy(energy) => handle_energy_input(state.clone(), energy),
+        ReplayEvent::Gantry(angle) => handle_gantry_input(state.clone(), angle),
+        ReplayEvent::FieldSize(x, y) => handle_field_size_input(state.clone(), x, y),
+        ReplayEvent::Dose(dose) => {
+            handle_dose_input(state.clone(), dose);
+        }
+        ReplayEvent::CompleteDataEntry => complete_data_entry(state.clone()),
+        ReplayEvent::Treat => {
+            start_treatment(state.clone());
+        }
+        ReplayEvent::Stop => stop_treatment(state.clone()),
+        ReplayEvent::Resume => resume_treatment(state.clone()),
+        ReplayEvent::Reset => handle_reset_command(state.clone()),
+        ReplayEvent::NewPrescription => {
+            handle_new_prescription_command(state.clone());
+        }
+        ReplayEvent::Narrate(ref text) => {
+            state.write_state().add_log(format!("[SCENARIO] {}", text));
+        }
+    }
+}
+
+/// Plays a bundle on a timeline at an adjustable speed, or one event at a time
+/// The caller supplies elapsed wall-clock time, so the player itself never reads a clock.
+pub struct ReplayPlayer {
+    bundle: ReplayBundle,
+    next: usize,
+    position_ms: f64,
+    speed: f64,
+    paused: bool,
+}
+
+impl ReplayPlayer {
+    /// Speeds offered by the interactive player
+    pub const SPEEDS: [f64; 3] = [0.25, 1.0, 4.0];
+
+    pub fn new(bundle: ReplayBundle) -> Self {
+        Self {
+            bundle,
+            next: 0,
+            position_ms: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Position on the recording's timeline
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.position_ms / 1000.0)
+    }
+
+    /// Number of events applied so far
+    pub fn applied(&self) -> usize {
+        self.next
+    }
+
+    pub fn len(&self) -> usize {
+        self.bundle.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bundle.events.is_empty()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.bundle.events.len()
+    }
+
+    /// Events applied so far, oldest first
+    pub fn applied_events(&self) -> &[TimedEvent] {
+        &self.bundle.events[..self.next]
+    }
+
+    /// Next event to be applied
+    pub fn upcoming(&self) -> Option<&TimedEvent> {
+        self.bundle.events.get(self.next)
+    }
+
+    /// Move the timeline on by `elapsed` (scaled by the speed) and apply every event
+    /// that is now due. Does nothing while paused. Returns how many events were applied.
+    pub fn advance(&mut self, state: &SharedTheracState, elapsed: Duration) -> usize {
+        if self.paused || self.is_finished() {
+            return 0;
+        }
+        self.position_ms += elapsed.as_secs_f64() * 1000.0 * self.speed;
+
+        let start = self.next;
+        while let Some(timed) = self.bundle.events.get(self.next) {
+            if timed.at_ms as f64 > self.position_ms {
+                break;
+            }
+            apply_event(state, &timed.event);
+            self.next += 1;
+        }
+        self.next - start
+    }
+
+    /// Apply the next event immediately, jumping the timeline to it
+    pub fn step(&mut self, state: &SharedTheracState) -> Option<&TimedEvent> {
+        let timed = self.bundle.events.get(self.next)?;
+        apply_event(state, &timed.event);
+        self.position_ms = self.position_ms.max(timed.at_ms as f64);
+        self.next += 1;
+        Some(timed)
+    }
+}
+
+/// Stable fingerprint of what happened in a session, for grading and dedup
+/// Covers the ordered incidents (treatment outcomes and their malfunctions, overrides),
+/// the logged transitions and the final dose, but no wall-clock time: log timestamps
+/// are stripped and housekeeper entries, whose timing depends on the scheduler, are
+/// left out. Two runs from the same starting state with the same events match.
+pub struct SessionFingerprint {
+    hash: u64,
+}
+
+impl SessionFingerprint {
+    // 64-bit FNV-1a: fixed across Rust versions, unlike `DefaultHasher`
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    /// Fingerprint of `state` as 16 hex digits
+    pub fn of(state: &TheracState) -> String {
+        let mut fp = Self { hash: Self::OFFSET };
+        for treatment in &state.completed_treatments {
+            fp.field(&format!("{:?}", treatment.outcome));
+            for malfunction in &treatment.malfunctions {
+                fp.field(malfunction);
+            }
+        }
+        for event in &state.overrides {
+            fp.field(&format!("{}|{}|{:.3}", event.kind, event.detail, event.dose_delivered));
+        }
+        for entry in &state.log {
+            if entry.source() != LogSource::Housekeeper {
+                fp.field(&entry.message);
+            }
+        }
+        fp.field(&format!(
+            "{}|{:.3}|{:.3}|{}|{}",
+            state.phase, state.dose_delivered, state.dose_target,
+            state.malfunction_count, state.overdose_count
+        ));
+        format!("{:016x}", fp.hash)
+    }
+
+    fn field(&mut self, text: &str) {
+        // Terminate every field so ["ab", "c"] and ["a", "bc"] differ
+        for byte in text.bytes().chain([0x1f]) {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(Self::PRIME);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_therac_state;
+    use crate::state::{LogEntry, LogLevel, TPhase};
+
+    fn bundle() -> ReplayBundle {
+        ReplayBundle {
+            events: vec![
+                TimedEvent { at_ms: 0, event: ReplayEvent::Mode(BeamType::XRay) },
+                TimedEvent { at_ms: 1_000, event: ReplayEvent::Mode(BeamType::Electron) },
+                TimedEvent { at_ms: 2_000, event: ReplayEvent::Energy(BeamEnergy::E10) },
+                TimedEvent { at_ms: 4_000, event: ReplayEvent::CompleteDataEntry },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_advance_scales_with_speed() {
+        let state = create_therac_state();
+        state.write().phase = TPhase::DataEntry;
+
+        let mut slow = ReplayPlayer::new(bundle());
+        slow.set_speed(0.25);
+        assert_eq!(slow.advance(&state, Duration::from_secs(4)), 2);
+        assert_eq!(state.read().console_meos.beam_type, BeamType::Electron);
+
+        let mut fast = ReplayPlayer::new(bundle());
+        fast.set_speed(4.0);
+        assert_eq!(fast.advance(&state, Duration::from_secs(1)), 4);
+        assert!(fast.is_finished());
+        assert!(state.read().data_entry_complete);
+    }
+
+    #[test]
+    fn test_pause_and_step() {
+        let state = create_therac_state();
+        let mut player = ReplayPlayer::new(bundle());
+        player.toggle_pause();
+        assert_eq!(player.advance(&state, Duration::from_secs(10)), 0);
+
+        assert_eq!(player.step(&state).unwrap().at_ms, 0);
+        assert_eq!(player.step(&state).unwrap().at_ms, 1_000);
+        assert_eq!(player.position(), Duration::from_secs(1));
+        assert_eq!(player.applied(), 2);
+        assert_eq!(state.read().console_meos.beam_type, BeamType::Electron);
+    }
+
+    #[test]
+    fn test_bundle_json_round_trip_sorts_events() {
+        let mut shuffled = bundle();
+        shuffled.events.reverse();
+        let json = shuffled.to_json().unwrap();
+
+        assert_eq!(ReplayBundle::from_json(&json).unwrap(), bundle());
+        assert_eq!(bundle().duration(), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_identical_replays_fingerprint_match() {
+        let start = TheracState::new();
+        let run = |bundle: ReplayBundle| {
+            let state = std::sync::Arc::new(parking_lot::RwLock::new(start.clone()));
+            state.write().phase = TPhase::DataEntry;
+            let mut player = ReplayPlayer::new(bundle);
+            while player.step(&state).is_some() {}
+            let fingerprint = SessionFingerprint::of(&state.read());
+            fingerprint
+        };
+
+        let first = run(bundle());
+        assert_eq!(first.len(), 16);
+        assert_eq!(run(bundle()), first);
+
+        let mut changed = bundle();
+        changed.events[2].event = ReplayEvent::Energy(BeamEnergy::E15);
+        assert_ne!(run(changed), first);
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_timestamps() {
+        let entry = |timestamp: &str| LogEntry {
+            timestamp: timestamp.to_string(),
+            operator_id: None,
+            level: LogLevel::Info,
+            message: "[CONSOLE] Mode set to XRay".to_string(),
+        };
+        let mut a = TheracState::new();
+        a.log = vec![entry("10:00:00")];
+        let mut b = a.clone();
+        b.log = vec![entry("23:59:59")];
+        assert_eq!(SessionFingerprint::of(&a), SessionFingerprint::of(&b));
+    }
+
+    #[test]
+    fn test_example_bundle_loads() {
+        let bundle = ReplayBundle::from_json(include_str!("../examples/replays/quick_edit.json")).unwrap();
+        assert_eq!(bundle.events.last().unwrap().event, ReplayEvent::Treat);
+    }
+}
@@ -0,0 +1,298 @@
+//! Scripted scenarios reproducing documented Therac-25 incidents
+//!
+//! `historical_edit_sequence` drives a machine without the background tasks, through
+//! `tick::step` on a logical clock, so the outcome depends only on the timing passed in,
+//! not on scheduler luck. A `Scenario` is a headless script for CI: operator actions and
+//! waits played against a machine whose monitor and housekeeper are running.
+
+use crate::config::SimConfig;
+use crate::input::{complete_data_entry, handle_mode_input};
+use crate::replay::ReplayEvent;
+use crate::simulator::{start_treatment, treatment_outcome, BENDING_MAGNET_SETTLE_TIME};
+use crate::state::*;
+use crate::locking::LockState;
+use crate::tick;
+use std::time::Duration;
+
+/// Time the bending magnets take to set once the turntable has brought the beam type in
+/// Leveson & Turner (1993): the magnet-setting routine checked for console edits only
+/// when it started, so an edit completed within these ~8 seconds was never seen by the
+/// setup, and the beam was configured from the entry the operator had just corrected.
+pub const HISTORICAL_EDIT_WINDOW: Duration = BENDING_MAGNET_SETTLE_TIME;
+
+/// Logical clock step `historical_edit_sequence` drives the machine by
+const HISTORICAL_TICK: Duration = Duration::from_millis(10);
+
+/// Reproduce the East Texas Cancer Center edit sequence
+/// The operator enters X-ray (25 MeV), completes entry, notices the mistake, moves back
+/// up and changes the mode to Electron, completing the edit `edit_completed_after` the
+/// first entry was completed, then fires once the machine has finished setting up. The
+/// machine runs with the original's magnet timing (`HISTORICAL_EDIT_WINDOW`). An edit
+/// made while the magnets are still settling goes unseen: the turntable follows it to the
+/// electron position but the beam stays set up for 25 MeV X-ray, which fires unflattened.
+/// A later edit is flagged, the operator re-confirms it and the treatment is a normal one.
+pub fn historical_edit_sequence(state: SharedTheracState, edit_completed_after: Duration) -> TreatmentOutcome {
+    {
+        let mut s = state.write_state();
+        s.config.bending_magnet_settle_ms = HISTORICAL_EDIT_WINDOW.as_millis() as u64;
+        s.reset();
+    }
+    run_ticks(&state, SCENARIO_WAIT_LIMIT, |s| s.phase == TPhase::DataEntry);
+
+    // t = 0: X-ray entered by mistake, entry completed
+    handle_mode_input(state.clone(), BeamType::XRay);
+    complete_data_entry(state.clone());
+    run_ticks(&state, edit_completed_after, |_| false);
+
+    // The operator corrects the mode to Electron, and re-confirms if the console asks
+    handle_mode_input(state.clone(), BeamType::Electron);
+    let edit_at = edit_completed_after.as_secs_f64();
+    if state.read_state().editing_taking_place {
+        state.write_state().add_log(format!("[SCENARIO] t={:.1}s: edit flagged, re-confirming", edit_at));
+        complete_data_entry(state.clone());
+    } else {
+        state.write_state().add_log(format!("[SCENARIO] t={:.1}s: edit completed, no re-confirmation asked", edit_at));
+    }
+
+    // Fire once the setup is done and the magnets, energy and turntable have settled
+    run_ticks(&state, SCENARIO_WAIT_LIMIT, setup_settled);
+    let phase = state.read_state().phase;
+    if phase != TPhase::SetupDone {
+        return TreatmentOutcome::NotReady(phase);
+    }
+    start_treatment(state.clone());
+    let mut before = {
+        let s = state.read_state();
+        (s.dose_delivered, s.hardware_meos)
+    };
+    run_ticks(&state, SCENARIO_WAIT_LIMIT, |s| {
+        let treating = matches!(s.phase, TPhase::BeamReady | TPhase::PatientTreatment);
+        if treating {
+            before = (s.dose_delivered, s.hardware_meos);
+        }
+        !treating
+    });
+    let s = state.read_state();
+    treatment_outcome(&s, before.0, before.1)
+}
+
+/// Setup done, with the magnets, the energy system and the turntable at rest: the
+/// operator can fire without tripping an interlock that only asks them to wait
+fn setup_settled(s: &TheracState) -> bool {
+    s.phase == TPhase::SetupDone
+        && !s.bending_magnet_flag
+        && s.energy_settle_remaining_ms == 0
+        && s.hardware_meos.collimator != CollimatorPosition::Transitioning
+}
+
+/// Step the machine tick by tick until `done` holds, for at most `limit`
+fn run_ticks(state: &SharedTheracState, limit: Duration, mut done: impl FnMut(&TheracState) -> bool) {
+    let mut elapsed = Duration::ZERO;
+    while elapsed < limit && !done(&state.read_state()) {
+        tick::step(state.clone(), HISTORICAL_TICK);
+        elapsed += HISTORICAL_TICK;
+    }
+}
+
+/// One step of a headless scenario
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScenarioStep {
+    /// An operator action, applied through the same helpers as a replay
+    Act(ReplayEvent),
+    /// Let the background tasks run
+    Wait(Duration),
+    /// Let the background tasks run until the machine reaches a phase, for at most
+    /// `SCENARIO_WAIT_LIMIT`
+    WaitForPhase(TPhase),
+}
+
+/// Longest a `ScenarioStep::WaitForPhase` waits before the scenario moves on
+pub const SCENARIO_WAIT_LIMIT: Duration = Duration::from_secs(30);
+
+/// A headless script of operator actions and waits
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub steps: Vec<ScenarioStep>,
+    /// Configuration the machine runs the scenario with; `None` keeps its own
+    pub config: Option<SimConfig>,
+}
+
+/// Where a scenario left the machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioResult {
+    pub phase: TPhase,
+    pub malfunction_count: u32,
+    pub overdose_count: u32,
+    /// Total dose delivered (cGy)
+    pub dose_delivered: f64,
+    /// Outcome of the last treatment that ended, if any
+    pub outcome: Option<TreatmentOutcome>,
+}
+
+impl Scenario {
+    pub fn new(name: &str, steps: Vec<ScenarioStep>) -> Self {
+        Self { name: name.to_string(), steps, config: None }
+    }
+
+    /// Run the scenario with `config` in place of the machine's configuration
+    pub fn with_config(mut self, config: SimConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// The Tyler overdose: X-ray entered, corrected to electrons three seconds later
+    /// while the bending magnets were still settling, then fired once the setup was done.
+    /// Runs with the original's magnet timing, so the edit always lands inside
+    /// `HISTORICAL_EDIT_WINDOW` and the overdose happens every time.
+    pub fn therac_accident() -> Self {
+        let config = SimConfig {
+            bending_magnet_settle_ms: HISTORICAL_EDIT_WINDOW.as_millis() as u64,
+            ..SimConfig::default()
+        };
+        Self::new("Tyler, Texas, March 1986", vec![
+            ScenarioStep::Act(ReplayEvent::Narrate(
+                "X-ray entered by mistake, corrected to electrons while the magnets were settling".to_string(),
+            )),
+            ScenarioStep::WaitForPhase(TPhase::DataEntry),
+            ScenarioStep::Act(ReplayEvent::Mode(BeamType::XRay)),
+            ScenarioStep::Act(ReplayEvent::CompleteDataEntry),
+            ScenarioStep::Wait(Duration::from_secs(3)),
+            ScenarioStep::Act(ReplayEvent::Mode(BeamType::Electron)),
+            // The operator waits for the setup, magnets included, before firing
+            ScenarioStep::Wait(HISTORICAL_EDIT_WINDOW),
+            ScenarioStep::Act(ReplayEvent::Treat),
+            ScenarioStep::WaitForPhase(TPhase::PauseTreatment),
+        ])
+        .with_config(config)
+    }
+}
+
+impl ScenarioResult {
+    pub fn of(state: &TheracState) -> Self {
+        Self {
+            phase: state.phase,
+            malfunction_count: state.malfunction_count,
+            overdose_count: state.overdose_count,
+            dose_delivered: state.dose_delivered,
+            outcome: state.last_outcome.clone(),
+        }
+    }
+}
+
+/// Play a scenario against `state` with the monitor and housekeeper running
+/// The tasks are spawned for the scenario and aborted when it ends. On a current-thread
+/// runtime (`#[tokio::test]`'s default) they only run while a step waits, so a scenario
+/// replays the same way every time it is run.
+#[cfg(feature = "standalone")]
+pub async fn run_scenario(state: SharedTheracState, scenario: Scenario) -> ScenarioResult {
+    use crate::simulator::{cleanup_tasks, spawn_treatment_tasks};
+
+    {
+        let mut s = state.write_state();
+        if let Some(config) = &scenario.config {
+            s.config = config.clone();
+        }
+        s.add_log(format!("[SCENARIO] {}", scenario.name));
+    }
+    let tasks = spawn_treatment_tasks(state.clone());
+    for step in &scenario.steps {
+        match step {
+            ScenarioStep::Act(event) => crate::replay::apply_event(&state, event),
+            ScenarioStep::Wait(duration) => tokio::time::sleep(*duration).await,
+            ScenarioStep::WaitForPhase(phase) => {
+                let deadline = tokio::time::Instant::now() + SCENARIO_WAIT_LIMIT;
+                while state.read_state().phase != *phase && tokio::time::Instant::now() < deadline {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            }
+        }
+    }
+    cleanup_tasks(tasks);
+
+    let s = state.read_state();
+    ScenarioResult::of(&s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_therac_state;
+    use crate::simulator::FLATNESS_FILTER_SPREAD;
+
+    #[test]
+    fn test_overdose_only_inside_edit_window() {
+        let inside = create_therac_state();
+        let outcome = historical_edit_sequence(inside.clone(), Duration::from_secs(7));
+        assert!(
+            matches!(outcome, TreatmentOutcome::Overdose { multiplier, .. } if multiplier == FLATNESS_FILTER_SPREAD),
+            "got {:?}", outcome
+        );
+        {
+            let s = inside.read();
+            assert_eq!(s.overdose_count, 1);
+            // The turntable followed the edit; the beam stayed set up for X-ray
+            assert_eq!(s.console_meos.beam_type, BeamType::Electron);
+            assert_eq!(s.hardware_meos, Meos {
+                beam_type: BeamType::XRay,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::OutOfPosition,
+            });
+            assert!(s.log.iter().any(|entry| entry.message.contains("not seen by the setup")));
+        }
+
+        let after = create_therac_state();
+        let outcome = historical_edit_sequence(after.clone(), Duration::from_secs(10));
+        assert!(!matches!(outcome, TreatmentOutcome::Overdose { .. }), "got {:?}", outcome);
+        let s = after.read();
+        assert_eq!(s.overdose_count, 0);
+        assert_eq!(s.missed_edit_setup, None);
+        assert_eq!(s.hardware_meos.beam_type, BeamType::Electron);
+        assert!(s.hardware_meos.is_safe());
+    }
+
+    #[cfg(feature = "standalone")]
+    #[tokio::test(start_paused = true)]
+    async fn test_therac_accident_scenario_overdoses() {
+        let state = create_therac_state();
+        let result = run_scenario(state.clone(), Scenario::therac_accident()).await;
+        assert!(
+            matches!(result.outcome, Some(TreatmentOutcome::Overdose { multiplier, .. }) if multiplier == FLATNESS_FILTER_SPREAD),
+            "got {:?}", result
+        );
+        assert_eq!(result.overdose_count, 1);
+        assert_eq!(result.phase, TPhase::PauseTreatment);
+        assert!(result.dose_delivered > 0.0);
+        let s = state.read();
+        assert_eq!(s.console_meos.beam_type, BeamType::Electron);
+        assert_eq!(s.hardware_meos.beam_type, BeamType::XRay);
+        assert_eq!(s.hardware_meos.collimator, CollimatorPosition::OutOfPosition);
+        assert!(s.log.iter().any(|entry| entry.message.contains("not seen by the setup")));
+    }
+
+    #[cfg(feature = "standalone")]
+    #[tokio::test]
+    async fn test_scenario_drives_the_background_tasks() {
+        let state = create_therac_state();
+        state.write().config.collimator_move_ms = 50;
+        state.write().config.param_sync_ms = 10;
+        let setup = Scenario::new("Electron setup", vec![
+            ScenarioStep::WaitForPhase(TPhase::DataEntry),
+            ScenarioStep::Act(ReplayEvent::Mode(BeamType::Electron)),
+            ScenarioStep::Act(ReplayEvent::Energy(BeamEnergy::E10)),
+            ScenarioStep::Act(ReplayEvent::Dose(20.0)),
+            ScenarioStep::Act(ReplayEvent::CompleteDataEntry),
+            ScenarioStep::WaitForPhase(TPhase::SetupDone),
+            ScenarioStep::Wait(Duration::from_millis(100)),
+        ]);
+
+        let result = run_scenario(state.clone(), setup).await;
+        assert_eq!(result.phase, TPhase::SetupDone);
+        assert_eq!(result.dose_delivered, 0.0);
+        let s = state.read();
+        assert_eq!(s.console_meos.beam_type, BeamType::Electron);
+        for line in ["[SCENARIO] Electron setup", "[CONSOLE] Data entry complete", "[MONITOR] Setup test complete"] {
+            assert!(s.log.iter().any(|entry| entry.message.contains(line)), "missing {}", line);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+//! JSON Schemas for the serialized types
+//!
+//! External tools that write scenario, config or replay files can validate them (and
+//! editors can autocomplete them) against these schemas. They are generated from the
+//! same serde derives that read the files, so they cannot drift from the format.
+
+use crate::config::SimConfig;
+use crate::replay::ReplayBundle;
+use crate::state::TheracState;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+/// Names accepted by `schema`, one per exported type
+pub const SCHEMA_TYPES: [&str; 3] = ["TheracState", "SimConfig", "ReplayBundle"];
+
+/// Schema of the type called `name` (case-insensitive), or `None` if there is none
+pub fn schema(name: &str) -> Option<RootSchema> {
+    match name.to_lowercase().as_str() {
+        "theracstate" => Some(schema_for!(TheracState)),
+        "simconfig" => Some(schema_for!(SimConfig)),
+        "replaybundle" => Some(schema_for!(ReplayBundle)),
+        _ => None,
+    }
+}
+
+/// Schema of the type called `name` as pretty-printed JSON
+pub fn schema_json(name: &str) -> Option<String> {
+    schema(name).map(|schema| serde_json::to_string_pretty(&schema).expect("schemas always serialize"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emitted_schemas_are_valid_json() {
+        for name in SCHEMA_TYPES {
+            let json: serde_json::Value = serde_json::from_str(&schema_json(name).unwrap()).unwrap();
+            assert_eq!(json["title"], name);
+            assert!(json["$schema"].as_str().unwrap().contains("json-schema.org"));
+            assert!(json["properties"].is_object(), "{} has no properties", name);
+        }
+        assert_eq!(schema_json("Messages"), None);
+    }
+
+    #[test]
+    fn test_schema_follows_serde_attributes() {
+        let state: serde_json::Value = serde_json::from_str(&schema_json("therac_state".replace('_', "").as_str()).unwrap()).unwrap();
+        // Skipped fields are not part of the file format
+        assert!(state["properties"]["messages"].is_null());
+        assert!(state["properties"]["console_meos"].is_object());
+
+        let config: serde_json::Value = serde_json::from_str(&schema_json("simconfig").unwrap()).unwrap();
+        assert!(config["properties"]["sync_order"].is_object());
+        // `#[serde(default)]`: every config field may be left out
+        assert!(config["required"].is_null());
+    }
+}
@@ -0,0 +1,135 @@
+//! Verification sensor model
+//!
+//! The check in `zap_the_specimen` trusts a single reading of the hardware MEOS.
+//! Real position sensors are flaky: with `SimConfig::sensor_glitch_probability` a
+//! reading is occasionally stale (the previous pulse's value) or inverted (the
+//! turntable position flipped). One bad sample is enough to mask a real mismatch or
+//! trip a spurious MALFUNCTION 54. With `SimConfig::sensor_majority_vote` the check
+//! takes three samples and uses the value at least two of them agree on.
+
+use crate::config::SimConfig;
+use crate::state::{CollimatorPosition, Meos};
+use rand::Rng;
+
+/// How one reading went wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorGlitch {
+    /// Reported the previous reading instead of the current position
+    Stale,
+    /// Reported the turntable in the opposite position
+    Inverted,
+}
+
+impl SensorGlitch {
+    /// What the sensor reports for `actual` when this glitch hits
+    pub fn apply(self, actual: Meos, previous: Meos) -> Meos {
+        match self {
+            SensorGlitch::Stale => previous,
+            SensorGlitch::Inverted => Meos {
+                collimator: match actual.collimator {
+                    CollimatorPosition::InPosition => CollimatorPosition::OutOfPosition,
+                    CollimatorPosition::OutOfPosition => CollimatorPosition::InPosition,
+                    position @ (CollimatorPosition::Transitioning | CollimatorPosition::Stuck) => position,
+                },
+                ..actual
+            },
+        }
+    }
+}
+
+/// Take one reading of `actual`, glitched with probability `glitch_probability`
+/// `previous` is what a stale reading reports.
+pub fn sample<R: Rng>(actual: Meos, previous: Meos, glitch_probability: f32, rng: &mut R) -> Meos {
+    if glitch_probability <= 0.0 || !rng.gen_bool(glitch_probability.min(1.0) as f64) {
+        return actual;
+    }
+    let glitch = if rng.gen_bool(0.5) {
+        SensorGlitch::Stale
+    } else {
+        SensorGlitch::Inverted
+    };
+    glitch.apply(actual, previous)
+}
+
+/// Value reported by at least two of three samples
+/// With no majority the first sample is returned; a debounced check cannot do better.
+pub fn majority_vote(samples: [Meos; 3]) -> Meos {
+    let [a, b, c] = samples;
+    if a == b || a == c {
+        a
+    } else if b == c {
+        b
+    } else {
+        a
+    }
+}
+
+/// Reading used by the verification check, as configured
+/// A single sample unless `sensor_majority_vote` is set.
+pub fn read_verification<R: Rng>(actual: Meos, previous: Meos, config: &SimConfig, rng: &mut R) -> Meos {
+    let p = config.sensor_glitch_probability;
+    if config.sensor_majority_vote {
+        majority_vote([
+            sample(actual, previous, p, rng),
+            sample(actual, previous, p, rng),
+            sample(actual, previous, p, rng),
+        ])
+    } else {
+        sample(actual, previous, p, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{BeamEnergy, BeamType};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const ELECTRON: Meos = Meos {
+        beam_type: BeamType::Electron,
+        beam_energy: BeamEnergy::E10,
+        collimator: CollimatorPosition::OutOfPosition,
+    };
+
+    #[test]
+    fn test_majority_vote_rejects_a_single_glitch() {
+        let inverted = SensorGlitch::Inverted.apply(ELECTRON, ELECTRON);
+        assert_ne!(inverted, ELECTRON);
+        assert_eq!(majority_vote([inverted, ELECTRON, ELECTRON]), ELECTRON);
+        assert_eq!(majority_vote([ELECTRON, inverted, ELECTRON]), ELECTRON);
+        assert_eq!(majority_vote([ELECTRON, ELECTRON, inverted]), ELECTRON);
+    }
+
+    #[test]
+    fn test_voted_check_misreads_far_less_than_single_sample() {
+        let config = |vote| SimConfig {
+            sensor_glitch_probability: 0.1,
+            sensor_majority_vote: vote,
+            ..SimConfig::default()
+        };
+        let previous = Meos { beam_type: BeamType::XRay, ..ELECTRON };
+        let misreads = |vote| {
+            let mut rng = StdRng::seed_from_u64(1184);
+            (0..2_000)
+                .filter(|_| read_verification(ELECTRON, previous, &config(vote), &mut rng) != ELECTRON)
+                .count()
+        };
+
+        let single = misreads(false);
+        let voted = misreads(true);
+        // ~10% of single samples are wrong; a vote fails only when two of three glitch
+        // the same way or all three disagree (~2.5%)
+        assert!(single > 150, "single-sample misreads: {}", single);
+        assert!(voted * 3 < single, "voted {} vs single {}", voted, single);
+    }
+
+    #[test]
+    fn test_no_glitches_by_default() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let previous = Meos { beam_type: BeamType::XRay, ..ELECTRON };
+        for _ in 0..100 {
+            assert_eq!(read_verification(ELECTRON, previous, &SimConfig::default(), &mut rng), ELECTRON);
+        }
+    }
+}
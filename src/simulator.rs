@@ -3,12 +3,23 @@
 //! This module implements the concurrent tasks that manage the Therac-25 treatment process:
 //! - Treatment monitor: manages the state machine through treatment phases
 //! - Housekeeper: synchronizes hardware collimator position with console settings
+//! - Phantom operator (optional): a colleague who nudges console entries at random
+//! - Bending magnet settler: clears `bending_magnet_flag` once the magnets have settled
 //! - The critical race condition in zap_the_specimen()
 
 use crate::state::*;
+use crate::locking::LockState;
+use crate::sensor::read_verification;
+use crate::messages::MessageKey;
+use crate::config::{SyncOrder, RANDOM_FAULT_PROBABILITY};
 use std::time::Duration;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
+#[cfg(feature = "standalone")]
+use crate::alarm::AlarmSink;
+#[cfg(feature = "standalone")]
+use std::sync::Arc;
 #[cfg(feature = "standalone")]
 use tokio::task::JoinHandle;
 
@@ -33,11 +44,28 @@ async fn sleep(_duration: Duration) {
     // No-op for embeddable mode - external integrator provides their own runtime
 }
 
+/// How often a paused task checks whether the simulation has been resumed
+const PAUSE_POLL: Duration = Duration::from_millis(50);
+
+/// Sleep for `duration`, then hold for as long as the simulation is paused
+/// Every background task sleeps through this, so none acts on the state while paused.
+async fn task_sleep(state: &SharedTheracState, duration: Duration) {
+    sleep(duration).await;
+    while state.read_state().simulation_paused {
+        sleep(PAUSE_POLL).await;
+    }
+}
+
 /// Task handles for background simulator tasks
 #[cfg(feature = "standalone")]
 pub struct TheracTaskHandles {
     pub treatment_monitor: JoinHandle<()>,
     pub housekeeper: JoinHandle<()>,
+    pub phantom_operator: JoinHandle<()>,
+    pub hardware_watchdog: JoinHandle<()>,
+    pub bending_magnet_settler: JoinHandle<()>,
+    /// Passes alarms to the embedder's sink; only with `spawn_treatment_tasks_with_alarms`
+    pub alarm_dispatcher: Option<JoinHandle<()>>,
 }
 
 /// Spawn background tasks for the simulator
@@ -54,196 +82,604 @@ pub fn spawn_treatment_tasks(state: SharedTheracState) -> TheracTaskHandles {
         housekeeper(state_clone).await;
     });
 
+    let state_clone = state.clone();
+    let phantom_handle = tokio::spawn(async move {
+        phantom_operator(state_clone).await;
+    });
+
+    let state_clone = state.clone();
+    let watchdog_handle = tokio::spawn(async move {
+        hardware_watchdog(state_clone).await;
+    });
+
+    let state_clone = state.clone();
+    let magnet_handle = tokio::spawn(async move {
+        bending_magnet_settler(state_clone).await;
+    });
+
     TheracTaskHandles {
         treatment_monitor: treatment_handle,
         housekeeper: housekeeper_handle,
+        phantom_operator: phantom_handle,
+        hardware_watchdog: watchdog_handle,
+        bending_magnet_settler: magnet_handle,
+        alarm_dispatcher: None,
     }
 }
 
+/// Spawn the background tasks, and an alarm dispatcher telling `sink` about every
+/// malfunction and completed dose
+#[cfg(feature = "standalone")]
+pub fn spawn_treatment_tasks_with_alarms(state: SharedTheracState, sink: Arc<dyn AlarmSink>) -> TheracTaskHandles {
+    let mut handles = spawn_treatment_tasks(state.clone());
+    handles.alarm_dispatcher = Some(tokio::spawn(crate::alarm::alarm_dispatcher(state, sink)));
+    handles
+}
+
 /// Cleanup tasks when exiting simulator
 /// Call this when leaving the Therac-25 screen or shutting down
 #[cfg(feature = "standalone")]
 pub fn cleanup_tasks(handles: TheracTaskHandles) {
     handles.treatment_monitor.abort();
     handles.housekeeper.abort();
+    handles.phantom_operator.abort();
+    handles.hardware_watchdog.abort();
+    handles.bending_magnet_settler.abort();
+    if let Some(dispatcher) = handles.alarm_dispatcher {
+        dispatcher.abort();
+    }
 }
 
+/// Time between treatment monitor passes
+pub const MONITOR_POLL: Duration = Duration::from_micros(1666);
+
 /// Treatment monitor task
-/// Manages the treatment state machine, cycling through phases
+/// Manages the treatment state machine, cycling through phases: the tick driver's
+/// monitor moved on by one poll per pass, with the time in each phase charged on the
+/// wall clock (`TheracState::track_phase`)
 pub async fn treatment_monitor(state: SharedTheracState) {
     loop {
-        sleep(Duration::from_micros(1666)).await; // ~60Hz polling
+        task_sleep(&state, MONITOR_POLL).await;
 
-        let current_phase = {
-            let s = state.read();
-            s.phase
-        };
+        let mut s = state.write_state_logged("[MONITOR]");
+        s.track_phase();
+        crate::tick::monitor_advance(&mut s, MONITOR_POLL);
+    }
+}
 
-        match current_phase {
-            TPhase::Reset => handle_reset(state.clone()).await,
-            TPhase::DataEntry => handle_data_entry(state.clone()).await,
-            TPhase::SetupTest => handle_setup_test(state.clone()).await,
-            TPhase::SetupDone => handle_setup_done(state.clone()).await,
-            TPhase::PatientTreatment => handle_patient_treatment(state.clone()).await,
-            TPhase::PauseTreatment => handle_pause_treatment(state.clone()).await,
-            TPhase::TerminateTreatment => handle_terminate_treatment(state.clone()).await,
-            TPhase::DateTimeIdChanges => handle_datetime_changes(state.clone()).await,
-        }
+/// Activity the treatment monitor publishes for a phase
+pub(crate) fn monitor_activity(phase: TPhase) -> &'static str {
+    match phase {
+        TPhase::Reset => "handle_reset",
+        TPhase::DataEntry => "handle_data_entry",
+        TPhase::SetupTest => "handle_setup_test",
+        TPhase::SetupDone => "handle_setup_done",
+        TPhase::BeamReady => "handle_beam_ready",
+        TPhase::PatientTreatment => "handle_patient_treatment",
+        TPhase::PauseTreatment => "handle_pause_treatment",
+        TPhase::TerminateTreatment => "handle_terminate_treatment",
+        TPhase::EmergencyStop => "handle_emergency_stop",
+        TPhase::DateTimeIdChanges => "handle_datetime_changes",
     }
 }
 
+/// Time between housekeeper passes
+pub const HOUSEKEEPER_POLL: Duration = Duration::from_millis(10);
+
 /// Housekeeper task
-/// Continuously synchronizes collimator position between console and hardware settings
+/// Continuously synchronizes collimator position between console and hardware settings,
+/// one `housekeeper_tick` per poll
 /// This task runs concurrently and can modify hardware_meos, creating the race condition
 pub async fn housekeeper(state: SharedTheracState) {
     loop {
-        sleep(Duration::from_micros(1666)).await; // ~60Hz polling
+        task_sleep(&state, HOUSEKEEPER_POLL).await;
 
-        sync_collimator(state.clone()).await;
+        crate::tick::housekeeper_tick(&state, HOUSEKEEPER_POLL);
     }
 }
 
-/// Synchronize collimator position and other hardware parameters
-/// This is the concurrent task that creates the race condition with zap_the_specimen
-async fn sync_collimator(state: SharedTheracState) {
-    let needs_sync = {
-        let s = state.read();
-        // Only sync if not in critical treatment phase
-        s.phase != TPhase::PatientTreatment && s.console_meos.needs_collimator_sync()
-    };
+/// Pause (ms) between the phantom operator's visits to the console
+const PHANTOM_INTERVAL_MS: std::ops::RangeInclusive<u64> = 500..=3000;
 
-    if needs_sync {
-        let (console_beam_type, current_collimator) = {
-            let s = state.read();
-            (s.console_meos.beam_type, s.hardware_meos.collimator)
-        };
+/// Phantom operator task
+/// While `config.phantom_operator` is set, a colleague reaches over the console at
+/// random intervals and nudges one entry. The edit lands whatever the monitor and
+/// housekeeper are doing, so it can open a mismatch nobody at the console made.
+pub async fn phantom_operator(state: SharedTheracState) {
+    // Its own stream, forked from the session's so a seeded session seeds it too
+    let mut rng = StdRng::seed_from_u64(state.write_state().rng.gen());
+    loop {
+        if state.read_state().config.phantom_operator {
+            phantom_nudge(&state, &mut rng);
+        } else {
+            publish_phantom_activity(&state, "idle");
+        }
+        task_sleep(&state, Duration::from_millis(rng.gen_range(PHANTOM_INTERVAL_MS))).await;
+    }
+}
 
-        // Simulate collimator movement delay
-        if current_collimator != CollimatorPosition::Transitioning {
-            {
-                let mut s = state.write();
-                s.hardware_meos.collimator = CollimatorPosition::Transitioning;
-                s.add_log("Collimator moving...".to_string());
+/// One visit by the phantom operator: change a random console entry
+/// Only in phases where the console takes edits; returns whether anything changed.
+pub fn phantom_nudge<R: Rng>(state: &SharedTheracState, rng: &mut R) -> bool {
+    let mut s = state.write_state();
+    if !matches!(
+        s.phase,
+        TPhase::DataEntry
+            | TPhase::SetupTest
+            | TPhase::SetupDone
+            | TPhase::BeamReady
+            | TPhase::PatientTreatment
+            | TPhase::PauseTreatment
+    ) {
+        return false;
+    }
+
+    let (field, change) = match rng.gen_range(0..4) {
+        0 => {
+            let mode = if s.console_meos.beam_type == BeamType::XRay {
+                BeamType::Electron
+            } else {
+                BeamType::XRay
+            };
+            s.console_meos.beam_type = mode;
+            if mode == BeamType::XRay && s.config.auto_max_energy_on_xray {
+                s.set_console_energy(BeamEnergy::E25);
             }
+            ("mode", format!("Mode set to {:?}", mode))
+        }
+        1 => {
+            let energy = BeamEnergy::ALL[rng.gen_range(0..BeamEnergy::ALL.len())];
+            s.set_console_energy(energy);
+            ("energy", format!("Energy set to {}", energy))
+        }
+        2 => {
+            let delta = rng.gen_range(1..=10);
+            let angle = if rng.gen_bool(0.5) {
+                (s.console_params.gantry_angle + delta) % 360
+            } else {
+                (s.console_params.gantry_angle + 360 - delta) % 360
+            };
+            s.console_params.gantry_angle = angle;
+            ("gantry", format!("Gantry angle set to {} deg", angle))
         }
+        _ => {
+            let dose = if rng.gen_bool(0.5) { s.dose_target + 10.0 } else { (s.dose_target - 10.0).max(10.0) };
+            s.set_dose_target(dose);
+            ("dose", format!("Dose set to {:.1} cGy", dose))
+        }
+    };
+    s.mark_console_edit();
+    s.task_status.phantom_operator = format!("nudged {}", field);
+    s.add_log(format!("[CONSOLE] Phantom operator: {}", change));
+    true
+}
+
+/// Publish the phantom operator's current activity for the UI
+fn publish_phantom_activity(state: &SharedTheracState, activity: &str) {
+    if state.read_state().task_status.phantom_operator != activity {
+        state.write_state().task_status.phantom_operator = activity.to_string();
+    }
+}
 
-        // Simulate physical movement time (magnet hysteresis + mechanical delays)
-        // Real Therac-25 had ~100ms, but we use 800ms to make the race window
-        // more educational while still being realistic
-        sleep(Duration::from_millis(800)).await;
+/// Poll interval of the hardware watchdog
+const WATCHDOG_POLL: Duration = Duration::from_millis(50);
 
-        // Move to target position
-        let target_position = match console_beam_type {
-            BeamType::XRay => CollimatorPosition::InPosition,
-            BeamType::Electron => CollimatorPosition::OutOfPosition,
-            BeamType::Undefined => CollimatorPosition::OutOfPosition,
+/// Hardware watchdog task
+/// While `config.hardware_watchdog` is set, an independent timer watches the turntable.
+/// The primary logic trusts the housekeeper to finish every move; a jammed turntable
+/// never does, and only a watchdog notices.
+pub async fn hardware_watchdog(state: SharedTheracState) {
+    let mut watchdog = CollimatorWatchdog::default();
+    loop {
+        task_sleep(&state, WATCHDOG_POLL).await;
+        watchdog.check(&state, WATCHDOG_POLL);
+    }
+}
+
+/// How long the turntable has been moving, on the watchdog's own clock
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CollimatorWatchdog {
+    moving_for: Duration,
+}
+
+impl CollimatorWatchdog {
+    /// Longest the turntable may keep moving: twice a move that is reversed at the
+    /// last moment (transit plus hysteresis), on the machine as worn as it is
+    pub fn limit(state: &TheracState) -> Duration {
+        let hysteresis = Duration::from_millis(state.config.collimator_hysteresis_ms as u64);
+        2 * (state.worn(state.config.collimator_move_time()) + hysteresis)
+    }
+
+    /// Count `elapsed` towards the current move and trip COLLIMATOR TIMEOUT once it is
+    /// over `limit`: the beam is inhibited and a treatment in progress is paused.
+    /// Returns true when it trips.
+    pub fn check(&mut self, state: &SharedTheracState, elapsed: Duration) -> bool {
+        let (enabled, moving, latched, limit) = {
+            let s = state.read_state();
+            (
+                s.config.hardware_watchdog,
+                s.hardware_meos.collimator == CollimatorPosition::Transitioning,
+                s.collimator_timeout,
+                Self::limit(&s),
+            )
         };
+        if !enabled || !moving || latched {
+            self.moving_for = Duration::ZERO;
+            publish_watchdog_activity(state, if !enabled { "idle" } else if latched { "TRIPPED" } else { "watching" });
+            return false;
+        }
 
-        {
-            let mut s = state.write();
-            s.hardware_meos.collimator = target_position;
-            // Also sync beam type and energy during collimator movement
-            s.hardware_meos.beam_type = s.console_meos.beam_type;
-            s.hardware_meos.beam_energy = s.console_meos.beam_energy;
-            let beam_type = s.hardware_meos.beam_type;
-            let beam_energy = s.hardware_meos.beam_energy;
-            s.add_log(format!("Hardware synced: {} @ {} with collimator {}",
-                beam_type,
-                beam_energy,
-                target_position));
+        self.moving_for += elapsed;
+        if self.moving_for <= limit {
+            publish_watchdog_activity(state, &format!(
+                "collimator moving {} / {} ms",
+                self.moving_for.as_millis(),
+                limit.as_millis()
+            ));
+            return false;
+        }
+
+        let mut s = state.write_state();
+        s.collimator_timeout = true;
+        s.malfunction_count += 1;
+        let message = s.messages.format(MessageKey::CollimatorTimeout, &[&self.moving_for.as_millis()]);
+        s.report_malfunction(MalfunctionKind::CollimatorTimeout, message);
+        if matches!(s.phase, TPhase::PatientTreatment | TPhase::BeamReady) {
+            s.phase = TPhase::PauseTreatment;
         }
+        s.task_status.watchdog = "TRIPPED".to_string();
+        self.moving_for = Duration::ZERO;
+        true
     }
+}
 
-    // Also sync other hardware parameters (gantry, field size, etc.)
-    // This happens continuously and more slowly
-    let params_need_sync = {
-        let s = state.read();
-        s.phase != TPhase::PatientTreatment && s.console_params != s.hardware_params
-    };
+/// Publish the hardware watchdog's current activity for the UI
+fn publish_watchdog_activity(state: &SharedTheracState, activity: &str) {
+    if state.read_state().task_status.watchdog != activity {
+        state.write_state().task_status.watchdog = activity.to_string();
+    }
+}
 
-    if params_need_sync {
-        // Simulate mechanical movement delays for gantry, collimator rotation, etc.
-        sleep(Duration::from_millis(200)).await;
+/// Time the real bending magnets took to settle after a beam type switch, the ~8 s
+/// during which the original's edit check famously looked away. Not the default:
+/// see `SimConfig::bending_magnet_settle_ms`.
+pub const BENDING_MAGNET_SETTLE_TIME: Duration = Duration::from_secs(8);
 
-        let mut s = state.write();
-        s.hardware_params = s.console_params;
+/// Default time for the energy system to settle after the energy goes up
+/// (`SimConfig::energy_settle_ms`)
+pub const ENERGY_SETTLE_TIME: Duration = Duration::from_millis(250);
+
+/// How often the settler counts down the bending magnet settle time
+pub const MAGNET_SETTLE_POLL: Duration = Duration::from_millis(50);
+
+/// Bending magnet settler task
+/// Counts down `bending_magnet_settle_remaining_ms` while the magnets settle and clears
+/// `bending_magnet_flag` when they have. Counts down the energy system settling too.
+pub async fn bending_magnet_settler(state: SharedTheracState) {
+    loop {
+        task_sleep(&state, MAGNET_SETTLE_POLL).await;
+        settle_bending_magnets(&state, MAGNET_SETTLE_POLL);
+        settle_energy(&state, MAGNET_SETTLE_POLL);
     }
 }
 
-/// Handle reset phase
-async fn handle_reset(state: SharedTheracState) {
-    sleep(Duration::from_millis(100)).await;
+/// Count `elapsed` towards the energy system settling; returns true when it settles
+pub fn settle_energy(state: &SharedTheracState, elapsed: Duration) -> bool {
+    if state.read_state().energy_settle_remaining_ms == 0 {
+        return false;
+    }
+    let mut s = state.write_state();
+    let elapsed_ms = elapsed.as_millis().min(u32::MAX as u128) as u32;
+    s.energy_settle_remaining_ms = s.energy_settle_remaining_ms.saturating_sub(elapsed_ms);
+    if s.energy_settle_remaining_ms > 0 {
+        return false;
+    }
+    let energy = s.console_meos.beam_energy;
+    s.add_log(format!("[HOUSEKEEPER] Energy system settled at {}", energy));
+    true
+}
 
-    let mut s = state.write();
-    s.phase = TPhase::DataEntry;
-    s.add_log("Entering data entry mode".to_string());
+/// Count `elapsed` towards the magnets settling; returns true when they settle
+pub fn settle_bending_magnets(state: &SharedTheracState, elapsed: Duration) -> bool {
+    if !state.read_state().bending_magnet_flag {
+        return false;
+    }
+    let mut s = state.write_state();
+    let elapsed_ms = elapsed.as_millis().min(u32::MAX as u128) as u32;
+    s.bending_magnet_settle_remaining_ms = s.bending_magnet_settle_remaining_ms.saturating_sub(elapsed_ms);
+    if s.bending_magnet_settle_remaining_ms > 0 {
+        return false;
+    }
+    s.bending_magnet_flag = false;
+    s.add_log("[HOUSEKEEPER] Bending magnets settled".to_string());
+    true
 }
 
-/// Handle data entry phase
-async fn handle_data_entry(state: SharedTheracState) {
-    let data_complete = {
-        let s = state.read();
-        s.data_entry_complete
+/// Start setting the bending magnets for the hardware beam type, if settling takes any time
+fn set_bending_magnets(s: &mut TheracState) {
+    let settle_ms = s.config.bending_magnet_settle_ms;
+    if settle_ms == 0 {
+        return;
+    }
+    s.bending_magnet_flag = true;
+    s.bending_magnet_settle_remaining_ms = settle_ms.min(u32::MAX as u64) as u32;
+    let beam_type = s.hardware_meos.beam_type;
+    s.add_log(format!("[HOUSEKEEPER] Setting bending magnets for {} ({} ms to settle)", beam_type, settle_ms));
+}
+
+/// Default time for the turntable to move between positions (`SimConfig::collimator_move_ms`)
+/// Real Therac-25 had ~100ms, but we use 800ms to make the race window
+/// more educational while still being realistic.
+pub const COLLIMATOR_TRANSIT_TIME: Duration = Duration::from_millis(800);
+
+/// A turntable move in progress, on the housekeeper's logical clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CollimatorTransit {
+    /// Beam type the turntable is heading for
+    pub target: BeamType,
+    /// Times the move was reversed before arriving
+    pub reversals: u32,
+    /// Time for one full move
+    transit: Duration,
+    elapsed: Duration,
+    remaining: Duration,
+}
+
+impl CollimatorTransit {
+    pub fn start(target: BeamType) -> Self {
+        Self::with_transit_time(target, COLLIMATOR_TRANSIT_TIME)
+    }
+
+    /// Start a move taking `transit` instead of `COLLIMATOR_TRANSIT_TIME` (a worn machine)
+    pub fn with_transit_time(target: BeamType, transit: Duration) -> Self {
+        Self {
+            target,
+            reversals: 0,
+            transit,
+            elapsed: Duration::ZERO,
+            remaining: transit,
+        }
+    }
+
+    /// Move on by `step`
+    pub fn advance(&mut self, step: Duration) {
+        let step = step.min(self.remaining);
+        self.elapsed += step;
+        self.remaining -= step;
+    }
+
+    /// Head for `target` instead; returns true if that reverses the move
+    /// A reversal restarts the transit and adds `hysteresis` on top: the mechanism
+    /// has to stop and take up its play before it can move the other way.
+    pub fn retarget(&mut self, target: BeamType, hysteresis: Duration) -> bool {
+        if target == self.target {
+            return false;
+        }
+        self.target = target;
+        self.reversals += 1;
+        self.remaining = self.transit + hysteresis;
+        true
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.remaining.is_zero()
+    }
+
+    /// Time spent moving so far
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Time left until arrival
+    pub fn remaining(&self) -> Duration {
+        self.remaining
+    }
+
+    /// Expected time from the start of the move to arrival
+    pub fn total(&self) -> Duration {
+        self.elapsed + self.remaining
+    }
+}
+
+/// Default time the housekeeper takes to move gantry, field size and the other treatment
+/// params (`SimConfig::param_sync_ms`)
+pub const PARAM_SYNC_TIME: Duration = Duration::from_millis(200);
+
+/// How long the operator should wait before the hardware has caught up with the console
+/// Counts the rest of the turntable move in progress (or a whole move the housekeeper
+/// is about to start) plus a pending param sync. `None` when nothing is pending: the
+/// hardware is already synced, or the housekeeper will not sync it (during treatment).
+pub fn time_until_safe_to_treat(state: &TheracState) -> Option<Duration> {
+    if state.phase == TPhase::PatientTreatment {
+        return None;
+    }
+    let console = state.console_meos;
+    let hardware = state.hardware_meos;
+
+    let mut wait = Duration::ZERO;
+    if hardware.collimator == CollimatorPosition::Transitioning {
+        wait += match state.collimator_transit_remaining_ms {
+            // Not published yet: the housekeeper is only about to start the move
+            0 => state.worn(state.config.collimator_move_time()),
+            ms => Duration::from_millis(ms as u64),
+        };
+    } else if state.collimator_sync_needed()
+        && (hardware.beam_type != console.beam_type
+            || hardware.beam_energy != console.beam_energy
+            || !hardware.is_safe())
+    {
+        wait += state.worn(state.config.collimator_move_time());
+    }
+    if state.console_params != state.hardware_params {
+        wait += state.worn(state.config.param_sync_time());
+    }
+
+    (!wait.is_zero()).then_some(wait)
+}
+
+/// Start a turntable move: the hardware shows `Transitioning`, with whatever
+/// `sync_order` syncs ahead of the collimator already switched
+pub(crate) fn begin_collimator_move(s: &mut TheracState, sync_order: &SyncOrder) {
+    let before = s.hardware_meos;
+    s.hardware_meos = sync_order.transient(s.setup_meos(), before);
+    let transient = s.hardware_meos;
+    if transient.beam_type != before.beam_type || transient.beam_energy != before.beam_energy {
+        s.add_log(format!(
+            "[HOUSEKEEPER] Synced ahead of the collimator: {} @ {}",
+            transient.beam_type, transient.beam_energy
+        ));
+    }
+    if transient.beam_type != before.beam_type {
+        set_bending_magnets(s);
+    }
+    s.add_log("[HOUSEKEEPER] Collimator moving...".to_string());
+}
+
+/// Log a turntable move reversed for `beam_type` (`CollimatorTransit::retarget`)
+pub(crate) fn log_collimator_reversal(s: &mut TheracState, beam_type: BeamType, hysteresis_ms: u32) {
+    s.add_log(format!(
+        "[HOUSEKEEPER] Collimator reversing mid-move for {} (+{} ms hysteresis)",
+        beam_type, hysteresis_ms
+    ));
+}
+
+/// Finish a turntable move towards `target`, syncing beam type and energy with it
+/// With `SimConfig::collimator_stick_probability` the move may jam instead, leaving
+/// the turntable `Stuck` short of its target. After a missed edit the beam type and
+/// energy stay as the setup has them (`TheracState::setup_meos`).
+pub(crate) fn complete_collimator_move(s: &mut TheracState, target: BeamType) {
+    s.collimator_transit_remaining_ms = 0;
+    // `clamp` passes NaN through, and `gen_bool` panics on it: a NaN never sticks
+    let stick_probability = match s.config.collimator_stick_probability {
+        p if p.is_nan() => 0.0,
+        p => p.clamp(0.0, 1.0) as f64,
     };
+    if s.rng.gen_bool(stick_probability) {
+        s.hardware_meos.collimator = CollimatorPosition::Stuck;
+        s.add_log_with_level(LogLevel::Warn, "[HOUSEKEEPER] WARNING: Collimator stuck mid-rotation - free the turntable to continue".to_string());
+        return;
+    }
+    let target_position = target.collimator_position();
+    s.hardware_meos.collimator = target_position;
+    // Also sync beam type and energy during collimator movement
+    let previous_beam_type = s.hardware_meos.beam_type;
+    let setup = s.setup_meos();
+    s.hardware_meos.beam_type = setup.beam_type;
+    s.hardware_meos.beam_energy = setup.beam_energy;
+    let beam_type = s.hardware_meos.beam_type;
+    let beam_energy = s.hardware_meos.beam_energy;
+    s.add_log(format!("[HOUSEKEEPER] Hardware synced: {} @ {} with collimator {}",
+        beam_type,
+        beam_energy,
+        target_position));
+    if beam_type != previous_beam_type {
+        set_bending_magnets(s);
+    }
+}
 
-    if data_complete {
-        let mut s = state.write();
-        s.phase = TPhase::SetupTest;
-        s.class3 = 0;
-        s.add_log("Data entry complete, starting setup test".to_string());
+/// Time the monitor takes to leave Reset, TerminateTreatment or DateTimeIdChanges
+const PHASE_CHANGE_TIME: Duration = Duration::from_millis(100);
+
+/// One setup test pass on a new machine
+const SETUP_TEST_PASS_TIME: Duration = Duration::from_millis(50);
+
+/// Time the treatment monitor spends in one pass in `phase` before it acts
+pub(crate) fn monitor_pass_time(s: &TheracState, phase: TPhase) -> Duration {
+    match phase {
+        TPhase::Reset | TPhase::TerminateTreatment | TPhase::DateTimeIdChanges => PHASE_CHANGE_TIME,
+        // Magnets settle more slowly on a worn machine
+        TPhase::SetupTest => s.worn(SETUP_TEST_PASS_TIME),
+        TPhase::BeamReady => BEAM_HOLD_TICK,
+        // No pulse is ever due at a zero dose rate, nor at one too slow for a Duration
+        TPhase::PatientTreatment => match pulse_repetition_rate(s) {
+            Some(hz) if hz > 0.0 => Duration::try_from_secs_f64(1.0 / hz).unwrap_or(Duration::MAX),
+            Some(_) => Duration::MAX,
+            None => Duration::ZERO,
+        },
+        _ => Duration::ZERO,
     }
 }
 
-/// Handle setup test phase
-async fn handle_setup_test(state: SharedTheracState) {
-    sleep(Duration::from_millis(50)).await;
+/// Pulses per second with `SimConfig::pulse_repetition_hz` set: the configured PRF
+/// scaled by the hardware dose rate. `None` when pulses are not spaced in time.
+pub fn pulse_repetition_rate(s: &TheracState) -> Option<f64> {
+    let prf = s.config.pulse_repetition_hz;
+    (prf > 0.0).then(|| prf * s.hardware_params.dose_rate.max(0.0) as f64 / REFERENCE_DOSE_RATE)
+}
+
+/// Beam-on time one pulse stands for: the pulse period, or one monitor pass when
+/// pulses are not spaced in time
+fn pulse_period(s: &TheracState) -> Duration {
+    match monitor_pass_time(s, TPhase::PatientTreatment) {
+        period if period.is_zero() => MONITOR_POLL,
+        period => period,
+    }
+}
+
+/// Leave Reset for data entry
+pub(crate) fn finish_reset(s: &mut TheracState) {
+    s.phase = TPhase::DataEntry;
+    s.add_log("[MONITOR] Entering data entry mode".to_string());
+}
+
+/// Data entry is complete: start the setup test
+pub(crate) fn begin_setup_test(s: &mut TheracState) {
+    s.phase = TPhase::SetupTest;
+    s.class3 = 0;
+    s.add_log("[MONITOR] Data entry complete, starting setup test".to_string());
+}
 
-    let mut s = state.write();
+/// One setup test pass; setup is done after the eleventh
+pub(crate) fn setup_test_pass(s: &mut TheracState) {
     s.class3 = s.class3.wrapping_add(1);
 
     // After several iterations, move to setup done
     if s.class3 > 10 {
         s.phase = TPhase::SetupDone;
-        s.add_log("Setup test complete".to_string());
+        s.add_log("[MONITOR] Setup test complete".to_string());
     }
 }
 
-/// Handle setup done phase
-async fn handle_setup_done(_state: SharedTheracState) {
-    // Wait for external trigger to start treatment
-    // In the TUI, this will be triggered by operator action
-}
+/// Step in which the beam hold counts down
+const BEAM_HOLD_TICK: Duration = Duration::from_millis(100);
 
-/// Handle patient treatment phase
-/// This is where the critical beam delivery happens
-async fn handle_patient_treatment(state: SharedTheracState) {
-    zap_the_specimen(state.clone()).await;
+/// Count one `BEAM_HOLD_TICK` off the beam hold and turn the beam on when it runs out
+pub(crate) fn beam_hold_pass(s: &mut TheracState) {
+    // The operator may have aborted while we slept
+    if s.phase != TPhase::BeamReady {
+        return;
+    }
+    s.beam_hold_remaining_ms = s
+        .beam_hold_remaining_ms
+        .saturating_sub(BEAM_HOLD_TICK.as_millis() as u32);
+    if s.beam_hold_remaining_ms == 0 {
+        s.beam_on();
+        s.add_log("[MONITOR] Beam hold elapsed - beam on".to_string());
+    }
 }
 
-/// Handle pause treatment phase
-async fn handle_pause_treatment(_state: SharedTheracState) {
-    // Treatment is paused, waiting for operator action
+/// One pulse through `zap_the_specimen_safe` in safe mode, `zap_the_specimen` otherwise
+async fn zap_as_configured(state: SharedTheracState) {
+    if state.read_state().config.safe_mode {
+        zap_the_specimen_safe(state).await;
+    } else {
+        zap_the_specimen(state).await;
+    }
 }
 
-/// Handle terminate treatment phase
-async fn handle_terminate_treatment(state: SharedTheracState) {
-    sleep(Duration::from_millis(100)).await;
-
-    let mut s = state.write();
+/// Log the dose delivered and go back to Reset
+pub(crate) fn finish_termination(s: &mut TheracState) {
     let dose_delivered = s.dose_delivered;
     let dose_target = s.dose_target;
     s.add_log(format!(
-        "Treatment terminated. Dose delivered: {:.1}/{:.1} cGy",
+        "[MONITOR] Treatment terminated. Dose delivered: {:.1}/{:.1} cGy",
         dose_delivered, dose_target
     ));
     s.phase = TPhase::Reset;
 }
 
-/// Handle date/time/ID changes phase
-async fn handle_datetime_changes(state: SharedTheracState) {
-    sleep(Duration::from_millis(100)).await;
-
-    let mut s = state.write();
+/// Go back to data entry
+pub(crate) fn finish_datetime_changes(s: &mut TheracState) {
     s.phase = TPhase::DataEntry;
 }
 
@@ -263,14 +699,11 @@ async fn handle_datetime_changes(state: SharedTheracState) {
 /// 4. But the hardware was still in X-ray mode with high-energy beam
 /// 5. Without the flatness filter to spread the beam, patients received 100x the intended dose
 pub async fn zap_the_specimen(state: SharedTheracState) {
-    // Simulate random hardware reliability issues
-    let really_good_number: u32 = rand::thread_rng().gen_range(12..=53);
-
     // CRITICAL BUG: Read state outside the atomic operation
     // This creates a check-then-act race condition
     let (console_meos, hardware_meos) = {
-        let s = state.read();
-        (s.console_meos, s.hardware_meos)
+        let s = state.read_state();
+        (s.setup_meos(), s.hardware_meos)
     };
 
     // Small delay to increase chance of race condition manifesting
@@ -278,21 +711,135 @@ pub async fn zap_the_specimen(state: SharedTheracState) {
 
     // CRITICAL SECTION: Check if parameters match
     // But hardware_meos might have changed since we read it above!
-    let mut s = state.write();
+    let mut s = state.write_state_logged("[MONITOR]");
+    // Simulate random hardware reliability issues
+    let fault_roll = random_fault_roll(&mut s);
+    verify_and_fire(&mut s, console_meos, hardware_meos, fault_roll);
+}
+
+/// ZAP THE SPECIMEN, without the race
+/// The fix for `zap_the_specimen`: the hardware is read inside the same write-lock
+/// acquisition that checks it and fires, so the housekeeper cannot move anything
+/// between check and act. A configuration that is unsafe in itself is refused too,
+/// rather than trusted because the console asked for it.
+pub async fn zap_the_specimen_safe(state: SharedTheracState) {
+    zap_safe_locked(&mut state.write_state_logged("[MONITOR]"));
+}
+
+/// Draw the roll (0.0-1.0) that decides a random hardware fault: the pulse faults if it
+/// falls below `SimConfig::random_fault_probability`
+pub(crate) fn random_fault_roll(s: &mut TheracState) -> f64 {
+    s.rng.gen()
+}
+
+/// The body of `zap_the_specimen_safe`, with the write lock already held
+pub(crate) fn zap_safe_locked(s: &mut TheracState) {
+    let fault_roll = random_fault_roll(s);
+    let (console_meos, hardware_meos) = (s.setup_meos(), s.hardware_meos);
+    if console_meos == hardware_meos && !hardware_meos.is_safe() && s.phase != TPhase::EmergencyStop {
+        s.record_near_miss();
+        s.phase = TPhase::PauseTreatment;
+        s.add_log(format!(
+            "[MONITOR] Beam inhibited - {} with collimator {} is unsafe",
+            hardware_meos.beam_type, hardware_meos.collimator
+        ));
+        return;
+    }
+    verify_and_fire(s, console_meos, hardware_meos, fault_roll);
+}
+
+/// Fire one pulse through the zap interlocks (shared by both variants): `console_meos`
+/// and `hardware_meos` are what the check compares, however fresh they are
+pub(crate) fn verify_and_fire(s: &mut TheracState, console_meos: Meos, hardware_meos: Meos, fault_roll: f64) {
+    // Emergency-off cuts beam power: a pulse in flight when it was pressed never fires
+    if s.phase == TPhase::EmergencyStop {
+        return;
+    }
+
+    // The watchdog latched a stuck turntable: no beam until a reset
+    if s.collimator_timeout {
+        s.phase = TPhase::PauseTreatment;
+        s.add_log_with_level(LogLevel::Warn, "[MONITOR] Beam inhibited - COLLIMATOR TIMEOUT latched, reset to clear".to_string());
+        return;
+    }
+
+    // A target that is not positive can never be treated to: zero would be reached
+    // before the first pulse, a negative one never
+    if !valid_dose_target(s.dose_target) {
+        s.phase = TPhase::PauseTreatment;
+        let target = s.dose_target;
+        s.add_log_with_level(LogLevel::Warn, format!("[MONITOR] Treatment refused - dose target {} cGy is not positive", target));
+        return;
+    }
+
+    // The check sees the hardware through a sensor that can glitch for a pulse
+    let previous_reading = s.last_sensor_reading.replace(hardware_meos).unwrap_or(hardware_meos);
+    let hardware_meos = read_verification(hardware_meos, previous_reading, &s.config, &mut s.rng);
+
+    // Console edited since data entry was completed: the hardware cannot have
+    // synced to the latest edit yet, whatever the MEOS comparison says
+    if s.editing_taking_place {
+        s.malfunction_count += 1;
+        s.record_near_miss();
+        let malfunction_msg = s.messages.format(MessageKey::MalfunctionEditInProgress, &[&s.malfunction_count]);
+        s.report_checked_malfunction(MalfunctionKind::EditInProgress, malfunction_msg, console_meos, hardware_meos);
+        s.phase = TPhase::PauseTreatment;
+        return;
+    }
+
+    // A turntable stuck mid-rotation leaves the beam path undefined, whatever the
+    // console was set to
+    if hardware_meos.collimator == CollimatorPosition::Stuck {
+        s.malfunction_count += 1;
+        let malfunction_msg = s.messages.format(MessageKey::CollimatorStuck, &[&s.malfunction_count]);
+        s.report_checked_malfunction(MalfunctionKind::CollimatorStuck, malfunction_msg, console_meos, hardware_meos);
+        s.phase = TPhase::PauseTreatment;
+        return;
+    }
 
     // Check for parameter mismatch
     if console_meos != hardware_meos {
-        // MALFUNCTION 54: Parameter mismatch detected
+        // MALFUNCTION 54: Parameter mismatch detected - a near miss, not an overdose
+        s.malfunction_count += 1;
+        s.record_near_miss();
+        let malfunction_msg = s.messages.format(MessageKey::Malfunction54, &[
+            &s.malfunction_count,
+            &format!("{:?}", console_meos.beam_type),
+            &console_meos.collimator,
+            &format!("{:?}", hardware_meos.beam_type),
+            &hardware_meos.collimator,
+        ]);
+        s.report_checked_malfunction(MalfunctionKind::ParameterMismatch, malfunction_msg, console_meos, hardware_meos);
+        s.phase = TPhase::PauseTreatment;
+        return;
+    }
+
+    // The bending magnets are still settling after a beam type switch: the beam would
+    // be steered off its path
+    if s.bending_magnet_flag {
+        s.malfunction_count += 1;
+        s.record_near_miss();
+        let malfunction_msg = s.messages.format(MessageKey::MagnetNotSettled, &[
+            &s.malfunction_count,
+            &s.bending_magnet_settle_remaining_ms,
+        ]);
+        s.report_checked_malfunction(MalfunctionKind::MagnetNotSettled, malfunction_msg, console_meos, hardware_meos);
+        s.phase = TPhase::PauseTreatment;
+        return;
+    }
+
+    // The energy went up and the klystron has not ramped to it yet: the beam would come
+    // out at neither the old energy nor the new
+    if s.energy_settle_remaining_ms > 0 {
         s.malfunction_count += 1;
+        s.record_near_miss();
+        let malfunction_msg = s.messages.format(MessageKey::EnergyNotSettled, &[
+            &s.malfunction_count,
+            &console_meos.beam_energy,
+            &s.energy_settle_remaining_ms,
+        ]);
+        s.report_checked_malfunction(MalfunctionKind::EnergyNotSettled, malfunction_msg, console_meos, hardware_meos);
         s.phase = TPhase::PauseTreatment;
-        let malfunction_msg = format!("MALFUNCTION 54 - Parameter mismatch (occurrence #{}) - Console: {:?}/{}, Hardware: {:?}/{}",
-            s.malfunction_count,
-            console_meos.beam_type,
-            console_meos.collimator,
-            hardware_meos.beam_type,
-            hardware_meos.collimator);
-        s.last_malfunction = Some(malfunction_msg.clone());
-        s.add_log(malfunction_msg);
         return;
     }
 
@@ -300,133 +847,561 @@ pub async fn zap_the_specimen(state: SharedTheracState) {
     if !s.hardware_meos.is_safe() {
         // CRITICAL SAFETY VIOLATION
         // Delivering beam with wrong collimator position!
+        let dose_this_pulse = jittered_dose(pulse_dose(s), s.config.dose_jitter, &mut s.rng);
+        if dose_interlock_trips(s, dose_this_pulse) {
+            return;
+        }
         s.malfunction_count += 1;
 
-        let dose_multiplier = match s.hardware_meos.beam_type {
-            BeamType::XRay if s.hardware_meos.collimator == CollimatorPosition::OutOfPosition => {
-                // X-ray mode without flatness filter = MASSIVE overdose
-                // The flatness filter normally spreads the beam over a large area
-                // Without it, all energy is concentrated in a small spot
-                100.0
-            },
-            BeamType::Electron if s.hardware_meos.collimator == CollimatorPosition::InPosition => {
-                // Electron mode with filter = underdose (filter blocks electrons)
-                0.1
-            },
-            _ => 1.0,
-        };
+        let dose_multiplier = overdose_factor(&s.hardware_meos);
+        if dose_multiplier > 1.0 {
+            s.record_overdose();
+        }
 
-        let dose_this_pulse = calculate_dose(&s.hardware_meos) * dose_multiplier;
+        let current = beam_current(&s.hardware_meos);
+        s.monitor_units_delivered += pulse_dose(s);
         s.dose_delivered += dose_this_pulse;
+        s.beam_on_seconds += pulse_period(s).as_secs_f64();
+        s.beam_current = current;
+        s.record_dose_sample();
 
-        s.phase = TPhase::PauseTreatment;
         let dose_delivered = s.dose_delivered;
         let dose_target = s.dose_target;
-        let malfunction_msg = format!(
-            "CRITICAL SAFETY VIOLATION! Beam fired with unsafe configuration! Dose multiplier: {:.1}x - Delivered {:.1} cGy this pulse (total: {:.1}/{:.1} cGy)",
-            dose_multiplier, dose_this_pulse, dose_delivered, dose_target
-        );
-        s.last_malfunction = Some(malfunction_msg.clone());
-        s.add_log(malfunction_msg);
+        let mut malfunction_msg = s.messages.format(MessageKey::MalfunctionUnsafeBeam, &[
+            &format!("{:.1}", dose_multiplier),
+            &s.config.reported_dose(dose_this_pulse),
+            &s.config.reported_dose(dose_delivered),
+            &format!("{:.1}", dose_target),
+        ]);
+        if s.config.exceeds_reported_cap(dose_delivered) {
+            let lethal = s.messages.format(MessageKey::LethalOverdose, &[&format!("{:.0}", s.config.reported_dose_cap)]);
+            malfunction_msg = format!("{} - {}", malfunction_msg, lethal);
+        }
+        s.report_checked_malfunction(MalfunctionKind::UnsafeBeam { dose_multiplier, dose_this_pulse }, malfunction_msg, console_meos, hardware_meos);
+        s.phase = TPhase::PauseTreatment;
+        if dose_multiplier > 1.0 {
+            s.finish_treatment(TreatmentOutcome::Overdose {
+                dose_delivered,
+                dose_this_pulse,
+                multiplier: dose_multiplier,
+            });
+        }
         return;
     }
 
     // Simulate random hardware malfunctions
-    if really_good_number > 22 {
+    let fault_probability = s.config.random_fault_probability;
+    if fault_roll < fault_probability {
+        // Numbered from the code table (`codes.rs`), as the operator would have seen them
+        let really_good_number = crate::codes::random_fault_code(&mut s.rng).code;
         s.malfunction_count += 1;
+        let malfunction_msg = s.messages.format(MessageKey::MalfunctionRandomFault, &[&really_good_number]);
+        s.report_checked_malfunction(MalfunctionKind::RandomFault(really_good_number), malfunction_msg, console_meos, hardware_meos);
         s.phase = TPhase::PauseTreatment;
-        let malfunction_msg = format!("MALFUNCTION {} - Random hardware fault", really_good_number);
-        s.last_malfunction = Some(malfunction_msg.clone());
-        s.add_log(malfunction_msg);
         return;
     }
+    if fault_roll < RANDOM_FAULT_PROBABILITY {
+        s.add_log(format!(
+            "[MONITOR] Random fault suppressed (probability {:.2}) - the original would have faulted",
+            fault_probability
+        ));
+    }
 
     // Normal beam delivery
-    let dose_this_pulse = calculate_dose(&s.hardware_meos);
+    let current = beam_current(&s.hardware_meos);
+    let dose_this_pulse = jittered_dose(pulse_dose(s), s.config.dose_jitter, &mut s.rng);
+    if dose_interlock_trips(s, dose_this_pulse) {
+        return;
+    }
+    // The ion chamber counts the nominal output, not the jittered dose the patient gets
+    s.monitor_units_delivered += pulse_dose(s);
     s.dose_delivered += dose_this_pulse;
+    s.beam_on_seconds += pulse_period(s).as_secs_f64();
+    s.beam_current = current;
+    s.record_dose_sample();
 
     let dose_delivered = s.dose_delivered;
     let dose_target = s.dose_target;
     s.add_log(format!(
-        "Beam delivered: {:.2} cGy (total: {:.1}/{:.1} cGy)",
-        dose_this_pulse, dose_delivered, dose_target
+        "[MONITOR] Beam delivered: {:.2} cGy (total: {:.1}/{:.1} cGy, {:.1} MU)",
+        dose_this_pulse, dose_delivered, dose_target, s.monitor_units_delivered
     ));
 
-    // Check if target dose reached
-    if s.dose_delivered >= s.dose_target {
+    // Check if the prescription is delivered: monitor units when set, else the dose target
+    if s.beam_target_reached() {
         s.phase = TPhase::TerminateTreatment;
-        s.add_log("Target dose reached".to_string());
+        if s.monitor_units > 0.0 {
+            let monitor_units = s.monitor_units;
+            s.add_log(format!("[MONITOR] Monitor units reached ({:.1} MU)", monitor_units));
+        } else {
+            s.add_log("[MONITOR] Target dose reached".to_string());
+        }
+        s.raise_alarm(crate::alarm::Alarm::DoseComplete);
+        s.finish_treatment(TreatmentOutcome::TargetReached { dose_delivered });
     }
 }
 
-/// Calculate dose for a single beam pulse
-/// Dose depends on beam type and energy level
-fn calculate_dose(meos: &Meos) -> f64 {
-    let base_dose = match meos.beam_energy {
-        BeamEnergy::E5 => 2.0,
-        BeamEnergy::E10 => 4.0,
-        BeamEnergy::E15 => 6.0,
-        BeamEnergy::E20 => 8.0,
-        BeamEnergy::E25 => 10.0,
-    };
-
-    // X-ray mode delivers dose over larger area (with flatness filter)
-    match meos.beam_type {
-        BeamType::XRay => base_dose * 0.8,
-        BeamType::Electron => base_dose,
-        BeamType::Undefined => 0.0,
+/// The hardware dose interlock (`SimConfig::max_safe_dose_cgy`): a pulse that would take
+/// the delivered dose past the limit is cut before it fires and the treatment terminated.
+/// Returns whether it tripped.
+fn dose_interlock_trips(s: &mut TheracState, dose_this_pulse: f64) -> bool {
+    let total = s.dose_delivered + dose_this_pulse;
+    if !s.config.exceeds_safe_dose(total) {
+        return false;
     }
+    let limit = s.config.max_safe_dose_cgy;
+    s.add_log_with_level(LogLevel::Warn, format!(
+        "[MONITOR] DOSE INTERLOCK TRIPPED - {:.1} cGy pulse would bring the total to {:.1} cGy, over the {:.1} cGy limit; beam off",
+        dose_this_pulse, total, limit
+    ));
+    s.abandon_treatment();
+    s.phase = TPhase::TerminateTreatment;
+    true
 }
 
-/// Start treatment
-pub fn start_treatment(state: SharedTheracState) {
-    let mut s = state.write();
-    if s.phase == TPhase::SetupDone {
-        s.phase = TPhase::PatientTreatment;
-        s.add_log("Starting patient treatment".to_string());
+/// Intensity of the unscanned X-ray mode pencil beam, relative to the same current
+/// swept over the field by the scanning magnets in electron mode
+pub const PENCIL_BEAM_CONCENTRATION: f64 = 80.0;
+
+/// Area the flatness filter spreads the X-ray mode beam over, relative to the bare
+/// pencil beam. This is the "100x": take the filter away and the same current lands
+/// on a hundredth of the area.
+pub const FLATNESS_FILTER_SPREAD: f64 = 100.0;
+
+/// Fraction of an electron beam that gets through the flatness filter
+pub const FILTER_ELECTRON_TRANSMISSION: f64 = 0.1;
+
+/// Accelerator beam current (µA) at each preset energy (MeV), lowest first
+pub const PRESET_BEAM_CURRENT: [(f64, f64); 5] = [(5.0, 2.0), (10.0, 4.0), (15.0, 6.0), (20.0, 8.0), (25.0, 10.0)];
+
+/// Accelerator beam current (µA) for a pulse, before anything in the beam path
+/// Depends on the energy only: X-ray and electron mode draw the same current.
+/// Energies between the presets interpolate linearly. Zero with no beam type selected.
+pub fn beam_current(meos: &Meos) -> f64 {
+    if meos.beam_type == BeamType::Undefined {
+        return 0.0;
     }
+    let mev = meos.beam_energy.mev() as f64;
+    let upper = PRESET_BEAM_CURRENT
+        .iter()
+        .position(|&(preset, _)| preset >= mev)
+        .unwrap_or(PRESET_BEAM_CURRENT.len() - 1)
+        .max(1);
+    let ((e0, i0), (e1, i1)) = (PRESET_BEAM_CURRENT[upper - 1], PRESET_BEAM_CURRENT[upper]);
+    i0 + (i1 - i0) * (mev - e0) / (e1 - e0)
 }
 
-/// Stop treatment
-pub fn stop_treatment(state: SharedTheracState) {
-    let mut s = state.write();
-    if s.phase == TPhase::PatientTreatment {
-        s.phase = TPhase::PauseTreatment;
-        s.add_log("Treatment paused by operator".to_string());
+/// Dose per µA of beam current (cGy per pulse) for the beam geometry
+/// A turntable still moving (or stuck) counts as the intended position for the beam type.
+fn dose_per_microamp(meos: &Meos) -> f64 {
+    let filter_in = match meos.collimator {
+        CollimatorPosition::InPosition => true,
+        CollimatorPosition::OutOfPosition => false,
+        CollimatorPosition::Transitioning | CollimatorPosition::Stuck => meos.beam_type == BeamType::XRay,
+    };
+    match (meos.beam_type, filter_in) {
+        // The filter spreads the pencil beam over the treatment field
+        (BeamType::XRay, true) => PENCIL_BEAM_CONCENTRATION / FLATNESS_FILTER_SPREAD,
+        // Without it the whole current is concentrated in a small spot
+        (BeamType::XRay, false) => PENCIL_BEAM_CONCENTRATION,
+        // The filter blocks most of an electron beam
+        (BeamType::Electron, true) => FILTER_ELECTRON_TRANSMISSION,
+        (BeamType::Electron, false) => 1.0,
+        (BeamType::Undefined, _) => 0.0,
     }
 }
 
-/// Resume treatment
-pub fn resume_treatment(state: SharedTheracState) {
-    let mut s = state.write();
-    if s.phase == TPhase::PauseTreatment {
-        s.phase = TPhase::PatientTreatment;
-        s.last_malfunction = None;
-        s.add_log("Treatment resumed".to_string());
+/// Dose multiplier caused by the collimator position for the hardware beam type
+/// The dose this configuration delivers over the dose with the filter where it
+/// belongs: 1.0 when the configuration is as intended.
+pub fn overdose_factor(meos: &Meos) -> f64 {
+    let intended = Meos {
+        collimator: match meos.beam_type {
+            BeamType::XRay => CollimatorPosition::InPosition,
+            _ => CollimatorPosition::OutOfPosition,
+        },
+        ..*meos
+    };
+    let intended_dose = dose_per_microamp(&intended);
+    if intended_dose == 0.0 {
+        return 1.0;
     }
+    dose_per_microamp(meos) / intended_dose
 }
 
-/// Complete data entry
-/// Update console MEOS (operator input)
-pub fn update_console_meos(state: SharedTheracState, meos: Meos) {
-    let mut s = state.write();
-    if s.phase == TPhase::DataEntry || s.phase == TPhase::SetupTest {
-        s.console_meos = meos;
-        s.add_log(format!(
-            "Console updated: {} @ {} with collimator {}",
-            meos.beam_type, meos.beam_energy, meos.collimator
-        ));
-    }
+/// Dose rate (cGy/min) the per-pulse doses above are calibrated for: the default
+/// treatment parameters
+pub const REFERENCE_DOSE_RATE: f64 = 100.0;
+
+/// Field area (cm²) the per-pulse doses above are calibrated for: a 10x10 cm field
+pub const REFERENCE_FIELD_AREA: f64 = 100.0;
+
+/// Smallest field area (cm²) `field_size_factor` scales to, so a zero-sized field
+/// does not concentrate the beam without limit
+pub const MIN_FIELD_AREA: f64 = 1.0;
+
+/// Calculate dose for a single beam pulse
+/// The accelerator current for the energy is turned into dose by the beam geometry
+/// (scanned or pencil beam, and what the flatness filter does to it), then scaled by
+/// the dose rate: the pulses keep coming at ~60Hz, so twice the cGy/min is twice the
+/// dose per pulse. At `REFERENCE_DOSE_RATE` the dose is the calibrated one.
+pub fn calculate_dose(meos: &Meos, params: &TreatmentParams) -> f64 {
+    beam_current(meos) * dose_per_microamp(meos) * (params.dose_rate.max(0.0) as f64 / REFERENCE_DOSE_RATE)
 }
 
-/// Generate random treatment parameters
-pub fn generate_random_parameters() -> Meos {
-    let mut rng = rand::thread_rng();
+/// Dose multiplier of the field size: the same beam collimated to a smaller field
+/// concentrates its dose, inversely to the area. 1.0 for a 10x10 cm field.
+pub fn field_size_factor(params: &TreatmentParams) -> f64 {
+    let area = params.field_size_x as f64 * params.field_size_y as f64;
+    REFERENCE_FIELD_AREA / area.max(MIN_FIELD_AREA)
+}
 
-    let beam_type = if rng.gen_bool(0.5) {
-        BeamType::XRay
+/// Nominal dose of the next pulse from the hardware as set up: `calculate_dose`,
+/// concentrated by the field size when `SimConfig::field_size_scales_dose` is on.
+/// With `SimConfig::pulse_repetition_hz` set the dose rate sets how often pulses come
+/// instead, so every pulse carries the dose of `REFERENCE_DOSE_RATE`.
+pub fn pulse_dose(state: &TheracState) -> f64 {
+    pulse_dose_for(state, &state.hardware_meos)
+}
+
+/// Whether the delivered dose is past the target by more than one pulse of the entered
+/// setup (at its largest jitter) can take it: an overdose, not the routine overshoot of
+/// a treatment whose last pulse straddles the target
+pub fn dose_past_target(state: &TheracState) -> bool {
+    let last_pulse = pulse_dose_for(state, &state.console_meos) * (1.0 + state.config.dose_jitter.max(0.0) as f64);
+    state.dose_delivered > state.dose_target + last_pulse
+}
+
+/// Nominal dose of one pulse with the beam set up as `meos`
+fn pulse_dose_for(state: &TheracState, meos: &Meos) -> f64 {
+    let dose = if pulse_repetition_rate(state).is_some() {
+        calculate_dose(meos, &TreatmentParams { dose_rate: REFERENCE_DOSE_RATE as f32, ..state.hardware_params })
     } else {
-        BeamType::Electron
+        calculate_dose(meos, &state.hardware_params)
+    };
+    if state.config.field_size_scales_dose {
+        dose * field_size_factor(&state.hardware_params)
+    } else {
+        dose
+    }
+}
+
+/// Apply shot-to-shot variation to a nominal pulse dose
+/// `jitter` is fractional: 0.05 gives a uniform spread of ±5%. Zero returns `nominal` exactly.
+pub fn jittered_dose<R: Rng>(nominal: f64, jitter: f32, rng: &mut R) -> f64 {
+    if jitter <= 0.0 {
+        return nominal;
+    }
+    let jitter = jitter as f64;
+    nominal * (1.0 + rng.gen_range(-jitter..=jitter))
+}
+
+/// Nominal treatment depth in cm of tissue for a beam configuration
+/// Electrons have a sharp range, roughly a third of the energy in MeV for the
+/// therapeutic (80%) depth. X-rays fall off slowly and reach much deeper.
+/// The depth follows what actually leaves the machine: with the turntable in the
+/// wrong position an X-ray request delivers the raw electron beam, and an electron
+/// request is converted to photons by the target.
+pub fn treatment_depth_cm(meos: &Meos) -> f32 {
+    let mev = meos.beam_energy.mev();
+
+    let photons = match (meos.beam_type, meos.collimator) {
+        (BeamType::Undefined, _) => return 0.0,
+        (BeamType::XRay, CollimatorPosition::OutOfPosition) => false,
+        (BeamType::Electron, CollimatorPosition::InPosition) => true,
+        (beam_type, _) => beam_type == BeamType::XRay,
+    };
+
+    if photons {
+        // Depth of 50% dose, ~15 cm at 5 MV rising to ~22 cm at 25 MV
+        13.5 + 0.35 * mev
+    } else {
+        mev / 3.0
+    }
+}
+
+/// Clinical classification of a delivered dose against the prescription
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryClass {
+    /// Less than 95% of the prescribed dose
+    Underdose,
+    /// Within 5% of the prescribed dose
+    AsPrescribed,
+    /// More than prescribed, but below SEVERE_OVERDOSE_CGY
+    Overdose,
+    /// At or above SEVERE_OVERDOSE_CGY in a single treatment
+    SevereOverdose,
+}
+
+/// Single-session dose (cGy) above which severe radiation injury is expected
+pub const SEVERE_OVERDOSE_CGY: f64 = 1000.0;
+
+impl std::fmt::Display for DeliveryClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeliveryClass::Underdose => write!(f, "Underdose - tumour under-treated"),
+            DeliveryClass::AsPrescribed => write!(f, "Delivered as prescribed"),
+            DeliveryClass::Overdose => write!(f, "Overdose - excess tissue damage likely"),
+            DeliveryClass::SevereOverdose => write!(f, "Severe overdose - radiation burns, potentially fatal"),
+        }
+    }
+}
+
+/// Classify `delivered` cGy against `prescribed` cGy
+pub fn classify_delivery(delivered: f64, prescribed: f64) -> DeliveryClass {
+    if delivered >= SEVERE_OVERDOSE_CGY {
+        DeliveryClass::SevereOverdose
+    } else if delivered > prescribed * 1.05 {
+        DeliveryClass::Overdose
+    } else if delivered < prescribed * 0.95 {
+        DeliveryClass::Underdose
+    } else {
+        DeliveryClass::AsPrescribed
+    }
+}
+
+/// One row of a dose sweep
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DoseSweepEntry {
+    /// Hardware configuration the beam would fire with
+    pub meos: Meos,
+    /// Accelerator beam current for that configuration (µA)
+    pub beam_current: f64,
+    /// Dose delivered by a single pulse in that configuration (cGy)
+    pub dose_per_pulse: f64,
+}
+
+/// Per-pulse dose for every energy and beam type, with the collimator both where
+/// it belongs and in the wrong position. Purely computed from the dose model.
+pub fn dose_sweep() -> Vec<DoseSweepEntry> {
+    let mut entries = Vec::new();
+    for beam_type in [BeamType::XRay, BeamType::Electron] {
+        for beam_energy in BeamEnergy::ALL {
+            for collimator in [CollimatorPosition::InPosition, CollimatorPosition::OutOfPosition] {
+                let meos = Meos { beam_type, beam_energy, collimator };
+                let current = beam_current(&meos);
+                entries.push(DoseSweepEntry {
+                    meos,
+                    beam_current: current,
+                    dose_per_pulse: calculate_dose(&meos, &TreatmentParams::default()),
+                });
+            }
+        }
+    }
+    entries
+}
+
+/// Start treatment
+/// Refused while a required pre-treatment checklist is incomplete.
+/// Like the original, this does not refuse to start mid-edit; the beam-on check in
+/// zap_the_specimen pauses treatment instead. Returns whether the beam was switched on
+/// (or its hold started).
+pub fn start_treatment(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    if s.phase == TPhase::SetupDone && s.recalibration_required() {
+        let treatments = s.treatments_since_calibration;
+        s.add_log_with_level(LogLevel::Warn, format!(
+            "[OPERATOR] RECALIBRATION REQUIRED - {} treatments since the last calibration",
+            treatments
+        ));
+    } else if s.phase == TPhase::SetupDone && !s.checklist_satisfied() {
+        s.add_log_with_level(LogLevel::Warn, "[OPERATOR] Cannot start treatment - pre-treatment checklist incomplete".to_string());
+    } else if s.phase == TPhase::SetupDone {
+        s.request_beam_on();
+        if s.editing_taking_place {
+            s.add_log_with_level(LogLevel::Warn, "[OPERATOR] WARNING: Starting treatment with unconfirmed console edits".to_string());
+        }
+        s.add_log("[OPERATOR] Starting patient treatment".to_string());
+        return true;
+    }
+    false
+}
+
+/// Start treatment from SetupDone and fire pulses until the phase leaves PatientTreatment
+/// Every pulse goes through zap_the_specimen (or its safe variant in safe mode), so all
+/// of its interlocks (edit check, MALFUNCTION 54, random faults) apply exactly as they
+/// do under the treatment monitor.
+/// Don't run this while the treatment monitor is spawned on the same state, or both will fire.
+pub async fn run_treatment_to_completion(state: SharedTheracState) -> TreatmentOutcome {
+    let phase = state.read_state().phase;
+    if phase != TPhase::SetupDone {
+        return TreatmentOutcome::NotReady(phase);
+    }
+    start_treatment(state.clone());
+    while state.read_state().phase == TPhase::BeamReady {
+        task_sleep(&state, BEAM_HOLD_TICK).await;
+        beam_hold_pass(&mut state.write_state());
+    }
+
+    loop {
+        let (dose_before, hardware_before) = {
+            let s = state.read_state();
+            if s.phase != TPhase::PatientTreatment {
+                return treatment_outcome(&s, s.dose_delivered, s.hardware_meos);
+            }
+            (s.dose_delivered, s.hardware_meos)
+        };
+
+        zap_as_configured(state.clone()).await;
+
+        let s = state.read_state();
+        if s.phase != TPhase::PatientTreatment {
+            return treatment_outcome(&s, dose_before, hardware_before);
+        }
+    }
+}
+
+/// Classify the state after the last pulse of a batched run
+pub(crate) fn treatment_outcome(s: &TheracState, dose_before: f64, hardware_before: Meos) -> TreatmentOutcome {
+    let dose_delivered = s.dose_delivered;
+    match s.phase {
+        TPhase::PauseTreatment
+            if dose_delivered > dose_before && overdose_factor(&hardware_before) > 1.0 =>
+        {
+            TreatmentOutcome::Overdose {
+                dose_delivered,
+                dose_this_pulse: dose_delivered - dose_before,
+                multiplier: overdose_factor(&hardware_before),
+            }
+        }
+        TPhase::PauseTreatment => TreatmentOutcome::Paused {
+            dose_delivered,
+            malfunction: s.last_malfunction.clone(),
+        },
+        TPhase::TerminateTreatment if s.beam_target_reached() => {
+            TreatmentOutcome::TargetReached { dose_delivered }
+        }
+        phase => TreatmentOutcome::Terminated { dose_delivered, phase },
+    }
+}
+
+/// Stop treatment
+/// During the beam hold this aborts beam-on cleanly, back to SetupDone with no dose.
+pub fn stop_treatment(state: SharedTheracState) {
+    let mut s = state.write_state();
+    if s.phase == TPhase::PatientTreatment {
+        s.phase = TPhase::PauseTreatment;
+        s.add_log("[OPERATOR] Treatment paused by operator".to_string());
+    } else if s.phase == TPhase::BeamReady {
+        s.phase = TPhase::SetupDone;
+        s.beam_hold_remaining_ms = 0;
+        s.add_log("[OPERATOR] Beam-on aborted during hold - no dose delivered".to_string());
+    }
+}
+
+/// Resume treatment
+pub fn resume_treatment(state: SharedTheracState) {
+    let mut s = state.write_state();
+    if s.phase == TPhase::PauseTreatment {
+        s.phase = TPhase::PatientTreatment;
+        if s.treatment_in_progress.is_none() {
+            // Resumed after the record was closed (e.g. past an overdose): a new treatment
+            s.begin_treatment();
+        }
+        if let Some(malfunction) = s.last_malfunction.take() {
+            s.record_override(OverrideKind::MalfunctionDismissed, malfunction.message());
+        }
+        s.add_log("[OPERATOR] Treatment resumed".to_string());
+    }
+}
+
+/// Emergency-off: cut the beam at once, whatever the phase
+/// Unlike `stop_treatment` there is no continuing: resume, treat and acknowledge are all
+/// refused until `handle_reset_command`. A treatment under way is recorded as terminated.
+pub fn emergency_off(state: SharedTheracState) {
+    let mut s = state.write_state();
+    if s.phase == TPhase::EmergencyStop {
+        return;
+    }
+    let interrupted = s.phase;
+    s.phase = TPhase::EmergencyStop;
+    s.beam_hold_remaining_ms = 0;
+    s.abandon_treatment();
+    let dose_delivered = s.dose_delivered;
+    let dose_target = s.dose_target;
+    s.add_log_with_level(LogLevel::Critical, format!(
+        "[CRITICAL] EMERGENCY OFF during {} - beam cut at {:.1}/{:.1} cGy. Reset required",
+        interrupted, dose_delivered, dose_target
+    ));
+}
+
+/// Freeze the background tasks where they stand, e.g. to inspect the state mid-race
+/// Each task holds at its next sleep, so no phase advances and no sync lands until
+/// `resume_simulation`; a pulse already in flight still completes. The tick driver
+/// (`tick::step`) is not affected. Returns false if already paused.
+pub fn pause_simulation(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    if s.simulation_paused {
+        return false;
+    }
+    s.simulation_paused = true;
+    s.add_log("[OPERATOR] Simulation paused - background tasks frozen".to_string());
+    true
+}
+
+/// Let the background tasks carry on after `pause_simulation`
+/// Returns false if the simulation was not paused.
+pub fn resume_simulation(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    if !s.simulation_paused {
+        return false;
+    }
+    s.simulation_paused = false;
+    s.add_log("[OPERATOR] Simulation resumed".to_string());
+    true
+}
+
+/// Whether the background tasks are frozen, e.g. to show a PAUSED indicator
+pub fn is_simulation_paused(state: &SharedTheracState) -> bool {
+    state.read_state().simulation_paused
+}
+
+/// Acknowledge the current malfunction and go back to data entry to fix it
+/// Unlike `resume_treatment`, nothing is retried: the treatment ends, data entry must
+/// be completed again, and `malfunction_count` is kept for the record.
+/// Also works once the message itself has been dismissed but treatment is still paused.
+/// Returns false if there was neither a malfunction nor a paused treatment, or after
+/// an emergency-off (which only a reset clears).
+pub fn clear_malfunction(state: SharedTheracState) -> bool {
+    let mut s = state.write_state();
+    if s.phase == TPhase::EmergencyStop
+        || (s.last_malfunction.is_none() && s.phase != TPhase::PauseTreatment)
+    {
+        return false;
+    }
+    s.last_malfunction = None;
+    s.abandon_treatment();
+    s.phase = TPhase::DataEntry;
+    s.data_entry_complete = false;
+    s.add_log("[OPERATOR] Malfunction acknowledged, returning to data entry".to_string());
+    true
+}
+
+/// Complete data entry
+/// Update console MEOS (operator input)
+pub fn update_console_meos(state: SharedTheracState, meos: Meos) {
+    let mut s = state.write_state();
+    if s.phase == TPhase::DataEntry || s.phase == TPhase::SetupTest {
+        s.console_meos = meos;
+        s.mark_console_edit();
+        s.add_log(format!(
+            "[CONSOLE] Console updated: {} @ {} with collimator {}",
+            meos.beam_type, meos.beam_energy, meos.collimator
+        ));
+    }
+}
+
+/// Generate random treatment parameters
+pub fn generate_random_parameters() -> Meos {
+    generate_random_parameters_with(&mut rand::thread_rng())
+}
+
+/// Generate random treatment parameters from `rng`, e.g. a session's seeded stream
+pub fn generate_random_parameters_with<R: Rng>(rng: &mut R) -> Meos {
+    let beam_type = if rng.gen_bool(0.5) {
+        BeamType::XRay
+    } else {
+        BeamType::Electron
     };
 
     let beam_energy = match rng.gen_range(0..5) {
@@ -468,3 +1443,1925 @@ pub fn generate_race_condition_parameters(current: Meos) -> Meos {
         collimator: current.collimator,
     }
 }
+
+/// Open the race window on purpose: put the machine where a quick X-ray edit leaves it
+/// when the beam type and energy have synced ahead of the turntable, and turn the beam on
+///
+/// Afterwards:
+/// - console and hardware MEOS are both X-ray at 25 MeV with the flatness filter out
+///   (the turntable never moved), so the console sees no mismatch
+/// - data entry is complete with no edit pending, the bending magnets have settled,
+///   no COLLIMATOR TIMEOUT is latched and the sensor has no stale reading
+/// - the phase is `PatientTreatment` with a treatment record open; the housekeeper
+///   does not start a move during treatment, so the window stays open
+///
+/// The next pulse through `zap_the_specimen` therefore fires unflattened and records a
+/// CRITICAL SAFETY VIOLATION. In safe mode `zap_the_specimen_safe` refuses it instead,
+/// and a glitching sensor (`SimConfig::sensor_glitch_probability`) can still mask it.
+pub fn force_race_window(state: SharedTheracState) {
+    let mut s = state.write_state();
+    let unflattened = Meos {
+        beam_type: BeamType::XRay,
+        beam_energy: BeamEnergy::E25,
+        collimator: CollimatorPosition::OutOfPosition,
+    };
+    s.console_meos = unflattened;
+    s.hardware_meos = unflattened;
+    s.data_entry_complete = true;
+    s.editing_taking_place = false;
+    s.bending_magnet_flag = false;
+    s.bending_magnet_settle_remaining_ms = 0;
+    s.energy_settle_remaining_ms = 0;
+    s.collimator_timeout = false;
+    s.collimator_transit_remaining_ms = 0;
+    s.last_sensor_reading = None;
+    s.last_malfunction = None;
+    s.beam_on();
+    s.add_log("[OPERATOR] Race window forced: X-ray at 25 MeV with the flatness filter out, beam on".to_string());
+}
+
+/// Operator command typed at either console's command prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Complete data entry and turn the beam on
+    Treat,
+    /// Reset the machine and draw a new prescription
+    Reset,
+    /// Complete data entry, leading to the setup test
+    Proceed,
+    /// Replace the prescription only, keeping the console entries
+    New,
+    /// Pause treatment (or abort beam-on during the hold)
+    Stop,
+    /// Resume a paused treatment
+    Continue,
+    /// Acknowledge the malfunction and go back to data entry
+    Acknowledge,
+    /// Leave the console; up to the frontend
+    Quit,
+}
+
+impl Command {
+    pub const ALL: [Command; 8] = [
+        Command::Treat,
+        Command::Reset,
+        Command::Proceed,
+        Command::New,
+        Command::Stop,
+        Command::Continue,
+        Command::Acknowledge,
+        Command::Quit,
+    ];
+
+    /// The one-letter form, as typed by operators in a hurry
+    pub fn short_form(self) -> &'static str {
+        match self {
+            Command::Treat => "t",
+            Command::Reset => "r",
+            Command::Proceed => "p",
+            Command::New => "n",
+            Command::Stop => "s",
+            Command::Continue => "c",
+            Command::Acknowledge => "a",
+            Command::Quit => "q",
+        }
+    }
+}
+
+/// Displays the long form, which parses back to the same command
+impl std::fmt::Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Command::Treat => "treat",
+            Command::Reset => "reset",
+            Command::Proceed => "proceed",
+            Command::New => "new",
+            Command::Stop => "stop",
+            Command::Continue => "continue",
+            Command::Acknowledge => "ack",
+            Command::Quit => "quit",
+        })
+    }
+}
+
+/// A command prompt entry that is not a `Command`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownCommand(pub String);
+
+impl std::fmt::Display for UnknownCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown command: '{}'", self.0)
+    }
+}
+
+impl std::error::Error for UnknownCommand {}
+
+/// Accepts the short or long form in any case, surrounding whitespace ignored
+impl std::str::FromStr for Command {
+    type Err = UnknownCommand;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim().to_lowercase();
+        Command::ALL
+            .into_iter()
+            .find(|command| text == command.short_form() || text == command.to_string())
+            .ok_or(UnknownCommand(text))
+    }
+}
+
+/// Carry out an operator command against the machine, the same way from either console
+/// Returns false if it was refused (no prescription change during treatment, nothing to
+/// acknowledge); `Quit` changes nothing here and is left to the frontend.
+pub fn apply_command(state: SharedTheracState, command: Command) -> bool {
+    match command {
+        Command::Treat => {
+            crate::input::complete_data_entry(state.clone());
+            start_treatment(state);
+        }
+        Command::Reset => state.write_state().reset(),
+        Command::Proceed => crate::input::complete_data_entry(state),
+        Command::New => return crate::input::handle_new_prescription_command(state),
+        Command::Stop => stop_treatment(state),
+        Command::Continue => resume_treatment(state),
+        Command::Acknowledge => return clear_malfunction(state),
+        Command::Quit => {}
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{SimConfig, SyncAspect, SyncOrder};
+    use crate::input::*;
+    use crate::tick::{housekeeper_tick, monitor_tick};
+    use std::sync::Arc;
+    use parking_lot::RwLock;
+
+    fn setup_done_state() -> SharedTheracState {
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        {
+            let mut s = state.write();
+            s.phase = TPhase::DataEntry;
+            s.console_meos = s.reference_meos;
+            s.hardware_meos = s.reference_meos;
+        }
+        complete_data_entry(state.clone());
+        state.write().phase = TPhase::SetupDone;
+        state
+    }
+
+    #[test]
+    fn test_treatment_commands_log_under_a_source() {
+        let state = setup_done_state();
+        let logged = state.read().log.len();
+        {
+            let mut s = state.write();
+            s.config.recalibration_interval = 1;
+            s.treatments_since_calibration = 1;
+        }
+        assert!(!start_treatment(state.clone()));
+        state.write().recalibrate();
+        state.write().reset_wear();
+        state.write().config.require_checklist = true;
+        assert!(!start_treatment(state.clone()));
+        {
+            let mut s = state.write();
+            s.config.require_checklist = false;
+            s.config.beam_hold_ms = 100;
+            s.editing_taking_place = true;
+        }
+        assert!(start_treatment(state.clone()));
+        stop_treatment(state.clone());
+        state.write().config.beam_hold_ms = 0;
+        assert!(start_treatment(state.clone()));
+        stop_treatment(state.clone());
+        resume_treatment(state.clone());
+        stop_treatment(state.clone());
+        assert!(clear_malfunction(state.clone()));
+
+        let s = state.read();
+        let untagged: Vec<&str> = s.log[logged..]
+            .iter()
+            .filter(|e| e.source() == LogSource::Other)
+            .map(|e| e.message.as_str())
+            .collect();
+        assert!(untagged.is_empty(), "{:?}", untagged);
+        assert!(s.log[logged..].iter().any(|e| e.message == "[MONITOR] Beam ready - beam on in 100 ms, stop to abort"));
+    }
+
+    /// One treatment monitor pass: a tick as long as the pass for the current phase
+    fn monitor_pass(state: &SharedTheracState) {
+        let pass_time = {
+            let s = state.read();
+            monitor_pass_time(&s, s.phase)
+        };
+        monitor_tick(state, pass_time);
+    }
+
+    /// Tick the housekeeper through the sync it takes on next, or until it gives up
+    /// on a jammed turntable
+    fn housekeeper_pass(state: &SharedTheracState) {
+        for _ in 0..1000 {
+            housekeeper_tick(state, HOUSEKEEPER_POLL);
+            if state.read().task_status.housekeeper == "idle" {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_parses_every_alias() {
+        for command in Command::ALL {
+            assert_eq!(command.short_form().parse(), Ok(command));
+            assert_eq!(command.to_string().parse(), Ok(command));
+            assert_eq!(command.to_string().to_uppercase().parse(), Ok(command));
+        }
+        assert_eq!(" t ".parse(), Ok(Command::Treat));
+        assert_eq!("continue".parse(), Ok(Command::Continue));
+        assert_eq!("ack".parse(), Ok(Command::Acknowledge));
+    }
+
+    #[test]
+    fn test_unknown_command_is_refused() {
+        for text in ["", "x", "treatment", "re set"] {
+            assert_eq!(text.parse::<Command>(), Err(UnknownCommand(text.to_string())));
+        }
+        assert_eq!("FOO".parse::<Command>().unwrap_err().to_string(), "Unknown command: 'foo'");
+    }
+
+    #[test]
+    fn test_apply_command_acknowledges_only_a_malfunction() {
+        let state = setup_done_state();
+        assert!(!apply_command(state.clone(), Command::Acknowledge));
+        assert!(apply_command(state.clone(), Command::Treat));
+        assert_eq!(state.read().phase, TPhase::PatientTreatment);
+        assert!(!apply_command(state.clone(), Command::New));
+        assert!(apply_command(state.clone(), Command::Stop));
+        assert_eq!(state.read().phase, TPhase::PauseTreatment);
+        assert!(apply_command(state.clone(), Command::Acknowledge));
+        assert_eq!(state.read().phase, TPhase::DataEntry);
+    }
+
+    #[tokio::test]
+    async fn test_edit_after_complete_is_flagged() {
+        let state = setup_done_state();
+        handle_gantry_input(state.clone(), 90);
+
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PauseTreatment);
+        assert_eq!(s.dose_delivered, 0.0);
+        assert_eq!(s.last_malfunction.as_ref().unwrap().kind, MalfunctionKind::EditInProgress);
+    }
+
+    #[test]
+    fn test_edit_after_complete_logs_the_hazard_once() {
+        let edit_warnings = |state: &SharedTheracState| {
+            state.read().log.iter()
+                .filter(|e| e.message.contains("Edit after data-entry completion") && e.level == LogLevel::Warn)
+                .count()
+        };
+        let state = setup_done_state();
+        state.write().phase = TPhase::SetupTest;
+        let meos = state.read().console_meos;
+        let flipped = Meos {
+            beam_type: if meos.beam_type == BeamType::XRay { BeamType::Electron } else { BeamType::XRay },
+            ..meos
+        };
+
+        update_console_meos(state.clone(), flipped);
+        update_console_meos(state.clone(), meos);
+        assert!(state.read().editing_taking_place);
+        assert_eq!(edit_warnings(&state), 1);
+
+        // Edits before completion are ordinary data entry
+        let state = Arc::new(RwLock::new(TheracState { phase: TPhase::DataEntry, ..TheracState::new() }));
+        update_console_meos(state.clone(), flipped);
+        assert_eq!(edit_warnings(&state), 0);
+    }
+
+    /// Treatments (of up to 5 pulses) that ended on a console/hardware mismatch
+    async fn mismatch_malfunctions(phantom: bool, rng: &mut StdRng) -> usize {
+        let mut mismatches = 0;
+        for _ in 0..40 {
+            let state = setup_done_state();
+            start_treatment(state.clone());
+            for _ in 0..5 {
+                if state.read().phase != TPhase::PatientTreatment {
+                    break;
+                }
+                if phantom && rng.gen_bool(0.5) {
+                    phantom_nudge(&state, rng);
+                }
+                zap_the_specimen(state.clone()).await;
+            }
+            let s = state.read();
+            if matches!(
+                s.last_malfunction.as_ref().map(|m| &m.kind),
+                Some(MalfunctionKind::EditInProgress | MalfunctionKind::ParameterMismatch)
+            ) {
+                mismatches += 1;
+            }
+        }
+        mismatches
+    }
+
+    #[tokio::test]
+    async fn test_phantom_operator_raises_mismatch_rate() {
+        let mut rng = StdRng::seed_from_u64(1194);
+        let alone = mismatch_malfunctions(false, &mut rng).await;
+        let with_phantom = mismatch_malfunctions(true, &mut rng).await;
+        assert_eq!(alone, 0);
+        assert!(with_phantom >= 10, "only {} of 40 treatments hit a mismatch", with_phantom);
+
+        // Outside the phases that take console edits the colleague keeps their hands off
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        assert!(!phantom_nudge(&state, &mut rng));
+        assert!(!state.read().editing_taking_place);
+
+        // A dose nudge goes through the console's setter like any other dose entry
+        let state = Arc::new(RwLock::new(TheracState { phase: TPhase::DataEntry, ..TheracState::new() }));
+        for _ in 0..100 {
+            phantom_nudge(&state, &mut rng);
+        }
+        let s = state.read();
+        let dose_nudges = s.log.iter().filter(|e| e.message.contains("Phantom operator: Dose set")).count();
+        let dose_entries = s.log.iter().filter(|e| e.message.starts_with("[CONSOLE] Dose target set to")).count();
+        assert!(dose_nudges > 0);
+        assert_eq!(dose_entries, dose_nudges);
+    }
+
+    #[tokio::test]
+    async fn test_malfunction_54_is_a_near_miss_not_an_overdose() {
+        let state = setup_done_state();
+        {
+            // Console says electrons, the turntable still holds the X-ray setup
+            let mut s = state.write();
+            s.console_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E10,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            s.hardware_meos = Meos {
+                beam_type: BeamType::XRay,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::InPosition,
+            };
+        }
+        assert_eq!(state.read().near_miss_report(), None);
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        assert_eq!(s.last_malfunction.as_ref().unwrap().code(), 54);
+        assert_eq!(s.dose_delivered, 0.0);
+        assert_eq!(s.near_misses, 1);
+        assert_eq!(s.overdose_count, 0);
+        assert!(s.near_miss_report().unwrap().starts_with("1 near miss this session"));
+    }
+
+    #[tokio::test]
+    async fn test_malfunction_history_keeps_every_fault_in_order() {
+        let state = setup_done_state();
+        handle_gantry_input(state.clone(), 90);
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        // The history survives a reset; the next treatment finds the turntable mid-move
+        {
+            let mut s = state.write();
+            s.reset();
+            s.phase = TPhase::DataEntry;
+            s.console_meos = s.reference_meos;
+            s.hardware_meos = Meos { collimator: CollimatorPosition::Transitioning, ..s.reference_meos };
+        }
+        complete_data_entry(state.clone());
+        state.write().phase = TPhase::SetupDone;
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        let kinds: Vec<MalfunctionKind> = s.incidents.iter().map(|i| i.kind).collect();
+        assert_eq!(kinds, [MalfunctionKind::EditInProgress, MalfunctionKind::ParameterMismatch]);
+        let mismatch = &s.incidents[1];
+        // Recorded in the phase the fault interrupted, with the MEOS that disagreed
+        assert_eq!(mismatch.phase, TPhase::PatientTreatment);
+        assert_eq!(mismatch.console_meos, s.console_meos);
+        assert_eq!(mismatch.hardware_meos.collimator, CollimatorPosition::Transitioning);
+        assert_eq!(mismatch.malfunction(), *s.last_malfunction.as_ref().unwrap());
+
+        let json: serde_json::Value = serde_json::from_str(&s.export_malfunction_history().unwrap()).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        assert_eq!(json[1]["phase"], "PatientTreatment");
+    }
+
+    #[tokio::test]
+    async fn test_summary_reports_overdose_and_malfunctions_by_kind() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            let unflattened = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::OutOfPosition };
+            s.console_meos = unflattened;
+            s.hardware_meos = unflattened;
+        }
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let summary = state.read().summary();
+        assert!(summary.critical_safety_violation);
+        assert!(summary.overdose_ratio > 1.0);
+        assert_eq!(summary.final_phase, TPhase::PauseTreatment);
+        assert_eq!(summary.malfunction_count, 1);
+        assert_eq!(summary.malfunction_count, state.read().malfunction_count as usize);
+        assert_eq!(summary.malfunctions_by_kind.get("Unsafe beam fired"), Some(&1));
+        assert!(summary.to_string().starts_with("SESSION SUMMARY\n"));
+        assert!(summary.to_string().contains("CRITICAL SAFETY VIOLATION occurred"));
+        let json: serde_json::Value = serde_json::from_str(&summary.to_json().unwrap()).unwrap();
+        assert_eq!(json["final_phase"], "PauseTreatment");
+    }
+
+    /// Fire two pulses on the tick driver, flipping the hardware from the console's
+    /// electron setup to an unflattened 25 MeV X-ray beam right after the first monitor
+    /// tick: between the unsafe zap's read and its check. Returns the unsafe beams fired
+    /// and the MALFUNCTION 54s raised.
+    fn flip_after_first_tick(safe_mode: bool) -> (usize, u32) {
+        let electron = Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::E25,
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        let unflattened = Meos { beam_type: BeamType::XRay, ..electron };
+        let state = Arc::new(RwLock::new(TheracState {
+            phase: TPhase::PatientTreatment,
+            console_meos: electron,
+            hardware_meos: electron,
+            dose_target: f64::MAX,
+            ..TheracState::default()
+        }));
+        {
+            let mut s = state.write();
+            s.config.safe_mode = safe_mode;
+            s.config.random_fault_probability = 0.0;
+        }
+
+        crate::tick::monitor_tick(&state, Duration::from_millis(1));
+        assert_eq!(state.read().tick.check_pending(), !safe_mode);
+        state.write().hardware_meos = unflattened;
+        crate::tick::monitor_tick(&state, Duration::from_millis(1));
+
+        let s = state.read();
+        let unsafe_beams = s.incidents.iter().filter(|i| matches!(i.kind, MalfunctionKind::UnsafeBeam { .. })).count();
+        let mismatches = s.incidents.iter().filter(|i| i.kind == MalfunctionKind::ParameterMismatch).count();
+        (unsafe_beams, mismatches as u32)
+    }
+
+    #[test]
+    fn test_safe_mode_never_fires_an_unsafe_beam() {
+        // Unsafe: the pulse is checked against the reading from before the flip, and
+        // the X-ray beam goes out unflattened
+        assert_eq!(flip_after_first_tick(false), (1, 0));
+
+        // Safe: the same flip is caught by the check on the firing tick
+        assert_eq!(flip_after_first_tick(true), (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_zap_refuses_non_positive_dose_target() {
+        for target in [0.0, -100.0] {
+            let state = setup_done_state();
+            start_treatment(state.clone());
+            // Set behind the setter's back, as a deserialized or embedded state could
+            state.write().dose_target = target;
+            zap_the_specimen(state.clone()).await;
+
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::PauseTreatment, "target {}", target);
+            assert_eq!(s.dose_delivered, 0.0);
+            assert_eq!(s.malfunction_count, 0);
+            assert!(s.last_outcome.is_none());
+            assert!(s.log.last().unwrap().message.starts_with("[MONITOR] Treatment refused"));
+        }
+    }
+
+    async fn jammed_treatment(watchdog: bool) -> SharedTheracState {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.hardware_watchdog = watchdog;
+            s.collimator_jammed = true;
+            s.phase = TPhase::BeamReady;
+            s.hardware_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E10,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            // A switch to X-ray sends the turntable on its way to bring the filter in
+            s.console_meos = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, ..s.hardware_meos };
+        }
+        housekeeper_pass(&state);
+        assert_eq!(state.read().hardware_meos.collimator, CollimatorPosition::Transitioning);
+        state
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_trips_on_a_jammed_collimator() {
+        let state = jammed_treatment(true).await;
+        let limit = CollimatorWatchdog::limit(&state.read());
+        let step = Duration::from_millis(100);
+        let mut watchdog = CollimatorWatchdog::default();
+        let mut moving = Duration::ZERO;
+        while moving + step <= limit {
+            assert!(!watchdog.check(&state, step), "tripped after {:?} of {:?}", moving, limit);
+            moving += step;
+            // The housekeeper keeps retrying the move; it never gets anywhere
+            housekeeper_pass(&state);
+        }
+        assert!(watchdog.check(&state, step));
+
+        {
+            let s = state.read();
+            assert!(s.collimator_timeout);
+            assert_eq!(s.phase, TPhase::PauseTreatment);
+            assert_eq!(s.malfunction_count, 1);
+            assert_eq!(s.last_malfunction.as_ref().unwrap().kind, MalfunctionKind::CollimatorTimeout);
+        }
+        // Latched: proceeding does not bring the beam back
+        state.write().phase = TPhase::PatientTreatment;
+        zap_the_specimen(state.clone()).await;
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PauseTreatment);
+        assert_eq!(s.dose_delivered, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_firing_while_the_bending_magnets_settle_is_flagged() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.collimator_move_ms = 10;
+            s.config.bending_magnet_settle_ms = 2000;
+            s.hardware_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            s.console_meos = Meos { beam_type: BeamType::XRay, ..s.hardware_meos };
+        }
+        housekeeper_pass(&state);
+        {
+            let mut s = state.write();
+            assert_eq!(s.hardware_meos.beam_type, BeamType::XRay);
+            assert_eq!(s.hardware_meos.collimator, CollimatorPosition::InPosition);
+            // Console entries that match the new hardware, so only the magnets are left
+            s.console_meos.collimator = CollimatorPosition::InPosition;
+            assert!(s.bending_magnet_flag);
+            assert_eq!(s.bending_magnet_settle_remaining_ms, 2000);
+        }
+
+        // Hardware matches the console and is safe, but the magnets are still settling
+        state.write().phase = TPhase::PatientTreatment;
+        zap_the_specimen(state.clone()).await;
+        {
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::PauseTreatment);
+            assert_eq!(s.dose_delivered, 0.0);
+            assert_eq!(s.near_misses, 1);
+            assert_eq!(s.last_malfunction.as_ref().unwrap().kind, MalfunctionKind::MagnetNotSettled);
+        }
+
+        assert!(!settle_bending_magnets(&state, Duration::from_millis(1500)));
+        assert!(settle_bending_magnets(&state, Duration::from_millis(500)));
+        assert!(!state.read().bending_magnet_flag);
+        state.write().phase = TPhase::PatientTreatment;
+        zap_the_specimen(state.clone()).await;
+        let s = state.read();
+        let unsettled = s.incidents.iter().filter(|i| i.kind == MalfunctionKind::MagnetNotSettled).count();
+        assert_eq!(unsettled, 1);
+    }
+
+    #[tokio::test]
+    async fn test_firing_before_the_energy_settles_is_flagged() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.energy_settle_ms = 300;
+            s.console_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E5,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            // Going down needs no settling
+            s.set_console_energy(BeamEnergy::E5);
+            assert_eq!(s.energy_settle_remaining_ms, 0);
+
+            s.set_console_energy(BeamEnergy::E25);
+            assert_eq!(s.energy_settle_remaining_ms, 300);
+            s.hardware_meos = s.console_meos;
+            s.phase = TPhase::PatientTreatment;
+        }
+
+        // 5 -> 25 MeV and fired at once: the hardware matches, but the energy has not settled
+        zap_the_specimen(state.clone()).await;
+        {
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::PauseTreatment);
+            assert_eq!(s.dose_delivered, 0.0);
+            assert_eq!(s.near_misses, 1);
+            let malfunction = s.last_malfunction.as_ref().unwrap();
+            assert_eq!(malfunction.kind, MalfunctionKind::EnergyNotSettled);
+            assert!(malfunction.text.contains("300 ms before the energy system settled at 25 MeV"), "{}", malfunction.text);
+        }
+
+        assert!(!settle_energy(&state, Duration::from_millis(250)));
+        assert!(settle_energy(&state, Duration::from_millis(50)));
+        {
+            let mut s = state.write();
+            s.config.random_fault_probability = 0.0;
+            s.phase = TPhase::PatientTreatment;
+        }
+        zap_the_specimen(state.clone()).await;
+        assert!(state.read().dose_delivered > 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_bending_magnets_settle_at_once_by_default() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.collimator_move_ms = 10;
+            s.console_meos.beam_type = match s.hardware_meos.beam_type {
+                BeamType::XRay => BeamType::Electron,
+                _ => BeamType::XRay,
+            };
+        }
+        housekeeper_pass(&state);
+        let s = state.read();
+        assert_eq!(s.hardware_meos.beam_type, s.console_meos.beam_type);
+        assert!(!s.bending_magnet_flag);
+    }
+
+    #[tokio::test]
+    async fn test_jam_goes_unnoticed_without_the_watchdog() {
+        let state = jammed_treatment(false).await;
+        let mut watchdog = CollimatorWatchdog::default();
+        for _ in 0..100 {
+            assert!(!watchdog.check(&state, Duration::from_millis(100)));
+        }
+        let s = state.read();
+        assert!(!s.collimator_timeout);
+        assert_eq!(s.task_status.watchdog, "idle");
+    }
+
+    #[tokio::test]
+    async fn test_incidents_are_attributed_to_the_logged_in_operator() {
+        let state = setup_done_state();
+        let mismatch = |state: &SharedTheracState| {
+            let mut s = state.write();
+            s.phase = TPhase::SetupDone;
+            s.console_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E10,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            s.hardware_meos = Meos {
+                beam_type: BeamType::XRay,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::InPosition,
+            };
+        };
+        assert!(!login_operator(state.clone(), "no one"));
+        assert!(login_operator(state.clone(), "alice"));
+
+        mismatch(&state);
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+        assert!(dismiss_malfunction(state.clone()));
+        state.write().abandon_treatment();
+        {
+            let s = state.read();
+            let entry = s.log.iter().find(|e| e.message.starts_with("MALFUNCTION 54")).unwrap();
+            assert_eq!(entry.operator_id.as_deref(), Some("alice"));
+            assert_eq!(s.overrides[0].operator_id.as_deref(), Some("alice"));
+            assert_eq!(s.completed_treatments[0].operator_id.as_deref(), Some("alice"));
+            assert_eq!(
+                s.operator_stats["alice"],
+                OperatorStats { treatments: 1, overdoses: 0, near_misses: 1 }
+            );
+        }
+
+        assert!(logout_operator(state.clone()));
+        assert!(login_operator(state.clone(), "bob"));
+        mismatch(&state);
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        let entry = s.log.iter().rev().find(|e| e.message.starts_with("MALFUNCTION 54")).unwrap();
+        assert_eq!(entry.operator_id.as_deref(), Some("bob"));
+        assert_eq!(s.operator_stats["alice"].near_misses, 1);
+        assert_eq!(s.operator_stats["bob"].near_misses, 1);
+        assert_eq!(s.near_misses, 2);
+    }
+
+    /// Housekeeper log entries reporting an aspect synced ahead of the turntable
+    async fn early_syncs(order: SyncOrder) -> Vec<String> {
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        {
+            let mut s = state.write();
+            s.config.sync_order = order;
+            s.phase = TPhase::DataEntry;
+            s.hardware_meos = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E5,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            // Operator switched to X-ray: the filter has to come in
+            s.console_meos = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, ..s.hardware_meos };
+        }
+        housekeeper_pass(&state);
+
+        let s = state.read();
+        assert_eq!(s.hardware_meos.beam_type, BeamType::XRay);
+        assert_eq!(s.hardware_meos.beam_energy, BeamEnergy::E25);
+        assert_eq!(s.hardware_meos.collimator, CollimatorPosition::InPosition);
+        s.log.iter()
+            .map(|e| e.message.as_str())
+            .filter(|m| m.starts_with("[HOUSEKEEPER] Synced ahead"))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_sync_order_decides_the_transient_mismatch() {
+        let energy_first = SyncOrder(vec![SyncAspect::Energy, SyncAspect::Collimator]);
+        let beam_type_first = SyncOrder(vec![SyncAspect::BeamType, SyncAspect::Collimator]);
+
+        // Default: nothing changes until the turntable arrives
+        assert!(early_syncs(SyncOrder::default()).await.is_empty());
+        // Energy first: 25 MeV electrons with the filter not yet in
+        assert_eq!(
+            early_syncs(energy_first.clone()).await,
+            ["[HOUSEKEEPER] Synced ahead of the collimator: Electron @ 25 MeV"]
+        );
+        // Beam type first: a 5 MeV X-ray target
+        assert_eq!(
+            early_syncs(beam_type_first.clone()).await,
+            ["[HOUSEKEEPER] Synced ahead of the collimator: X-Ray @ 5 MeV"]
+        );
+
+        let console = Meos {
+            beam_type: BeamType::XRay,
+            beam_energy: BeamEnergy::E25,
+            collimator: CollimatorPosition::InPosition,
+        };
+        let hardware = Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::E5,
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        let moving = |beam_type, beam_energy| Meos { beam_type, beam_energy, collimator: CollimatorPosition::Transitioning };
+        assert_eq!(SyncOrder::default().transient(console, hardware), moving(BeamType::Electron, BeamEnergy::E5));
+        assert_eq!(energy_first.transient(console, hardware), moving(BeamType::Electron, BeamEnergy::E25));
+        assert_eq!(beam_type_first.transient(console, hardware), moving(BeamType::XRay, BeamEnergy::E5));
+        assert_eq!(SyncOrder::parse("Energy, collimator"), Some(energy_first.clone()));
+        assert_eq!(SyncOrder::parse("energy filter"), None);
+        assert_eq!(
+            energy_first.aspects(),
+            [SyncAspect::Energy, SyncAspect::Collimator, SyncAspect::BeamType, SyncAspect::Params]
+        );
+    }
+
+    #[test]
+    fn test_configuration_matrix_is_complete() {
+        let safe = Meos::all_safe_configurations();
+        let dangerous = Meos::all_unsafe_configurations();
+        let combinations = BeamType::ALL.len() * BeamEnergy::ALL.len() * CollimatorPosition::ALL.len();
+        assert_eq!(safe.len() + dangerous.len(), combinations);
+        // Only X-ray with the filter in and electrons with it out, at any energy
+        assert_eq!(safe.len(), 2 * BeamEnergy::ALL.len());
+        assert!(safe.iter().all(|m| overdose_factor(m) == 1.0));
+    }
+
+    #[tokio::test]
+    async fn test_every_unsafe_configuration_fires_its_dose_multiplier() {
+        // A stuck turntable never fires (test_stuck_collimator_inhibits_the_beam_until_freed)
+        let firing = Meos::all_unsafe_configurations().into_iter().filter(|m| m.collimator != CollimatorPosition::Stuck);
+        for meos in firing {
+            let state = setup_done_state();
+            {
+                let mut s = state.write();
+                s.console_meos = meos;
+                s.hardware_meos = meos;
+            }
+            start_treatment(state.clone());
+            zap_the_specimen(state.clone()).await;
+
+            let factor = overdose_factor(&meos);
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::PauseTreatment, "{:?}", meos);
+            assert_eq!(s.dose_delivered, calculate_dose(&meos, &TreatmentParams::default()), "{:?}", meos);
+            assert!(
+                s.last_malfunction.as_ref().unwrap().text
+                    .contains(&format!("Dose multiplier: {:.1}x", factor)),
+                "{:?}: {:?}", meos, s.last_malfunction
+            );
+            assert_eq!(s.overdose_count, u32::from(factor > 1.0), "{:?}", meos);
+            match (meos.beam_type, meos.collimator) {
+                (BeamType::XRay, CollimatorPosition::OutOfPosition) => assert_eq!(factor, FLATNESS_FILTER_SPREAD),
+                (BeamType::Electron, CollimatorPosition::InPosition) => assert_eq!(factor, FILTER_ELECTRON_TRANSMISSION),
+                _ => assert_eq!(factor, 1.0, "{:?}", meos),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forced_race_window_fires_unsafe_on_the_next_pulse() {
+        for phase in [TPhase::Reset, TPhase::DataEntry, TPhase::SetupDone, TPhase::PauseTreatment] {
+            let state = setup_done_state();
+            {
+                let mut s = state.write();
+                s.phase = phase;
+                s.editing_taking_place = true;
+                s.bending_magnet_flag = true;
+            }
+            force_race_window(state.clone());
+            assert_eq!(state.read().phase, TPhase::PatientTreatment);
+            zap_the_specimen(state.clone()).await;
+
+            let s = state.read();
+            let malfunction = s.last_malfunction.as_ref().unwrap();
+            assert!(matches!(malfunction.kind, MalfunctionKind::UnsafeBeam { .. }), "{:?}", phase);
+            assert!(malfunction.text.contains("CRITICAL SAFETY VIOLATION"));
+            assert_eq!(s.overdose_count, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dose_interlock_cuts_the_unsafe_pulse() {
+        let state = setup_done_state();
+        state.write().config.max_safe_dose_cgy = 500.0;
+        force_race_window(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::TerminateTreatment);
+        assert_eq!(s.dose_delivered, 0.0);
+        assert_eq!(s.overdose_count, 0);
+        assert!(s.last_malfunction.is_none());
+        let trip = s.log.iter().find(|e| e.message.contains("DOSE INTERLOCK TRIPPED")).unwrap();
+        assert_eq!(trip.level, LogLevel::Warn);
+        assert!(matches!(
+            s.completed_treatments.last().unwrap().outcome,
+            TreatmentOutcome::Terminated { phase: TPhase::PatientTreatment, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_without_dose_interlock_the_race_overdoses() {
+        let state = setup_done_state();
+        assert_eq!(state.read().config.max_safe_dose_cgy, 0.0);
+        force_race_window(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PauseTreatment);
+        assert!(s.dose_delivered > 500.0, "{}", s.dose_delivered);
+        assert_eq!(s.overdose_count, 1);
+        assert!(!s.log.iter().any(|e| e.message.contains("DOSE INTERLOCK")));
+    }
+
+    #[tokio::test]
+    async fn test_stuck_collimator_inhibits_the_beam_until_freed() {
+        for beam_type in [BeamType::XRay, BeamType::Electron] {
+            let stuck = Meos { beam_type, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::Stuck };
+            assert!(!stuck.is_safe());
+            assert!(!stuck.needs_collimator_sync());
+        }
+
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.console_meos.collimator = CollimatorPosition::Stuck;
+            s.hardware_meos.collimator = CollimatorPosition::Stuck;
+        }
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+        {
+            let s = state.read();
+            assert_eq!(s.last_malfunction.as_ref().unwrap().kind, MalfunctionKind::CollimatorStuck);
+            assert_eq!(s.dose_delivered, 0.0);
+            assert_eq!(s.phase, TPhase::PauseTreatment);
+        }
+
+        // The housekeeper cannot move it; an operator has to free it
+        let mut s = state.write();
+        s.phase = TPhase::DataEntry;
+        s.console_meos = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::OutOfPosition };
+        drop(s);
+        crate::tick::housekeeper_tick(&state, Duration::from_millis(10));
+        assert_eq!(state.read().hardware_meos.collimator, CollimatorPosition::Stuck);
+
+        assert!(state.write().free_stuck_collimator());
+        assert!(!state.write().free_stuck_collimator());
+        // Re-homed where the console's X-ray needs it, not to a default that would
+        // leave the filter out of an X-ray beam
+        assert_eq!(state.read().hardware_meos.collimator, CollimatorPosition::InPosition);
+        crate::tick::housekeeper_tick(&state, Duration::from_millis(10));
+        assert_eq!(state.read().hardware_meos.collimator, CollimatorPosition::Transitioning);
+    }
+
+    #[test]
+    fn test_turntable_move_can_stick() {
+        let state = Arc::new(RwLock::new(TheracState {
+            phase: TPhase::DataEntry,
+            console_meos: Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::OutOfPosition },
+            ..TheracState::default()
+        }));
+        state.write().config.collimator_stick_probability = 1.0;
+        crate::tick::housekeeper_tick(&state, Duration::from_millis(10));
+        crate::tick::housekeeper_tick(&state, COLLIMATOR_TRANSIT_TIME);
+
+        let s = state.read();
+        assert_eq!(s.hardware_meos.collimator, CollimatorPosition::Stuck);
+        assert!(s.log.iter().any(|e| e.message.contains("Collimator stuck mid-rotation") && e.level == LogLevel::Warn));
+    }
+
+    #[test]
+    fn test_nan_stick_probability_never_sticks() {
+        let state = Arc::new(RwLock::new(TheracState {
+            phase: TPhase::DataEntry,
+            console_meos: Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::OutOfPosition },
+            ..TheracState::default()
+        }));
+        state.write().config.collimator_stick_probability = f32::NAN;
+        crate::tick::housekeeper_tick(&state, Duration::from_millis(10));
+        crate::tick::housekeeper_tick(&state, COLLIMATOR_TRANSIT_TIME);
+        assert_eq!(state.read().hardware_meos.collimator, CollimatorPosition::InPosition);
+    }
+
+    #[tokio::test]
+    async fn test_reported_overdose_is_capped_but_delivered_dose_is_not() {
+        let unflattened = Meos {
+            beam_type: BeamType::XRay,
+            beam_energy: BeamEnergy::E25,
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        let dose = calculate_dose(&unflattened, &TreatmentParams::default());
+        let cap = (dose / 2.0).round();
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.reported_dose_cap = cap;
+            s.console_meos = unflattened;
+            s.hardware_meos = unflattened;
+        }
+        start_treatment(state.clone());
+        zap_the_specimen(state.clone()).await;
+
+        let s = state.read();
+        let message = &s.last_malfunction.as_ref().unwrap().text;
+        assert!(message.contains(&format!("Delivered >{:.0} cGy this pulse", cap)), "{}", message);
+        assert!(message.contains("LETHAL OVERDOSE"), "{}", message);
+        assert!(!message.contains(&format!("{:.1}", dose)), "{}", message);
+        assert_eq!(s.dose_delivered, dose);
+        assert!(matches!(
+            s.completed_treatments[0].outcome,
+            TreatmentOutcome::Overdose { dose_this_pulse, .. } if dose_this_pulse == dose
+        ));
+
+        let uncapped = SimConfig::default();
+        assert_eq!(uncapped.reported_dose(dose), format!("{:.1}", dose));
+        assert!(!uncapped.exceeds_reported_cap(dose));
+    }
+
+    #[test]
+    fn test_dose_sweep_covers_every_configuration() {
+        let sweep = dose_sweep();
+        assert_eq!(sweep.len(), 2 * BeamEnergy::ALL.len() * 2);
+
+        for safe in sweep.iter().filter(|e| e.meos.is_safe()) {
+            let wrong_filter = sweep.iter()
+                .find(|e| !e.meos.is_safe()
+                    && e.meos.beam_type == safe.meos.beam_type
+                    && e.meos.beam_energy == safe.meos.beam_energy)
+                .unwrap();
+            let ratio = wrong_filter.dose_per_pulse / safe.dose_per_pulse;
+            match safe.meos.beam_type {
+                BeamType::XRay => assert!((ratio - 100.0).abs() < 1e-9),
+                _ => assert!((ratio - 0.1).abs() < 1e-9),
+            }
+        }
+    }
+
+    #[test]
+    fn test_beam_current_is_the_same_in_both_modes() {
+        let params = TreatmentParams::default();
+        for beam_energy in BeamEnergy::ALL {
+            let electron = Meos {
+                beam_type: BeamType::Electron,
+                beam_energy,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            let xray = Meos {
+                beam_type: BeamType::XRay,
+                collimator: CollimatorPosition::InPosition,
+                ..electron
+            };
+            let bare_xray = Meos { collimator: CollimatorPosition::OutOfPosition, ..xray };
+            let current = beam_current(&electron);
+            assert!(current > 0.0);
+            assert_eq!(beam_current(&xray), current);
+            assert_eq!(beam_current(&bare_xray), current);
+
+            // Same current: the filter alone makes the 100x difference
+            let ratio = calculate_dose(&bare_xray, &params) / calculate_dose(&xray, &params);
+            assert!((ratio - FLATNESS_FILTER_SPREAD).abs() < 1e-9);
+            assert!((overdose_factor(&bare_xray) - FLATNESS_FILTER_SPREAD).abs() < 1e-9);
+            assert_eq!(overdose_factor(&xray), 1.0);
+            assert_eq!(overdose_factor(&electron), 1.0);
+        }
+        assert_eq!(beam_current(&Meos::default()), 0.0);
+    }
+
+    #[test]
+    fn test_intermediate_energies_interpolate_the_beam_current() {
+        let at = |mev: f32| beam_current(&Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::from_mev(mev).unwrap(),
+            collimator: CollimatorPosition::OutOfPosition,
+        });
+        for (preset, (mev, current)) in BeamEnergy::ALL.iter().zip(PRESET_BEAM_CURRENT) {
+            assert_eq!(preset.mev() as f64, mev);
+            assert_eq!(at(mev as f32), current);
+        }
+        assert!((at(18.0) - 7.2).abs() < 1e-6);
+        assert!((at(12.5) - 5.0).abs() < 1e-6);
+
+        let electron = Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::EnergyMeV(18.0),
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        let params = TreatmentParams::default();
+        let dose = calculate_dose(&electron, &params);
+        let below = calculate_dose(&Meos { beam_energy: BeamEnergy::E15, ..electron }, &params);
+        let above = calculate_dose(&Meos { beam_energy: BeamEnergy::E20, ..electron }, &params);
+        assert!(below < dose && dose < above);
+        assert!((treatment_depth_cm(&electron) - 6.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_pulse_dose_scales_with_dose_rate() {
+        let xray = Meos {
+            beam_type: BeamType::XRay,
+            beam_energy: BeamEnergy::E25,
+            collimator: CollimatorPosition::InPosition,
+        };
+        let params = TreatmentParams::default();
+        let reference = calculate_dose(&xray, &params);
+        assert_eq!(reference, beam_current(&xray) * PENCIL_BEAM_CONCENTRATION / FLATNESS_FILTER_SPREAD);
+
+        let doubled = TreatmentParams { dose_rate: params.dose_rate * 2.0, ..params };
+        assert!((calculate_dose(&xray, &doubled) / reference - 2.0).abs() < 1e-9);
+        let stopped = TreatmentParams { dose_rate: 0.0, ..params };
+        assert_eq!(calculate_dose(&xray, &stopped), 0.0);
+    }
+
+    #[test]
+    fn test_field_size_concentrates_dose_when_enabled() {
+        let mut s = TheracState {
+            hardware_meos: Meos {
+                beam_type: BeamType::Electron,
+                beam_energy: BeamEnergy::E10,
+                collimator: CollimatorPosition::OutOfPosition,
+            },
+            hardware_params: TreatmentParams { field_size_x: 5.0, field_size_y: 5.0, ..TreatmentParams::default() },
+            ..TheracState::default()
+        };
+        let nominal = calculate_dose(&s.hardware_meos, &s.hardware_params);
+
+        // Off by default: the field size changes nothing
+        assert_eq!(pulse_dose(&s), nominal);
+
+        // A quarter of the 10x10 area, four times the dose
+        s.config.field_size_scales_dose = true;
+        assert!((pulse_dose(&s) / nominal - 4.0).abs() < 1e-9);
+        s.hardware_params.field_size_x = 20.0;
+        s.hardware_params.field_size_y = 20.0;
+        assert!((pulse_dose(&s) / nominal - 0.25).abs() < 1e-9);
+
+        // A degenerate field is capped, not infinite
+        s.hardware_params.field_size_x = 0.0;
+        assert_eq!(field_size_factor(&s.hardware_params), REFERENCE_FIELD_AREA / MIN_FIELD_AREA);
+    }
+
+    #[tokio::test]
+    async fn test_run_to_completion_requires_setup_done() {
+        let state = setup_done_state();
+        state.write().phase = TPhase::DataEntry;
+
+        let outcome = run_treatment_to_completion(state.clone()).await;
+
+        assert_eq!(outcome, TreatmentOutcome::NotReady(TPhase::DataEntry));
+        assert_eq!(state.read().dose_delivered, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_run_to_completion_reaches_target() {
+        // Random hardware faults can pause any pulse, so retry until a run gets through
+        for _ in 0..200 {
+            let state = setup_done_state();
+            state.write().dose_target = 1.0;
+
+            if let TreatmentOutcome::TargetReached { dose_delivered } =
+                run_treatment_to_completion(state.clone()).await
+            {
+                assert!(dose_delivered >= 1.0);
+                assert_eq!(state.read().phase, TPhase::TerminateTreatment);
+                return;
+            }
+        }
+        panic!("no run reached the target dose");
+    }
+
+    fn run_transit(reverse_at: Option<Duration>, hysteresis: Duration) -> CollimatorTransit {
+        let step = Duration::from_millis(80);
+        let mut transit = CollimatorTransit::start(BeamType::XRay);
+        while !transit.is_done() {
+            transit.advance(step);
+            if Some(transit.elapsed()) == reverse_at {
+                assert!(transit.retarget(BeamType::Electron, hysteresis));
+            }
+            // The same target again is not a reversal
+            assert!(!transit.retarget(transit.target, hysteresis));
+        }
+        transit
+    }
+
+    #[test]
+    fn test_mid_move_reversal_adds_hysteresis() {
+        let hysteresis = Duration::from_millis(400);
+
+        let straight = run_transit(None, hysteresis);
+        assert_eq!(straight.elapsed(), COLLIMATOR_TRANSIT_TIME);
+        assert_eq!(straight.reversals, 0);
+
+        let reversed = run_transit(Some(Duration::from_millis(400)), hysteresis);
+        assert_eq!(reversed.target, BeamType::Electron);
+        assert_eq!(reversed.reversals, 1);
+        assert_eq!(reversed.elapsed(), Duration::from_millis(400) + COLLIMATOR_TRANSIT_TIME + hysteresis);
+
+        // Without hysteresis a reversal still costs the restart, but no more
+        let frictionless = run_transit(Some(Duration::from_millis(400)), Duration::ZERO);
+        assert_eq!(frictionless.elapsed(), Duration::from_millis(400) + COLLIMATOR_TRANSIT_TIME);
+        assert!(reversed.elapsed() > frictionless.elapsed());
+    }
+
+    #[test]
+    fn test_wear_lengthens_transit_until_serviced() {
+        let mut s = TheracState {
+            phase: TPhase::SetupDone,
+            ..TheracState::default()
+        };
+        s.config.wear_per_treatment = 0.05;
+        s.console_meos.beam_type = BeamType::XRay;
+        s.hardware_params = s.console_params;
+        let new_machine = time_until_safe_to_treat(&s).unwrap();
+        assert_eq!(new_machine, COLLIMATOR_TRANSIT_TIME);
+
+        for _ in 0..5 {
+            s.begin_treatment();
+        }
+        assert!(s.wear_factor > 1.0);
+        let worn = time_until_safe_to_treat(&s).unwrap();
+        assert!(worn > new_machine, "{:?} vs {:?}", worn, new_machine);
+        let mut transit = CollimatorTransit::with_transit_time(BeamType::XRay, s.worn(COLLIMATOR_TRANSIT_TIME));
+        transit.advance(COLLIMATOR_TRANSIT_TIME);
+        assert!(!transit.is_done());
+
+        // Bounded however many treatments are run
+        for _ in 0..1000 {
+            s.begin_treatment();
+        }
+        assert_eq!(s.wear_factor, s.config.max_wear_factor);
+
+        s.reset_wear();
+        assert_eq!(time_until_safe_to_treat(&s), Some(new_machine));
+    }
+
+    #[tokio::test]
+    async fn test_emergency_off_stops_dose_and_refuses_resume() {
+        let state = setup_done_state();
+        start_treatment(state.clone());
+        assert_eq!(state.read().phase, TPhase::PatientTreatment);
+
+        emergency_off(state.clone());
+        let dose = state.read().dose_delivered;
+        // A pulse that was already under way does not fire
+        zap_the_specimen(state.clone()).await;
+        monitor_pass(&state);
+        assert_eq!(state.read().dose_delivered, dose);
+
+        resume_treatment(state.clone());
+        start_treatment(state.clone());
+        assert!(!clear_malfunction(state.clone()));
+        {
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::EmergencyStop);
+            assert!(matches!(
+                s.completed_treatments.last().unwrap().outcome,
+                TreatmentOutcome::Terminated { phase: TPhase::EmergencyStop, .. }
+            ));
+        }
+
+        handle_reset_command(state.clone());
+        assert_eq!(state.read().phase, TPhase::Reset);
+    }
+
+    #[test]
+    fn test_paused_monitor_holds_the_phase_until_resumed() {
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        assert!(pause_simulation(state.clone()));
+        assert!(!pause_simulation(state.clone()));
+        assert!(is_simulation_paused(&state));
+
+        // The monitor's next pass, after its sleep
+        let monitor = {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_time()
+                    .build()
+                    .unwrap()
+                    .block_on(task_sleep(&state, MONITOR_POLL));
+                monitor_pass(&state);
+            })
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(state.read().phase, TPhase::Reset);
+
+        assert!(resume_simulation(state.clone()));
+        monitor.join().unwrap();
+        assert_eq!(state.read().phase, TPhase::DataEntry);
+        assert!(!resume_simulation(state.clone()));
+        assert!(state.read().log.iter().any(|e| e.message.contains("Simulation paused")));
+    }
+
+    #[test]
+    fn test_time_until_safe_to_treat() {
+        let electron = Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::E10,
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        let mut s = TheracState {
+            phase: TPhase::SetupDone,
+            console_meos: electron,
+            hardware_meos: electron,
+            ..TheracState::default()
+        };
+        s.hardware_params = s.console_params;
+        assert_eq!(time_until_safe_to_treat(&s), None);
+
+        // Console flipped to X-ray: a whole move is about to start
+        s.console_meos.beam_type = BeamType::XRay;
+        assert_eq!(time_until_safe_to_treat(&s), Some(COLLIMATOR_TRANSIT_TIME));
+
+        // Mid-transit, with the gantry still to follow
+        s.hardware_meos.collimator = CollimatorPosition::Transitioning;
+        s.collimator_transit_remaining_ms = 600;
+        s.console_params.gantry_angle = s.hardware_params.gantry_angle + 90;
+        assert_eq!(
+            time_until_safe_to_treat(&s),
+            Some(Duration::from_millis(600) + PARAM_SYNC_TIME)
+        );
+
+        // The housekeeper does not sync during treatment
+        s.phase = TPhase::PatientTreatment;
+        assert_eq!(time_until_safe_to_treat(&s), None);
+    }
+
+    #[test]
+    fn test_sync_timings_follow_the_config() {
+        let electron = Meos {
+            beam_type: BeamType::Electron,
+            beam_energy: BeamEnergy::E10,
+            collimator: CollimatorPosition::OutOfPosition,
+        };
+        let mut s = TheracState {
+            phase: TPhase::SetupDone,
+            console_meos: Meos { beam_type: BeamType::XRay, ..electron },
+            hardware_meos: electron,
+            ..TheracState::default()
+        };
+        s.console_params.gantry_angle = s.hardware_params.gantry_angle + 90;
+        assert_eq!(time_until_safe_to_treat(&s), Some(COLLIMATOR_TRANSIT_TIME + PARAM_SYNC_TIME));
+
+        // Widened for a demonstration: the whole window moves with it
+        s.config.collimator_move_ms = 2000;
+        s.config.param_sync_ms = 50;
+        assert_eq!(time_until_safe_to_treat(&s), Some(Duration::from_millis(2050)));
+        let hysteresis = Duration::from_millis(s.config.collimator_hysteresis_ms as u64);
+        assert_eq!(CollimatorWatchdog::limit(&s), 2 * (Duration::from_millis(2000) + hysteresis));
+    }
+
+    #[tokio::test]
+    async fn test_clear_malfunction_keeps_count_and_allows_reentry() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.hardware_meos.beam_energy = BeamEnergy::E5;
+            s.console_meos.beam_energy = BeamEnergy::E25;
+        }
+        let outcome = run_treatment_to_completion(state.clone()).await;
+        assert!(matches!(outcome, TreatmentOutcome::Paused { .. }));
+
+        assert!(clear_malfunction(state.clone()));
+        assert!(!clear_malfunction(state.clone()));
+        {
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::DataEntry);
+            assert!(s.last_malfunction.is_none());
+            assert_eq!(s.malfunction_count, 1);
+            assert!(!s.data_entry_complete);
+            assert!(s.overrides.is_empty());
+        }
+
+        // Fix the entry and go through setup again
+        handle_energy_input(state.clone(), BeamEnergy::E5);
+        complete_data_entry(state.clone());
+        for _ in 0..20 {
+            monitor_pass(&state);
+        }
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::SetupDone);
+        assert_eq!(s.malfunction_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_abort_during_beam_hold_delivers_no_dose() {
+        let state = setup_done_state();
+        state.write().config.beam_hold_ms = 300;
+
+        start_treatment(state.clone());
+        assert_eq!(state.read().phase, TPhase::BeamReady);
+        monitor_pass(&state);
+        assert_eq!(state.read().beam_hold_remaining_ms, 200);
+
+        stop_treatment(state.clone());
+        // The monitor keeps running after the abort; nothing may fire
+        for _ in 0..5 {
+            monitor_pass(&state);
+        }
+
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::SetupDone);
+        assert_eq!(s.dose_delivered, 0.0);
+        assert!(s.completed_treatments.is_empty());
+        assert!(s.treatment_in_progress.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_beam_turns_on_after_hold() {
+        let state = setup_done_state();
+        state.write().config.beam_hold_ms = 250;
+
+        start_treatment(state.clone());
+        for _ in 0..3 {
+            assert_eq!(state.read().phase, TPhase::BeamReady);
+            monitor_pass(&state);
+        }
+
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PatientTreatment);
+        assert!(s.treatment_in_progress.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_full_treatment_produces_summary() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.dose_target = 20.0;
+            s.patient_name = "J. Doe".to_string();
+        }
+
+        // Operator resumes past every random fault until the target is reached
+        start_treatment(state.clone());
+        for _ in 0..1000 {
+            zap_the_specimen(state.clone()).await;
+            let phase = state.read().phase;
+            match phase {
+                TPhase::PauseTreatment => resume_treatment(state.clone()),
+                TPhase::TerminateTreatment => break,
+                _ => {}
+            }
+        }
+
+        let s = state.read();
+        assert_eq!(s.completed_treatments.len(), 1);
+        let summary = &s.completed_treatments[0];
+        assert_eq!(summary.patient_name, "J. Doe");
+        assert_eq!(summary.prescribed_meos, s.reference_meos);
+        assert_eq!(summary.prescribed_dose, s.reference_dose_target);
+        assert!(summary.dose_delivered >= 20.0);
+        assert_eq!(summary.malfunctions.len() as u32, s.malfunction_count);
+        assert!(matches!(summary.outcome, TreatmentOutcome::TargetReached { .. }));
+        let started = chrono::DateTime::parse_from_rfc3339(&summary.started_at).unwrap();
+        let ended = chrono::DateTime::parse_from_rfc3339(&summary.ended_at).unwrap();
+        assert!(started <= ended);
+        assert!(summary.duration_secs >= 0.0);
+
+        let exported: Vec<TreatmentSummary> =
+            serde_json::from_str(&s.export_treatment_summaries().unwrap()).unwrap();
+        assert_eq!(&exported, &s.completed_treatments);
+    }
+
+    #[tokio::test]
+    async fn test_nominal_treatment_dose_trace_is_linear() {
+        let state = setup_done_state();
+        state.write().dose_target = 40.0;
+        let hardware = state.read().hardware_meos;
+        let pulse = calculate_dose(&hardware, &TreatmentParams::default());
+
+        start_treatment(state.clone());
+        for _ in 0..1000 {
+            zap_the_specimen(state.clone()).await;
+            let phase = state.read().phase;
+            match phase {
+                TPhase::PauseTreatment => resume_treatment(state.clone()),
+                TPhase::TerminateTreatment => break,
+                _ => {}
+            }
+        }
+
+        let s = state.read();
+        let samples = &s.dose_samples;
+        assert_eq!(samples[0], (0.0, 0.0));
+        assert_eq!(samples.last().unwrap().1, s.dose_delivered);
+        for pair in samples.windows(2) {
+            assert!(pair[1].0 >= pair[0].0, "time went backwards: {:?}", pair);
+            // Same dose every pulse: a straight line, no spike
+            assert!((pair[1].1 - pair[0].1 - pulse).abs() < 1e-9, "{:?}", pair);
+        }
+        assert_eq!(s.dose_samples_flat().len(), samples.len() * 2);
+    }
+
+    #[test]
+    fn test_reset_mid_treatment_records_termination() {
+        let state = setup_done_state();
+        start_treatment(state.clone());
+        state.write().dose_delivered = 12.0;
+
+        handle_reset_command(state.clone());
+        // A second reset has no open treatment to close
+        handle_reset_command(state.clone());
+
+        let s = state.read();
+        assert_eq!(s.completed_treatments.len(), 1);
+        assert_eq!(
+            s.completed_treatments[0].outcome,
+            TreatmentOutcome::Terminated { dose_delivered: 12.0, phase: TPhase::PatientTreatment }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_to_completion_pauses_on_interlock() {
+        let state = setup_done_state();
+        state.write().hardware_meos.beam_energy = BeamEnergy::E5;
+        state.write().console_meos.beam_energy = BeamEnergy::E25;
+
+        let outcome = run_treatment_to_completion(state.clone()).await;
+
+        match outcome {
+            TreatmentOutcome::Paused { dose_delivered, malfunction } => {
+                assert_eq!(dose_delivered, 0.0);
+                let malfunction = malfunction.unwrap();
+                assert_eq!(malfunction.kind, MalfunctionKind::ParameterMismatch);
+                assert!(malfunction.text.starts_with("MALFUNCTION 54"));
+            }
+            other => panic!("expected Paused, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_to_completion_reports_overdose() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            let unflattened = Meos {
+                beam_type: BeamType::XRay,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            s.console_meos = unflattened;
+            s.hardware_meos = unflattened;
+        }
+
+        let outcome = run_treatment_to_completion(state.clone()).await;
+
+        assert_eq!(outcome, TreatmentOutcome::Overdose {
+            dose_delivered: 800.0,
+            dose_this_pulse: 800.0,
+            multiplier: 100.0,
+        });
+        assert_eq!(state.read().last_outcome, Some(outcome));
+    }
+
+    #[test]
+    fn test_treatment_outcome_terminated_short_of_target() {
+        let mut s = TheracState::new();
+        s.phase = TPhase::Reset;
+        s.dose_delivered = 40.0;
+
+        assert_eq!(
+            treatment_outcome(&s, 40.0, s.hardware_meos),
+            TreatmentOutcome::Terminated { dose_delivered: 40.0, phase: TPhase::Reset }
+        );
+    }
+
+    #[test]
+    fn test_treatment_depth_per_energy() {
+        let meos = |beam_type, beam_energy, collimator| Meos { beam_type, beam_energy, collimator };
+        let mut last_xray = 0.0;
+        let mut last_electron = 0.0;
+
+        for energy in BeamEnergy::ALL {
+            let xray = treatment_depth_cm(&meos(BeamType::XRay, energy, CollimatorPosition::InPosition));
+            let electron = treatment_depth_cm(&meos(BeamType::Electron, energy, CollimatorPosition::OutOfPosition));
+            assert!(xray > last_xray && electron > last_electron);
+            assert!(xray > electron);
+            last_xray = xray;
+            last_electron = electron;
+
+            // Unflattened X-ray mode fires the raw electron beam
+            let unflattened = treatment_depth_cm(&meos(BeamType::XRay, energy, CollimatorPosition::OutOfPosition));
+            assert_eq!(unflattened, electron);
+        }
+
+        let e5 = meos(BeamType::Electron, BeamEnergy::E5, CollimatorPosition::OutOfPosition);
+        assert!((treatment_depth_cm(&e5) - 5.0 / 3.0).abs() < 1e-6);
+        let x25 = meos(BeamType::XRay, BeamEnergy::E25, CollimatorPosition::InPosition);
+        assert!((treatment_depth_cm(&x25) - 22.25).abs() < 1e-6);
+        assert_eq!(treatment_depth_cm(&Meos::default()), 0.0);
+    }
+
+    #[test]
+    fn test_random_parameters_are_safe_and_follow_the_stream() {
+        assert!(generate_random_parameters().is_safe());
+        let draw = |seed| generate_random_parameters_with(&mut StdRng::seed_from_u64(seed));
+        assert_eq!(draw(7), draw(7));
+        assert!(draw(7).is_safe());
+    }
+
+    #[test]
+    fn test_classify_delivery() {
+        assert_eq!(classify_delivery(100.0, 200.0), DeliveryClass::Underdose);
+        assert_eq!(classify_delivery(204.0, 200.0), DeliveryClass::AsPrescribed);
+        assert_eq!(classify_delivery(400.0, 200.0), DeliveryClass::Overdose);
+        assert_eq!(classify_delivery(800.0 * 100.0, 200.0), DeliveryClass::SevereOverdose);
+    }
+
+    #[test]
+    fn test_partial_checklist_refuses_treatment() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.require_checklist = true;
+            s.checklist.toggle(ChecklistItem::DoorClosed);
+            s.checklist.toggle(ChecklistItem::PatientPositioned);
+            s.checklist.toggle(ChecklistItem::PrescriptionVerified);
+        }
+
+        start_treatment(state.clone());
+        assert_eq!(state.read().phase, TPhase::SetupDone);
+
+        state.write().checklist.toggle(ChecklistItem::CollimatorConfirmed);
+        start_treatment(state.clone());
+        assert_eq!(state.read().phase, TPhase::PatientTreatment);
+    }
+
+    #[tokio::test]
+    async fn test_treatment_refused_until_recalibrated() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.recalibration_interval = 2;
+            s.treatments_since_calibration = 1;
+            s.wear_factor = 1.3;
+        }
+        start_treatment(state.clone());
+        assert_eq!(state.read().phase, TPhase::PatientTreatment);
+        assert_eq!(state.read().treatments_since_calibration, 2);
+
+        // The limit is reached: the next treatment is refused
+        state.write().phase = TPhase::SetupDone;
+        assert!(!start_treatment(state.clone()));
+        {
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::SetupDone);
+            assert!(s.log.last().unwrap().message.starts_with("[OPERATOR] RECALIBRATION REQUIRED"));
+            assert!(s.recalibration_required());
+        }
+        // The embedding API's treat command is held to the same gate
+        assert!(!handle_treat_command(state.clone()));
+        assert_eq!(state.read().phase, TPhase::SetupDone);
+
+        // Recalibration also services the worn mechanics
+        state.write().recalibrate();
+        assert_eq!(state.read().wear_factor, 1.0);
+        start_treatment(state.clone());
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PatientTreatment);
+        assert_eq!(s.treatments_since_calibration, 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_all_does_not_make_beam_safe() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.require_checklist = true;
+            let unflattened = Meos {
+                beam_type: BeamType::XRay,
+                beam_energy: BeamEnergy::E25,
+                collimator: CollimatorPosition::OutOfPosition,
+            };
+            s.console_meos = unflattened;
+            s.hardware_meos = unflattened;
+        }
+        check_all_checklist_items(state.clone());
+        assert!(state.read().checklist.all_checked());
+
+        // "Collimator confirmed" was ticked, yet the turntable is out
+        let outcome = run_treatment_to_completion(state.clone()).await;
+        assert!(matches!(outcome, TreatmentOutcome::Overdose { .. }));
+    }
+
+    #[test]
+    fn test_repeated_dismissals_raise_normalized_deviance() {
+        let state = crate::create_therac_state();
+        state.write().config.normalized_deviance_threshold = 3;
+
+        for i in 1..=3 {
+            {
+                let mut s = state.write();
+                s.phase = TPhase::PauseTreatment;
+                s.last_malfunction = Some(Malfunction {
+                    kind: MalfunctionKind::ParameterMismatch,
+                    text: format!("MALFUNCTION 54 #{}", i),
+                });
+            }
+            if i % 2 == 0 {
+                assert!(dismiss_malfunction(state.clone()));
+            } else {
+                resume_treatment(state.clone());
+            }
+            assert_eq!(state.read().normalized_deviance_warnings, u32::from(i == 3));
+        }
+
+        let s = state.read();
+        assert_eq!(s.malfunction_dismissals, 3);
+        assert_eq!(s.overrides.len(), 3);
+        assert!(s.overrides.iter().all(|o| o.kind == OverrideKind::MalfunctionDismissed));
+        assert_eq!(s.overrides[1].detail, "MALFUNCTION 54 #2");
+        assert!(s.log.iter().any(|entry| entry.message.contains("NORMALIZED DEVIANCE")));
+    }
+
+    #[test]
+    fn test_tasks_and_operator_do_not_deadlock_under_load() {
+        use std::sync::mpsc;
+        use std::thread;
+
+        const ITERATIONS: usize = 2_000;
+
+        let state = Arc::new(RwLock::new(TheracState::new()));
+        let (done, finished) = mpsc::channel();
+
+        let monitor_state = state.clone();
+        let monitor_done = done.clone();
+        thread::spawn(move || {
+            for _ in 0..ITERATIONS {
+                monitor_tick(&monitor_state, MONITOR_POLL);
+            }
+            monitor_done.send("monitor").unwrap();
+        });
+
+        let housekeeper_state = state.clone();
+        let housekeeper_done = done.clone();
+        thread::spawn(move || {
+            for _ in 0..ITERATIONS {
+                housekeeper_tick(&housekeeper_state, HOUSEKEEPER_POLL);
+            }
+            housekeeper_done.send("housekeeper").unwrap();
+        });
+
+        let operator_state = state.clone();
+        thread::spawn(move || {
+            for i in 0..ITERATIONS {
+                let mode = if i % 2 == 0 { BeamType::XRay } else { BeamType::Electron };
+                handle_mode_input(operator_state.clone(), mode);
+                handle_gantry_input(operator_state.clone(), (i % 360) as u16);
+                complete_data_entry(operator_state.clone());
+                start_treatment(operator_state.clone());
+                stop_treatment(operator_state.clone());
+                resume_treatment(operator_state.clone());
+                if i % 100 == 0 {
+                    handle_reset_command(operator_state.clone());
+                }
+            }
+            done.send("operator").unwrap();
+        });
+
+        for _ in 0..3 {
+            finished
+                .recv_timeout(Duration::from_secs(60))
+                .expect("a task or the operator thread deadlocked");
+        }
+    }
+
+    #[test]
+    fn test_dose_jitter_averages_to_nominal() {
+        let mut rng = rand::thread_rng();
+        let nominal = 8.0;
+        assert_eq!(jittered_dose(nominal, 0.0, &mut rng), nominal);
+
+        let pulses = 20_000;
+        let doses: Vec<f64> = (0..pulses).map(|_| jittered_dose(nominal, 0.05, &mut rng)).collect();
+        let mean = doses.iter().sum::<f64>() / pulses as f64;
+
+        assert!((mean - nominal).abs() < nominal * 0.005, "mean {} too far from {}", mean, nominal);
+        assert!(doses.iter().all(|d| (d - nominal).abs() <= nominal * 0.05 + 1e-9));
+        assert!(doses.iter().any(|d| *d != nominal));
+    }
+
+    #[tokio::test]
+    async fn test_zero_fault_probability_never_faults() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.random_fault_probability = 0.0;
+            s.dose_target = 1_000_000.0;
+        }
+        start_treatment(state.clone());
+
+        for _ in 0..500 {
+            zap_the_specimen(state.clone()).await;
+            let s = state.read();
+            assert_eq!(s.phase, TPhase::PatientTreatment);
+            assert!(!s.log.iter().any(|e| e.message.contains("Random hardware fault")));
+        }
+        let s = state.read();
+        assert!(s.incidents.is_empty());
+        assert!(s.log.iter().any(|e| e.message.contains("Random fault suppressed")));
+    }
+
+    #[test]
+    fn test_pulse_repetition_spaces_pulses_by_dose_rate() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.pulse_repetition_hz = 100.0;
+            s.config.random_fault_probability = 0.0;
+            s.config.safe_mode = true;
+            s.dose_target = 1_000_000.0;
+            s.console_params.dose_rate = 2.0 * REFERENCE_DOSE_RATE as f32;
+            s.hardware_params = s.console_params;
+        }
+        start_treatment(state.clone());
+        // Twice the reference dose rate: 200 pulses a second, each of the reference dose
+        assert_eq!(pulse_repetition_rate(&state.read()), Some(200.0));
+        let per_pulse = calculate_dose(&state.read().hardware_meos, &TreatmentParams::default());
+        assert_eq!(pulse_dose(&state.read()), per_pulse);
+
+        for _ in 0..50 {
+            crate::tick::monitor_tick(&state, Duration::from_millis(1));
+        }
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PatientTreatment);
+        assert!((s.beam_on_seconds - 0.05).abs() < 1e-9, "beam on {} s", s.beam_on_seconds);
+        assert!((s.dose_delivered - 10.0 * per_pulse).abs() < 1e-9);
+        // Dose accumulates at the dose rate set: dose per second = PRF x dose per pulse
+        assert!((s.dose_delivered / s.beam_on_seconds - 200.0 * per_pulse).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_zero_dose_rate_fires_no_pulse_with_prf() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.pulse_repetition_hz = 100.0;
+            s.config.safe_mode = true;
+            s.hardware_params.dose_rate = 0.0;
+        }
+        start_treatment(state.clone());
+        for _ in 0..100 {
+            crate::tick::monitor_tick(&state, Duration::from_millis(10));
+        }
+        let s = state.read();
+        assert_eq!(s.dose_delivered, 0.0);
+        assert_eq!(s.beam_on_seconds, 0.0);
+    }
+
+    #[test]
+    fn test_routine_completion_is_not_flagged_as_an_overdose() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.random_fault_probability = 0.0;
+            s.config.safe_mode = true;
+            // The last pulse straddles the target
+            s.dose_target = 2.5 * pulse_dose(&s);
+        }
+        start_treatment(state.clone());
+        for _ in 0..10 {
+            crate::tick::monitor_tick(&state, Duration::from_millis(1));
+        }
+        {
+            let s = state.read();
+            assert_ne!(s.phase, TPhase::PatientTreatment);
+            assert!(s.dose_percent_uncapped() > 100.0);
+            assert!(!dose_past_target(&s));
+        }
+
+        // An unflattened pulse on an electron setup is far past it
+        let mut s = state.write();
+        s.dose_delivered = 0.99 * s.dose_target;
+        let unflattened = Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::OutOfPosition };
+        s.dose_delivered += calculate_dose(&unflattened, &s.hardware_params);
+        assert!(dose_past_target(&s));
+    }
+
+    #[test]
+    fn test_tiny_prf_never_overflows_the_pulse_period() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.pulse_repetition_hz = 1e-20;
+            s.config.safe_mode = true;
+        }
+        start_treatment(state.clone());
+        assert_eq!(monitor_pass_time(&state.read(), TPhase::PatientTreatment), Duration::MAX);
+        for _ in 0..10 {
+            crate::tick::monitor_tick(&state, Duration::from_millis(10));
+        }
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::PatientTreatment);
+        assert_eq!(s.dose_delivered, 0.0);
+    }
+
+    #[test]
+    fn test_recompleting_clears_edit_and_repeats_setup() {
+        let state = setup_done_state();
+        handle_dose_input(state.clone(), 180.0);
+        assert!(state.read().editing_taking_place);
+
+        complete_data_entry(state.clone());
+
+        let s = state.read();
+        assert!(!s.editing_taking_place);
+        assert_eq!(s.phase, TPhase::DataEntry);
+        assert!(s.data_entry_complete);
+    }
+
+    #[tokio::test]
+    async fn test_beam_terminates_at_monitor_units() {
+        let state = setup_done_state();
+        {
+            let mut s = state.write();
+            s.config.random_fault_probability = 0.0;
+            s.config.dose_jitter = 0.2;
+            // The dose target alone would never end the treatment
+            s.dose_target = 1_000_000.0;
+            assert!(s.set_monitor_units(20.0));
+        }
+        start_treatment(state.clone());
+
+        let per_pulse = pulse_dose(&state.read());
+        while state.read().phase == TPhase::PatientTreatment {
+            zap_the_specimen(state.clone()).await;
+        }
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::TerminateTreatment);
+        assert!(s.monitor_units_delivered >= 20.0);
+        assert!(s.monitor_units_delivered < 20.0 + per_pulse);
+        // Jitter moves the dose the patient gets, not the monitor units counted
+        let pulses = (s.monitor_units_delivered / per_pulse).round();
+        assert!((s.monitor_units_delivered - pulses * per_pulse).abs() < 1e-9);
+        assert!(matches!(s.last_outcome, Some(TreatmentOutcome::TargetReached { .. })));
+    }
+
+    #[test]
+    fn test_non_positive_monitor_units_are_refused() {
+        let state = setup_done_state();
+        let mut s = state.write();
+        assert!(!s.set_monitor_units(0.0));
+        assert!(!s.set_monitor_units(-5.0));
+        assert_eq!(s.monitor_units, 0.0);
+        s.dose_delivered = s.dose_target;
+        assert!(s.beam_target_reached());
+    }
+}
@@ -0,0 +1,149 @@
+//! Read-only copy of the state for drawing a frame
+//!
+//! A renderer that keeps the read guard for a whole frame holds off the monitor and the
+//! housekeeper, both of which need the write lock, for as long as the terminal takes to
+//! draw. `TheracState::snapshot` copies out what a display needs instead, so the lock is
+//! held only for the copy and the frame is drawn from the `TheracSnapshot` afterwards.
+//! Derived values that need the whole state (time to a safe treatment, pulse dose) are
+//! worked out under the same acquisition, so the snapshot is internally consistent.
+//!
+//! Lock holds feed into the race: while a frame held the lock, a pulse or a turntable
+//! step waited for it, and the beam fired on the frame's schedule rather than its own.
+//! With short holds the tasks interleave more evenly, so the race may show up more or less
+//! often than with a renderer that holds the lock for the frame. The window itself is
+//! still the turntable move (`SimConfig::collimator_move_ms`).
+
+use crate::config::SimConfig;
+use crate::messages::Messages;
+use crate::simulator::{dose_past_target, pulse_dose, time_until_safe_to_treat};
+use crate::state::{
+    Checklist, LogEntry, Malfunction, Meos, TPhase, TaskStatus, TheracState, TreatmentParams,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What the displays show of a `TheracState` at one moment
+#[derive(Debug, Clone)]
+pub struct TheracSnapshot {
+    pub phase: TPhase,
+    pub reference_meos: Meos,
+    pub reference_params: TreatmentParams,
+    pub reference_dose_target: f64,
+    pub console_meos: Meos,
+    pub console_params: TreatmentParams,
+    pub hardware_meos: Meos,
+    pub hardware_params: TreatmentParams,
+    pub dose_target: f64,
+    pub dose_delivered: f64,
+    /// `TheracState::dose_percent_uncapped`
+    pub dose_percent: f64,
+    /// `TheracState::estimated_time_remaining`
+    pub time_remaining: Option<Duration>,
+    /// `time_until_safe_to_treat`
+    pub safe_to_treat_in: Option<Duration>,
+    /// Dose the next pulse would deliver (`pulse_dose`)
+    pub pulse_dose: f64,
+    /// `dose_past_target`
+    pub dose_past_target: bool,
+    pub beam_hold_remaining_ms: u32,
+    pub editing_taking_place: bool,
+    pub bending_magnet_flag: bool,
+    pub bending_magnet_settle_remaining_ms: u32,
+    pub energy_settle_remaining_ms: u32,
+    pub collimator_timeout: bool,
+    pub simulation_paused: bool,
+    pub malfunction_count: u32,
+    pub last_malfunction: Option<Malfunction>,
+    pub near_misses: u32,
+    pub override_count: usize,
+    pub normalized_deviance_warnings: u32,
+    pub malfunction_dismissals: u32,
+    pub operator_id: Option<String>,
+    pub wear_factor: f32,
+    /// `TheracState::recalibration_required`
+    pub recalibration_required: bool,
+    pub treatments_since_calibration: u32,
+    pub task_status: TaskStatus,
+    pub checklist: Checklist,
+    /// The event log, oldest first (at most the 100 entries the state keeps)
+    pub log: Vec<LogEntry>,
+    pub config: SimConfig,
+    pub messages: Arc<Messages>,
+}
+
+impl TheracSnapshot {
+    pub(crate) fn capture(state: &TheracState) -> Self {
+        Self {
+            phase: state.phase,
+            reference_meos: state.reference_meos,
+            reference_params: state.reference_params,
+            reference_dose_target: state.reference_dose_target,
+            console_meos: state.console_meos,
+            console_params: state.console_params,
+            hardware_meos: state.hardware_meos,
+            hardware_params: state.hardware_params,
+            dose_target: state.dose_target,
+            dose_delivered: state.dose_delivered,
+            dose_percent: state.dose_percent_uncapped(),
+            time_remaining: state.estimated_time_remaining(),
+            safe_to_treat_in: time_until_safe_to_treat(state),
+            pulse_dose: pulse_dose(state),
+            dose_past_target: dose_past_target(state),
+            beam_hold_remaining_ms: state.beam_hold_remaining_ms,
+            editing_taking_place: state.editing_taking_place,
+            bending_magnet_flag: state.bending_magnet_flag,
+            bending_magnet_settle_remaining_ms: state.bending_magnet_settle_remaining_ms,
+            energy_settle_remaining_ms: state.energy_settle_remaining_ms,
+            collimator_timeout: state.collimator_timeout,
+            simulation_paused: state.simulation_paused,
+            malfunction_count: state.malfunction_count,
+            last_malfunction: state.last_malfunction.clone(),
+            near_misses: state.near_misses,
+            override_count: state.overrides.len(),
+            normalized_deviance_warnings: state.normalized_deviance_warnings,
+            malfunction_dismissals: state.malfunction_dismissals,
+            operator_id: state.operator_id.clone(),
+            wear_factor: state.wear_factor,
+            recalibration_required: state.recalibration_required(),
+            treatments_since_calibration: state.treatments_since_calibration,
+            task_status: state.task_status.clone(),
+            checklist: state.checklist.clone(),
+            log: state.log.clone(),
+            config: state.config.clone(),
+            messages: state.messages.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::BeamType;
+
+    #[test]
+    fn test_snapshot_matches_the_state() {
+        let mut s = TheracState {
+            phase: TPhase::PatientTreatment,
+            dose_delivered: 50.0,
+            dose_target: 200.0,
+            malfunction_count: 3,
+            ..TheracState::default()
+        };
+        s.console_meos.beam_type = BeamType::XRay;
+        s.add_log("[CONSOLE] Mode entered".to_string());
+        let snapshot = s.snapshot();
+        assert_eq!(snapshot.phase, TPhase::PatientTreatment);
+        assert_eq!(snapshot.console_meos, s.console_meos);
+        assert_eq!(snapshot.dose_percent, 25.0);
+        assert_eq!(snapshot.time_remaining, s.estimated_time_remaining());
+        assert_eq!(snapshot.pulse_dose, pulse_dose(&s));
+        assert_eq!(snapshot.malfunction_count, 3);
+        assert_eq!(snapshot.log, s.log);
+
+        // A copy: later changes to the state do not show in it
+        s.phase = TPhase::PauseTreatment;
+        s.add_log("[MONITOR] Paused".to_string());
+        assert_eq!(snapshot.phase, TPhase::PatientTreatment);
+        assert_eq!(snapshot.log.len(), s.log.len() - 1);
+    }
+}
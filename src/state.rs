@@ -2,12 +2,22 @@
 //!
 //! This module contains all state structures and types used throughout the simulator.
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 use parking_lot::RwLock;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use crate::alarm::Alarm;
+use crate::config::SimConfig;
+use crate::messages::{MessageKey, Messages};
 
 /// Beam type for radiation therapy
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub enum BeamType {
     /// X-ray mode - requires flatness filter (turntable in position)
     XRay,
@@ -17,12 +27,27 @@ pub enum BeamType {
     Undefined,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for BeamType {
     fn default() -> Self {
         Self::Undefined
     }
 }
 
+impl BeamType {
+    /// Every beam type, including `Undefined`
+    pub const ALL: [BeamType; 3] = [BeamType::XRay, BeamType::Electron, BeamType::Undefined];
+
+    /// Where the turntable goes for this beam type: the flatness filter in for X-rays,
+    /// out otherwise
+    pub fn collimator_position(self) -> CollimatorPosition {
+        match self {
+            BeamType::XRay => CollimatorPosition::InPosition,
+            BeamType::Electron | BeamType::Undefined => CollimatorPosition::OutOfPosition,
+        }
+    }
+}
+
 impl std::fmt::Display for BeamType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -34,7 +59,10 @@ impl std::fmt::Display for BeamType {
 }
 
 /// Beam energy level in MeV (Mega-electron volts)
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// The five presets are what the console offers; `EnergyMeV` models any energy in
+/// between. Energies compare by value, so `EnergyMeV(10.0)` equals `E10`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub enum BeamEnergy {
     /// 5 MeV
     E5,
@@ -46,23 +74,63 @@ pub enum BeamEnergy {
     E20,
     /// 25 MeV
     E25,
+    /// Any other energy in `BeamEnergy::RANGE_MEV`, e.g. 18 MeV
+    EnergyMeV(f32),
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for BeamEnergy {
     fn default() -> Self {
         Self::E10
     }
 }
 
-impl std::fmt::Display for BeamEnergy {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl BeamEnergy {
+    /// Every selectable energy, lowest first
+    pub const ALL: [BeamEnergy; 5] = [
+        BeamEnergy::E5,
+        BeamEnergy::E10,
+        BeamEnergy::E15,
+        BeamEnergy::E20,
+        BeamEnergy::E25,
+    ];
+
+    /// Energies the accelerator can be set to, in MeV
+    pub const RANGE_MEV: std::ops::RangeInclusive<f32> = 5.0..=25.0;
+
+    /// The energy in MeV
+    pub fn mev(&self) -> f32 {
         match self {
-            BeamEnergy::E5 => write!(f, "5 MeV"),
-            BeamEnergy::E10 => write!(f, "10 MeV"),
-            BeamEnergy::E15 => write!(f, "15 MeV"),
-            BeamEnergy::E20 => write!(f, "20 MeV"),
-            BeamEnergy::E25 => write!(f, "25 MeV"),
+            BeamEnergy::E5 => 5.0,
+            BeamEnergy::E10 => 10.0,
+            BeamEnergy::E15 => 15.0,
+            BeamEnergy::E20 => 20.0,
+            BeamEnergy::E25 => 25.0,
+            BeamEnergy::EnergyMeV(mev) => *mev,
+        }
+    }
+
+    /// The preset for `mev` if there is one, otherwise `EnergyMeV`; `None` outside
+    /// `RANGE_MEV`
+    pub fn from_mev(mev: f32) -> Option<Self> {
+        if !Self::RANGE_MEV.contains(&mev) {
+            return None;
         }
+        Some(Self::ALL.into_iter().find(|preset| preset.mev() == mev).unwrap_or(BeamEnergy::EnergyMeV(mev)))
+    }
+}
+
+impl PartialEq for BeamEnergy {
+    fn eq(&self, other: &Self) -> bool {
+        self.mev().to_bits() == other.mev().to_bits()
+    }
+}
+
+impl Eq for BeamEnergy {}
+
+impl std::fmt::Display for BeamEnergy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} MeV", self.mev())
     }
 }
 
@@ -70,6 +138,7 @@ impl std::fmt::Display for BeamEnergy {
 /// In the real Therac-25, the turntable rotated to position different beam modification
 /// devices (flatness filter for X-ray mode, or out of the way for electron mode)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub enum CollimatorPosition {
     /// Flatness filter in beam path (required for X-ray mode)
     InPosition,
@@ -77,20 +146,35 @@ pub enum CollimatorPosition {
     OutOfPosition,
     /// Moving between positions
     Transitioning,
+    /// Jammed mid-rotation: neither position, and no move finishes until an operator
+    /// frees the turntable (`TheracState::free_stuck_collimator`)
+    Stuck,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for CollimatorPosition {
     fn default() -> Self {
         Self::OutOfPosition
     }
 }
 
+impl CollimatorPosition {
+    /// Every turntable position, including mid-move and stuck
+    pub const ALL: [CollimatorPosition; 4] = [
+        CollimatorPosition::InPosition,
+        CollimatorPosition::OutOfPosition,
+        CollimatorPosition::Transitioning,
+        CollimatorPosition::Stuck,
+    ];
+}
+
 impl std::fmt::Display for CollimatorPosition {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             CollimatorPosition::InPosition => write!(f, "In Position"),
             CollimatorPosition::OutOfPosition => write!(f, "Out"),
             CollimatorPosition::Transitioning => write!(f, "Moving..."),
+            CollimatorPosition::Stuck => write!(f, "STUCK"),
         }
     }
 }
@@ -98,6 +182,7 @@ impl std::fmt::Display for CollimatorPosition {
 /// MEOS - Mode/Energy/Offset Structure
 /// Represents the complete configuration for a treatment
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct Meos {
     /// Beam type and energy level
     pub beam_type: BeamType,
@@ -106,6 +191,7 @@ pub struct Meos {
     pub collimator: CollimatorPosition,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for Meos {
     fn default() -> Self {
         Self {
@@ -128,13 +214,38 @@ impl Meos {
     }
 
     /// Check if collimator needs to move to match beam type
+    /// Not while it is already moving, nor while it is stuck: no move can start then.
     pub fn needs_collimator_sync(&self) -> bool {
-        !self.is_safe() && self.collimator != CollimatorPosition::Transitioning
+        !self.is_safe() && !matches!(self.collimator, CollimatorPosition::Transitioning | CollimatorPosition::Stuck)
+    }
+
+    /// Every combination of beam type, energy and collimator position
+    pub fn all_configurations() -> Vec<Meos> {
+        let mut configurations = Vec::new();
+        for beam_type in BeamType::ALL {
+            for beam_energy in BeamEnergy::ALL {
+                for collimator in CollimatorPosition::ALL {
+                    configurations.push(Meos { beam_type, beam_energy, collimator });
+                }
+            }
+        }
+        configurations
+    }
+
+    /// Every configuration `is_safe` accepts
+    pub fn all_safe_configurations() -> Vec<Meos> {
+        Self::all_configurations().into_iter().filter(Meos::is_safe).collect()
+    }
+
+    /// Every configuration `is_safe` rejects
+    pub fn all_unsafe_configurations() -> Vec<Meos> {
+        Self::all_configurations().into_iter().filter(|m| !m.is_safe()).collect()
     }
 }
 
 /// Treatment parameters - additional settings beyond MEOS
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct TreatmentParams {
     /// Gantry angle in degrees (0-360)
     pub gantry_angle: u16,
@@ -160,8 +271,21 @@ impl Default for TreatmentParams {
     }
 }
 
+impl TreatmentParams {
+    /// Side of the square field with the same scatter as this rectangular one (cm)
+    /// Uses the area-to-perimeter rule 2XY/(X+Y); a field with a zero side is 0.
+    pub fn equivalent_square(&self) -> f32 {
+        let (x, y) = (self.field_size_x, self.field_size_y);
+        if x <= 0.0 || y <= 0.0 {
+            return 0.0;
+        }
+        2.0 * x * y / (x + y)
+    }
+}
+
 /// Treatment phase state machine
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub enum TPhase {
     /// System reset/initialization
     Reset,
@@ -171,16 +295,22 @@ pub enum TPhase {
     SetupTest,
     /// Setup complete, ready for treatment
     SetupDone,
+    /// Beam-on requested, holding for `config.beam_hold_ms` before the first pulse;
+    /// stopping here aborts with no dose delivered
+    BeamReady,
     /// Patient treatment in progress
     PatientTreatment,
     /// Treatment paused (e.g., due to malfunction)
     PauseTreatment,
     /// Treatment terminated
     TerminateTreatment,
+    /// Emergency-off pressed: beam cut, nothing fires or resumes until a reset
+    EmergencyStop,
     /// Date/Time/ID changes
     DateTimeIdChanges,
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for TPhase {
     fn default() -> Self {
         Self::Reset
@@ -189,21 +319,595 @@ impl Default for TPhase {
 
 impl std::fmt::Display for TPhase {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(MessageKey::phase(*self).english())
+    }
+}
+
+/// Activity published by the background tasks
+/// Lets the UI show what the treatment monitor and housekeeper are doing at any instant
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(default)]
+pub struct TaskStatus {
+    /// Current activity of the treatment monitor task
+    pub monitor: String,
+    /// Current activity of the housekeeper task
+    pub housekeeper: String,
+    /// Last thing the phantom operator did (`config.phantom_operator`)
+    pub phantom_operator: String,
+    /// Current activity of the hardware watchdog (`config.hardware_watchdog`)
+    pub watchdog: String,
+}
+
+impl Default for TaskStatus {
+    fn default() -> Self {
+        Self {
+            monitor: "idle".to_string(),
+            housekeeper: "idle".to_string(),
+            phantom_operator: "idle".to_string(),
+            watchdog: "idle".to_string(),
+        }
+    }
+}
+
+/// One item on the pre-treatment checklist
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum ChecklistItem {
+    DoorClosed,
+    PatientPositioned,
+    PrescriptionVerified,
+    CollimatorConfirmed,
+}
+
+impl ChecklistItem {
+    pub const ALL: [ChecklistItem; 4] = [
+        ChecklistItem::DoorClosed,
+        ChecklistItem::PatientPositioned,
+        ChecklistItem::PrescriptionVerified,
+        ChecklistItem::CollimatorConfirmed,
+    ];
+}
+
+impl std::fmt::Display for ChecklistItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChecklistItem::DoorClosed => write!(f, "Door closed"),
+            ChecklistItem::PatientPositioned => write!(f, "Patient positioned"),
+            ChecklistItem::PrescriptionVerified => write!(f, "Prescription verified"),
+            ChecklistItem::CollimatorConfirmed => write!(f, "Collimator confirmed"),
+        }
+    }
+}
+
+/// Pre-treatment checklist acknowledged by the operator
+/// Ticking an item records an acknowledgement, nothing more: "Collimator confirmed"
+/// can be ticked while the turntable is in the wrong position.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Checklist {
+    pub door_closed: bool,
+    pub patient_positioned: bool,
+    pub prescription_verified: bool,
+    pub collimator_confirmed: bool,
+    /// Everything was ticked with the check-all shortcut rather than item by item
+    pub bulk_checked: bool,
+}
+
+impl Checklist {
+    pub fn is_checked(&self, item: ChecklistItem) -> bool {
+        match item {
+            ChecklistItem::DoorClosed => self.door_closed,
+            ChecklistItem::PatientPositioned => self.patient_positioned,
+            ChecklistItem::PrescriptionVerified => self.prescription_verified,
+            ChecklistItem::CollimatorConfirmed => self.collimator_confirmed,
+        }
+    }
+
+    /// Toggle one item; returns the new value
+    pub fn toggle(&mut self, item: ChecklistItem) -> bool {
+        let value = match item {
+            ChecklistItem::DoorClosed => &mut self.door_closed,
+            ChecklistItem::PatientPositioned => &mut self.patient_positioned,
+            ChecklistItem::PrescriptionVerified => &mut self.prescription_verified,
+            ChecklistItem::CollimatorConfirmed => &mut self.collimator_confirmed,
+        };
+        *value = !*value;
+        self.bulk_checked = false;
+        *value
+    }
+
+    /// Tick every item at once without looking at any of them
+    pub fn check_all(&mut self) {
+        self.door_closed = true;
+        self.patient_positioned = true;
+        self.prescription_verified = true;
+        self.collimator_confirmed = true;
+        self.bulk_checked = true;
+    }
+
+    pub fn all_checked(&self) -> bool {
+        ChecklistItem::ALL.iter().all(|item| self.is_checked(*item))
+    }
+}
+
+/// A safety mechanism the operator chose to bypass
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum OverrideKind {
+    /// A malfunction message was dismissed (popup closed or treatment resumed past it)
+    MalfunctionDismissed,
+    /// The pre-treatment checklist was ticked with the check-all shortcut
+    ChecklistBulkChecked,
+}
+
+impl std::fmt::Display for OverrideKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverrideKind::MalfunctionDismissed => write!(f, "Malfunction dismissed"),
+            OverrideKind::ChecklistBulkChecked => write!(f, "Checklist bulk-checked"),
+        }
+    }
+}
+
+/// A console entry that differs from the prescription, found by the read-back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct PrescriptionDifference {
+    pub field: String,
+    pub entered: String,
+    pub prescribed: String,
+}
+
+/// A field of the MEOS, as named in a `MeosDiff`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum MeosField {
+    BeamType,
+    BeamEnergy,
+    Collimator,
+}
+
+/// How the console, hardware and reference MEOS differ, from `TheracState::meos_diff`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct MeosDiff {
+    pub console_matches_hardware: bool,
+    pub hardware_matches_reference: bool,
+    pub console_matches_reference: bool,
+    /// MEOS fields not the same in all three
+    pub differing_fields: Vec<MeosField>,
+    /// MEOS fields where the console and hardware disagree, e.g. mid-sync
+    pub console_hardware_fields: Vec<MeosField>,
+    /// The housekeeper has yet to bring the hardware to the console: they differ, or
+    /// the turntable is still moving
+    pub sync_pending: bool,
+}
+
+impl MeosDiff {
+    /// Whether `field` is one of `differing_fields`
+    pub fn differs(&self, field: MeosField) -> bool {
+        self.differing_fields.contains(&field)
+    }
+
+    /// Whether the console and hardware disagree on `field`
+    pub fn console_hardware_differ(&self, field: MeosField) -> bool {
+        self.console_hardware_fields.contains(&field)
+    }
+}
+
+/// One recorded bypass of a safety check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct OverrideEvent {
+    pub kind: OverrideKind,
+    /// What was overridden, e.g. the malfunction message
+    pub detail: String,
+    /// Wall-clock time (UTC, HH:MM:SS) of the override
+    pub at: String,
+    /// Dose delivered when the override happened (cGy)
+    pub dose_delivered: f64,
+    /// Operator logged in at the time
+    #[serde(default)]
+    pub operator_id: Option<String>,
+}
+
+/// Which check raised a malfunction
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum MalfunctionKind {
+    /// EDIT IN PROGRESS: console edited after data entry was completed
+    EditInProgress,
+    /// MALFUNCTION 54: console and hardware MEOS disagree
+    ParameterMismatch,
+    /// The beam fired with the hardware in an unsafe configuration
+    UnsafeBeam { dose_multiplier: f64, dose_this_pulse: f64 },
+    /// COLLIMATOR TIMEOUT latched by the hardware watchdog
+    CollimatorTimeout,
+    /// The beam was triggered while the bending magnets were still settling
+    MagnetNotSettled,
+    /// The beam was triggered before the energy system settled at a higher energy
+    EnergyNotSettled,
+    /// The beam was triggered with the turntable stuck mid-rotation
+    CollimatorStuck,
+    /// Random hardware fault with its malfunction number
+    RandomFault(u32),
+}
+
+impl MalfunctionKind {
+    /// Number the console shows as "MALFUNCTION n"
+    /// The interlocks with no number of their own show 54, as the original did for
+    /// every dose/mode mismatch; an unsafe beam shows 26.
+    pub fn code(&self) -> u32 {
+        match self {
+            MalfunctionKind::EditInProgress
+            | MalfunctionKind::ParameterMismatch
+            | MalfunctionKind::CollimatorTimeout
+            | MalfunctionKind::MagnetNotSettled
+            | MalfunctionKind::EnergyNotSettled
+            | MalfunctionKind::CollimatorStuck => 54,
+            MalfunctionKind::UnsafeBeam { .. } => 26,
+            MalfunctionKind::RandomFault(number) => *number,
+        }
+    }
+
+    /// Short name of the check, as in reports
+    pub fn name(&self) -> &'static str {
+        match self {
+            MalfunctionKind::EditInProgress => "Edit in progress",
+            MalfunctionKind::ParameterMismatch => "Parameter mismatch",
+            MalfunctionKind::UnsafeBeam { .. } => "Unsafe beam fired",
+            MalfunctionKind::CollimatorTimeout => "Collimator timeout",
+            MalfunctionKind::MagnetNotSettled => "Bending magnet not settled",
+            MalfunctionKind::EnergyNotSettled => "Energy not settled",
+            MalfunctionKind::CollimatorStuck => "Collimator stuck",
+            MalfunctionKind::RandomFault(_) => "Random hardware fault",
+        }
+    }
+
+    /// Level the malfunction is logged at: critical once the beam has fired unsafe
+    pub fn log_level(&self) -> LogLevel {
         match self {
-            TPhase::Reset => write!(f, "Reset"),
-            TPhase::DataEntry => write!(f, "Data Entry"),
-            TPhase::SetupTest => write!(f, "Setup Test"),
-            TPhase::SetupDone => write!(f, "Setup Done"),
-            TPhase::PatientTreatment => write!(f, "Patient Treatment"),
-            TPhase::PauseTreatment => write!(f, "Paused"),
-            TPhase::TerminateTreatment => write!(f, "Terminated"),
-            TPhase::DateTimeIdChanges => write!(f, "Date/Time/ID Changes"),
+            MalfunctionKind::UnsafeBeam { .. } => LogLevel::Critical,
+            _ => LogLevel::Warn,
+        }
+    }
+}
+
+/// A malfunction showing to the operator: which check raised it and the text shown
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Malfunction {
+    pub kind: MalfunctionKind,
+    /// Message in the translation loaded when it was raised, as logged
+    pub text: String,
+}
+
+impl Malfunction {
+    /// Number the console shows as "MALFUNCTION n"
+    pub fn code(&self) -> u32 {
+        self.kind.code()
+    }
+
+    /// Short description of the code, from the code table (`codes::MALFUNCTION_CODES`)
+    pub fn description(&self) -> Option<&'static str> {
+        crate::codes::describe_code(self.code())
+    }
+
+    /// Message as logged
+    pub fn message(&self) -> String {
+        self.text.clone()
+    }
+}
+
+impl std::fmt::Display for Malfunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// One malfunction raised, as it was raised
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct Incident {
+    pub kind: MalfunctionKind,
+    /// Message shown to the operator
+    pub message: String,
+    /// When it was raised (RFC 3339, UTC)
+    pub at: String,
+    /// Dose delivered and dose target when it was raised (cGy)
+    pub dose_delivered: f64,
+    pub dose_target: f64,
+    /// Operator logged in at the time
+    pub operator_id: Option<String>,
+    /// Phase the machine was in when the check tripped
+    #[serde(default)]
+    pub phase: TPhase,
+    /// Console and hardware MEOS the check compared: the sensor reading it acted on,
+    /// which may be stale by the time it is recorded
+    #[serde(default)]
+    pub console_meos: Meos,
+    #[serde(default)]
+    pub hardware_meos: Meos,
+}
+
+impl Incident {
+    /// The malfunction as it was shown to the operator
+    pub fn malfunction(&self) -> Malfunction {
+        Malfunction { kind: self.kind, text: self.message.clone() }
+    }
+}
+
+/// How a batched treatment run ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum TreatmentOutcome {
+    /// The machine was not in SetupDone, so no beam was fired
+    NotReady(TPhase),
+    /// Prescribed dose delivered, treatment terminated normally
+    TargetReached { dose_delivered: f64 },
+    /// Treatment paused by an interlock or malfunction
+    Paused { dose_delivered: f64, malfunction: Option<Malfunction> },
+    /// A pulse was fired with the beam unflattened (X-ray without the flatness filter)
+    Overdose { dose_delivered: f64, dose_this_pulse: f64, multiplier: f64 },
+    /// Treatment left PatientTreatment some other way (operator, reset) before the target was reached
+    Terminated { dose_delivered: f64, phase: TPhase },
+}
+
+/// Which actor wrote a log entry, from its `[TAG]` prefix
+/// The console and the housekeeper change the machine concurrently; telling their
+/// entries apart shows the two interleaving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum LogSource {
+    /// Operator data entry at the console
+    Console,
+    /// Operator commands and acknowledgements
+    Operator,
+    /// Housekeeper task moving the hardware
+    Housekeeper,
+    /// Treatment monitor task
+    Monitor,
+    /// Anything else (system, scenarios, replay)
+    Other,
+}
+
+impl LogSource {
+    /// Source of a log message from its tag ("[TAG] message")
+    pub fn of_message(message: &str) -> Self {
+        if message.starts_with("[CONSOLE]") {
+            LogSource::Console
+        } else if message.starts_with("[OPERATOR]") {
+            LogSource::Operator
+        } else if message.starts_with("[HOUSEKEEPER]") {
+            LogSource::Housekeeper
+        } else if message.starts_with("[MONITOR]") {
+            LogSource::Monitor
+        } else {
+            LogSource::Other
+        }
+    }
+}
+
+/// Severity of a log entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub enum LogLevel {
+    /// Routine: data entry, hardware moves, beam delivered
+    #[default]
+    Info,
+    /// Something the operator has to act on: malfunctions, refusals, warnings
+    Warn,
+    /// The beam fired in an unsafe configuration
+    Critical,
+}
+
+/// One entry of the treatment log
+/// Displays as the text log always read: "[HH:MM:SS] message", or
+/// "[HH:MM:SS operator] message" while someone is logged in.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct LogEntry {
+    /// Time of day it was written, "HH:MM:SS" (UTC)
+    pub timestamp: String,
+    /// Operator logged in at the time
+    #[serde(default)]
+    pub operator_id: Option<String>,
+    pub level: LogLevel,
+    /// The message, starting with the `[TAG]` of the actor that wrote it
+    pub message: String,
+}
+
+impl LogEntry {
+    /// Which actor wrote the entry
+    pub fn source(&self) -> LogSource {
+        LogSource::of_message(&self.message)
+    }
+}
+
+impl std::fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.operator_id {
+            Some(operator) => write!(f, "[{} {}] {}", self.timestamp, operator, self.message),
+            None => write!(f, "[{}] {}", self.timestamp, self.message),
+        }
+    }
+}
+
+/// Whether `dose` (cGy) can be treated to: positive and finite
+/// A zero target is reached before the first pulse and a negative one never is.
+pub fn valid_dose_target(dose: f64) -> bool {
+    dose.is_finite() && dose > 0.0
+}
+
+/// Whether `id` can be used as an operator ID: non-empty, no whitespace or brackets
+/// (the ID is written into every log entry's stamp)
+pub fn valid_operator_id(id: &str) -> bool {
+    !id.is_empty() && !id.chars().any(|c| c.is_whitespace() || c == '[' || c == ']')
+}
+
+/// What one operator did this session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct OperatorStats {
+    /// Treatments started (beam turned on)
+    pub treatments: u32,
+    /// Pulses fired with the beam unflattened
+    pub overdoses: u32,
+    /// Mismatches caught before the beam fired
+    pub near_misses: u32,
+}
+
+/// Dose changes smaller than this (cGy) are treated as float noise, not a change
+pub const DOSE_DIFF_TOLERANCE: f64 = 1e-6;
+
+/// What changed between two snapshots of the state; `None` fields are unchanged
+/// Lets pollers send or react to changes instead of whole snapshots.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct StateDiff {
+    /// New phase
+    pub phase: Option<TPhase>,
+    /// Change in delivered dose (cGy); negative after a reset
+    pub dose_delivered_change: Option<f64>,
+    /// New console dose target (cGy)
+    pub dose_target: Option<f64>,
+    pub console_meos: Option<Meos>,
+    pub hardware_meos: Option<Meos>,
+    /// New turntable position, when only that is of interest
+    pub collimator: Option<CollimatorPosition>,
+    /// Malfunction raised since the earlier snapshot
+    pub new_malfunction: Option<Malfunction>,
+    /// The malfunction showing in the earlier snapshot has been cleared
+    pub malfunction_cleared: bool,
+    /// Log entries appended since the earlier snapshot
+    pub new_log_entries: Vec<LogEntry>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Lines of `newer` appended after `older`, allowing for the oldest lines having been
+/// dropped by the log cap: the longest suffix of `older` that starts `newer` is shared
+fn appended_log_entries(older: &[LogEntry], newer: &[LogEntry]) -> Vec<LogEntry> {
+    let max_overlap = older.len().min(newer.len());
+    let overlap = (0..=max_overlap)
+        .rev()
+        .find(|&n| older[older.len() - n..] == newer[..n])
+        .unwrap_or(0);
+    newer[overlap..].to_vec()
+}
+
+/// Clinical record of one treatment, for export to an electronic health record
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct TreatmentSummary {
+    pub patient_name: String,
+    /// Prescribed mode, energy and collimator
+    pub prescribed_meos: Meos,
+    pub prescribed_params: TreatmentParams,
+    /// Prescribed dose (cGy)
+    pub prescribed_dose: f64,
+    /// Dose the console was set to deliver (cGy)
+    pub dose_target: f64,
+    /// Dose actually delivered (cGy)
+    pub dose_delivered: f64,
+    /// Start and end of the treatment (RFC 3339, UTC)
+    pub started_at: String,
+    pub ended_at: String,
+    pub duration_secs: f64,
+    /// Malfunction messages raised during the treatment, in order
+    pub malfunctions: Vec<String>,
+    /// Mismatches caught before the beam fired during the treatment
+    #[serde(default)]
+    pub near_misses: u32,
+    /// Operator logged in when the beam was turned on
+    #[serde(default)]
+    pub operator_id: Option<String>,
+    pub outcome: TreatmentOutcome,
+}
+
+/// Outcome of a session at a glance, from `TheracState::summary`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+pub struct SessionSummary {
+    /// Dose delivered and dose target of the current (or last) treatment (cGy)
+    pub dose_delivered: f64,
+    pub dose_target: f64,
+    /// Delivered over target; above 1.0 is an overdose, 0.0 with no positive target
+    pub overdose_ratio: f64,
+    /// Malfunctions raised this session, in total (`TheracState::malfunction_count`) and by kind (`MalfunctionKind::name`)
+    pub malfunction_count: usize,
+    pub malfunctions_by_kind: BTreeMap<String, u32>,
+    pub final_phase: TPhase,
+    /// The beam fired with the hardware in an unsafe configuration at least once
+    pub critical_safety_violation: bool,
+    /// Time spent in each phase, in seconds (`TheracState::time_in_phase`)
+    #[serde(default)]
+    pub phase_seconds: BTreeMap<TPhase, f64>,
+}
+
+impl SessionSummary {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+impl std::fmt::Display for SessionSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "SESSION SUMMARY")?;
+        writeln!(f, "  Dose delivered: {:.1} / {:.1} cGy ({:.2}x target)", self.dose_delivered, self.dose_target, self.overdose_ratio)?;
+        writeln!(f, "  Final phase:    {}", self.final_phase)?;
+        writeln!(f, "  Malfunctions:   {}", self.malfunction_count)?;
+        for (kind, count) in &self.malfunctions_by_kind {
+            writeln!(f, "    {}: {}", kind, count)?;
+        }
+        if !self.phase_seconds.is_empty() {
+            writeln!(f, "  Time in phase:")?;
+            for (phase, seconds) in &self.phase_seconds {
+                writeln!(f, "    {}: {:.1} s", phase, seconds)?;
+            }
+        }
+        if self.critical_safety_violation {
+            write!(f, "  CRITICAL SAFETY VIOLATION occurred")
+        } else {
+            write!(f, "  No critical safety violation")
         }
     }
 }
 
+/// One CSV field, quoted (with quotes doubled) when it would otherwise break the row
+fn csv_field(text: &str) -> std::borrow::Cow<'_, str> {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\"")).into()
+    } else {
+        text.into()
+    }
+}
+
+/// Bookkeeping for the treatment currently under way
+#[derive(Debug, Clone)]
+pub struct TreatmentInProgress {
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub malfunctions: Vec<String>,
+    pub near_misses: u32,
+    pub operator_id: Option<String>,
+}
+
+fn new_machine_wear() -> f32 {
+    1.0
+}
+
+/// Most samples kept in `TheracState::dose_samples`; the oldest are dropped first
+pub const DOSE_SAMPLE_CAP: usize = 1000;
+
 /// Main Therac-25 state structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
 pub struct TheracState {
     /// Console MEOS - parameters entered by operator
     pub console_meos: Meos,
@@ -225,7 +929,7 @@ pub struct TheracState {
     pub f_small: bool,
     /// Class3 counter - incremented during setup verification
     pub class3: u8,
-    /// Bending magnet flag - indicates electron beam bending magnet status
+    /// Bending magnet flag - set while the bending magnets settle after a beam type switch
     pub bending_magnet_flag: bool,
     /// Editing taking place - operator is modifying parameters
     pub editing_taking_place: bool,
@@ -235,66 +939,269 @@ pub struct TheracState {
     pub class3_ignore: bool,
     /// Malfunction counter
     pub malfunction_count: u32,
+    /// Pulses fired with the beam unflattened (X-ray without the flatness filter)
+    pub overdose_count: u32,
+    /// Console/hardware mismatches caught before the beam fired (EDIT IN PROGRESS or
+    /// MALFUNCTION 54) - each one could have been an overdose; survives a reset
+    #[serde(default)]
+    pub near_misses: u32,
     /// Total dose delivered (in cGy - centigray)
     pub dose_delivered: f64,
+    /// Simulated beam-on time (s) behind `dose_delivered`: one pulse period per pulse
+    #[serde(default)]
+    pub beam_on_seconds: f64,
+    /// Accelerator beam current of the last pulse fired (µA), before the filter
+    #[serde(default)]
+    pub beam_current: f64,
     /// Target dose (in cGy)
     pub dose_target: f64,
     /// Reference dose target (in cGy)
     pub reference_dose_target: f64,
+    /// Prescribed monitor units; when set, the beam terminates on these rather than
+    /// on `dose_target`. Zero until the operator enters them
+    #[serde(default)]
+    pub monitor_units: f64,
+    /// Monitor units counted by the ion chamber so far: the nominal output of each
+    /// pulse, one MU per calibrated cGy
+    #[serde(default)]
+    pub monitor_units_delivered: f64,
     /// Treatment outcome message
     pub treatment_outcome: String,
     /// Treatment log
-    pub log: Vec<String>,
-    /// Last malfunction message
-    pub last_malfunction: Option<String>,
+    pub log: Vec<LogEntry>,
+    /// Malfunction showing to the operator
+    pub last_malfunction: Option<Malfunction>,
+    /// How the last treatment ended, set when the target is reached or an overdose fires
+    #[serde(default)]
+    pub last_outcome: Option<TreatmentOutcome>,
+    /// What each background task is currently doing
+    pub task_status: TaskStatus,
+    /// Simulator configuration
+    #[serde(default)]
+    pub config: SimConfig,
+    /// Pre-treatment checklist, enforced when `config.require_checklist` is set
+    #[serde(default)]
+    pub checklist: Checklist,
+    /// Differences the operator acknowledged at the last read-back; any change to the
+    /// entries or the prescription since then needs a fresh acknowledgment
+    #[serde(default)]
+    pub acknowledged_differences: Option<Vec<PrescriptionDifference>>,
+    /// Differences found at the last read-back (`verify_entry`), until a console takes
+    /// them to show
+    #[serde(default)]
+    pub last_readback: Option<Vec<PrescriptionDifference>>,
+    /// Operator currently logged in; written into log entries, overrides and treatment
+    /// summaries. Survives a reset
+    #[serde(default)]
+    pub operator_id: Option<String>,
+    /// Per-operator statistics for the session, by operator ID; survives a reset
+    #[serde(default)]
+    pub operator_stats: BTreeMap<String, OperatorStats>,
+    /// Every safety check bypassed this session; survives a reset
+    #[serde(default)]
+    pub overrides: Vec<OverrideEvent>,
+    /// Every malfunction raised this session with the phase and MEOS it was raised in,
+    /// oldest first; survives a reset
+    #[serde(default)]
+    pub incidents: Vec<Incident>,
+    /// Malfunction messages dismissed this session
+    #[serde(default)]
+    pub malfunction_dismissals: u32,
+    /// Normalized deviance warnings raised this session
+    #[serde(default)]
+    pub normalized_deviance_warnings: u32,
+    /// Time left in the beam-on hold (ms), counted down while in `BeamReady`
+    #[serde(default)]
+    pub beam_hold_remaining_ms: u32,
+    /// Time left in the current turntable move (ms), published by the housekeeper
+    #[serde(default)]
+    pub collimator_transit_remaining_ms: u32,
+    /// Time left until the bending magnets settle (ms), counted down while
+    /// `bending_magnet_flag` is set
+    #[serde(default)]
+    pub bending_magnet_settle_remaining_ms: u32,
+    /// Time left until the energy system settles after the console energy went up (ms),
+    /// counted down by the bending magnet settler
+    #[serde(default)]
+    pub energy_settle_remaining_ms: u32,
+    /// Entry the setup carries on with after missing an edit. The original checked for
+    /// edits only when it started setting the bending magnets, so one made while they
+    /// set went unseen: the beam stays set up, and is checked, for the entry the magnets
+    /// were being set for. Cleared when data entry is completed again and on reset.
+    #[serde(default)]
+    pub missed_edit_setup: Option<Meos>,
+    /// Patient name entered at the console
+    #[serde(default)]
+    pub patient_name: String,
+    /// Treatment started but not yet completed or terminated
+    #[serde(skip)]
+    pub treatment_in_progress: Option<TreatmentInProgress>,
+    /// One summary per completed or terminated treatment this session; survives a reset
+    #[serde(default)]
+    pub completed_treatments: Vec<TreatmentSummary>,
+    /// (seconds since treatment start, cumulative dose in cGy) after every pulse of the
+    /// current or most recent treatment, for plotting; at most `DOSE_SAMPLE_CAP` kept
+    #[serde(default)]
+    pub dose_samples: Vec<(f64, f64)>,
+    /// Machine wear: multiplies collimator transit, param sync and setup times.
+    /// 1.0 is a new machine; grows each treatment by `config.wear_per_treatment`,
+    /// up to `config.max_wear_factor`. Survives a reset; `reset_wear` services it.
+    #[serde(default = "new_machine_wear")]
+    pub wear_factor: f32,
+    /// Treatments begun since the machine was last recalibrated; survives a reset
+    #[serde(default)]
+    pub treatments_since_calibration: u32,
+    /// Injected fault: the turntable is stuck and a move in progress makes no headway
+    /// until the jam is cleared. Survives a reset
+    #[serde(default)]
+    pub collimator_jammed: bool,
+    /// COLLIMATOR TIMEOUT latched by the hardware watchdog; the beam is inhibited
+    /// until a reset
+    #[serde(default)]
+    pub collimator_timeout: bool,
+    /// Background tasks frozen by `pause_simulation`; not saved with a session and
+    /// not cleared by a reset
+    #[serde(skip)]
+    pub simulation_paused: bool,
+    /// Time spent in each phase this session: wall time added by `track_phase` when the
+    /// async monitor sees a phase left, or logical time added by `charge_phase_time` on
+    /// every tick; not saved with a session and not cleared by a reset
+    #[serde(skip)]
+    pub phase_durations: BTreeMap<TPhase, Duration>,
+    /// Phase the monitor last saw, and when it saw it entered
+    #[serde(skip)]
+    pub phase_entered: Option<(TPhase, chrono::DateTime<chrono::Utc>)>,
+    /// Progress of the monitor and housekeeper when driven tick by tick (`tick::step`)
+    #[serde(skip)]
+    pub tick: crate::tick::TickProgress,
+    /// Hardware MEOS at the previous verification (what a stale reading reports)
+    #[serde(skip)]
+    pub last_sensor_reading: Option<Meos>,
+    /// Stream every random draw of the session comes from, seeded by
+    /// `with_seed`/`reseed`
+    #[serde(skip)]
+    pub rng: SessionRng,
+    /// Text for operator-facing messages; English unless a translation is loaded.
+    /// Survives a reset.
+    #[serde(skip)]
+    pub messages: Arc<Messages>,
+    /// Alarms raised and not yet handed to an `AlarmSink` (`dispatch_alarms`), oldest
+    /// first; at most `MAX_PENDING_ALARMS`. Not saved with a session
+    #[serde(skip)]
+    pub pending_alarms: Vec<Alarm>,
 }
 
-impl Default for TheracState {
-    fn default() -> Self {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
+/// Most alarms kept waiting for a dispatcher; older ones are dropped first
+pub const MAX_PENDING_ALARMS: usize = 100;
 
-        // Generate random reference parameters (prescribed treatment)
-        let beam_type = if rng.gen_bool(0.5) {
-            BeamType::XRay
-        } else {
-            BeamType::Electron
-        };
+/// Console dose target (in cGy) used until the operator enters one
+/// Deliberately independent of the prescription: the operator must copy or type it
+pub const DEFAULT_DOSE_TARGET: f64 = 200.0;
 
-        let beam_energy = match rng.gen_range(0..5) {
-            0 => BeamEnergy::E5,
-            1 => BeamEnergy::E10,
-            2 => BeamEnergy::E15,
-            3 => BeamEnergy::E20,
-            _ => BeamEnergy::E25,
-        };
+/// The session's random stream: prescriptions, random faults and their codes, sensor
+/// glitches, dose jitter, wear, stuck turntables and the phantom operator all draw
+/// from it. Seeded, the same seed and the same inputs give the same session; unseeded
+/// it draws from the thread RNG.
+#[derive(Debug, Clone, Default)]
+pub struct SessionRng(Option<StdRng>);
 
-        let collimator = match beam_type {
-            BeamType::XRay => CollimatorPosition::InPosition,
-            BeamType::Electron => CollimatorPosition::OutOfPosition,
-            BeamType::Undefined => CollimatorPosition::OutOfPosition,
-        };
+impl SessionRng {
+    pub fn seeded(seed: u64) -> Self {
+        Self(Some(StdRng::seed_from_u64(seed)))
+    }
 
-        let reference_meos = Meos {
-            beam_type,
-            beam_energy,
-            collimator,
-        };
+    pub fn is_seeded(&self) -> bool {
+        self.0.is_some()
+    }
+}
 
-        let reference_dose = (rng.gen_range(150.0_f64..250.0_f64)).round();
-
-        // Generate random reference treatment parameters
-        let reference_params = TreatmentParams {
-            gantry_angle: rng.gen_range(0..360),
-            collimator_angle: rng.gen_range(0..360),
-            field_size_x: rng.gen_range(5.0_f32..20.0_f32).round(),
-            field_size_y: rng.gen_range(5.0_f32..20.0_f32).round(),
-            dose_rate: match beam_type {
-                BeamType::XRay => rng.gen_range(80.0_f32..120.0_f32).round(),
-                BeamType::Electron => rng.gen_range(100.0_f32..200.0_f32).round(),
-                BeamType::Undefined => 100.0,
-            },
-        };
+impl RngCore for SessionRng {
+    fn next_u32(&mut self) -> u32 {
+        match &mut self.0 {
+            Some(rng) => rng.next_u32(),
+            None => rand::thread_rng().next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match &mut self.0 {
+            Some(rng) => rng.next_u64(),
+            None => rand::thread_rng().next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match &mut self.0 {
+            Some(rng) => rng.fill_bytes(dest),
+            None => rand::thread_rng().fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match &mut self.0 {
+            Some(rng) => rng.try_fill_bytes(dest),
+            None => rand::thread_rng().try_fill_bytes(dest),
+        }
+    }
+}
+
+/// Generate a random prescription (reference MEOS, parameters, and dose in cGy)
+/// The reference collimator always matches the beam type, so prescriptions are safe
+fn random_prescription<R: Rng>(rng: &mut R) -> (Meos, TreatmentParams, f64) {
+    let beam_type = if rng.gen_bool(0.5) {
+        BeamType::XRay
+    } else {
+        BeamType::Electron
+    };
+
+    let beam_energy = match rng.gen_range(0..5) {
+        0 => BeamEnergy::E5,
+        1 => BeamEnergy::E10,
+        2 => BeamEnergy::E15,
+        3 => BeamEnergy::E20,
+        _ => BeamEnergy::E25,
+    };
+
+    let collimator = match beam_type {
+        BeamType::XRay => CollimatorPosition::InPosition,
+        BeamType::Electron => CollimatorPosition::OutOfPosition,
+        BeamType::Undefined => CollimatorPosition::OutOfPosition,
+    };
+
+    let meos = Meos {
+        beam_type,
+        beam_energy,
+        collimator,
+    };
+
+    let dose = (rng.gen_range(150.0_f64..250.0_f64)).round();
+
+    let params = TreatmentParams {
+        gantry_angle: rng.gen_range(0..360),
+        collimator_angle: rng.gen_range(0..360),
+        field_size_x: rng.gen_range(5.0_f32..20.0_f32).round(),
+        field_size_y: rng.gen_range(5.0_f32..20.0_f32).round(),
+        dose_rate: match beam_type {
+            BeamType::XRay => rng.gen_range(80.0_f32..120.0_f32).round(),
+            BeamType::Electron => rng.gen_range(100.0_f32..200.0_f32).round(),
+            BeamType::Undefined => 100.0,
+        },
+    };
+
+    (meos, params, dose)
+}
+
+/// A freshly constructed state guarantees:
+/// - the reference prescription is self-consistent and safe (`reference_meos.is_safe()`)
+/// - console and hardware MEOS start `Undefined` with the collimator out, i.e. unsafe,
+///   so nothing can be treated before data entry and a hardware sync
+/// - console and hardware treatment parameters start at `TreatmentParams::default()`
+/// - phase is `Reset`, no dose has been delivered, and no malfunction is recorded
+/// - the console dose target is `DEFAULT_DOSE_TARGET`, not the prescribed dose
+impl Default for TheracState {
+    fn default() -> Self {
+        let (reference_meos, reference_params, reference_dose) =
+            random_prescription(&mut rand::thread_rng());
 
         Self {
             console_meos: Meos::default(),
@@ -312,12 +1219,51 @@ impl Default for TheracState {
             reset_pending: false,
             class3_ignore: false,
             malfunction_count: 0,
+            overdose_count: 0,
+            near_misses: 0,
             dose_delivered: 0.0,
-            dose_target: 200.0,
+            beam_on_seconds: 0.0,
+            beam_current: 0.0,
+            dose_target: DEFAULT_DOSE_TARGET,
             reference_dose_target: reference_dose,
+            monitor_units: 0.0,
+            monitor_units_delivered: 0.0,
             treatment_outcome: String::new(),
             log: Vec::new(),
             last_malfunction: None,
+            last_outcome: None,
+            task_status: TaskStatus::default(),
+            config: SimConfig::default(),
+            checklist: Checklist::default(),
+            acknowledged_differences: None,
+            last_readback: None,
+            operator_id: None,
+            operator_stats: BTreeMap::new(),
+            overrides: Vec::new(),
+            incidents: Vec::new(),
+            malfunction_dismissals: 0,
+            normalized_deviance_warnings: 0,
+            beam_hold_remaining_ms: 0,
+            collimator_transit_remaining_ms: 0,
+            bending_magnet_settle_remaining_ms: 0,
+            energy_settle_remaining_ms: 0,
+            missed_edit_setup: None,
+            patient_name: String::new(),
+            treatment_in_progress: None,
+            completed_treatments: Vec::new(),
+            dose_samples: Vec::new(),
+            wear_factor: new_machine_wear(),
+            treatments_since_calibration: 0,
+            collimator_jammed: false,
+            collimator_timeout: false,
+            simulation_paused: false,
+            phase_durations: BTreeMap::new(),
+            phase_entered: None,
+            tick: crate::tick::TickProgress::default(),
+            last_sensor_reading: None,
+            rng: SessionRng::default(),
+            messages: Arc::new(Messages::default()),
+            pending_alarms: Vec::new(),
         }
     }
 }
@@ -327,51 +1273,700 @@ impl TheracState {
         Self::default()
     }
 
-    /// Generate new reference parameters (called on reset)
-    pub fn generate_new_reference(&mut self) {
-        use rand::Rng;
-        let mut rng = rand::thread_rng();
+    /// State whose random draws all come from `seed`: the same seed gives the same
+    /// sequence of prescriptions, first one included, and with the same inputs the
+    /// same faults, jitter and wear, on every run
+    pub fn with_seed(seed: u64) -> Self {
+        let mut state = Self::default();
+        state.reseed(seed);
+        let (meos, params, dose) = state.next_prescription();
+        state.reference_meos = meos;
+        state.reference_params = params;
+        state.reference_dose_target = dose;
+        state
+    }
+
+    /// Make every later random draw from `seed`; the current prescription is kept
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = SessionRng::seeded(seed);
+    }
+
+    /// Next prescription from the session's stream
+    fn next_prescription(&mut self) -> (Meos, TreatmentParams, f64) {
+        random_prescription(&mut self.rng)
+    }
+
+    /// Whether the checklist allows the beam to be turned on
+    /// Always true unless `config.require_checklist` is set.
+    pub fn checklist_satisfied(&self) -> bool {
+        !self.config.require_checklist || self.checklist.all_checked()
+    }
+
+    /// Console entries that differ from the prescription, in form order
+    pub fn prescription_differences(&self) -> Vec<PrescriptionDifference> {
+        let console = &self.console_params;
+        let prescribed = &self.reference_params;
+        let rows = [
+            ("Mode", self.console_meos.beam_type.to_string(), self.reference_meos.beam_type.to_string()),
+            ("Energy", self.console_meos.beam_energy.to_string(), self.reference_meos.beam_energy.to_string()),
+            ("Gantry", format!("{} deg", console.gantry_angle), format!("{} deg", prescribed.gantry_angle)),
+            ("Collimator", format!("{} deg", console.collimator_angle), format!("{} deg", prescribed.collimator_angle)),
+            (
+                "Field",
+                format!("{}x{} cm", console.field_size_x, console.field_size_y),
+                format!("{}x{} cm", prescribed.field_size_x, prescribed.field_size_y),
+            ),
+            ("Dose", format!("{} cGy", self.dose_target), format!("{} cGy", self.reference_dose_target)),
+        ];
+        rows.into_iter()
+            .filter(|(_, entered, prescribed)| entered != prescribed)
+            .map(|(field, entered, prescribed)| PrescriptionDifference {
+                field: field.to_string(),
+                entered,
+                prescribed,
+            })
+            .collect()
+    }
 
-        let beam_type = if rng.gen_bool(0.5) {
-            BeamType::XRay
+    /// Compare the console, hardware and reference MEOS in one go
+    pub fn meos_diff(&self) -> MeosDiff {
+        let (console, hardware, reference) = (&self.console_meos, &self.hardware_meos, &self.reference_meos);
+        // Per field: console == hardware, hardware == reference
+        let fields = [
+            (MeosField::BeamType, console.beam_type == hardware.beam_type, hardware.beam_type == reference.beam_type),
+            (MeosField::BeamEnergy, console.beam_energy == hardware.beam_energy, hardware.beam_energy == reference.beam_energy),
+            (MeosField::Collimator, console.collimator == hardware.collimator, hardware.collimator == reference.collimator),
+        ];
+        let names = |differs: fn(bool, bool) -> bool| {
+            fields
+                .iter()
+                .filter(|&&(_, console_hardware, hardware_reference)| differs(console_hardware, hardware_reference))
+                .map(|&(field, _, _)| field)
+                .collect()
+        };
+        MeosDiff {
+            console_matches_hardware: console == hardware,
+            hardware_matches_reference: hardware == reference,
+            console_matches_reference: console == reference,
+            differing_fields: names(|console_hardware, hardware_reference| !(console_hardware && hardware_reference)),
+            console_hardware_fields: names(|console_hardware, _| !console_hardware),
+            sync_pending: console != hardware || hardware.collimator == CollimatorPosition::Transitioning,
+        }
+    }
+
+    /// Whether data entry may be completed as far as the read-back is concerned
+    /// True when not required, when the entries match the prescription, or when exactly
+    /// the current differences were acknowledged.
+    pub fn readback_satisfied(&self) -> bool {
+        if !self.config.require_readback {
+            return true;
+        }
+        let differences = self.prescription_differences();
+        differences.is_empty() || self.acknowledged_differences.as_ref() == Some(&differences)
+    }
+
+    /// Record a bypassed safety check
+    /// Every `config.normalized_deviance_threshold` malfunction dismissals a
+    /// NORMALIZED DEVIANCE warning is raised: the messages have stopped meaning anything.
+    pub fn record_override(&mut self, kind: OverrideKind, detail: String) {
+        self.add_log(format!("[OVERRIDE] {}: {}", kind, detail));
+        self.overrides.push(OverrideEvent {
+            kind,
+            detail,
+            at: chrono::Utc::now().format("%H:%M:%S").to_string(),
+            dose_delivered: self.dose_delivered,
+            operator_id: self.operator_id.clone(),
+        });
+
+        if kind == OverrideKind::MalfunctionDismissed {
+            self.malfunction_dismissals += 1;
+            let threshold = self.config.normalized_deviance_threshold;
+            if threshold > 0 && self.malfunction_dismissals.is_multiple_of(threshold) {
+                self.normalized_deviance_warnings += 1;
+                self.add_log_with_level(LogLevel::Warn, format!(
+                    "[WARNING] NORMALIZED DEVIANCE: {} malfunction messages dismissed this session",
+                    self.malfunction_dismissals
+                ));
+            }
+        }
+    }
+
+    /// Raise a malfunction: shown to the operator, logged, noted on the treatment record
+    /// and recorded in `incidents` with the console and hardware MEOS as they stand
+    /// Call it before leaving the phase the malfunction interrupted, so the incident
+    /// records that phase.
+    pub fn report_malfunction(&mut self, kind: MalfunctionKind, message: String) {
+        let (console_meos, hardware_meos) = (self.console_meos, self.hardware_meos);
+        self.report_checked_malfunction(kind, message, console_meos, hardware_meos);
+    }
+
+    /// `report_malfunction` for a check that compared a console and a hardware MEOS:
+    /// the incident records the MEOS the check saw rather than the machine now
+    pub fn report_checked_malfunction(&mut self, kind: MalfunctionKind, message: String, console_meos: Meos, hardware_meos: Meos) {
+        if let Some(treatment) = &mut self.treatment_in_progress {
+            treatment.malfunctions.push(message.clone());
+        }
+        self.incidents.push(Incident {
+            kind,
+            message: message.clone(),
+            at: chrono::Utc::now().to_rfc3339(),
+            dose_delivered: self.dose_delivered,
+            dose_target: self.dose_target,
+            operator_id: self.operator_id.clone(),
+            phase: self.phase,
+            console_meos,
+            hardware_meos,
+        });
+        let malfunction = Malfunction { kind, text: message.clone() };
+        self.raise_alarm(Alarm::Malfunction(malfunction.clone()));
+        self.last_malfunction = Some(malfunction);
+        self.add_log_with_level(kind.log_level(), message);
+    }
+
+    /// Queue an alarm for the next `dispatch_alarms`
+    pub fn raise_alarm(&mut self, alarm: Alarm) {
+        if self.pending_alarms.len() >= MAX_PENDING_ALARMS {
+            self.pending_alarms.remove(0);
+        }
+        self.pending_alarms.push(alarm);
+    }
+
+    /// The alarms raised since the last call, oldest first
+    pub fn take_alarms(&mut self) -> Vec<Alarm> {
+        std::mem::take(&mut self.pending_alarms)
+    }
+
+    /// `incidents` as a JSON array, e.g. for charting fault frequency
+    pub fn export_malfunction_history(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.incidents)
+    }
+
+    /// Set the console dose target (cGy); a target that is not positive is refused and
+    /// logged, leaving the previous one. Returns whether it was accepted.
+    pub fn set_dose_target(&mut self, dose: f64) -> bool {
+        if !valid_dose_target(dose) {
+            self.add_log_with_level(LogLevel::Warn, format!("[CONSOLE] Dose target {} cGy rejected - must be positive", dose));
+            return false;
+        }
+        self.dose_target = dose;
+        self.add_log(format!("[CONSOLE] Dose target set to {} cGy", dose));
+        true
+    }
+
+    /// Set the prescribed monitor units; like the dose target, a count that is not
+    /// positive is refused and logged. Returns whether it was accepted.
+    pub fn set_monitor_units(&mut self, monitor_units: f64) -> bool {
+        if !valid_dose_target(monitor_units) {
+            self.add_log_with_level(LogLevel::Warn, format!("[CONSOLE] Monitor units {} rejected - must be positive", monitor_units));
+            return false;
+        }
+        self.monitor_units = monitor_units;
+        self.add_log(format!("[CONSOLE] Monitor units set to {}", monitor_units));
+        true
+    }
+
+    /// Whether the beam has delivered what was prescribed: the monitor units when they
+    /// are set, otherwise the dose target
+    pub fn beam_target_reached(&self) -> bool {
+        if self.monitor_units > 0.0 {
+            self.monitor_units_delivered >= self.monitor_units
+        } else {
+            self.dose_delivered >= self.dose_target
+        }
+    }
+
+    /// Dose delivered as a percentage of the target, not capped at 100: an unflattened
+    /// pulse shows as thousands of percent. Zero without a positive target.
+    pub fn dose_percent_uncapped(&self) -> f64 {
+        if valid_dose_target(self.dose_target) {
+            self.dose_delivered / self.dose_target * 100.0
         } else {
-            BeamType::Electron
+            0.0
+        }
+    }
+
+    /// Beam-on time left to deliver the prescription at the hardware's effective dose rate
+    /// (the dose rate, concentrated by the field size when `field_size_scales_dose` is
+    /// on). Counts the monitor units left when they are set, otherwise the dose. Zero once
+    /// the target is reached or passed; `None` at a zero dose rate, which never gets there.
+    pub fn estimated_time_remaining(&self) -> Option<Duration> {
+        let mut rate_per_minute = self.hardware_params.dose_rate.max(0.0) as f64;
+        if self.config.field_size_scales_dose {
+            rate_per_minute *= crate::simulator::field_size_factor(&self.hardware_params);
+        }
+        if rate_per_minute <= 0.0 {
+            return None;
+        }
+        let remaining = if self.monitor_units > 0.0 {
+            self.monitor_units - self.monitor_units_delivered
+        } else {
+            self.dose_target - self.dose_delivered
         };
+        Some(Duration::from_secs_f64((remaining.max(0.0) / rate_per_minute * 60.0).min(u32::MAX as f64)))
+    }
+
+    /// Free a turntable stuck mid-rotation: it is re-homed to the position the console
+    /// beam type needs. A hardware beam type or energy the jam left behind is re-synced
+    /// by the housekeeper (`collimator_sync_needed`).
+    /// Returns false if it was not stuck.
+    pub fn free_stuck_collimator(&mut self) -> bool {
+        if self.hardware_meos.collimator != CollimatorPosition::Stuck {
+            return false;
+        }
+        let position = self.console_meos.beam_type.collimator_position();
+        self.hardware_meos.collimator = position;
+        self.add_log(format!("[OPERATOR] Stuck collimator freed and re-homed to {}", position));
+        true
+    }
+
+    /// Enter the console energy; going up starts the energy system settling for
+    /// `SimConfig::energy_settle_ms`, and a pulse before it has raises ENERGY NOT SETTLED
+    pub fn set_console_energy(&mut self, energy: BeamEnergy) {
+        let rising = energy.mev() > self.console_meos.beam_energy.mev();
+        self.console_meos.beam_energy = energy;
+        let settle_ms = self.config.energy_settle_ms;
+        if rising && settle_ms > 0 {
+            self.energy_settle_remaining_ms = settle_ms.min(u32::MAX as u64) as u32;
+            self.add_log(format!("[CONSOLE] Energy system ramping to {} ({} ms to settle)", energy, settle_ms));
+        }
+    }
+
+    /// Record an operator edit of the console entries
+    /// An edit after data entry was completed, with the monitor already past data entry,
+    /// opens the race window: the hardware has not synced to it yet. The first such edit
+    /// logs a warning; the window stays flagged until data entry is completed again.
+    /// While the bending magnets are settling during setup the edit goes unseen instead, as it
+    /// did on the original (`missed_edit_setup`).
+    pub fn mark_console_edit(&mut self) {
+        let past_data_entry = !matches!(self.phase, TPhase::Reset | TPhase::DataEntry);
+        let magnets_settling = self.bending_magnet_flag && matches!(self.phase, TPhase::SetupTest | TPhase::SetupDone);
+        if self.data_entry_complete && magnets_settling && !self.editing_taking_place {
+            if self.missed_edit_setup.is_none() {
+                self.missed_edit_setup = Some(Meos {
+                    beam_type: self.hardware_meos.beam_type,
+                    beam_energy: self.hardware_meos.beam_energy,
+                    collimator: self.console_meos.collimator,
+                });
+                self.add_log("[CONSOLE] Edit completed while the bending magnets are settling - not seen by the setup".to_string());
+            }
+            return;
+        }
+        if self.data_entry_complete && past_data_entry && !self.editing_taking_place {
+            self.add_log_with_level(
+                LogLevel::Warn,
+                "[CONSOLE] WARNING: Edit after data-entry completion - hardware sync pending".to_string(),
+            );
+        }
+        self.editing_taking_place = true;
+    }
+
+    /// Entry the setup works from: the console MEOS, or the entry it kept after missing
+    /// an edit (`missed_edit_setup`)
+    pub fn setup_meos(&self) -> Meos {
+        self.missed_edit_setup.unwrap_or(self.console_meos)
+    }
+
+    /// Whether the housekeeper has a turntable move to make: the console entry is unsafe
+    /// as it stands, or the hardware is at rest and either the turntable is short of
+    /// where the console beam type needs it or the beam type or energy is not the setup's
+    pub fn collimator_sync_needed(&self) -> bool {
+        let hardware = self.hardware_meos;
+        let setup = self.setup_meos();
+        let at_rest = !matches!(hardware.collimator, CollimatorPosition::Transitioning | CollimatorPosition::Stuck);
+        self.console_meos.needs_collimator_sync()
+            || (at_rest
+                && (hardware.collimator != self.console_meos.beam_type.collimator_position()
+                    || hardware.beam_type != setup.beam_type
+                    || hardware.beam_energy != setup.beam_energy))
+    }
+
+    /// Whether the race-relevant switches (safe mode, turntable timing) may change now:
+    /// not while the beam is on or about to be, so a treatment runs under one setting
+    pub fn config_change_allowed(&self) -> bool {
+        !matches!(self.phase, TPhase::BeamReady | TPhase::PatientTreatment)
+    }
+
+    /// Switch `SimConfig::safe_mode`, logging the change; refused and logged during
+    /// treatment. Returns whether it was accepted.
+    pub fn set_safe_mode(&mut self, enabled: bool) -> bool {
+        if !self.config_change_allowed() {
+            self.add_log_with_level(LogLevel::Warn, "[OPERATOR] Safe mode cannot change during treatment".to_string());
+            return false;
+        }
+        self.config.safe_mode = enabled;
+        self.add_log(format!(
+            "[OPERATOR] Safe mode {}",
+            if enabled { "on - race condition fixed" } else { "off - original race" }
+        ));
+        true
+    }
+
+    /// Set `SimConfig::collimator_move_ms`, logging the change; refused and logged
+    /// during treatment. Returns whether it was accepted.
+    pub fn set_collimator_move_ms(&mut self, ms: u64) -> bool {
+        if !self.config_change_allowed() {
+            self.add_log_with_level(LogLevel::Warn, "[OPERATOR] Turntable move time cannot change during treatment".to_string());
+            return false;
+        }
+        self.config.collimator_move_ms = ms;
+        self.add_log(format!("[OPERATOR] Turntable move {} ms", ms));
+        true
+    }
+
+    /// Set `SimConfig::param_sync_ms`, logging the change; refused and logged during
+    /// treatment. Returns whether it was accepted.
+    pub fn set_param_sync_ms(&mut self, ms: u64) -> bool {
+        if !self.config_change_allowed() {
+            self.add_log_with_level(LogLevel::Warn, "[OPERATOR] Param sync time cannot change during treatment".to_string());
+            return false;
+        }
+        self.config.param_sync_ms = ms;
+        self.add_log(format!("[OPERATOR] Param sync {} ms", ms));
+        true
+    }
+
+    /// Operator asked for beam on: hold in `BeamReady` if a beam hold is configured,
+    /// otherwise turn the beam on at once
+    pub fn request_beam_on(&mut self) {
+        if self.config.beam_hold_ms > 0 {
+            self.phase = TPhase::BeamReady;
+            self.beam_hold_remaining_ms = self.config.beam_hold_ms;
+            let hold = self.beam_hold_remaining_ms;
+            self.add_log(format!("[MONITOR] Beam ready - beam on in {} ms, stop to abort", hold));
+        } else {
+            self.beam_on();
+        }
+    }
+
+    /// Enter PatientTreatment and open the treatment record
+    pub fn beam_on(&mut self) {
+        self.phase = TPhase::PatientTreatment;
+        self.beam_hold_remaining_ms = 0;
+        self.begin_treatment();
+    }
+
+    /// Open the treatment record (beam turned on)
+    pub fn begin_treatment(&mut self) {
+        self.treatment_in_progress = Some(TreatmentInProgress {
+            started_at: chrono::Utc::now(),
+            malfunctions: Vec::new(),
+            near_misses: 0,
+            operator_id: self.operator_id.clone(),
+        });
+        if let Some(stats) = self.current_operator_stats() {
+            stats.treatments += 1;
+        }
+        self.treatments_since_calibration += 1;
+        self.dose_samples.clear();
+        self.dose_samples.push((0.0, self.dose_delivered));
+        self.add_wear();
+    }
+
+    /// Wear the machine by one treatment: `config.wear_per_treatment` with ±50% jitter
+    pub fn add_wear(&mut self) {
+        let step = self.config.wear_per_treatment;
+        if step <= 0.0 {
+            return;
+        }
+        let max = self.config.max_wear_factor.max(1.0);
+        self.wear_factor = (self.wear_factor + step * self.rng.gen_range(0.5..=1.5)).min(max);
+    }
+
+    /// Count a mismatch caught before the beam fired
+    pub fn record_near_miss(&mut self) {
+        self.near_misses += 1;
+        if let Some(treatment) = &mut self.treatment_in_progress {
+            treatment.near_misses += 1;
+        }
+        if let Some(stats) = self.current_operator_stats() {
+            stats.near_misses += 1;
+        }
+    }
+
+    /// Count a pulse fired with the beam unflattened
+    pub fn record_overdose(&mut self) {
+        self.overdose_count += 1;
+        if let Some(stats) = self.current_operator_stats() {
+            stats.overdoses += 1;
+        }
+    }
+
+    /// Statistics of the operator logged in, if any
+    fn current_operator_stats(&mut self) -> Option<&mut OperatorStats> {
+        let id = self.operator_id.clone()?;
+        Some(self.operator_stats.entry(id).or_default())
+    }
+
+    /// Session near-miss report, e.g. "3 near misses this session - the system caught
+    /// what could have been overdoses"; `None` before the first one
+    pub fn near_miss_report(&self) -> Option<String> {
+        match self.near_misses {
+            0 => None,
+            1 => Some("1 near miss this session - the system caught what could have been an overdose".to_string()),
+            n => Some(format!(
+                "{} near misses this session - the system caught what could have been overdoses",
+                n
+            )),
+        }
+    }
+
+    /// A treatment is under way: the beam is on or about to be, or a treatment record is
+    /// still open (paused on a malfunction, say). The prescription must not change now.
+    pub fn treatment_under_way(&self) -> bool {
+        matches!(self.phase, TPhase::BeamReady | TPhase::PatientTreatment) || self.treatment_in_progress.is_some()
+    }
+
+    /// The treatment count has reached `config.recalibration_interval`
+    pub fn recalibration_required(&self) -> bool {
+        self.config.recalibration_interval > 0
+            && self.treatments_since_calibration >= self.config.recalibration_interval
+    }
+
+    /// Recalibrate the machine: the treatment count starts over, and the mechanics are
+    /// serviced back to new along the way
+    pub fn recalibrate(&mut self) {
+        self.treatments_since_calibration = 0;
+        self.wear_factor = new_machine_wear();
+        self.add_log("[OPERATOR] Machine recalibrated - wear reset".to_string());
+    }
 
-        let beam_energy = match rng.gen_range(0..5) {
-            0 => BeamEnergy::E5,
-            1 => BeamEnergy::E10,
-            2 => BeamEnergy::E15,
-            3 => BeamEnergy::E20,
-            _ => BeamEnergy::E25,
+    /// Service the machine: wear back to new
+    pub fn reset_wear(&mut self) {
+        self.wear_factor = new_machine_wear();
+        self.add_log("[OPERATOR] Machine serviced - wear reset".to_string());
+    }
+
+    /// `base` mechanical time stretched by the current wear
+    pub fn worn(&self, base: std::time::Duration) -> std::time::Duration {
+        if self.wear_factor <= 1.0 {
+            return base;
+        }
+        base.mul_f64(self.wear_factor as f64)
+    }
+
+    /// Append the dose delivered so far to `dose_samples` (called after every pulse)
+    pub fn record_dose_sample(&mut self) {
+        let at = match &self.treatment_in_progress {
+            Some(treatment) => (chrono::Utc::now() - treatment.started_at)
+                .num_microseconds()
+                .map_or(0.0, |us| us as f64 / 1e6),
+            // Pulse outside a treatment record: keep the trace's last time
+            None => self.dose_samples.last().map_or(0.0, |&(at, _)| at),
         };
+        self.dose_samples.push((at, self.dose_delivered));
+        if self.dose_samples.len() > DOSE_SAMPLE_CAP {
+            self.dose_samples.drain(0..self.dose_samples.len() - DOSE_SAMPLE_CAP);
+        }
+    }
 
-        let collimator = match beam_type {
-            BeamType::XRay => CollimatorPosition::InPosition,
-            BeamType::Electron => CollimatorPosition::OutOfPosition,
-            BeamType::Undefined => CollimatorPosition::OutOfPosition,
+    /// `dose_samples` flattened to [t0, dose0, t1, dose1, ...] for a typed array
+    pub fn dose_samples_flat(&self) -> Vec<f64> {
+        self.dose_samples.iter().flat_map(|&(at, dose)| [at, dose]).collect()
+    }
+
+    /// Record how the treatment ended and close its record into `completed_treatments`
+    pub fn finish_treatment(&mut self, outcome: TreatmentOutcome) {
+        self.last_outcome = Some(outcome.clone());
+        self.close_treatment_record(outcome);
+    }
+
+    /// Close a treatment that is still open as terminated in its current phase
+    pub fn abandon_treatment(&mut self) {
+        let outcome = TreatmentOutcome::Terminated {
+            dose_delivered: self.dose_delivered,
+            phase: self.phase,
         };
+        self.close_treatment_record(outcome);
+    }
 
-        self.reference_meos = Meos {
-            beam_type,
-            beam_energy,
-            collimator,
+    fn close_treatment_record(&mut self, outcome: TreatmentOutcome) {
+        let Some(treatment) = self.treatment_in_progress.take() else {
+            return;
         };
+        let ended_at = chrono::Utc::now();
+        self.completed_treatments.push(TreatmentSummary {
+            patient_name: self.patient_name.clone(),
+            prescribed_meos: self.reference_meos,
+            prescribed_params: self.reference_params,
+            prescribed_dose: self.reference_dose_target,
+            dose_target: self.dose_target,
+            dose_delivered: self.dose_delivered,
+            started_at: treatment.started_at.to_rfc3339(),
+            ended_at: ended_at.to_rfc3339(),
+            duration_secs: (ended_at - treatment.started_at).num_milliseconds() as f64 / 1000.0,
+            malfunctions: treatment.malfunctions,
+            near_misses: treatment.near_misses,
+            operator_id: treatment.operator_id,
+            outcome,
+        });
+    }
+
+    /// Changes from `self` (the earlier snapshot) to `other`
+    pub fn diff(&self, other: &TheracState) -> StateDiff {
+        fn changed<T: PartialEq + Copy>(old: T, new: T) -> Option<T> {
+            (old != new).then_some(new)
+        }
 
-        self.reference_dose_target = (rng.gen_range(150.0_f64..250.0_f64)).round();
-
-        self.reference_params = TreatmentParams {
-            gantry_angle: rng.gen_range(0..360),
-            collimator_angle: rng.gen_range(0..360),
-            field_size_x: rng.gen_range(5.0_f32..20.0_f32).round(),
-            field_size_y: rng.gen_range(5.0_f32..20.0_f32).round(),
-            dose_rate: match beam_type {
-                BeamType::XRay => rng.gen_range(80.0_f32..120.0_f32).round(),
-                BeamType::Electron => rng.gen_range(100.0_f32..200.0_f32).round(),
-                BeamType::Undefined => 100.0,
-            },
+        let dose_change = other.dose_delivered - self.dose_delivered;
+        let new_malfunction = match (&self.last_malfunction, &other.last_malfunction) {
+            (old, Some(new)) if old.as_ref() != Some(new) => Some(new.clone()),
+            _ => None,
         };
 
+        StateDiff {
+            phase: changed(self.phase, other.phase),
+            dose_delivered_change: (dose_change.abs() > DOSE_DIFF_TOLERANCE).then_some(dose_change),
+            dose_target: ((other.dose_target - self.dose_target).abs() > DOSE_DIFF_TOLERANCE)
+                .then_some(other.dose_target),
+            console_meos: changed(self.console_meos, other.console_meos),
+            hardware_meos: changed(self.hardware_meos, other.hardware_meos),
+            collimator: changed(self.hardware_meos.collimator, other.hardware_meos.collimator),
+            new_malfunction,
+            malfunction_cleared: self.last_malfunction.is_some() && other.last_malfunction.is_none(),
+            new_log_entries: appended_log_entries(&self.log, &other.log),
+        }
+    }
+
+    /// Outcome of the session so far: dose against target, malfunctions by kind, the
+    /// phase it ended in and whether an unsafe beam ever fired
+    pub fn summary(&self) -> SessionSummary {
+        let mut malfunctions_by_kind = BTreeMap::new();
+        for incident in &self.incidents {
+            *malfunctions_by_kind.entry(incident.kind.name().to_string()).or_insert(0) += 1;
+        }
+        SessionSummary {
+            dose_delivered: self.dose_delivered,
+            dose_target: self.dose_target,
+            overdose_ratio: if self.dose_target > 0.0 { self.dose_delivered / self.dose_target } else { 0.0 },
+            malfunction_count: self.malfunction_count as usize,
+            malfunctions_by_kind,
+            final_phase: self.phase,
+            critical_safety_violation: self
+                .incidents
+                .iter()
+                .any(|incident| matches!(incident.kind, MalfunctionKind::UnsafeBeam { .. })),
+            phase_seconds: self
+                .phase_durations
+                .keys()
+                .chain(self.phase_entered.iter().map(|(phase, _)| phase))
+                .map(|&phase| (phase, self.time_in_phase(phase).as_secs_f64()))
+                .collect(),
+        }
+    }
+
+    /// Record a phase change seen by the treatment monitor: the time since the last
+    /// change is added to the phase that was left. Returns whether the phase changed.
+    pub fn track_phase(&mut self) -> bool {
+        let now = chrono::Utc::now();
+        match self.phase_entered {
+            Some((phase, _)) if phase == self.phase => false,
+            Some((phase, entered)) => {
+                *self.phase_durations.entry(phase).or_default() += (now - entered).to_std().unwrap_or_default();
+                self.phase_entered = Some((self.phase, now));
+                true
+            }
+            None => {
+                self.phase_entered = Some((self.phase, now));
+                true
+            }
+        }
+    }
+
+    /// Add `dt` of logical time to `phase`: the tick driver's counterpart to `track_phase`
+    pub fn charge_phase_time(&mut self, phase: TPhase, dt: Duration) {
+        *self.phase_durations.entry(phase).or_default() += dt;
+    }
+
+    /// Time spent in `phase` this session, including the current stay in it on the wall
+    /// clock
+    pub fn time_in_phase(&self, phase: TPhase) -> Duration {
+        let past = self.phase_durations.get(&phase).copied().unwrap_or_default();
+        match self.phase_entered {
+            Some((current, entered)) if current == phase => {
+                past + (chrono::Utc::now() - entered).to_std().unwrap_or_default()
+            }
+            _ => past,
+        }
+    }
+
+    /// Completed treatment summaries as a JSON array
+    pub fn export_treatment_summaries(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&self.completed_treatments)
+    }
+
+    /// The whole session as JSON, to resume later with `from_json`
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    /// Resume a session saved with `to_json`
+    /// Fields that are not serialized start fresh: no open treatment record (the
+    /// treatment resumes but leaves no summary), no seeded random stream, and
+    /// the English messages. A configuration that fails `SimConfig::validate` is
+    /// rejected, as it is from a config file.
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        let state: Self = serde_json::from_str(json)?;
+        state.config.validate().map_err(serde::de::Error::custom)?;
+        Ok(state)
+    }
+
+    /// Save the session to a file as JSON
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_to_path(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json()?)
+    }
+
+    /// Resume a session saved with `save_to_path`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_from_path(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::from_json(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Multi-section debug report of every field that decides the next pulse, each part
+    /// annotated safe or dangerous; see `inspect.rs`
+    pub fn inspect(&self) -> String {
+        crate::inspect::report(self)
+    }
+
+    /// Copy of what the displays show, to draw a frame from after releasing the lock;
+    /// see `snapshot.rs`
+    pub fn snapshot(&self) -> crate::snapshot::TheracSnapshot {
+        crate::snapshot::TheracSnapshot::capture(self)
+    }
+
+    /// The event log as CSV with a `timestamp,level,message` header, oldest first;
+    /// a field containing a comma, quote or line break is quoted
+    pub fn export_log_csv(&self) -> String {
+        let mut csv = String::from("timestamp,level,message\n");
+        for entry in &self.log {
+            csv += &format!("{},{:?},{}\n", csv_field(&entry.timestamp), entry.level, csv_field(&entry.message));
+        }
+        csv
+    }
+
+    /// Write the event log to a file as CSV (`export_log_csv`)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_log_csv(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<()> {
+        std::fs::write(path, self.export_log_csv())
+    }
+
+    /// Incidents of the session as CEF lines for a SIEM, one per line
+    pub fn export_incidents_cef(&self) -> String {
+        self.incidents.iter().map(|incident| crate::cef::incident_line(incident) + "\n").collect()
+    }
+
+    /// Generate new reference parameters (called on reset)
+    pub fn generate_new_reference(&mut self) {
+        let (meos, params, dose) = self.next_prescription();
+        self.set_reference(meos, params, dose);
+    }
+
+    /// Make `meos`, `params` and `dose` (cGy) the prescription
+    pub fn set_reference(&mut self, meos: Meos, params: TreatmentParams, dose: f64) {
+        self.reference_meos = meos;
+        self.reference_params = params;
+        self.reference_dose_target = dose;
+
         self.add_log(format!(
             "New prescription: {} @ {} - {} cGy - Gantry {} deg - Field {}x{} cm",
             self.reference_meos.beam_type,
@@ -383,8 +1978,18 @@ impl TheracState {
         ));
     }
 
+    /// Log a routine message; warnings and malfunctions go through `add_log_with_level`
     pub fn add_log(&mut self, message: String) {
-        self.log.push(format!("[{}] {}", chrono::Utc::now().format("%H:%M:%S"), message));
+        self.add_log_with_level(LogLevel::Info, message);
+    }
+
+    pub fn add_log_with_level(&mut self, level: LogLevel, message: String) {
+        self.log.push(LogEntry {
+            timestamp: chrono::Utc::now().format("%H:%M:%S").to_string(),
+            operator_id: self.operator_id.clone(),
+            level,
+            message,
+        });
         // Keep only last 100 log entries
         if self.log.len() > 100 {
             self.log.drain(0..self.log.len() - 100);
@@ -392,20 +1997,35 @@ impl TheracState {
     }
 
     pub fn reset(&mut self) {
+        // A treatment still open at reset was terminated before it completed
+        self.abandon_treatment();
+
         self.phase = TPhase::Reset;
         self.data_entry_complete = false;
         self.f_small = false;
         self.class3 = 0;
         self.bending_magnet_flag = false;
+        self.bending_magnet_settle_remaining_ms = 0;
+        self.energy_settle_remaining_ms = 0;
+        self.missed_edit_setup = None;
         self.editing_taking_place = false;
         self.reset_pending = false;
         self.class3_ignore = false;
         self.dose_delivered = 0.0;
-        self.dose_target = 200.0;
+        self.beam_on_seconds = 0.0;
+        self.beam_current = 0.0;
+        self.dose_target = DEFAULT_DOSE_TARGET;
+        self.monitor_units = 0.0;
+        self.monitor_units_delivered = 0.0;
         self.last_malfunction = None;
+        self.last_outcome = None;
+        self.checklist = Checklist::default();
+        self.acknowledged_differences = None;
         self.treatment_outcome = String::new();
         self.console_meos = Meos::default();
         self.console_params = TreatmentParams::default();
+        self.last_sensor_reading = None;
+        self.collimator_timeout = false;
         self.add_log("System reset".to_string());
         self.generate_new_reference();
     }
@@ -418,6 +2038,247 @@ pub type SharedTheracState = Arc<RwLock<TheracState>>;
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_log_source_from_entry() {
+        let mut s = TheracState::default();
+        s.add_log("[HOUSEKEEPER] Hardware synced".to_string());
+        s.add_log("[CONSOLE] Mode set to X-Ray".to_string());
+        s.add_log("System reset".to_string());
+        let sources: Vec<LogSource> = s.log.iter().map(LogEntry::source).collect();
+        assert_eq!(sources, [LogSource::Housekeeper, LogSource::Console, LogSource::Other]);
+        assert_eq!(LogSource::of_message("[MONITOR] untimed"), LogSource::Monitor);
+
+        s.recalibrate();
+        s.reset_wear();
+        s.config.beam_hold_ms = 100;
+        s.request_beam_on();
+        let sources: Vec<LogSource> = s.log[3..].iter().map(LogEntry::source).collect();
+        assert_eq!(sources, [LogSource::Operator, LogSource::Operator, LogSource::Monitor]);
+    }
+
+    #[test]
+    fn test_log_levels() {
+        let mut s = TheracState::default();
+        s.add_log("[CONSOLE] Mode set to X-Ray".to_string());
+        s.report_malfunction(MalfunctionKind::ParameterMismatch, "MALFUNCTION 54".to_string());
+        // Translated text carries no English keywords; the kind still decides the level
+        s.report_malfunction(
+            MalfunctionKind::UnsafeBeam { dose_multiplier: 100.0, dose_this_pulse: 800.0 },
+            "VIOLACION DE SEGURIDAD".to_string(),
+        );
+        s.add_log_with_level(LogLevel::Warn, "[OPERATOR] Checklist skipped".to_string());
+        let levels: Vec<LogLevel> = s.log.iter().map(|e| e.level).collect();
+        assert_eq!(levels, [LogLevel::Info, LogLevel::Warn, LogLevel::Critical, LogLevel::Warn]);
+        // The wording does not decide the level
+        s.add_log("[CONSOLE] WARNING: routine".to_string());
+        assert_eq!(s.log.last().unwrap().level, LogLevel::Info);
+
+        // Text dumps read as they always did
+        let text = s.log[0].to_string();
+        assert!(text.starts_with('[') && text.ends_with("] [CONSOLE] Mode set to X-Ray"), "{}", text);
+        assert_eq!(text.len(), "[HH:MM:SS] [CONSOLE] Mode set to X-Ray".len());
+        s.operator_id = Some("alice".to_string());
+        s.add_log("[OPERATOR] Logged in".to_string());
+        assert!(s.log.last().unwrap().to_string().ends_with(" alice] [OPERATOR] Logged in"));
+    }
+
+    #[test]
+    fn test_log_csv_quotes_commas_and_keeps_levels() {
+        let mut s = TheracState::default();
+        s.add_log("[CONSOLE] Mode set to X-Ray".to_string());
+        s.report_malfunction(MalfunctionKind::ParameterMismatch, "MALFUNCTION 54, dose \"low\"".to_string());
+        let csv = s.export_log_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], "timestamp,level,message");
+        assert!(rows[1].ends_with(",Info,[CONSOLE] Mode set to X-Ray"), "{}", rows[1]);
+        assert!(rows[2].ends_with(",Warn,\"MALFUNCTION 54, dose \"\"low\"\"\""), "{}", rows[2]);
+        assert_eq!(rows[2].split_once(',').unwrap().0, s.log[1].timestamp);
+    }
+
+    #[test]
+    fn test_intermediate_energies() {
+        let energy = BeamEnergy::from_mev(18.0).unwrap();
+        assert_eq!(energy, BeamEnergy::EnergyMeV(18.0));
+        assert_eq!(energy.to_string(), "18 MeV");
+        assert_eq!(BeamEnergy::EnergyMeV(17.5).to_string(), "17.5 MeV");
+        assert!(matches!(BeamEnergy::from_mev(20.0), Some(BeamEnergy::E20)));
+        assert_eq!(BeamEnergy::EnergyMeV(10.0), BeamEnergy::E10);
+        assert_eq!(BeamEnergy::from_mev(4.9), None);
+        assert_eq!(BeamEnergy::from_mev(f32::NAN), None);
+
+        // Presets serialize as before; only the new variant carries a number
+        assert_eq!(serde_json::to_string(&BeamEnergy::E25).unwrap(), "\"E25\"");
+        assert_eq!(serde_json::from_str::<BeamEnergy>("\"E5\"").unwrap(), BeamEnergy::E5);
+        let json = serde_json::to_string(&energy).unwrap();
+        assert_eq!(json, r#"{"EnergyMeV":18.0}"#);
+        assert_eq!(serde_json::from_str::<BeamEnergy>(&json).unwrap(), energy);
+    }
+
+    #[test]
+    fn test_session_round_trips_through_json() {
+        let mut s = TheracState {
+            phase: TPhase::PatientTreatment,
+            console_meos: Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::InPosition },
+            hardware_meos: Meos { beam_type: BeamType::XRay, beam_energy: BeamEnergy::E25, collimator: CollimatorPosition::Transitioning },
+            dose_delivered: 0.1 + 0.2,
+            beam_current: 10.0 / 3.0,
+            dose_target: 187.0,
+            malfunction_count: 3,
+            overdose_count: 1,
+            patient_name: "Test Patient".to_string(),
+            ..TheracState::default()
+        };
+        s.add_log("[CONSOLE] Mode set to X-Ray".to_string());
+        s.report_malfunction(MalfunctionKind::ParameterMismatch, "MALFUNCTION 54".to_string());
+
+        let restored = TheracState::from_json(&s.to_json().unwrap()).unwrap();
+        assert_eq!(restored.phase, s.phase);
+        assert_eq!(restored.console_meos, s.console_meos);
+        assert_eq!(restored.hardware_meos, s.hardware_meos);
+        assert_eq!(restored.reference_meos, s.reference_meos);
+        assert_eq!(restored.reference_params, s.reference_params);
+        assert_eq!(restored.reference_dose_target, s.reference_dose_target);
+        assert_eq!(restored.dose_delivered.to_bits(), s.dose_delivered.to_bits());
+        assert_eq!(restored.beam_current.to_bits(), s.beam_current.to_bits());
+        assert_eq!(restored.dose_target, s.dose_target);
+        assert_eq!(
+            (restored.malfunction_count, restored.overdose_count, restored.near_misses),
+            (s.malfunction_count, s.overdose_count, s.near_misses)
+        );
+        assert_eq!(restored.log, s.log);
+        assert_eq!(restored.last_malfunction, s.last_malfunction);
+        assert_eq!(restored.incidents, s.incidents);
+        assert_eq!(restored.patient_name, s.patient_name);
+    }
+
+    #[test]
+    fn test_session_save_and_load() {
+        let path = std::env::temp_dir().join(format!("rstherac25-session-{}.json", std::process::id()));
+        let s = TheracState { dose_delivered: 42.5, ..TheracState::default() };
+        s.save_to_path(&path).unwrap();
+        let restored = TheracState::load_from_path(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(restored.unwrap().dose_delivered, 42.5);
+
+        let error = TheracState::load_from_path(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+
+        // A session file cannot bring in a configuration a config file would be refused
+        let mut s = TheracState::default();
+        s.config.random_fault_probability = 1.5;
+        s.save_to_path(&path).unwrap();
+        let error = TheracState::load_from_path(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(error.to_string().contains("random_fault_probability"), "{}", error);
+        assert!(TheracState::from_json(&s.to_json().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_safe_mode_and_timing_hold_still_during_treatment() {
+        let mut s = TheracState { phase: TPhase::SetupDone, ..TheracState::default() };
+        assert!(s.set_safe_mode(true));
+        assert!(s.set_collimator_move_ms(2000));
+        assert!(s.log.last().unwrap().message.contains("2000 ms"));
+        assert!(s.set_param_sync_ms(50));
+
+        s.phase = TPhase::PatientTreatment;
+        assert!(!s.set_safe_mode(false));
+        assert!(!s.set_collimator_move_ms(100));
+        assert!(s.log.last().unwrap().message.contains("cannot change during treatment"));
+        assert!(!s.set_param_sync_ms(500));
+        assert!(s.config.safe_mode);
+        assert_eq!(s.config.collimator_move_ms, 2000);
+        assert_eq!(s.config.param_sync_ms, 50);
+    }
+
+    #[test]
+    fn test_diff_of_identical_states_is_empty() {
+        let mut s = TheracState::default();
+        s.add_log("System initialized".to_string());
+        assert!(s.diff(&s.clone()).is_empty());
+    }
+
+    #[test]
+    fn test_seeded_states_draw_the_same_prescriptions() {
+        fn prescriptions(s: &mut TheracState) -> Vec<(Meos, TreatmentParams, f64)> {
+            let mut drawn = vec![(s.reference_meos, s.reference_params, s.reference_dose_target)];
+            for _ in 0..5 {
+                s.reset();
+                drawn.push((s.reference_meos, s.reference_params, s.reference_dose_target));
+            }
+            drawn
+        }
+
+        let first = prescriptions(&mut TheracState::with_seed(1251));
+        assert_eq!(prescriptions(&mut TheracState::with_seed(1251)), first);
+        assert_ne!(prescriptions(&mut TheracState::with_seed(1252)), first);
+
+        // Reseeding keeps the current prescription and draws the next ones from the seed
+        let mut s = TheracState::new();
+        s.reseed(1251);
+        s.generate_new_reference();
+        assert_eq!(prescriptions(&mut s), first);
+    }
+
+    #[test]
+    fn test_diff_reports_changes() {
+        let before = TheracState::default();
+        let mut after = before.clone();
+        after.phase = TPhase::PauseTreatment;
+        after.dose_delivered += 8.0;
+        after.dose_delivered += DOSE_DIFF_TOLERANCE / 10.0;
+        after.hardware_meos.collimator = CollimatorPosition::Transitioning;
+        after.report_malfunction(MalfunctionKind::ParameterMismatch, "MALFUNCTION 54".to_string());
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.phase, Some(TPhase::PauseTreatment));
+        assert!((diff.dose_delivered_change.unwrap() - 8.0).abs() < DOSE_DIFF_TOLERANCE);
+        assert_eq!(diff.dose_target, None);
+        assert_eq!(diff.console_meos, None);
+        assert_eq!(diff.hardware_meos, Some(after.hardware_meos));
+        assert_eq!(diff.collimator, Some(CollimatorPosition::Transitioning));
+        assert_eq!(diff.new_malfunction.map(|m| m.code()), Some(54));
+        assert!(!diff.malfunction_cleared);
+        assert_eq!(diff.new_log_entries.len(), 1);
+
+        // Float noise is not a dose change; clearing the malfunction is reported
+        let mut later = after.clone();
+        later.dose_delivered += DOSE_DIFF_TOLERANCE / 10.0;
+        later.last_malfunction = None;
+        let diff = after.diff(&later);
+        assert_eq!(diff.dose_delivered_change, None);
+        assert_eq!(diff.new_malfunction, None);
+        assert!(diff.malfunction_cleared);
+    }
+
+    #[test]
+    fn test_diff_log_survives_the_cap() {
+        let mut before = TheracState::default();
+        for i in 0..100 {
+            before.add_log(format!("line {}", i));
+        }
+        let mut after = before.clone();
+        for i in 100..105 {
+            after.add_log(format!("line {}", i));
+        }
+        assert_eq!(after.log.len(), 100);
+
+        let new = before.diff(&after).new_log_entries;
+        assert_eq!(new.len(), 5);
+        assert_eq!(new[0].message, "line 100");
+    }
+
+    #[test]
+    fn test_equivalent_square() {
+        let field = |x, y| TreatmentParams { field_size_x: x, field_size_y: y, ..TreatmentParams::default() };
+        assert_eq!(field(10.0, 10.0).equivalent_square(), 10.0);
+        assert_eq!(field(5.0, 20.0).equivalent_square(), 8.0);
+        assert_eq!(field(20.0, 5.0).equivalent_square(), 8.0);
+        assert_eq!(field(0.0, 15.0).equivalent_square(), 0.0);
+        assert_eq!(field(0.0, 0.0).equivalent_square(), 0.0);
+    }
+
     #[test]
     fn test_meos_safety() {
         let xray_safe = Meos {
@@ -442,6 +2303,35 @@ mod tests {
         assert!(electron_safe.is_safe());
     }
 
+    #[test]
+    fn test_default_state_invariants() {
+        // The prescription is random, so check the contract over many constructions
+        for _ in 0..200 {
+            let s = TheracState::default();
+
+            assert!(s.reference_meos.is_safe());
+            assert_ne!(s.reference_meos.beam_type, BeamType::Undefined);
+            assert!((150.0..=250.0).contains(&s.reference_dose_target));
+
+            for meos in [s.console_meos, s.hardware_meos] {
+                assert_eq!(meos.beam_type, BeamType::Undefined);
+                assert_eq!(meos.collimator, CollimatorPosition::OutOfPosition);
+                assert!(!meos.is_safe());
+            }
+            assert_eq!(s.console_params, TreatmentParams::default());
+            assert_eq!(s.hardware_params, TreatmentParams::default());
+
+            assert_eq!(s.phase, TPhase::Reset);
+            assert_eq!(s.dose_delivered, 0.0);
+            assert_eq!(s.dose_target, DEFAULT_DOSE_TARGET);
+            assert_eq!(s.malfunction_count, 0);
+            assert_eq!(s.overdose_count, 0);
+            assert!(s.last_malfunction.is_none());
+            assert!(!s.data_entry_complete);
+            assert!(!s.editing_taking_place);
+        }
+    }
+
     #[test]
     fn test_collimator_sync_needed() {
         let needs_sync = Meos {
@@ -451,4 +2341,96 @@ mod tests {
         };
         assert!(needs_sync.needs_collimator_sync());
     }
+
+    #[test]
+    fn test_meos_diff_all_match() {
+        let mut s = TheracState::default();
+        s.console_meos = s.reference_meos;
+        s.hardware_meos = s.reference_meos;
+        let diff = s.meos_diff();
+        assert!(diff.console_matches_hardware && diff.hardware_matches_reference && diff.console_matches_reference);
+        assert!(diff.differing_fields.is_empty() && diff.console_hardware_fields.is_empty());
+        assert!(!diff.sync_pending);
+    }
+
+    #[test]
+    fn test_meos_diff_partial_mismatch() {
+        let mut s = TheracState::default();
+        s.reference_meos = Meos {
+            beam_type: BeamType::XRay,
+            beam_energy: BeamEnergy::E25,
+            collimator: CollimatorPosition::InPosition,
+        };
+        s.hardware_meos = s.reference_meos;
+        // The quick edit: the console went to electron mode, the hardware has not followed
+        s.console_meos = Meos { beam_type: BeamType::Electron, ..s.reference_meos };
+        let diff = s.meos_diff();
+        assert!(!diff.console_matches_hardware);
+        assert!(diff.hardware_matches_reference);
+        assert!(!diff.console_matches_reference);
+        assert_eq!(diff.differing_fields, [MeosField::BeamType]);
+        assert_eq!(diff.console_hardware_fields, [MeosField::BeamType]);
+        assert!(diff.differs(MeosField::BeamType) && !diff.differs(MeosField::Collimator));
+        assert!(diff.sync_pending);
+
+        // The turntable on its way: energy still differs and the sync is not done
+        s.console_meos.beam_energy = BeamEnergy::E10;
+        s.hardware_meos = Meos { collimator: CollimatorPosition::Transitioning, ..s.console_meos };
+        let diff = s.meos_diff();
+        assert!(!diff.hardware_matches_reference);
+        assert_eq!(
+            diff.differing_fields,
+            [MeosField::BeamType, MeosField::BeamEnergy, MeosField::Collimator]
+        );
+        // Console and hardware agree on everything but the turntable
+        assert_eq!(diff.console_hardware_fields, [MeosField::Collimator]);
+        // The diff serializes under the MEOS field names
+        let json = serde_json::to_string(&diff).unwrap();
+        assert!(json.contains(r#""console_hardware_fields":["collimator"]"#));
+        assert!(diff.sync_pending);
+    }
+
+    #[test]
+    fn test_dose_percent_uncapped() {
+        let mut s = TheracState { dose_target: 200.0, ..Default::default() };
+        assert_eq!(s.dose_percent_uncapped(), 0.0);
+        s.dose_delivered = 50.0;
+        assert_eq!(s.dose_percent_uncapped(), 25.0);
+        s.dose_delivered = 200.0;
+        assert_eq!(s.dose_percent_uncapped(), 100.0);
+
+        // One unflattened pulse: far past the target, and not clamped to 100
+        s.dose_delivered = 8600.0;
+        assert_eq!(s.dose_percent_uncapped(), 4300.0);
+
+        s.dose_target = 0.0;
+        assert_eq!(s.dose_percent_uncapped(), 0.0);
+    }
+
+    #[test]
+    fn test_estimated_time_remaining() {
+        let mut s = TheracState { dose_target: 200.0, dose_delivered: 50.0, ..Default::default() };
+        s.hardware_params.dose_rate = 100.0;
+        // 150 cGy at 100 cGy/min
+        assert_eq!(s.estimated_time_remaining(), Some(Duration::from_secs(90)));
+
+        // A 5x5 cm field concentrates the dose fourfold when the field size counts
+        s.config.field_size_scales_dose = true;
+        s.hardware_params.field_size_x = 5.0;
+        s.hardware_params.field_size_y = 5.0;
+        assert_eq!(s.estimated_time_remaining(), Some(Duration::from_secs_f64(22.5)));
+        s.config.field_size_scales_dose = false;
+
+        // Monitor units set: those left count, not the dose
+        s.monitor_units = 120.0;
+        s.monitor_units_delivered = 20.0;
+        assert_eq!(s.estimated_time_remaining(), Some(Duration::from_secs(60)));
+        s.monitor_units = 0.0;
+
+        s.dose_delivered = 8600.0;
+        assert_eq!(s.estimated_time_remaining(), Some(Duration::ZERO));
+
+        s.hardware_params.dose_rate = 0.0;
+        assert_eq!(s.estimated_time_remaining(), None);
+    }
 }
@@ -0,0 +1,348 @@
+//! Deterministic, tick-based driving of the simulator
+//!
+//! This is the task logic: the async `treatment_monitor` and `housekeeper` run one
+//! `monitor_tick` or `housekeeper_tick` per poll, sleeping on the wall clock between
+//! them, so whether the housekeeper moves the turntable between the monitor's read and
+//! its check is up to the scheduler. Driven from here nothing sleeps: `step` runs one
+//! tick of every task, while `housekeeper_tick` and `monitor_tick` let a test or a
+//! frontend interleave the two in whatever order it chooses.
+//!
+//! The unsafe zap spans two monitor ticks - the MEOS is read on one and checked and
+//! fired on the next - so a housekeeper tick in between reproduces the race exactly.
+//! In safe mode the read, check and pulse happen on one tick.
+
+use crate::config::SyncAspect;
+use crate::locking::LockState;
+use crate::simulator::*;
+use crate::state::*;
+use std::time::Duration;
+
+/// Where the monitor and housekeeper are in their work on the logical clock; kept in
+/// `TheracState::tick` between ticks
+#[derive(Debug, Clone, Default)]
+pub struct TickProgress {
+    /// Phase the monitor's current pass is in, and how long it has been at it
+    monitor_phase: TPhase,
+    monitor_pass: Duration,
+    /// Console and hardware MEOS the monitor read for the pulse it checks and fires
+    /// on its next tick
+    pending_check: Option<(Meos, Meos)>,
+    housekeeper: HousekeeperStage,
+}
+
+impl TickProgress {
+    /// Whether the monitor has read the MEOS for a pulse it has not yet checked
+    pub fn check_pending(&self) -> bool {
+        self.pending_check.is_some()
+    }
+}
+
+/// What the housekeeper is doing between ticks
+#[derive(Debug, Clone, Copy, Default)]
+enum HousekeeperStage {
+    #[default]
+    Idle,
+    /// Syncing the params ahead of a turntable move (`SyncOrder` puts them first),
+    /// for this long so far
+    ParamsFirst(Duration),
+    Collimator(CollimatorTransit),
+    /// Syncing the params, for this long so far
+    Params(Duration),
+}
+
+/// Advance every task by one tick of `dt`: the housekeeper, then the treatment
+/// monitor, then the bending magnets and energy system. The hardware watchdog and phantom operator are
+/// not stepped; drive them with `CollimatorWatchdog::check` and `phantom_nudge`.
+pub fn step(state: SharedTheracState, dt: Duration) {
+    housekeeper_tick(&state, dt);
+    monitor_tick(&state, dt);
+    settle_bending_magnets(&state, dt);
+    settle_energy(&state, dt);
+}
+
+/// One tick of the treatment monitor: a pulse read on the last tick is checked and
+/// fired against that reading; otherwise the pass for the current phase runs once it
+/// has taken as long as the async monitor sleeps for it. `dt` is charged to the current
+/// phase (`TheracState::charge_phase_time`).
+pub fn monitor_tick(state: &SharedTheracState, dt: Duration) {
+    let mut s = state.write_state_logged("[MONITOR]");
+    let phase = s.phase;
+    s.charge_phase_time(phase, dt);
+    monitor_advance(&mut s, dt);
+}
+
+/// Move the monitor on by `dt` with the write lock held, leaving the phase time to the
+/// caller: the async monitor charges the wall clock instead
+pub(crate) fn monitor_advance(s: &mut TheracState, dt: Duration) {
+    if let Some((console_meos, hardware_meos)) = s.tick.pending_check.take() {
+        let fault_roll = random_fault_roll(s);
+        verify_and_fire(s, console_meos, hardware_meos, fault_roll);
+        return;
+    }
+
+    let phase = s.phase;
+    s.task_status.monitor = monitor_activity(phase).to_string();
+    if s.tick.monitor_phase != phase {
+        s.tick.monitor_phase = phase;
+        s.tick.monitor_pass = Duration::ZERO;
+    }
+    s.tick.monitor_pass += dt;
+    if s.tick.monitor_pass < monitor_pass_time(s, phase) {
+        return;
+    }
+    s.tick.monitor_pass = Duration::ZERO;
+
+    match phase {
+        TPhase::Reset => finish_reset(s),
+        TPhase::DataEntry => {
+            if s.data_entry_complete {
+                begin_setup_test(s);
+            }
+        }
+        TPhase::SetupTest => setup_test_pass(s),
+        TPhase::BeamReady => beam_hold_pass(s),
+        TPhase::PatientTreatment if s.config.safe_mode => zap_safe_locked(s),
+        TPhase::PatientTreatment => s.tick.pending_check = Some((s.setup_meos(), s.hardware_meos)),
+        TPhase::TerminateTreatment => finish_termination(s),
+        TPhase::DateTimeIdChanges => finish_datetime_changes(s),
+        TPhase::SetupDone | TPhase::PauseTreatment | TPhase::EmergencyStop => {}
+    }
+}
+
+/// One tick of the housekeeper: start the next sync if idle, then move the current
+/// one on by `dt`. A move started before the beam came on runs to its end.
+pub fn housekeeper_tick(state: &SharedTheracState, dt: Duration) {
+    let mut s = state.write_state_logged("[HOUSEKEEPER]");
+    let stage = match s.tick.housekeeper {
+        HousekeeperStage::Idle => start_sync(&mut s),
+        stage => stage,
+    };
+    let stage = advance(&mut s, stage, dt);
+    s.task_status.housekeeper = match stage {
+        HousekeeperStage::Idle => "idle".to_string(),
+        HousekeeperStage::ParamsFirst(_) | HousekeeperStage::Params(_) => "syncing treatment params".to_string(),
+        HousekeeperStage::Collimator(_) if s.collimator_jammed => "collimator jammed".to_string(),
+        HousekeeperStage::Collimator(transit) => format!(
+            "collimator transit {}%",
+            transit.elapsed().as_millis() * 100 / transit.total().as_millis().max(1)
+        ),
+    };
+    s.tick.housekeeper = stage;
+}
+
+/// What an idle housekeeper takes on next
+fn start_sync(s: &mut TheracState) -> HousekeeperStage {
+    // Only sync if not in critical treatment phase
+    if s.phase == TPhase::PatientTreatment {
+        return HousekeeperStage::Idle;
+    }
+    let params_differ = s.console_params != s.hardware_params;
+    if s.collimator_sync_needed() && s.hardware_meos.collimator != CollimatorPosition::Stuck {
+        if params_differ && s.config.sync_order.before_collimator().contains(&SyncAspect::Params) {
+            return HousekeeperStage::ParamsFirst(Duration::ZERO);
+        }
+        return start_collimator(s);
+    }
+    if params_differ {
+        HousekeeperStage::Params(Duration::ZERO)
+    } else {
+        HousekeeperStage::Idle
+    }
+}
+
+fn start_collimator(s: &mut TheracState) -> HousekeeperStage {
+    if s.hardware_meos.collimator != CollimatorPosition::Transitioning {
+        let sync_order = s.config.sync_order.clone();
+        begin_collimator_move(s, &sync_order);
+    }
+    let transit = CollimatorTransit::with_transit_time(s.console_meos.beam_type, s.worn(s.config.collimator_move_time()));
+    s.collimator_transit_remaining_ms = transit.remaining().as_millis() as u32;
+    HousekeeperStage::Collimator(transit)
+}
+
+fn advance(s: &mut TheracState, stage: HousekeeperStage, dt: Duration) -> HousekeeperStage {
+    match stage {
+        HousekeeperStage::Idle => HousekeeperStage::Idle,
+        HousekeeperStage::ParamsFirst(elapsed) => {
+            if elapsed + dt < s.worn(s.config.param_sync_time()) {
+                return HousekeeperStage::ParamsFirst(elapsed + dt);
+            }
+            s.hardware_params = s.console_params;
+            start_collimator(s)
+        }
+        HousekeeperStage::Collimator(mut transit) => {
+            // Stuck between positions: no headway, still Transitioning
+            if s.collimator_jammed {
+                return stage;
+            }
+            transit.advance(dt);
+            let (beam_type, hysteresis) = (s.console_meos.beam_type, s.config.collimator_hysteresis_ms);
+            if transit.retarget(beam_type, Duration::from_millis(hysteresis as u64)) {
+                log_collimator_reversal(s, beam_type, hysteresis);
+            }
+            if !transit.is_done() {
+                s.collimator_transit_remaining_ms = transit.remaining().as_millis() as u32;
+                return HousekeeperStage::Collimator(transit);
+            }
+            complete_collimator_move(s, transit.target);
+            if s.phase != TPhase::PatientTreatment && s.console_params != s.hardware_params {
+                HousekeeperStage::Params(Duration::ZERO)
+            } else {
+                HousekeeperStage::Idle
+            }
+        }
+        HousekeeperStage::Params(elapsed) => {
+            if elapsed + dt < s.worn(s.config.param_sync_time()) {
+                return HousekeeperStage::Params(elapsed + dt);
+            }
+            s.hardware_params = s.console_params;
+            HousekeeperStage::Idle
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::complete_data_entry;
+    use parking_lot::RwLock;
+    use std::sync::Arc;
+
+    const TICK: Duration = Duration::from_millis(10);
+
+    fn xray_console() -> Meos {
+        Meos {
+            beam_type: BeamType::XRay,
+            beam_energy: BeamEnergy::E25,
+            collimator: CollimatorPosition::OutOfPosition,
+        }
+    }
+
+    /// X-ray treatment switched on one tick into the turntable's move from electrons,
+    /// with the console entries matching where the move will land
+    fn treatment_mid_move() -> SharedTheracState {
+        let state = Arc::new(RwLock::new(TheracState {
+            phase: TPhase::SetupDone,
+            data_entry_complete: true,
+            console_meos: xray_console(),
+            hardware_meos: Meos { beam_type: BeamType::Electron, ..xray_console() },
+            ..TheracState::default()
+        }));
+        housekeeper_tick(&state, TICK);
+        {
+            let mut s = state.write();
+            assert_eq!(s.hardware_meos.collimator, CollimatorPosition::Transitioning);
+            s.console_meos.collimator = CollimatorPosition::InPosition;
+        }
+        start_treatment(state.clone());
+        assert_eq!(state.read().phase, TPhase::PatientTreatment);
+        state
+    }
+
+    #[test]
+    fn test_monitor_reading_mid_move_raises_malfunction_54() {
+        let state = treatment_mid_move();
+        // The monitor reads the turntable mid-move, the housekeeper lands it, and the
+        // check compares the console against the stale reading
+        monitor_tick(&state, TICK);
+        assert!(state.read().tick.check_pending());
+        housekeeper_tick(&state, COLLIMATOR_TRANSIT_TIME);
+        assert_eq!(state.read().hardware_meos, state.read().console_meos);
+        monitor_tick(&state, TICK);
+
+        let s = state.read();
+        assert_eq!(s.last_malfunction.as_ref().map(|m| m.kind), Some(MalfunctionKind::ParameterMismatch));
+        // Recorded with the reading the check compared, not the landed turntable
+        assert_eq!(s.incidents[0].hardware_meos.collimator, CollimatorPosition::Transitioning);
+        assert_eq!(s.phase, TPhase::PauseTreatment);
+        assert_eq!(s.dose_delivered, 0.0);
+    }
+
+    #[test]
+    fn test_housekeeper_landing_first_avoids_malfunction_54() {
+        let state = treatment_mid_move();
+        housekeeper_tick(&state, COLLIMATOR_TRANSIT_TIME);
+        monitor_tick(&state, TICK);
+        monitor_tick(&state, TICK);
+
+        let s = state.read();
+        assert_ne!(s.last_malfunction.as_ref().map(|m| m.kind), Some(MalfunctionKind::ParameterMismatch));
+        assert_eq!(s.near_misses, 0);
+        assert_eq!(s.overdose_count, 0);
+    }
+
+    #[test]
+    fn test_safe_mode_reads_and_fires_on_one_tick() {
+        let state = treatment_mid_move();
+        state.write().config.safe_mode = true;
+        monitor_tick(&state, TICK);
+
+        let s = state.read();
+        assert!(!s.tick.check_pending());
+        assert_eq!(s.last_malfunction.as_ref().map(|m| m.kind), Some(MalfunctionKind::ParameterMismatch));
+    }
+
+    #[test]
+    fn test_step_runs_a_session_from_reset_to_setup_done() {
+        let state = Arc::new(RwLock::new(TheracState { console_meos: xray_console(), ..TheracState::default() }));
+        for _ in 0..10 {
+            step(state.clone(), TICK);
+        }
+        assert_eq!(state.read().phase, TPhase::DataEntry);
+        complete_data_entry(state.clone());
+
+        for _ in 0..150 {
+            step(state.clone(), TICK);
+        }
+        let s = state.read();
+        assert_eq!(s.phase, TPhase::SetupDone);
+        assert_eq!(s.hardware_meos, Meos { collimator: CollimatorPosition::InPosition, ..xray_console() });
+        assert_eq!(s.collimator_transit_remaining_ms, 0);
+        assert_eq!(s.task_status.housekeeper, "idle");
+    }
+
+    #[test]
+    fn test_seeded_sessions_replay_identically() {
+        let run = |seed| {
+            let mut s = TheracState::with_seed(seed);
+            s.config.dose_jitter = 0.2;
+            s.config.sensor_glitch_probability = 0.02;
+            s.config.random_fault_probability = 0.01;
+            s.config.wear_per_treatment = 0.1;
+            s.phase = TPhase::SetupDone;
+            s.data_entry_complete = true;
+            s.console_meos = s.reference_meos;
+            s.hardware_meos = s.reference_meos;
+            s.hardware_params = s.console_params;
+            let state = Arc::new(RwLock::new(s));
+            start_treatment(state.clone());
+            for _ in 0..200 {
+                step(state.clone(), TICK);
+            }
+            let s = state.read();
+            let log: Vec<_> = s.log.iter().map(|e| e.message.clone()).collect();
+            (s.dose_delivered, s.wear_factor, s.phase, log)
+        };
+        assert_eq!(run(1189), run(1189));
+        assert_ne!(run(1189).0, run(1190).0);
+    }
+
+    #[test]
+    fn test_phase_durations_are_charged_on_the_logical_clock() {
+        let state = Arc::new(RwLock::new(TheracState::default()));
+        while state.read().phase == TPhase::Reset {
+            monitor_tick(&state, TICK);
+        }
+        for _ in 0..3 {
+            monitor_tick(&state, TICK);
+        }
+
+        let s = state.read();
+        // Ten ticks to leave Reset, however long they took on the wall clock
+        assert_eq!(s.phase_durations[&TPhase::Reset], Duration::from_millis(100));
+        assert_eq!(s.time_in_phase(TPhase::DataEntry), 3 * TICK);
+        assert_eq!(s.time_in_phase(TPhase::SetupTest), Duration::ZERO);
+        let summary = s.summary();
+        assert_eq!(summary.phase_seconds.keys().copied().collect::<Vec<_>>(), [TPhase::Reset, TPhase::DataEntry]);
+    }
+}
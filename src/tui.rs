@@ -10,7 +10,7 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, BorderType, Paragraph, List, ListItem, Gauge},
+    widgets::{Block, Borders, BorderType, Paragraph, List, ListItem, Gauge, Wrap},
     Frame, Terminal,
 };
 use crossterm::{
@@ -19,30 +19,51 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use std::io;
-use std::time::Duration;
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum InputField {
-    Mode,
-    Energy,
-    Gantry,
-    FieldSize,
-    Dose,
-    Command,
-}
+use crate::replay::{ReplayBundle, ReplayPlayer};
+use crate::attract::AttractMode;
+use crate::cast::CastFile;
+use std::time::{Duration, Instant};
+
+/// Smallest terminal the analytical layout fits in: the compact layout's fixed-height
+/// panels, with room for one log entry
+const MIN_WIDTH: u16 = 80;
+const MIN_HEIGHT: u16 = 24;
+
+/// Height from which every panel is shown; below it the title, treatment plan and task
+/// activity are collapsed and the data entry fields are paired up
+const FULL_LAYOUT_HEIGHT: u16 = 43;
+
+/// How long a rejected entry stays flagged under its field
+const ENTRY_ERROR_TIME: Duration = Duration::from_secs(3);
 
 pub struct TuiApp {
     state: SharedTheracState,
     should_quit: bool,
     help_visible: bool,
+    sweep_visible: bool,
+    /// Differences from the prescription found by the last `verify`, shown until dismissed
+    readback: Option<Vec<PrescriptionDifference>>,
+    /// Prescription library open by the `library` command, with the highlighted entry
+    library_selection: Option<usize>,
+    /// Outcome of the treatment that just ended, shown full-screen until dismissed
+    consequences: Option<TreatmentOutcome>,
+    /// Recorded session being played back instead of keyboard data entry
+    replay: Option<ReplayPlayer>,
+    /// Hands-free demo loop driving the machine instead of the keyboard
+    attract: Option<AttractMode>,
+    /// asciinema recording of every frame drawn
+    recorder: Option<CastFile>,
+    /// Channel to the command processor; entries are applied directly without one
+    commands: Option<CommandSender>,
     current_field: InputField,
     mode_input: String,
     energy_input: String,
     gantry_input: String,
-    field_x_input: String,
-    field_y_input: String,
+    field_input: String,
     dose_input: String,
     command_input: String,
+    /// Why the last entry was rejected, and when; shown under the field for `ENTRY_ERROR_TIME`
+    entry_error: Option<(String, Instant)>,
 }
 
 impl TuiApp {
@@ -51,14 +72,55 @@ impl TuiApp {
             state,
             should_quit: false,
             help_visible: false,
+            sweep_visible: false,
+            readback: None,
+            library_selection: None,
+            consequences: None,
+            replay: None,
+            attract: None,
+            recorder: None,
+            commands: None,
             current_field: InputField::Mode,
             mode_input: String::new(),
             energy_input: String::new(),
             gantry_input: String::new(),
-            field_x_input: String::new(),
-            field_y_input: String::new(),
+            field_input: String::new(),
             dose_input: String::new(),
             command_input: String::new(),
+            entry_error: None,
+        }
+    }
+
+    /// Play back a recorded session; the keyboard then controls the player
+    pub fn with_replay(mut self, bundle: ReplayBundle) -> Self {
+        self.replay = Some(ReplayPlayer::new(bundle));
+        self
+    }
+
+    /// Run the attract-mode demo loop; the keyboard only quits
+    pub fn with_attract(mut self, attract: AttractMode) -> Self {
+        self.attract = Some(attract);
+        self
+    }
+
+    /// Record the session to an asciinema cast as it is drawn
+    pub fn with_recording(mut self, recorder: CastFile) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Send commands and entries through `spawn_command_processor`'s channel
+    pub fn with_command_sender(mut self, commands: CommandSender) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Apply a command or entry, through the channel if there is one
+    /// Through the channel it is applied later, so true only means it was queued.
+    fn send(&self, message: ControlMessage) -> bool {
+        match &self.commands {
+            Some(commands) => commands.send(message),
+            None => apply_message(self.state.clone(), message),
         }
     }
 
@@ -92,8 +154,28 @@ impl TuiApp {
     }
 
     async fn tui_loop(&mut self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> anyhow::Result<()> {
+        let mut last_tick = Instant::now();
         loop {
-            terminal.draw(|f| self.ui(f))?;
+            let elapsed = last_tick.elapsed();
+            last_tick = Instant::now();
+            if let Some(player) = &mut self.replay {
+                player.advance(&self.state, elapsed);
+            }
+            let new_scene = match &mut self.attract {
+                Some(attract) => attract.tick(&self.state, elapsed),
+                None => false,
+            };
+            if new_scene {
+                // Auto-dismiss whatever the last scene left up, the consequences screen included
+                self.consequences = None;
+                self.clear_inputs();
+            }
+
+            self.take_outcome();
+            let frame = terminal.draw(|f| self.ui(f))?;
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(frame.buffer)?;
+            }
 
             if self.should_quit {
                 break;
@@ -101,8 +183,11 @@ impl TuiApp {
 
             // Poll for events with timeout
             if event::poll(Duration::from_millis(100))? {
-                if let Event::Key(key) = event::read()? {
-                    self.handle_input(key.code, key.modifiers);
+                match event::read()? {
+                    Event::Key(key) => self.handle_input(key.code, key.modifiers),
+                    // The next draw picks up the new size and re-checks MIN_WIDTH/MIN_HEIGHT
+                    Event::Resize(_, _) => terminal.autoresize()?,
+                    _ => {}
                 }
             }
         }
@@ -110,12 +195,102 @@ impl TuiApp {
         Ok(())
     }
 
+    /// Pick up the outcome of a treatment that just ended, and the differences from a
+    /// read-back, once each
+    fn take_outcome(&mut self) {
+        if self.consequences.is_none() && self.state.read().last_outcome.is_some() {
+            self.consequences = self.state.write().last_outcome.take();
+        }
+        if self.state.read().last_readback.is_some() {
+            self.readback = self.state.write().last_readback.take();
+        }
+    }
+
+    fn clear_inputs(&mut self) {
+        self.mode_input.clear();
+        self.energy_input.clear();
+        self.gantry_input.clear();
+        self.field_input.clear();
+        self.dose_input.clear();
+        self.current_field = InputField::Mode;
+    }
+
     fn handle_input(&mut self, key: KeyCode, modifiers: KeyModifiers) {
+        // Emergency-off works from any screen, before anything else sees the key
+        if key == KeyCode::F(12) {
+            emergency_off(self.state.clone());
+            return;
+        }
+
+        if self.attract.is_some() {
+            // Kiosk: the demo runs itself, the keyboard can only quit
+            if key == KeyCode::Char('q') || (key == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL)) {
+                self.should_quit = true;
+            }
+            return;
+        }
+
+        if self.consequences.is_some() && !modifiers.contains(KeyModifiers::CONTROL) {
+            // Any key dismisses the consequences screen back to a fresh reset
+            self.consequences = None;
+            self.send(Command::Reset.into());
+            self.clear_inputs();
+            return;
+        }
+
         if self.help_visible {
             self.help_visible = false;
             return;
         }
 
+        if let Some(player) = &mut self.replay {
+            match key {
+                KeyCode::Char(' ') => player.toggle_pause(),
+                KeyCode::Right | KeyCode::Char('.') => {
+                    player.step(&self.state);
+                }
+                KeyCode::Char(c @ '1'..='3') => {
+                    player.set_speed(ReplayPlayer::SPEEDS[c as usize - '1' as usize]);
+                }
+                KeyCode::Esc => {
+                    self.replay = None;
+                    self.send(ControlMessage::Log(LogLevel::Info, "[REPLAY] Playback stopped".to_string()));
+                }
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.should_quit = true;
+                }
+                _ => {}
+            }
+            return;
+        }
+
+        if self.sweep_visible {
+            self.sweep_visible = false;
+            return;
+        }
+
+        if let Some(selected) = self.library_selection {
+            // Up/Down pick, ENTER loads, any other key closes without a change
+            match key {
+                KeyCode::Up => self.library_selection = Some(selected.saturating_sub(1)),
+                KeyCode::Down => self.library_selection = Some((selected + 1).min(PRESCRIPTIONS.len() - 1)),
+                KeyCode::Enter => {
+                    load_prescription(self.state.clone(), PRESCRIPTIONS[selected].name);
+                    self.library_selection = None;
+                }
+                _ => self.library_selection = None,
+            }
+            return;
+        }
+
+        if let Some(differences) = self.readback.take() {
+            // 'y' confirms the differences are intended; any other key just closes
+            if !differences.is_empty() && matches!(key, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                self.send(ControlMessage::AcknowledgeDifferences);
+            }
+            return;
+        }
+
         // Global commands
         match key {
             KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
@@ -126,6 +301,24 @@ impl TuiApp {
                 self.help_visible = true;
                 return;
             }
+            // Pre-treatment checklist
+            KeyCode::F(n @ 2..=5) => {
+                toggle_checklist_item(self.state.clone(), ChecklistItem::ALL[(n - 2) as usize]);
+                return;
+            }
+            KeyCode::F(6) => {
+                check_all_checklist_items(self.state.clone());
+                return;
+            }
+            // Free navigation between fields; nothing typed so far is committed
+            KeyCode::Down | KeyCode::Tab => {
+                self.current_field = self.current_field.next();
+                return;
+            }
+            KeyCode::Up | KeyCode::BackTab => {
+                self.current_field = self.current_field.prev();
+                return;
+            }
             _ => {}
         }
 
@@ -144,35 +337,26 @@ impl TuiApp {
         match key {
             KeyCode::Char('x') | KeyCode::Char('X') => {
                 self.mode_input = "X".to_string();
-                let mut s = self.state.write();
-                s.console_meos.beam_type = BeamType::XRay;
-                // Auto-set energy to 25 MeV for X-ray mode (as per real Therac-25)
-                s.console_meos.beam_energy = BeamEnergy::E25;
-                self.energy_input = "25".to_string();
-                s.add_log("Mode set to X-Ray, energy auto-set to 25 MeV".to_string());
-                // Move to gantry field (skip energy since it's auto-set)
-                self.current_field = InputField::Gantry;
+                let config = self.state.read().config.clone();
+                if config.auto_max_energy_on_xray {
+                    // Energy is auto-set to 25 MeV for X-ray mode (as per real Therac-25)
+                    self.energy_input = "25".to_string();
+                }
+                self.send(ControlMessage::Mode(BeamType::XRay));
+                // Skips energy when it was auto-set
+                self.current_field = field_after_mode(&config, BeamType::XRay);
             }
             KeyCode::Char('e') | KeyCode::Char('E') => {
                 self.mode_input = "E".to_string();
-                let mut s = self.state.write();
-                s.console_meos.beam_type = BeamType::Electron;
-                s.add_log("Mode set to Electron".to_string());
+                self.send(ControlMessage::Mode(BeamType::Electron));
                 // Move to energy field
                 self.current_field = InputField::Energy;
             }
             KeyCode::Enter => {
                 // Copy from reference
-                let s = self.state.read();
-                match s.reference_meos.beam_type {
-                    BeamType::XRay => {
-                        drop(s);
-                        self.handle_mode_input(KeyCode::Char('x'));
-                    }
-                    BeamType::Electron => {
-                        drop(s);
-                        self.handle_mode_input(KeyCode::Char('e'));
-                    }
+                match copy_from_prescription(&self.state, |s| s.reference_meos.beam_type) {
+                    Some(BeamType::XRay) => self.handle_mode_input(KeyCode::Char('x')),
+                    Some(BeamType::Electron) => self.handle_mode_input(KeyCode::Char('e')),
                     _ => {}
                 }
             }
@@ -185,42 +369,37 @@ impl TuiApp {
 
     fn handle_energy_input(&mut self, key: KeyCode) {
         match key {
-            KeyCode::Char(c) if c.is_ascii_digit() => {
+            KeyCode::Char(c) if c.is_ascii_digit() || c == '.' => {
+                self.entry_error = None;
                 self.energy_input.push(c);
             }
             KeyCode::Backspace => {
+                self.entry_error = None;
                 self.energy_input.pop();
             }
             KeyCode::Enter => {
                 if self.energy_input.is_empty() {
                     // Copy from reference
-                    let s = self.state.read();
-                    let ref_energy = match s.reference_meos.beam_energy {
-                        BeamEnergy::E5 => 5,
-                        BeamEnergy::E10 => 10,
-                        BeamEnergy::E15 => 15,
-                        BeamEnergy::E20 => 20,
-                        BeamEnergy::E25 => 25,
+                    let Some(ref_energy) = copy_from_prescription(&self.state, |s| {
+                        s.reference_meos.beam_energy.mev()
+                    }) else {
+                        return;
                     };
                     self.energy_input = ref_energy.to_string();
                 }
 
-                // Parse and set energy
-                if let Ok(energy_val) = self.energy_input.parse::<u8>() {
-                    let mut s = self.state.write();
-                    s.console_meos.beam_energy = match energy_val {
-                        5 => BeamEnergy::E5,
-                        10 => BeamEnergy::E10,
-                        15 => BeamEnergy::E15,
-                        20 => BeamEnergy::E20,
-                        25 => BeamEnergy::E25,
-                        _ => {
-                            s.add_log(format!("Invalid energy: {}. Use 5, 10, 15, 20, or 25", energy_val));
-                            return;
-                        }
-                    };
-                    s.add_log(format!("Energy set to {} MeV", energy_val));
-                }
+                // Parse and set energy; a rejected entry is cleared for another try
+                let energy = match parse_energy(&self.energy_input) {
+                    Ok(energy) => energy,
+                    Err(error) => {
+                        self.send(ControlMessage::Log(LogLevel::Warn, format!("[CONSOLE] {}", error)));
+                        self.entry_error = Some((error, Instant::now()));
+                        self.energy_input.clear();
+                        return;
+                    }
+                };
+                self.entry_error = None;
+                self.send(ControlMessage::Energy(energy));
                 // Move to gantry field
                 self.current_field = InputField::Gantry;
             }
@@ -239,15 +418,15 @@ impl TuiApp {
             KeyCode::Enter => {
                 if self.gantry_input.is_empty() {
                     // Copy from reference
-                    let s = self.state.read();
-                    self.gantry_input = s.reference_params.gantry_angle.to_string();
+                    let Some(angle) = copy_from_prescription(&self.state, |s| s.reference_params.gantry_angle) else {
+                        return;
+                    };
+                    self.gantry_input = angle.to_string();
                 }
 
                 // Parse and set gantry angle
                 if let Ok(angle) = self.gantry_input.parse::<u16>() {
-                    let mut s = self.state.write();
-                    s.console_params.gantry_angle = angle.min(359);
-                    s.editing_taking_place = true;
+                    self.send(ControlMessage::Gantry(angle.min(359)));
                 }
                 // Move to field size
                 self.current_field = InputField::FieldSize;
@@ -259,54 +438,30 @@ impl TuiApp {
     fn handle_field_size_input(&mut self, key: KeyCode) {
         match key {
             KeyCode::Char(c) if c.is_ascii_digit() || c == '.' => {
-                // Only allow digits and decimal point
-                if self.field_y_input.is_empty() && !self.field_x_input.contains('x') {
-                    // Still entering X dimension
-                    self.field_x_input.push(c);
-                } else {
-                    // Entering Y dimension
-                    self.field_y_input.push(c);
-                }
+                self.field_input.push(c);
             }
-            KeyCode::Char('x') | KeyCode::Char('X') => {
-                // 'x' acts as separator between X and Y dimensions
-                if !self.field_x_input.is_empty() && self.field_y_input.is_empty() {
-                    // Transition from X to Y dimension
-                    // Don't store the 'x', just use it as a signal
-                    // The display will show it between the values
-                }
+            // 'x' separates X and Y; without it the entry is an equivalent square
+            KeyCode::Char('x') | KeyCode::Char('X')
+                if !self.field_input.is_empty() && !self.field_input.contains('x') =>
+            {
+                self.field_input.push('x');
             }
             KeyCode::Backspace => {
-                if !self.field_y_input.is_empty() {
-                    self.field_y_input.pop();
-                } else if !self.field_x_input.is_empty() {
-                    self.field_x_input.pop();
-                }
+                self.field_input.pop();
             }
             KeyCode::Enter => {
-                if self.field_x_input.is_empty() {
+                if self.field_input.is_empty() {
                     // Copy from reference
-                    let s = self.state.read();
-                    self.field_x_input = s.reference_params.field_size_x.to_string();
-                    self.field_y_input = s.reference_params.field_size_y.to_string();
-                }
-
-                // Parse and set field sizes
-                // If only X dimension is entered, use it for both X and Y (square field)
-                if let Ok(size_x) = self.field_x_input.parse::<f32>() {
-                    let size_y = if self.field_y_input.is_empty() {
-                        size_x // Square field if Y not specified
-                    } else {
-                        self.field_y_input.parse::<f32>().unwrap_or(size_x)
+                    let Some(field) = copy_from_prescription(&self.state, |s| {
+                        format!("{}x{}", s.reference_params.field_size_x, s.reference_params.field_size_y)
+                    }) else {
+                        return;
                     };
+                    self.field_input = field;
+                }
 
-                    let mut s = self.state.write();
-                    s.console_params.field_size_x = size_x.max(1.0).min(40.0);
-                    s.console_params.field_size_y = size_y.max(1.0).min(40.0);
-                    s.editing_taking_place = true;
-                    let field_x = s.console_params.field_size_x;
-                    let field_y = s.console_params.field_size_y;
-                    s.add_log(format!("Field size set to {}×{} cm", field_x, field_y));
+                if let Some((size_x, size_y)) = crate::input::parse_field_size(&self.field_input) {
+                    self.send(ControlMessage::FieldSize(size_x.clamp(1.0, 40.0), size_y.clamp(1.0, 40.0)));
                 }
                 // Move to dose field
                 self.current_field = InputField::Dose;
@@ -326,15 +481,15 @@ impl TuiApp {
             KeyCode::Enter => {
                 if self.dose_input.is_empty() {
                     // Copy from reference
-                    let s = self.state.read();
-                    self.dose_input = s.reference_dose_target.to_string();
+                    let Some(dose) = copy_from_prescription(&self.state, |s| s.reference_dose_target) else {
+                        return;
+                    };
+                    self.dose_input = dose.to_string();
                 }
 
                 // Parse and set dose
                 if let Ok(dose_val) = self.dose_input.parse::<f64>() {
-                    let mut s = self.state.write();
-                    s.dose_target = dose_val;
-                    s.add_log(format!("Dose target set to {} cGy", dose_val));
+                    self.send(ControlMessage::Dose(dose_val));
                 }
                 // Move to command field
                 self.current_field = InputField::Command;
@@ -367,89 +522,185 @@ impl TuiApp {
     fn execute_command(&mut self) {
         let cmd = self.command_input.to_lowercase();
 
-        match cmd.as_str() {
-            "t" | "treat" => {
-                // Complete data entry and start treatment
-                complete_data_entry(self.state.clone());
-                start_treatment(self.state.clone());
-
+        if let Ok(command) = cmd.parse::<Command>() {
+            self.send(command.into());
+            match command {
                 // Clear inputs and return to mode field
-                self.mode_input.clear();
-                self.energy_input.clear();
-                self.gantry_input.clear();
-                self.field_x_input.clear();
-                self.field_y_input.clear();
-                self.dose_input.clear();
-                self.current_field = InputField::Mode;
+                Command::Treat | Command::Reset | Command::Proceed => self.clear_inputs(),
+                // Back to fix the entry; through the channel the acknowledgment has not been
+                // applied yet, so the cursor moves whether or not there was anything to clear
+                Command::Acknowledge => self.current_field = InputField::Mode,
+                Command::Quit => self.should_quit = true,
+                _ => {}
             }
-            "r" | "reset" => {
-                // Reset system and generate new reference
-                let mut s = self.state.write();
-                s.reset();
+            return;
+        }
 
-                // Clear inputs
-                self.mode_input.clear();
-                self.energy_input.clear();
-                self.gantry_input.clear();
-                self.field_x_input.clear();
-                self.field_y_input.clear();
-                self.dose_input.clear();
-                self.current_field = InputField::Mode;
+        // Everything that changes the machine is queued behind the entries sent before it
+        let usage = |text: &str| ControlMessage::Log(LogLevel::Info, format!("[OPERATOR] Usage: {}", text));
+        let message = match cmd.as_str() {
+            // Toggle whether the checklist gates treatment
+            "checklist" => Setting::ToggleChecklist.into(),
+            // Toggle a 3 second last-chance hold between beam-on and the first pulse
+            "hold" => Setting::ToggleBeamHold.into(),
+            // Toggle machine wear: each treatment makes the mechanics a little slower
+            "wear" => Setting::ToggleWear.into(),
+            // Toggle LOCK WAIT diagnostics for contended write-lock acquisitions
+            "lockwait" => Setting::ToggleLockWait.into(),
+            // Toggle the race-free pulse: check and fire under one lock acquisition
+            "safemode" => Setting::ToggleSafeMode.into(),
+            // Toggle a second operator who nudges console entries at random
+            "phantom" => Setting::TogglePhantom.into(),
+            // Toggle capping displayed overdoses at the documented incident magnitude
+            "cap" => Setting::ToggleDoseCap.into(),
+            // Toggle the independent watchdog that catches a stuck turntable
+            "watchdog" => Setting::ToggleWatchdog.into(),
+            // Toggle between instant magnets and the real ~8 s settle time
+            "magnets" => Setting::ToggleMagnets.into(),
+            // Fault injection: the turntable sticks mid-move until freed
+            "jam" => ControlMessage::ToggleJam,
+            // Toggle random hardware faults, leaving only the race and the interlocks
+            "faults" => Setting::ToggleFaults.into(),
+            // Freeze (or unfreeze) the background tasks to inspect the state
+            "freeze" => ControlMessage::ToggleFreeze,
+            // Teaching: open the race window and turn the beam on; the next pulse is unsafe
+            "race" => ControlMessage::ForceRace,
+            // Operator intervention: free a turntable stuck mid-rotation
+            "free" => ControlMessage::FreeCollimator,
+            "service" => ControlMessage::Service,
+            "recalibrate" => ControlMessage::Recalibrate,
+            // Treatments allowed between recalibrations; 0 never asks
+            cmd if cmd.starts_with("recal ") => match cmd["recal ".len()..].trim().parse::<u32>() {
+                Ok(interval) => Setting::RecalibrationInterval(interval).into(),
+                Err(_) => usage("recal <treatments> (0 = never)"),
+            },
+            // Hardware dose interlock the original lacked; 0 leaves it out
+            cmd if cmd.starts_with("interlock ") => match cmd["interlock ".len()..].trim().parse::<f64>() {
+                Ok(cgy) if cgy >= 0.0 => Setting::DoseInterlock(cgy).into(),
+                _ => usage("interlock <cGy> (0 = no interlock)"),
+            },
+            // Pulses per second at the reference dose rate; 0 pulses every monitor pass
+            cmd if cmd.starts_with("prf ") => match cmd["prf ".len()..].trim().parse::<f64>() {
+                Ok(hz) if hz >= 0.0 => Setting::PulseRepetitionHz(hz).into(),
+                _ => usage("prf <Hz> (0 = every monitor pass)"),
+            },
+            // Toggle the Enter-to-copy shortcut (expert workflow) vs typing every value
+            "autocopy" => Setting::ToggleAutoCopy.into(),
+            // Read the console entries back against the prescription; the differences
+            // show once the read-back has been applied (`take_outcome`)
+            "verify" => ControlMessage::Verify,
+            // Toggle requiring a verified read-back before data entry can complete
+            "readback" => Setting::ToggleReadback.into(),
+            "logout" => ControlMessage::Logout,
+            // Operator IDs keep their case; only the command word is lowercased
+            cmd if cmd.starts_with("login ") => {
+                ControlMessage::Login(self.command_input["login ".len()..].trim().to_string())
             }
-            "p" | "proceed" => {
-                // Complete data entry (for setup)
-                complete_data_entry(self.state.clone());
-
-                // Clear inputs and return to mode field
-                self.mode_input.clear();
-                self.energy_input.clear();
-                self.gantry_input.clear();
-                self.field_x_input.clear();
-                self.field_y_input.clear();
-                self.dose_input.clear();
-                self.current_field = InputField::Mode;
+            // Widen or narrow the race window live, e.g. "timing 2000 200"
+            cmd if cmd.starts_with("timing ") => {
+                let values: Vec<Option<u64>> = cmd["timing ".len()..]
+                    .split_whitespace()
+                    .map(|value| value.parse().ok())
+                    .collect();
+                match values[..] {
+                    [Some(move_ms)] | [Some(move_ms), None] => Setting::Timing { move_ms, param_sync_ms: None }.into(),
+                    [Some(move_ms), Some(param_ms)] => Setting::Timing { move_ms, param_sync_ms: Some(param_ms) }.into(),
+                    _ => usage("timing <move ms> [<param sync ms>]"),
+                }
             }
-            "s" | "stop" => {
-                // Stop treatment
-                stop_treatment(self.state.clone());
+            // Reorder the housekeeper's sync, e.g. "sync energy collimator"
+            cmd if cmd.starts_with("sync ") => match SyncOrder::parse(&cmd["sync ".len()..]) {
+                Some(order) => Setting::SyncOrder(order).into(),
+                None => ControlMessage::Log(
+                    LogLevel::Info,
+                    "[OPERATOR] Sync order takes: collimator, beam, energy, params".to_string(),
+                ),
+            },
+            // Paths keep their case; only the command word is lowercased
+            cmd if cmd.starts_with("csv ") => {
+                ControlMessage::WriteLogCsv(self.command_input["csv ".len()..].trim().to_string())
             }
-            "c" | "continue" => {
-                // Resume treatment
-                resume_treatment(self.state.clone());
+            // Log this session's statistics for every operator who has logged in
+            "operators" => ControlMessage::LogOperatorStats,
+            // Log the outcome of the session so far
+            "summary" => ControlMessage::LogSummary,
+            "sweep" => {
+                // Show the dose model across all configurations (no beam fired)
+                self.sweep_visible = true;
+                return;
             }
-            "q" | "quit" => {
-                self.should_quit = true;
+            "library" => {
+                // Pick a named prescription in place of the random one
+                self.library_selection = Some(0);
+                return;
             }
             "" => {
                 // Empty command, just return to mode field
                 self.current_field = InputField::Mode;
+                return;
             }
-            _ => {
-                let mut s = self.state.write();
-                s.add_log(format!("Unknown command: '{}'. Use t/r/p/s/c/q", cmd));
-            }
-        }
+            _ => ControlMessage::Log(
+                LogLevel::Info,
+                format!("[OPERATOR] Unknown command: '{}'. Use t/r/p/n/s/c/q", cmd),
+            ),
+        };
+        self.send(message);
     }
 
     fn ui(&self, f: &mut Frame) {
+        if f.area().width < MIN_WIDTH || f.area().height < MIN_HEIGHT {
+            render_too_small(f, MIN_WIDTH, MIN_HEIGHT);
+            return;
+        }
+
+        if let Some(outcome) = &self.consequences {
+            self.render_consequences(f, outcome);
+            return;
+        }
+
         if self.help_visible {
             self.render_help(f);
             return;
         }
 
-        let state = self.state.read();
+        if self.sweep_visible {
+            self.render_sweep(f);
+            return;
+        }
+
+        if let Some(differences) = &self.readback {
+            self.render_readback(f, differences);
+            return;
+        }
+
+        if let Some(selected) = self.library_selection {
+            self.render_library(f, selected);
+            return;
+        }
+
+        // Copy out what the frame shows and release the lock before drawing
+        let snapshot = self.state.read().snapshot();
+        self.render_main(f, &snapshot);
+    }
+
+    fn render_main(&self, f: &mut Frame, state: &TheracSnapshot) {
+        if f.area().height < FULL_LAYOUT_HEIGHT {
+            self.render_main_compact(f, state);
+            return;
+        }
 
         // Create layout
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Length(3),  // Title
-                Constraint::Length(8),  // Prescription (increased for more params)
-                Constraint::Length(16), // Data Entry Form (increased for all fields)
-                Constraint::Length(8),  // System Status (increased for more info)
-                Constraint::Length(8),  // Hardware State (increased for treatment params)
-                Constraint::Min(5),     // Log
-                Constraint::Length(2),  // Help hint
+                Constraint::Length(3), // Title
+                Constraint::Length(5), // Prescription
+                Constraint::Length(9), // Data Entry Form
+                Constraint::Length(8), // System Status
+                Constraint::Length(6), // Hardware State
+                Constraint::Length(6), // Task Activity / Checklist
+                Constraint::Min(5),    // Log
+                Constraint::Length(1), // Help hint
             ])
             .split(f.area());
 
@@ -457,22 +708,51 @@ impl TuiApp {
         self.render_title(f, chunks[0]);
 
         // Prescription (reference parameters)
-        self.render_prescription(f, chunks[1], &state);
+        self.render_prescription(f, chunks[1], state);
 
         // Data Entry Form
-        self.render_data_entry(f, chunks[2], &state);
+        self.render_data_entry(f, chunks[2], state, false);
 
         // System Status
-        self.render_status(f, chunks[3], &state);
+        self.render_status(f, chunks[3], state);
 
         // Hardware State
-        self.render_hardware(f, chunks[4], &state);
+        self.render_hardware(f, chunks[4], state);
+
+        // Task Activity and pre-treatment checklist side by side
+        let activity_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(chunks[5]);
+        self.render_task_status(f, activity_chunks[0], state);
+        self.render_checklist(f, activity_chunks[1], state);
 
         // Log
-        self.render_log(f, chunks[5], &state);
+        self.render_log(f, chunks[6], state);
 
         // Help hint
-        self.render_help_hint(f, chunks[6]);
+        self.render_help_hint(f, chunks[7], state);
+    }
+
+    /// The main screen on a short terminal: what the race needs (entries, status,
+    /// hardware and the log) with the plan, title and task activity collapsed
+    fn render_main_compact(&self, f: &mut Frame, state: &TheracSnapshot) {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(6), // Data Entry Form, two fields a line
+                Constraint::Length(8), // System Status
+                Constraint::Length(6), // Hardware State
+                Constraint::Min(3),    // Log
+                Constraint::Length(1), // Help hint
+            ])
+            .split(f.area());
+
+        self.render_data_entry(f, chunks[0], state, true);
+        self.render_status(f, chunks[1], state);
+        self.render_hardware(f, chunks[2], state);
+        self.render_log(f, chunks[3], state);
+        self.render_help_hint(f, chunks[4], state);
     }
 
     fn render_title(&self, f: &mut Frame, area: Rect) {
@@ -483,7 +763,7 @@ impl TuiApp {
         f.render_widget(title, area);
     }
 
-    fn render_prescription(&self, f: &mut Frame, area: Rect, state: &TheracState) {
+    fn render_prescription(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
         let text = vec![
             Line::from(vec![
                 Span::styled("PRESCRIPTION: ", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
@@ -493,10 +773,11 @@ impl TuiApp {
             ]),
             Line::from(vec![
                 Span::styled("  Parameters: ", Style::default().fg(Color::Yellow)),
-                Span::raw(format!("Gantry {}° | Field {}×{} cm | {} cGy @ {:.0} cGy/min",
+                Span::raw(format!("Gantry {}° | Field {}×{} cm (eq. sq. {:.1}) | {} cGy @ {:.0} cGy/min",
                     state.reference_params.gantry_angle,
                     state.reference_params.field_size_x,
                     state.reference_params.field_size_y,
+                    state.reference_params.equivalent_square(),
                     state.reference_dose_target,
                     state.reference_params.dose_rate)),
             ]),
@@ -514,15 +795,18 @@ impl TuiApp {
         f.render_widget(block, area);
     }
 
-    fn render_data_entry(&self, f: &mut Frame, area: Rect, _state: &TheracState) {
+    fn render_data_entry(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot, compact: bool) {
         let mode_style = if self.current_field == InputField::Mode {
             Style::default().fg(Color::Black).bg(Color::Green)
         } else {
             Style::default().fg(Color::White)
         };
 
+        let energy_flagged = energy_mode_warning(&state.console_meos).is_some();
         let energy_style = if self.current_field == InputField::Energy {
-            Style::default().fg(Color::Black).bg(Color::Green)
+            Style::default().fg(Color::Black).bg(if energy_flagged { Color::Red } else { Color::Green })
+        } else if energy_flagged {
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
         } else {
             Style::default().fg(Color::White)
         };
@@ -551,75 +835,63 @@ impl TuiApp {
             Style::default().fg(Color::White)
         };
 
-        let field_display = if self.field_x_input.is_empty() && self.field_y_input.is_empty() {
-            String::new()
-        } else if self.field_y_input.is_empty() {
-            format!("{}×", self.field_x_input)
-        } else {
-            format!("{}×{}", self.field_x_input, self.field_y_input)
-        };
+        let field_display = self.field_input.replace('x', "×");
 
-        let text = vec![
-            Line::from(vec![
-                Span::raw("Mode (X=X-ray, E=Electron): "),
-                Span::styled(&self.mode_input, mode_style),
-                if self.current_field == InputField::Mode {
-                    Span::styled("█", mode_style)
-                } else {
-                    Span::raw("")
-                },
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("Energy (5/10/15/20/25 MeV): "),
-                Span::styled(&self.energy_input, energy_style),
-                if self.current_field == InputField::Energy {
-                    Span::styled("█", energy_style)
-                } else {
-                    Span::raw("")
-                },
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("Gantry Angle (0-360 deg):   "),
-                Span::styled(&self.gantry_input, gantry_style),
-                if self.current_field == InputField::Gantry {
-                    Span::styled("█", gantry_style)
+        // One field: its label, what has been typed and a cursor when it has the focus
+        let entry = |label: &'static str, input: &str, style: Style, field: InputField| {
+            vec![
+                Span::raw(label),
+                Span::styled(input.to_string(), style),
+                if self.current_field == field {
+                    Span::styled("█", style)
                 } else {
                     Span::raw("")
                 },
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("Field Size (X×Y cm):        "),
-                Span::styled(&field_display, field_style),
-                if self.current_field == InputField::FieldSize {
-                    Span::styled("█", field_style)
-                } else {
-                    Span::raw("")
-                },
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("Dose (cGy):                 "),
-                Span::styled(&self.dose_input, dose_style),
-                if self.current_field == InputField::Dose {
-                    Span::styled("█", dose_style)
-                } else {
-                    Span::raw("")
-                },
-            ]),
-            Line::from(""),
-            Line::from(vec![
-                Span::raw("Command:                    "),
-                Span::styled(&self.command_input, command_style),
-                if self.current_field == InputField::Command {
-                    Span::styled("█", command_style)
-                } else {
-                    Span::raw("")
-                },
-            ]),
-        ];
+            ]
+        };
+        let error_line = match &self.entry_error {
+            Some((error, at)) if at.elapsed() < ENTRY_ERROR_TIME => Line::from(Span::styled(
+                format!("  ✗ {}", error),
+                Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD),
+            )),
+            _ => Line::from(""),
+        };
+
+        let text = if compact {
+            // Two fields a line, each padded to half the panel
+            let half = area.width.saturating_sub(2) as usize / 2;
+            let pair = |mut left: Vec<Span<'static>>, right: Vec<Span<'static>>| {
+                let used: usize = left.iter().map(|span| span.width()).sum();
+                left.push(Span::raw(" ".repeat(half.saturating_sub(used))));
+                left.extend(right);
+                Line::from(left)
+            };
+            vec![
+                pair(
+                    entry("Mode (X/E): ", &self.mode_input, mode_style, InputField::Mode),
+                    entry("Energy (MeV): ", &self.energy_input, energy_style, InputField::Energy),
+                ),
+                pair(
+                    entry("Gantry (deg): ", &self.gantry_input, gantry_style, InputField::Gantry),
+                    entry("Field (cm): ", &field_display, field_style, InputField::FieldSize),
+                ),
+                pair(
+                    entry("Dose (cGy): ", &self.dose_input, dose_style, InputField::Dose),
+                    entry("Command: ", &self.command_input, command_style, InputField::Command),
+                ),
+                error_line,
+            ]
+        } else {
+            vec![
+                Line::from(entry("Mode (X=X-ray, E=Electron): ", &self.mode_input, mode_style, InputField::Mode)),
+                Line::from(entry("Energy (5/10/15/20/25 MeV): ", &self.energy_input, energy_style, InputField::Energy)),
+                error_line,
+                Line::from(entry("Gantry Angle (0-360 deg):   ", &self.gantry_input, gantry_style, InputField::Gantry)),
+                Line::from(entry("Field Size (X×Y or sq. cm): ", &field_display, field_style, InputField::FieldSize)),
+                Line::from(entry("Dose (cGy):                 ", &self.dose_input, dose_style, InputField::Dose)),
+                Line::from(entry("Command:                    ", &self.command_input, command_style, InputField::Command)),
+            ]
+        };
 
         let block = Paragraph::new(text)
             .block(Block::default()
@@ -629,14 +901,16 @@ impl TuiApp {
         f.render_widget(block, area);
     }
 
-    fn render_status(&self, f: &mut Frame, area: Rect, state: &TheracState) {
+    fn render_status(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
         let phase_color = match state.phase {
             TPhase::Reset => Color::Gray,
             TPhase::DataEntry => Color::Yellow,
             TPhase::SetupTest | TPhase::SetupDone => Color::Blue,
+            TPhase::BeamReady => Color::LightYellow,
             TPhase::PatientTreatment => Color::Green,
             TPhase::PauseTreatment => Color::Red,
             TPhase::TerminateTreatment => Color::Magenta,
+            TPhase::EmergencyStop => Color::LightRed,
             TPhase::DateTimeIdChanges => Color::Cyan,
         };
 
@@ -646,17 +920,13 @@ impl TuiApp {
             ("UNSAFE!", Color::Red)
         };
 
-        let dose_percent = if state.dose_target > 0.0 {
-            (state.dose_delivered / state.dose_target * 100.0).min(100.0)
-        } else {
-            0.0
-        };
+        let dose_percent = state.dose_percent;
 
         let mut text = vec![
             Line::from(vec![
                 Span::raw("Phase: "),
                 Span::styled(
-                    format!("{:?}", state.phase),
+                    state.messages.phase(state.phase).to_string(),
                     Style::default().fg(phase_color).add_modifier(Modifier::BOLD)
                 ),
                 Span::raw("  |  Safety: "),
@@ -665,52 +935,135 @@ impl TuiApp {
                     Style::default().fg(safety_status.1).add_modifier(Modifier::BOLD)
                 ),
                 Span::raw(format!("  |  Malfunctions: {}", state.malfunction_count)),
+                Span::raw(format!("  |  Overrides: {}", state.override_count)),
+                Span::styled(
+                    format!("  |  Near misses: {}", state.near_misses),
+                    if state.near_misses > 0 {
+                        Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default()
+                    },
+                ),
+                Span::raw(format!(
+                    "  |  Operator: {}",
+                    state.operator_id.as_deref().unwrap_or("(not logged in)")
+                )),
+                Span::raw(if state.wear_factor > 1.0 {
+                    format!("  |  Wear: {:.2}x", state.wear_factor)
+                } else {
+                    String::new()
+                }),
+                if state.recalibration_required {
+                    Span::styled("  |  RECALIBRATION REQUIRED", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                } else if state.config.recalibration_interval > 0 {
+                    Span::raw(format!(
+                        "  |  Calibration: {}/{}",
+                        state.treatments_since_calibration, state.config.recalibration_interval
+                    ))
+                } else {
+                    Span::raw("")
+                },
             ]),
             Line::from(vec![
                 Span::styled("Console: ", Style::default().fg(Color::Cyan)),
-                Span::raw(format!("{} @ {}  |  Gantry {}°  |  Field {}×{} cm  |  {} cGy",
+                Span::raw(format!("{} @ {}  |  Gantry {}°  |  Field {}×{} cm (eq. sq. {:.1})  |  {} cGy",
                     state.console_meos.beam_type,
                     state.console_meos.beam_energy,
                     state.console_params.gantry_angle,
                     state.console_params.field_size_x,
                     state.console_params.field_size_y,
+                    state.console_params.equivalent_square(),
                     state.dose_target)),
             ]),
         ];
 
         if let Some(ref malfunction) = state.last_malfunction {
             text.push(Line::from(Span::styled(
-                malfunction.as_str(),
+                malfunction.text.as_str(),
                 Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
             )));
+            if state.config.harm_reporting_only {
+                text.push(Line::from(Span::styled(
+                    HARM_REPORTING_BANNER,
+                    Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
+                )));
+            }
+        }
+
+        if let Some(wait) = state.safe_to_treat_in {
+            text.push(Line::from(Span::styled(
+                format!("SAFE TO TREAT IN {:.1}s - hardware still syncing to the console", wait.as_secs_f32()),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            )));
+        }
+
+        if state.phase == TPhase::BeamReady {
+            text.push(Line::from(Span::styled(
+                format!("BEAM ON IN {:.1}s - type 's' to abort with no dose",
+                    state.beam_hold_remaining_ms as f32 / 1000.0),
+                Style::default().fg(Color::LightYellow).add_modifier(Modifier::BOLD)
+            )));
+        }
+
+        if state.normalized_deviance_warnings > 0 {
+            text.push(Line::from(Span::styled(
+                format!("NORMALIZED DEVIANCE: {} malfunction messages dismissed",
+                    state.malfunction_dismissals),
+                Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD)
+            )));
         }
 
         let status = Paragraph::new(text)
             .block(Block::default().title("System Status").borders(Borders::ALL));
         f.render_widget(status, area);
 
-        // Dose gauge
+        // Dose gauge, clipped to the inside of the status border so it can never
+        // draw over neighbouring panels when the terminal is short
+        let inner = Block::default().borders(Borders::ALL).inner(area);
         let gauge_area = Rect {
             x: area.x + 2,
             y: area.y + 4,
-            width: area.width - 4,
+            width: area.width.saturating_sub(4),
             height: 2,
-        };
+        }
+        .intersection(inner);
+        if gauge_area.is_empty() {
+            return;
+        }
 
+        // The bar stops at full; past the target by more than the last pulse's overshoot
+        // the label carries the true ratio, flashing
+        let overdosed = state.dose_past_target;
         let gauge = Gauge::default()
             .block(Block::default().title("Dose Progress"))
             .gauge_style(
                 Style::default()
-                    .fg(if dose_percent > 100.0 { Color::Red } else { Color::Green })
+                    .fg(if overdosed { Color::Red } else { Color::Green })
                     .bg(Color::Black)
             )
-            .percent(dose_percent as u16)
-            .label(format!("{:.1}/{:.1} cGy ({:.1}%)",
-                state.dose_delivered, state.dose_target, dose_percent));
+            .percent(dose_percent.min(100.0) as u16);
+        let gauge = if overdosed {
+            gauge
+                .label(Span::styled(
+                    format!("{:.1}/{:.1} cGy - {:.0}% OF TARGET",
+                        state.dose_delivered, state.dose_target, dose_percent),
+                    Style::default().fg(Color::White).bg(Color::Red)
+                        .add_modifier(Modifier::BOLD | Modifier::SLOW_BLINK),
+                ))
+        } else {
+            let mut label = format!("{:.1}/{:.1} cGy ({:.1}%)",
+                state.dose_delivered, state.dose_target, dose_percent);
+            if matches!(state.phase, TPhase::BeamReady | TPhase::PatientTreatment | TPhase::PauseTreatment) {
+                if let Some(left) = state.time_remaining {
+                    label.push_str(&format!(" - about {:.1} min left", left.as_secs_f64() / 60.0));
+                }
+            }
+            gauge.label(label)
+        };
         f.render_widget(gauge, gauge_area);
     }
 
-    fn render_hardware(&self, f: &mut Frame, area: Rect, state: &TheracState) {
+    fn render_hardware(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
         let hardware_text = vec![
             Line::from(format!("Type: {}  |  Energy: {}  |  Collimator: {}",
                 state.hardware_meos.beam_type,
@@ -729,12 +1082,19 @@ impl TuiApp {
                         if state.hardware_meos.is_safe() { Color::Green } else { Color::Red }
                     ).add_modifier(Modifier::BOLD)
                 ),
+                Span::raw(format!("  |  Depth: {:.1} cm (planned {:.1} cm)",
+                    treatment_depth_cm(&state.hardware_meos),
+                    treatment_depth_cm(&state.console_meos))),
                 if state.editing_taking_place {
                     Span::styled("  |  EDITING", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))
                 } else {
                     Span::raw("")
                 },
             ]),
+            Line::from(format!("Beam current: {:.1} µA  |  Pulse dose: {:.1} cGy ({}x intended)",
+                beam_current(&state.hardware_meos),
+                state.pulse_dose,
+                overdose_factor(&state.hardware_meos))),
         ];
 
         let hardware_block = Paragraph::new(hardware_text)
@@ -745,21 +1105,128 @@ impl TuiApp {
         f.render_widget(hardware_block, area);
     }
 
-    fn render_log(&self, f: &mut Frame, area: Rect, state: &TheracState) {
+    fn render_task_status(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
+        let housekeeper_style = if state.task_status.housekeeper == "idle" {
+            Style::default()
+        } else {
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+        };
+
+        let mut text = vec![
+            Line::from(vec![
+                Span::styled("Monitor:     ", Style::default().fg(Color::Cyan)),
+                Span::raw(state.task_status.monitor.as_str()),
+            ]),
+            Line::from(vec![
+                Span::styled("Housekeeper: ", Style::default().fg(Color::Cyan)),
+                Span::styled(state.task_status.housekeeper.as_str(), housekeeper_style),
+            ]),
+        ];
+        if state.config.phantom_operator {
+            text.push(Line::from(vec![
+                Span::styled("Phantom:     ", Style::default().fg(Color::Cyan)),
+                Span::styled(state.task_status.phantom_operator.as_str(), Style::default().fg(Color::Red)),
+            ]));
+        }
+        if state.config.hardware_watchdog {
+            let watchdog_style = if state.collimator_timeout {
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            text.push(Line::from(vec![
+                Span::styled("Watchdog:    ", Style::default().fg(Color::Cyan)),
+                Span::styled(state.task_status.watchdog.as_str(), watchdog_style),
+            ]));
+        }
+
+        if state.bending_magnet_flag {
+            text.push(Line::from(vec![
+                Span::styled("Magnets:     ", Style::default().fg(Color::Cyan)),
+                Span::styled(
+                    format!("settling {} ms", state.bending_magnet_settle_remaining_ms),
+                    Style::default().fg(Color::Yellow),
+                ),
+            ]));
+        }
+
+        if state.energy_settle_remaining_ms > 0 {
+            text.push(Line::from(vec![
+                Span::styled("Energy:      ", Style::default().fg(Color::Cyan)),
+                Span::styled(
+                    format!("settling {} ms", state.energy_settle_remaining_ms),
+                    Style::default().fg(Color::Yellow),
+                ),
+            ]));
+        }
+
+        let (title, border_color) = if state.simulation_paused {
+            ("Task Activity - PAUSED", Color::Red)
+        } else {
+            ("Task Activity", Color::Magenta)
+        };
+        let block = Paragraph::new(text)
+            .block(Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(border_color)));
+        f.render_widget(block, area);
+    }
+
+    fn render_checklist(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
+        let item = |n: usize| {
+            let item = ChecklistItem::ALL[n];
+            let (mark, color) = if state.checklist.is_checked(item) {
+                ("[x]", Color::Green)
+            } else {
+                ("[ ]", Color::DarkGray)
+            };
+            Span::styled(format!("F{} {} {:<22}", n + 2, mark, item.to_string()), Style::default().fg(color))
+        };
+
+        let text = vec![
+            Line::from(vec![item(0), item(1)]),
+            Line::from(vec![item(2), item(3)]),
+        ];
+
+        let mut title = format!(
+            "Checklist ({}, F6 all)",
+            if state.config.require_checklist { "required" } else { "off" }
+        );
+        if state.checklist.bulk_checked {
+            title.push_str(" - CHECKED IN BULK");
+        }
+        let border_color = if state.checklist.bulk_checked { Color::Yellow } else { Color::Magenta };
+
+        let block = Paragraph::new(text)
+            .block(Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(border_color)));
+        f.render_widget(block, area);
+    }
+
+    fn render_log(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
         let log_items: Vec<ListItem> = state
             .log
             .iter()
             .rev()
-            .take(area.height as usize - 2)
-            .map(|msg| {
-                let style = if msg.contains("MALFUNCTION") || msg.contains("CRITICAL") {
-                    Style::default().fg(Color::Red)
-                } else if msg.contains("complete") || msg.contains("reached") {
-                    Style::default().fg(Color::Green)
-                } else {
-                    Style::default()
+            .take(area.height.saturating_sub(2) as usize)
+            .map(|entry| {
+                let style = match entry.level {
+                    LogLevel::Critical => Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+                    LogLevel::Warn => Style::default().fg(Color::Red),
+                    LogLevel::Info if entry.message.contains("complete") || entry.message.contains("reached") => {
+                        Style::default().fg(Color::Green)
+                    }
+                    // Colour by actor so the console and housekeeper can be seen interleaving
+                    LogLevel::Info => match entry.source() {
+                        LogSource::Console | LogSource::Operator => Style::default().fg(Color::Yellow),
+                        LogSource::Housekeeper => Style::default().fg(Color::Cyan),
+                        LogSource::Monitor | LogSource::Other => Style::default(),
+                    },
                 };
-                ListItem::new(msg.as_str()).style(style)
+                ListItem::new(entry.to_string()).style(style)
             })
             .collect();
 
@@ -768,60 +1235,143 @@ impl TuiApp {
         f.render_widget(log_list, area);
     }
 
-    fn render_help_hint(&self, f: &mut Frame, area: Rect) {
-        let help_text = Paragraph::new("Commands: (t)reat | (r)eset | (p)roceed | (s)top | (c)ontinue | (q)uit  |  F1=Help")
+    fn render_help_hint(&self, f: &mut Frame, area: Rect, state: &TheracSnapshot) {
+        if let Some(attract) = &self.attract {
+            let narration = format!(
+                "ATTRACT MODE - {}: {}  |  q quits",
+                attract.scene().title,
+                attract.narration().unwrap_or("")
+            );
+            let narration_text = Paragraph::new(narration)
+                .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .alignment(Alignment::Center);
+            f.render_widget(narration_text, area);
+            return;
+        }
+
+        if let Some(player) = &self.replay {
+            let status = format!(
+                "REPLAY {}/{} events  t={:.1}s  {}x{}  |  Space pause  → step  1/2/3 = 0.25x/1x/4x  Esc exit",
+                player.applied(),
+                player.len(),
+                player.position().as_secs_f64(),
+                player.speed(),
+                if player.is_paused() { "  PAUSED" } else if player.is_finished() { "  DONE" } else { "" },
+            );
+            let replay_text = Paragraph::new(status)
+                .style(Style::default().fg(Color::Magenta).add_modifier(Modifier::BOLD))
+                .alignment(Alignment::Center);
+            f.render_widget(replay_text, area);
+            return;
+        }
+
+        let command_bar = state.messages.get(MessageKey::CommandBar).to_string();
+        let help_text = Paragraph::new(command_bar)
             .style(Style::default().fg(Color::DarkGray))
             .alignment(Alignment::Center);
         f.render_widget(help_text, area);
     }
 
+    fn render_consequences(&self, f: &mut Frame, outcome: &TreatmentOutcome) {
+        let (prescribed, entered, config, near_miss_report) = {
+            let s = self.state.read();
+            (s.reference_dose_target, s.dose_target, s.config.clone(), s.near_miss_report())
+        };
+        let hypothetical = config.dose_label();
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        // The plan's dose and what was typed at the console, which the beam counted to
+        let doses = Line::from(format!(
+            "Prescribed: {:.1} cGy{}  |  Entered: {:.1} cGy{}",
+            prescribed, hypothetical, entered, hypothetical
+        ));
+
+        let (title, color, mut text) = match outcome {
+            TreatmentOutcome::Overdose { dose_delivered, dose_this_pulse, multiplier } => (
+                "BEAM FIRED WITHOUT FLATNESS FILTER",
+                Color::Red,
+                vec![
+                    Line::from(Span::styled(
+                        if config.exceeds_reported_cap(*dose_delivered) {
+                            format!("{} cGy DELIVERED{} - LETHAL OVERDOSE", config.reported_dose(*dose_delivered), hypothetical)
+                        } else {
+                            format!("{:.0} cGy DELIVERED{}", dose_delivered, hypothetical)
+                        },
+                        bold,
+                    )),
+                    doses,
+                    Line::from(format!(
+                        "Last pulse: {} cGy{} at {:.0}x the intended intensity",
+                        config.reported_dose(*dose_this_pulse), hypothetical, multiplier
+                    )),
+                    Line::from(""),
+                    Line::from(Span::styled(classify_delivery(*dose_delivered, prescribed).to_string(), bold)),
+                    Line::from(""),
+                    Line::from("East Texas Cancer Center, Tyler, March 1986: the operator corrected an"),
+                    Line::from("X-ray entry to electrons within seconds and fired. The console showed"),
+                    Line::from("MALFUNCTION 54 and reported an underdose; the patient had received an"),
+                    Line::from("estimated 16,500-25,000 rad and died five months later."),
+                ],
+            ),
+            TreatmentOutcome::TargetReached { dose_delivered } => (
+                "TREATMENT COMPLETE",
+                Color::Green,
+                vec![
+                    Line::from(Span::styled(
+                        format!("Treatment complete, {:.1} cGy{} delivered", dose_delivered, hypothetical),
+                        bold,
+                    )),
+                    doses,
+                    Line::from(""),
+                    Line::from(classify_delivery(*dose_delivered, prescribed).to_string()),
+                ],
+            ),
+            other => (
+                "TREATMENT ENDED",
+                Color::Yellow,
+                vec![Line::from(format!("{:?}", other))],
+            ),
+        };
+        if config.harm_reporting_only {
+            text.insert(0, Line::from(""));
+            text.insert(0, Line::from(Span::styled(
+                HARM_REPORTING_BANNER,
+                Style::default().fg(Color::Black).bg(Color::White).add_modifier(Modifier::BOLD),
+            )));
+        }
+        if let Some(report) = near_miss_report {
+            text.push(Line::from(""));
+            text.push(Line::from(Span::styled(report, Style::default().fg(Color::Yellow))));
+        }
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled("Press any key to reset", Style::default().fg(Color::DarkGray))));
+
+        let screen = Paragraph::new(text)
+            .block(Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double)
+                .border_style(Style::default().fg(color).add_modifier(Modifier::BOLD)))
+            .style(Style::default().fg(color))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true });
+        f.render_widget(screen, f.area());
+    }
+
     fn render_help(&self, f: &mut Frame) {
-        let help_text = vec![
-            Line::from(Span::styled("THERAC-25 SIMULATOR - HELP", Style::default().add_modifier(Modifier::BOLD))),
-            Line::from(""),
-            Line::from("DATA ENTRY WORKFLOW:"),
-            Line::from("  1. Enter Mode: X (X-ray) or E (Electron)"),
-            Line::from("     - X-ray automatically sets energy to 25 MeV and skips to Gantry"),
-            Line::from("  2. Enter Energy: 5, 10, 15, 20, or 25 (MeV)"),
-            Line::from("  3. Enter Gantry Angle: 0-360 degrees"),
-            Line::from("  4. Enter Field Size: Type X value, press 'x', type Y value"),
-            Line::from("     - Example: 10x15 for 10cm × 15cm field"),
-            Line::from("  5. Enter Dose: target dose in cGy (centigray)"),
-            Line::from("  6. Enter Command at prompt"),
-            Line::from(""),
-            Line::from("COPYING PRESCRIPTION VALUES:"),
-            Line::from("  - Press ENTER on any numeric field to copy from prescription"),
-            Line::from("  - This simulates the quick-entry workflow that led to real accidents"),
-            Line::from("  - Quick entry was convenient but dangerous!"),
-            Line::from(""),
-            Line::from("FIELD NAVIGATION:"),
-            Line::from("  - Press ENTER to advance to next field"),
-            Line::from("  - Press ESC to return to Mode entry"),
-            Line::from("  - Backspace to delete characters"),
-            Line::from(""),
-            Line::from("COMMANDS:"),
-            Line::from("  t, treat    - Complete entry and start treatment immediately"),
-            Line::from("  r, reset    - Reset system and generate new prescription"),
-            Line::from("  p, proceed  - Complete data entry and move to setup phase"),
-            Line::from("  s, stop     - Pause current treatment"),
-            Line::from("  c, continue - Resume paused treatment"),
-            Line::from("  q, quit     - Exit simulator"),
+        let messages = self.state.read().messages.clone();
+        let mut help_text = vec![
+            Line::from(Span::styled(messages.get(MessageKey::HelpTitle).to_string(), Style::default().add_modifier(Modifier::BOLD))),
             Line::from(""),
-            Line::from(Span::styled("THE RACE CONDITION:", Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD))),
-            Line::from("The original Therac-25 bug occurred when operators:"),
-            Line::from("  1. Entered X-ray mode (auto-sets to 25 MeV high energy)"),
-            Line::from("  2. Noticed mistake, quickly changed to Electron mode"),
-            Line::from("  3. Started treatment before hardware collimator sync completed"),
-            Line::from("  4. Result: High-energy beam without flatness filter = 100x overdose"),
-            Line::from(""),
-            Line::from("TO TRIGGER THE BUG:"),
-            Line::from("  - Type X (X-ray mode), then immediately press Backspace"),
-            Line::from("  - Type E (Electron mode), fill in parameters quickly"),
-            Line::from("  - Use 't' command to treat before hardware finishes syncing"),
-            Line::from("  - Watch for MALFUNCTION 54 or CRITICAL SAFETY VIOLATION!"),
-            Line::from(""),
-            Line::from("Press any key to close help..."),
         ];
+        help_text.extend(messages.get(MessageKey::HelpBody).lines().map(|line| Line::from(line.to_string())));
+        help_text.push(Line::from(""));
+        help_text.push(Line::from(Span::styled(
+            messages.get(MessageKey::HelpRaceTitle).to_string(),
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        )));
+        help_text.extend(messages.get(MessageKey::HelpRaceBody).lines().map(|line| Line::from(line.to_string())));
+        help_text.push(Line::from(""));
+        help_text.push(Line::from(messages.get(MessageKey::HelpClose).to_string()));
 
         let help_block = Paragraph::new(help_text)
             .block(Block::default()
@@ -834,6 +1384,201 @@ impl TuiApp {
         f.render_widget(Block::default().style(Style::default().bg(Color::Black)), f.area());
         f.render_widget(help_block, area);
     }
+
+    fn render_sweep(&self, f: &mut Frame) {
+        const BAR_WIDTH: usize = 40;
+
+        let sweep = dose_sweep();
+        let max_dose = sweep.iter().map(|e| e.dose_per_pulse).fold(0.0, f64::max);
+
+        let mut text = vec![
+            Line::from(Span::styled(
+                "PER-PULSE DOSE SWEEP (dose model only - no beam fired)",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+        ];
+
+        for entry in &sweep {
+            let safe = entry.meos.is_safe();
+            let filter = if entry.meos.collimator == CollimatorPosition::InPosition {
+                "filter in "
+            } else {
+                "filter out"
+            };
+            let bar_len = if max_dose > 0.0 {
+                ((entry.dose_per_pulse / max_dose) * BAR_WIDTH as f64).round() as usize
+            } else {
+                0
+            };
+            let color = if safe {
+                Color::Green
+            } else if overdose_factor(&entry.meos) > 1.0 {
+                Color::Red
+            } else {
+                Color::Yellow
+            };
+
+            text.push(Line::from(vec![
+                Span::raw(format!("{:<8} {:>6} {} ",
+                    format!("{}", entry.meos.beam_type),
+                    format!("{}", entry.meos.beam_energy),
+                    filter)),
+                Span::styled(format!("{:<BAR_WIDTH$}", "█".repeat(bar_len)), Style::default().fg(color)),
+                Span::styled(
+                    format!(" {:>7.1} cGy{}", entry.dose_per_pulse, if safe { "" } else { "  UNSAFE" }),
+                    Style::default().fg(color),
+                ),
+            ]));
+        }
+
+        text.push(Line::from(""));
+        text.push(Line::from("Press any key to close..."));
+
+        let sweep_block = Paragraph::new(text)
+            .block(Block::default()
+                .title("Dose Sweep")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double))
+            .style(Style::default().bg(Color::Black));
+
+        let area = centered_rect(85, 95, f.area());
+        f.render_widget(Block::default().style(Style::default().bg(Color::Black)), f.area());
+        f.render_widget(sweep_block, area);
+    }
+
+    fn render_library(&self, f: &mut Frame, selected: usize) {
+        let mut text = vec![
+            Line::from(Span::styled(
+                "PRESCRIPTION LIBRARY",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+        ];
+
+        for (i, p) in PRESCRIPTIONS.iter().enumerate() {
+            let style = if i == selected {
+                Style::default().fg(Color::Black).bg(Color::Green)
+            } else {
+                Style::default().fg(Color::White)
+            };
+            text.push(Line::from(Span::styled(
+                format!(
+                    "{:<30} {:<8} {:>8}  {}x{} cm  {} cGy",
+                    p.name,
+                    format!("{}", p.meos.beam_type),
+                    format!("{}", p.meos.beam_energy),
+                    p.params.field_size_x,
+                    p.params.field_size_y,
+                    p.dose
+                ),
+                style,
+            )));
+        }
+
+        text.push(Line::from(""));
+        text.push(Line::from(Span::styled(
+            "Up/Down to choose, ENTER to load (console entries are kept), any other key to cancel",
+            Style::default().fg(Color::Yellow),
+        )));
+
+        let library_block = Paragraph::new(text)
+            .block(Block::default()
+                .title("Library")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double))
+            .style(Style::default().bg(Color::Black));
+
+        let area = centered_rect(85, 95, f.area());
+        f.render_widget(Block::default().style(Style::default().bg(Color::Black)), f.area());
+        f.render_widget(library_block, area);
+    }
+
+    fn render_readback(&self, f: &mut Frame, differences: &[PrescriptionDifference]) {
+        let mut text = vec![
+            Line::from(Span::styled(
+                "READ-BACK: CONSOLE ENTRY vs PRESCRIPTION",
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+        ];
+
+        if differences.is_empty() {
+            text.push(Line::from(Span::styled(
+                "Entry matches the prescription",
+                Style::default().fg(Color::Green),
+            )));
+            text.push(Line::from(""));
+            text.push(Line::from("Press any key to close..."));
+        } else {
+            text.push(Line::from(Span::styled(
+                format!("{:<12} {:<20} {:<20}", "FIELD", "ENTERED", "PRESCRIBED"),
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for d in differences {
+                text.push(Line::from(vec![
+                    Span::raw(format!("{:<12} ", d.field)),
+                    Span::styled(format!("{:<20} ", d.entered), Style::default().fg(Color::Yellow)),
+                    Span::raw(format!("{:<20}", d.prescribed)),
+                ]));
+            }
+            text.push(Line::from(""));
+            text.push(Line::from(format!(
+                "{} difference(s). Every other entry matches the prescription.",
+                differences.len()
+            )));
+            text.push(Line::from(""));
+            text.push(Line::from(Span::styled(
+                "Press 'y' to acknowledge the differences as intended, any other key to go back and correct them",
+                Style::default().fg(Color::Yellow),
+            )));
+        }
+
+        let readback_block = Paragraph::new(text)
+            .block(Block::default()
+                .title("Verify")
+                .borders(Borders::ALL)
+                .border_type(BorderType::Double))
+            .style(Style::default().bg(Color::Black))
+            .wrap(Wrap { trim: false });
+
+        let area = centered_rect(85, 95, f.area());
+        f.render_widget(Block::default().style(Style::default().bg(Color::Black)), f.area());
+        f.render_widget(readback_block, area);
+    }
+}
+
+/// Read an energy entry in MeV
+/// The error says why it was refused: not a number, or outside `BeamEnergy::RANGE_MEV`.
+fn parse_energy(text: &str) -> Result<BeamEnergy, String> {
+    let text = text.trim();
+    let mev = text
+        .parse::<f32>()
+        .ok()
+        .filter(|mev| mev.is_finite())
+        .ok_or_else(|| format!("Invalid energy: '{}' is not a number", text))?;
+    BeamEnergy::from_mev(mev).ok_or_else(|| {
+        format!(
+            "Invalid energy: {} MeV. Use {} to {} MeV",
+            mev,
+            BeamEnergy::RANGE_MEV.start(),
+            BeamEnergy::RANGE_MEV.end()
+        )
+    })
+}
+
+/// Shown instead of the normal layout when the terminal cannot fit it
+fn render_too_small(f: &mut Frame, min_width: u16, min_height: u16) {
+    let area = f.area();
+    let text = vec![
+        Line::from(Span::styled("Terminal too small", Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))),
+        Line::from(format!("Need {}x{}, have {}x{}", min_width, min_height, area.width, area.height)),
+        Line::from("Resize the terminal or press Ctrl+C to quit"),
+    ];
+    let message = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true });
+    f.render_widget(message, area);
 }
 
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
@@ -855,3 +1600,226 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_energy() {
+        assert_eq!(parse_energy("25"), Ok(BeamEnergy::E25));
+        assert_eq!(parse_energy(" 5.0 "), Ok(BeamEnergy::E5));
+        assert_eq!(parse_energy("18.5"), Ok(BeamEnergy::EnergyMeV(18.5)));
+
+        assert_eq!(parse_energy("30"), Err("Invalid energy: 30 MeV. Use 5 to 25 MeV".to_string()));
+        assert!(parse_energy("4.9").is_err());
+        assert!(parse_energy("0").is_err());
+
+        assert_eq!(parse_energy("1.2.3"), Err("Invalid energy: '1.2.3' is not a number".to_string()));
+        assert!(parse_energy("").is_err());
+        assert!(parse_energy("NaN").is_err());
+        assert!(parse_energy("inf").is_err());
+    }
+
+    #[test]
+    fn test_rejected_energy_clears_and_keeps_focus() {
+        let state = create_therac_state();
+        let mut app = TuiApp::new(state.clone());
+        app.current_field = InputField::Energy;
+        let before = state.read().console_meos.beam_energy;
+        for key in [KeyCode::Char('3'), KeyCode::Char('0'), KeyCode::Enter] {
+            app.handle_energy_input(key);
+        }
+        assert_eq!(app.current_field, InputField::Energy);
+        assert!(app.energy_input.is_empty());
+        assert!(app.entry_error.as_ref().is_some_and(|(error, _)| error.contains("30 MeV")));
+        assert_eq!(state.read().console_meos.beam_energy, before);
+
+        // The next key clears the banner; a valid energy moves on
+        for key in [KeyCode::Char('1'), KeyCode::Char('0'), KeyCode::Enter] {
+            app.handle_energy_input(key);
+        }
+        assert!(app.entry_error.is_none());
+        assert_eq!(app.current_field, InputField::Gantry);
+        assert_eq!(state.read().console_meos.beam_energy, BeamEnergy::E10);
+    }
+
+    #[tokio::test]
+    async fn test_entries_go_through_the_command_channel() {
+        let state = create_therac_state();
+        let mut app = TuiApp::new(state.clone()).with_command_sender(spawn_command_processor(state.clone()));
+        app.handle_mode_input(KeyCode::Char('x'));
+        app.current_field = InputField::FieldSize;
+        for key in [KeyCode::Char('5'), KeyCode::Char('0'), KeyCode::Enter] {
+            app.handle_field_size_input(key);
+        }
+
+        // Queued, not applied: the console never locked the state to change it
+        assert_eq!(app.current_field, InputField::Dose);
+        assert_eq!(state.read().console_meos.beam_type, BeamType::Undefined);
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        let s = state.read();
+        assert_eq!(s.console_meos.beam_type, BeamType::XRay);
+        assert_eq!(s.console_meos.beam_energy, BeamEnergy::E25);
+        assert_eq!((s.console_params.field_size_x, s.console_params.field_size_y), (40.0, 40.0));
+    }
+
+    #[tokio::test]
+    async fn test_verify_reads_back_the_entries_queued_before_it() {
+        let state = create_therac_state();
+        let mut app = TuiApp::new(state.clone()).with_command_sender(spawn_command_processor(state.clone()));
+        let angle = (state.read().reference_params.gantry_angle + 90) % 360;
+        app.current_field = InputField::Gantry;
+        for c in angle.to_string().chars() {
+            app.handle_gantry_input(KeyCode::Char(c));
+        }
+        app.handle_gantry_input(KeyCode::Enter);
+        app.command_input = "verify".to_string();
+        app.execute_command();
+        app.command_input = "hold".to_string();
+        app.execute_command();
+
+        // Nothing read back or changed until the processor gets a turn
+        app.take_outcome();
+        assert!(app.readback.is_none());
+        assert_eq!(state.read().config.beam_hold_ms, 0);
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        app.take_outcome();
+        let differences = app.readback.as_ref().unwrap();
+        assert!(differences.iter().any(|d| d.field == "Gantry" && d.entered == format!("{} deg", angle)));
+        assert_eq!(state.read().config.beam_hold_ms, crate::control::BEAM_HOLD_MS);
+        assert!(state.read().last_readback.is_none());
+    }
+
+    #[test]
+    fn test_too_small_terminal_gets_the_fallback() {
+        use ratatui::backend::TestBackend;
+
+        let app = TuiApp::new(create_therac_state());
+        let draw = |width, height| {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            let buffer = terminal.draw(|f| app.ui(f)).unwrap().buffer.clone();
+            buffer.content.iter().map(|cell| cell.symbol()).collect::<String>()
+        };
+
+        // Every fixed-height chunk of the compact layout fits at the minimum size
+        let text = draw(MIN_WIDTH, MIN_HEIGHT);
+        assert!(!text.contains("Terminal too small"));
+        assert!(text.contains("Command:"));
+
+        for (width, height) in [(MIN_WIDTH - 1, MIN_HEIGHT), (MIN_WIDTH, MIN_HEIGHT - 1), (40, 12)] {
+            let text = draw(width, height);
+            assert!(text.contains("Terminal too small"), "{}x{}", width, height);
+            assert!(text.contains(&format!("Need {}x{}, have {}x{}", MIN_WIDTH, MIN_HEIGHT, width, height)));
+        }
+    }
+
+    #[test]
+    fn test_timing_command_is_refused_during_treatment() {
+        let state = create_therac_state();
+        let mut app = TuiApp::new(state.clone());
+        let run = |app: &mut TuiApp, command: &str| {
+            app.command_input = command.to_string();
+            app.execute_command();
+        };
+        run(&mut app, "timing 2000 50");
+        assert_eq!((state.read().config.collimator_move_ms, state.read().config.param_sync_ms), (2000, 50));
+
+        state.write().phase = TPhase::PatientTreatment;
+        run(&mut app, "timing 100 10");
+        let s = state.read();
+        assert_eq!((s.config.collimator_move_ms, s.config.param_sync_ms), (2000, 50));
+        assert!(s.log.last().unwrap().message.contains("cannot change during treatment"));
+    }
+
+    #[test]
+    fn test_consequences_show_the_prescribed_and_entered_dose() {
+        use ratatui::backend::TestBackend;
+
+        let state = create_therac_state();
+        {
+            let mut s = state.write();
+            s.reference_dose_target = 200.0;
+            s.dose_target = 180.0;
+        }
+        let mut app = TuiApp::new(state);
+        app.consequences = Some(TreatmentOutcome::TargetReached { dose_delivered: 180.0 });
+        let mut terminal = Terminal::new(TestBackend::new(MIN_WIDTH, MIN_HEIGHT)).unwrap();
+        let buffer = terminal.draw(|f| app.ui(f)).unwrap().buffer.clone();
+        let text = buffer.content.iter().map(|cell| cell.symbol()).collect::<String>();
+        assert!(text.contains("Prescribed: 200.0 cGy  |  Entered: 180.0 cGy"));
+    }
+
+    #[test]
+    fn test_short_terminals_collapse_the_layout() {
+        use ratatui::backend::TestBackend;
+
+        let state = create_therac_state();
+        state.write().add_log("[CONSOLE] Mode entered".to_string());
+        let app = TuiApp::new(state);
+        let draw = |height| {
+            let mut terminal = Terminal::new(TestBackend::new(MIN_WIDTH, height)).unwrap();
+            let buffer = terminal.draw(|f| app.ui(f)).unwrap().buffer.clone();
+            buffer.content.iter().map(|cell| cell.symbol()).collect::<String>()
+        };
+
+        // 80x24: the panels the race needs, every field and at least one log entry
+        let text = draw(MIN_HEIGHT);
+        for panel in ["Data Entry", "System Status", "Hardware State", "Event Log", "Mode entered", "Command:"] {
+            assert!(text.contains(panel), "{} missing at 80x{}", panel, MIN_HEIGHT);
+        }
+        assert!(!text.contains("Treatment Plan"));
+
+        // A 50-line terminal has room for all of them
+        let text = draw(50);
+        for panel in ["THERAC-25", "Treatment Plan", "Data Entry", "Task Activity", "Checklist", "Mode entered"] {
+            assert!(text.contains(panel), "{} missing at 80x50", panel);
+        }
+    }
+
+    #[test]
+    fn test_frame_holds_the_lock_only_for_the_snapshot() {
+        use ratatui::backend::TestBackend;
+
+        const FRAMES: u32 = 20;
+        let state = create_therac_state();
+        {
+            let mut s = state.write();
+            for i in 0..100 {
+                s.add_log(format!("[CONSOLE] Entry {}", i));
+            }
+        }
+        let app = TuiApp::new(state.clone());
+        let mut terminal = Terminal::new(TestBackend::new(160, 90)).unwrap();
+
+        // Before: the read guard taken at the top of the frame and kept until it is drawn
+        let mut held_for_frame = Duration::ZERO;
+        for _ in 0..FRAMES {
+            let started = Instant::now();
+            let guard = state.read();
+            terminal.draw(|f| app.render_main(f, &guard.snapshot())).unwrap();
+            drop(guard);
+            held_for_frame += started.elapsed();
+        }
+
+        // After: the guard is dropped once the snapshot is taken, as `ui` does
+        let mut held_for_snapshot = Duration::ZERO;
+        for _ in 0..FRAMES {
+            let started = Instant::now();
+            let snapshot = state.read().snapshot();
+            held_for_snapshot += started.elapsed();
+            terminal.draw(|f| app.render_main(f, &snapshot)).unwrap();
+        }
+
+        assert!(
+            held_for_snapshot < held_for_frame,
+            "lock held per frame: {:?} drawing under the guard, {:?} for the snapshot",
+            held_for_frame / FRAMES,
+            held_for_snapshot / FRAMES,
+        );
+    }
+}
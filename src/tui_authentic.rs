@@ -4,6 +4,7 @@
 //! as it appeared on the DEC VT100 terminal in the 1980s.
 
 use crate::*;
+use crate::cast::CastFile;
 use crossterm::{
     event::{self, Event, KeyCode},
     execute,
@@ -14,10 +15,12 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, BorderType, Clear, Paragraph},
+    widgets::{Block, Borders, BorderType, Clear, Paragraph, Wrap},
     Frame, Terminal,
 };
-use std::io;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 // Column positions matching original Therac-25 layout
 const LEFT: usize = 10;
@@ -25,6 +28,244 @@ const CENTER_LEFT: usize = 33;
 const CENTER_RIGHT: usize = 50;
 const RIGHT: usize = 70;
 
+// Largest difference between an entry and the prescription that still verifies: half
+// the 0.1 resolution values are entered at
+const VERIFY_TOLERANCE: f64 = 0.05;
+
+// A VT100 screen; the form does not fit in anything smaller
+const MIN_WIDTH: u16 = 80;
+const MIN_HEIGHT: u16 = 24;
+
+/// What the terminal can display
+/// The green phosphor look needs colour; a real VT100 was monochrome and set text
+/// apart with bold, reverse video and underline only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ColorCapability {
+    /// Green on black
+    #[default]
+    Color,
+    /// Attributes only: bold, reverse and underline on the terminal's own colours
+    Monochrome,
+}
+
+impl ColorCapability {
+    /// Guess from the environment: `NO_COLOR` or a monochrome `TERM` gives `Monochrome`
+    pub fn detect() -> Self {
+        Self::from_env(
+            std::env::var("NO_COLOR").ok().as_deref(),
+            std::env::var("TERM").ok().as_deref(),
+        )
+    }
+
+    /// `detect` on the given `NO_COLOR` and `TERM` values
+    pub fn from_env(no_color: Option<&str>, term: Option<&str>) -> Self {
+        let mono_term = matches!(term, Some("dumb" | "vt100" | "vt102" | "vt220"))
+            || term.is_some_and(|t| t.ends_with("-mono") || t.ends_with("-m"));
+        if no_color.is_some_and(|v| !v.is_empty()) || mono_term {
+            ColorCapability::Monochrome
+        } else {
+            ColorCapability::Color
+        }
+    }
+
+    fn is_color(self) -> bool {
+        self == ColorCapability::Color
+    }
+
+    /// Whole screen background and ordinary text
+    fn screen(self) -> Style {
+        if self.is_color() {
+            Style::default().bg(Color::Black).fg(Color::Green)
+        } else {
+            Style::default()
+        }
+    }
+
+    /// An entry field, highlighted when it has the cursor
+    fn field(self, active: bool) -> Style {
+        match (self.is_color(), active) {
+            (true, true) => Style::default().fg(Color::Black).bg(Color::Green),
+            (true, false) => Style::default().fg(Color::Green),
+            (false, true) => Style::default().add_modifier(Modifier::REVERSED),
+            (false, false) => Style::default(),
+        }
+    }
+
+    /// Emphasised text: the cursor marker and VERIFIED
+    fn bold(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        }
+    }
+
+    /// An entry that disagrees with the prescription
+    fn mismatch(self, active: bool) -> Style {
+        if self.is_color() {
+            self.field(active).fg(Color::Red).add_modifier(Modifier::BOLD)
+        } else {
+            self.field(active).add_modifier(Modifier::UNDERLINED)
+        }
+    }
+
+    /// The status line under the command prompt
+    fn status(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::DarkGray)
+        } else {
+            Style::default()
+        }
+    }
+
+    /// The malfunction number in the popup
+    fn alarm(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD | Modifier::UNDERLINED)
+        }
+    }
+
+    /// The malfunction message text
+    fn alert(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        }
+    }
+
+    /// `HARM_REPORTING_BANNER`
+    fn banner(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::White).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD | Modifier::REVERSED)
+        }
+    }
+
+    /// "Press any key" hints
+    fn hint(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)
+        } else {
+            Style::default().add_modifier(Modifier::UNDERLINED)
+        }
+    }
+
+    /// Malfunction popup border
+    fn popup_border(self) -> Style {
+        if self.is_color() {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default()
+        }
+    }
+
+    /// Malfunction popup background
+    fn popup(self) -> Style {
+        if self.is_color() {
+            Style::default().bg(Color::Black)
+        } else {
+            Style::default()
+        }
+    }
+}
+
+/// A keystroke file (`AuthenticTuiApp::write_keystrokes` output) played back into the
+/// console on its original schedule, `speed` times as fast
+pub struct KeystrokeReplay {
+    keys: Vec<(Duration, KeyCode)>,
+    next: usize,
+    speed: f32,
+}
+
+impl KeystrokeReplay {
+    /// Keys with when they were pressed, relative to the first; `speed` must be positive
+    pub fn new(keys: Vec<(Duration, KeyCode)>, speed: f32) -> Self {
+        Self { keys, next: 0, speed }
+    }
+
+    /// Read a keystroke file; blank lines are skipped
+    pub fn parse(text: &str, speed: f32) -> io::Result<Self> {
+        let keys = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let (at, key) = line.trim().split_once(char::is_whitespace).unwrap_or((line.trim(), ""));
+                let at = at.parse::<f64>().ok().and_then(|at| Duration::try_from_secs_f64(at).ok());
+                match (at, parse_key_code(key.trim())) {
+                    (Some(at), Some(key)) => Ok((at, key)),
+                    _ => Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("bad keystroke line: '{}'", line),
+                    )),
+                }
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self::new(keys, speed))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.keys.len()
+    }
+
+    /// Keys due `elapsed` into the replay and not played yet, oldest first
+    pub fn due(&mut self, elapsed: Duration) -> Vec<KeyCode> {
+        let start = self.next;
+        while self.next < self.keys.len() && self.scheduled(self.next) <= elapsed {
+            self.next += 1;
+        }
+        self.keys[start..self.next].iter().map(|&(_, key)| key).collect()
+    }
+
+    /// Time from `elapsed` until the next key is due; `None` once every key is played
+    pub fn until_next(&self, elapsed: Duration) -> Option<Duration> {
+        (!self.is_finished()).then(|| self.scheduled(self.next).saturating_sub(elapsed))
+    }
+
+    /// When key `index` is due at `speed`; a key too late to fit a `Duration` never is
+    fn scheduled(&self, index: usize) -> Duration {
+        Duration::try_from_secs_f64(self.keys[index].0.as_secs_f64() / self.speed as f64)
+            .unwrap_or(Duration::MAX)
+    }
+}
+
+/// A key as written by `write_keystrokes` (its `Debug` form)
+fn parse_key_code(text: &str) -> Option<KeyCode> {
+    if let Some(c) = text.strip_prefix("Char('").and_then(|rest| rest.strip_suffix("')")) {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(KeyCode::Char(c)),
+            // Escaped by `Debug`: '\\', '\''
+            (Some('\\'), Some(c)) if chars.next().is_none() => Some(KeyCode::Char(c)),
+            _ => None,
+        };
+    }
+    if let Some(n) = text.strip_prefix("F(").and_then(|rest| rest.strip_suffix(')')) {
+        return n.parse().ok().map(KeyCode::F);
+    }
+    Some(match text {
+        "Backspace" => KeyCode::Backspace,
+        "Enter" => KeyCode::Enter,
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "PageUp" => KeyCode::PageUp,
+        "PageDown" => KeyCode::PageDown,
+        "Tab" => KeyCode::Tab,
+        "BackTab" => KeyCode::BackTab,
+        "Delete" => KeyCode::Delete,
+        "Insert" => KeyCode::Insert,
+        "Esc" => KeyCode::Esc,
+        _ => return None,
+    })
+}
+
 /// Input field positions on the authentic interface
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum InputField {
@@ -62,9 +303,28 @@ pub struct AuthenticTuiApp {
     accessory_num_input: String,
     command_input: String,
 
+    // Dose target last sent to the console, so an unchanged one is not set (or refused) again
+    synced_dose: Option<f64>,
+
     // Malfunction popup
     show_malfunction: bool,
-    malfunction_message: String,
+    malfunction: Option<Malfunction>,
+
+    // asciinema recording of every frame drawn
+    recorder: Option<CastFile>,
+
+    // Every key pressed, in order, and where to write them on quit
+    keystrokes: Vec<(Instant, KeyCode)>,
+    keystroke_log: Option<PathBuf>,
+
+    // Recorded keystrokes fed in as if typed
+    replay: Option<KeystrokeReplay>,
+
+    // Channel to the command processor; commands are applied directly without one
+    commands: Option<CommandSender>,
+
+    // Colour or attribute-only styling
+    color: ColorCapability,
 }
 
 impl AuthenticTuiApp {
@@ -95,61 +355,176 @@ impl AuthenticTuiApp {
             wedge_num_input: String::new(),
             accessory_num_input: String::new(),
             command_input: String::new(),
+            synced_dose: None,
             show_malfunction: false,
-            malfunction_message: String::new(),
+            malfunction: None,
+            recorder: None,
+            keystrokes: Vec::new(),
+            keystroke_log: None,
+            replay: None,
+            commands: None,
+            color: ColorCapability::default(),
+        }
+    }
+
+    /// Record the session to an asciinema cast as it is drawn
+    pub fn with_recording(mut self, recorder: CastFile) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// Write every key pressed to `path` on quit (see `write_keystrokes`)
+    pub fn with_keystroke_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.keystroke_log = Some(path.into());
+        self
+    }
+
+    /// Type the keys of a recorded session on their original schedule, starting when
+    /// the console comes up; keys from the keyboard still work alongside
+    pub fn with_replay(mut self, replay: KeystrokeReplay) -> Self {
+        self.replay = Some(replay);
+        self
+    }
+
+    /// Send commands and entries through `spawn_command_processor`'s channel
+    pub fn with_command_sender(mut self, commands: CommandSender) -> Self {
+        self.commands = Some(commands);
+        self
+    }
+
+    /// Apply a command or entry, through the channel if there is one
+    /// Through the channel it is applied later, so true only means it was queued.
+    fn send(&self, message: ControlMessage) -> bool {
+        match &self.commands {
+            Some(commands) => commands.send(message),
+            None => apply_message(self.state.clone(), message),
+        }
+    }
+
+    /// Every key pressed this session with when it was pressed, oldest first
+    pub fn keystrokes(&self) -> &[(Instant, KeyCode)] {
+        &self.keystrokes
+    }
+
+    /// The keystrokes as text, one per line: seconds since the first key, then the key,
+    /// e.g. `   0.412  Backspace`. Every key is kept, navigation and edits included,
+    /// so a quick "X, Backspace, E" shows up with its timing. `out` is flushed, so a
+    /// buffered writer reports a failed write here rather than losing it on drop.
+    pub fn write_keystrokes<W: Write>(&self, mut out: W) -> io::Result<()> {
+        if let Some(&(first, _)) = self.keystrokes.first() {
+            for (at, key) in &self.keystrokes {
+                writeln!(out, "{:8.3}  {:?}", at.duration_since(first).as_secs_f64(), key)?;
+            }
+        }
+        out.flush()
+    }
+
+    /// Write the keystrokes to the `with_keystroke_log` file, if there is one
+    fn save_keystrokes(&self) -> io::Result<()> {
+        match &self.keystroke_log {
+            Some(path) => self.write_keystrokes(io::BufWriter::new(std::fs::File::create(path)?)),
+            None => Ok(()),
         }
     }
 
+    /// Style for a terminal with the given colour support
+    pub fn with_color_capability(mut self, color: ColorCapability) -> Self {
+        self.color = color;
+        self
+    }
+
     pub fn run(&mut self) -> io::Result<()> {
         enable_raw_mode()?;
         let mut stdout = io::stdout();
         execute!(stdout, EnterAlternateScreen)?;
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
+        let replay_started = Instant::now();
 
         loop {
-            terminal.draw(|f| self.render(f))?;
+            let frame = terminal.draw(|f| self.render(f))?;
+            if let Some(recorder) = &mut self.recorder {
+                recorder.record(frame.buffer)?;
+            }
 
-            if let Event::Key(key) = event::read()? {
-                match key.code {
-                    KeyCode::Char('q') | KeyCode::Char('Q')
-                        if self.current_field == InputField::Command => {
-                        break;
-                    }
-                    KeyCode::Esc => {
-                        if self.show_malfunction {
-                            self.show_malfunction = false;
-                        } else {
-                            break;
-                        }
-                    }
-                    _ => {
-                        if self.show_malfunction {
-                            // Any key dismisses malfunction popup
-                            self.show_malfunction = false;
-                        } else {
-                            self.handle_input(key.code);
-                        }
-                    }
+            // Keys from the replay go in as typed, but a recorded quit does not end the
+            // replay: the outcome stays on screen
+            let due = self.replay.as_mut().map(|replay| replay.due(replay_started.elapsed()));
+            for key in due.unwrap_or_default() {
+                self.handle_key(key);
+            }
+            let wait = self.replay.as_ref().and_then(|replay| replay.until_next(replay_started.elapsed()));
+            if let Some(wait) = wait {
+                // Redraw at least every 100ms so the replay is seen to unfold
+                if !event::poll(wait.min(Duration::from_millis(100)))? {
+                    self.check_malfunction();
+                    continue;
                 }
             }
 
-            // Check for malfunctions
-            {
-                let s = self.state.read();
-                if let Some(ref msg) = s.last_malfunction {
-                    if !self.show_malfunction {
-                        self.malfunction_message = msg.clone();
-                        self.show_malfunction = true;
-                    }
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                // Redraw at the new size (or show the too-small message) on the next pass
+                Event::Resize(_, _) => {
+                    terminal.autoresize()?;
+                    continue;
                 }
+                _ => continue,
+            };
+            if self.handle_key(key.code) {
+                break;
             }
         }
 
         disable_raw_mode()?;
         execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
         terminal.show_cursor()?;
-        Ok(())
+        self.save_keystrokes()
+    }
+
+    /// Act on a key, typed or replayed; returns true if it quits the console
+    fn handle_key(&mut self, key: KeyCode) -> bool {
+        self.keystrokes.push((Instant::now(), key));
+
+        match key {
+            KeyCode::Char('q') | KeyCode::Char('Q')
+                if self.current_field == InputField::Command => {
+                return true;
+            }
+            // Emergency-off: cuts the beam whatever is on screen
+            KeyCode::F(12) => emergency_off(self.state.clone()),
+            KeyCode::Esc => {
+                if self.show_malfunction {
+                    self.show_malfunction = false;
+                    crate::input::dismiss_malfunction(self.state.clone());
+                } else {
+                    return true;
+                }
+            }
+            _ => {
+                if self.show_malfunction {
+                    // Any key dismisses malfunction popup
+                    self.show_malfunction = false;
+                    crate::input::dismiss_malfunction(self.state.clone());
+                } else {
+                    self.handle_input(key);
+                }
+            }
+        }
+
+        self.check_malfunction();
+        false
+    }
+
+    /// Pop up a malfunction the machine has raised, if one is not showing already
+    fn check_malfunction(&mut self) {
+        let s = self.state.read();
+        if let Some(ref malfunction) = s.last_malfunction {
+            if !self.show_malfunction {
+                self.malfunction = Some(malfunction.clone());
+                self.show_malfunction = true;
+            }
+        }
     }
 
     fn handle_input(&mut self, key: KeyCode) {
@@ -206,38 +581,34 @@ impl AuthenticTuiApp {
             InputField::Mode => {
                 let c = self.mode_input.to_uppercase();
                 if c == "X" {
-                    let mut s = self.state.write();
-                    s.console_meos.beam_type = BeamType::XRay;
-                    s.console_meos.beam_energy = BeamEnergy::E25;
-                    self.energy_input = "25000".to_string(); // 25 MeV = 25000 KeV
-                    s.add_log("Mode: X-Ray, Energy: 25 MeV".to_string());
+                    if self.state.read().config.auto_max_energy_on_xray {
+                        self.energy_input = "25000".to_string(); // 25 MeV = 25000 KeV
+                    }
+                    self.send(ControlMessage::Mode(BeamType::XRay));
                 } else if c == "E" {
-                    let mut s = self.state.write();
-                    s.console_meos.beam_type = BeamType::Electron;
-                    s.add_log("Mode: Electron".to_string());
+                    self.send(ControlMessage::Mode(BeamType::Electron));
                 }
                 self.next_field();
             }
             InputField::Energy => {
                 // Auto-copy if empty
                 if self.energy_input.is_empty() {
-                    let s = self.state.read();
-                    let energy_mev = match s.reference_meos.beam_energy {
-                        BeamEnergy::E5 => 5,
-                        BeamEnergy::E10 => 10,
-                        BeamEnergy::E15 => 15,
-                        BeamEnergy::E20 => 20,
-                        BeamEnergy::E25 => 25,
+                    let Some(energy_mev) = copy_from_prescription(&self.state, |s| {
+                        s.reference_meos.beam_energy.mev()
+                    }) else {
+                        return;
                     };
-                    self.energy_input = (energy_mev * 1000).to_string();
+                    self.energy_input = ((energy_mev * 1000.0).round() as u32).to_string();
                 }
                 self.next_field();
             }
             InputField::UnitRate => {
                 // Auto-copy if empty
                 if self.unit_rate_input.is_empty() {
-                    let s = self.state.read();
-                    self.unit_rate_input = format!("{:.1}", s.reference_params.dose_rate);
+                    let Some(rate) = copy_from_prescription(&self.state, |s| s.reference_params.dose_rate) else {
+                        return;
+                    };
+                    self.unit_rate_input = format!("{:.1}", rate);
                 }
                 self.next_field();
             }
@@ -248,9 +619,13 @@ impl AuthenticTuiApp {
             InputField::Time => {
                 // Auto-copy if empty - calculate from dose target and rate
                 if self.time_input.is_empty() {
-                    let s = self.state.read();
-                    if s.reference_params.dose_rate > 0.0 {
-                        let time = s.reference_dose_target / (s.reference_params.dose_rate as f64);
+                    let Some(time) = copy_from_prescription(&self.state, |s| {
+                        (s.reference_params.dose_rate > 0.0)
+                            .then(|| s.reference_dose_target / (s.reference_params.dose_rate as f64))
+                    }) else {
+                        return;
+                    };
+                    if let Some(time) = time {
                         self.time_input = format!("{:.1}", time);
                     }
                 }
@@ -259,32 +634,40 @@ impl AuthenticTuiApp {
             InputField::GantryRot => {
                 // Auto-copy if empty
                 if self.gantry_rot_input.is_empty() {
-                    let s = self.state.read();
-                    self.gantry_rot_input = s.reference_params.gantry_angle.to_string();
+                    let Some(angle) = copy_from_prescription(&self.state, |s| s.reference_params.gantry_angle) else {
+                        return;
+                    };
+                    self.gantry_rot_input = angle.to_string();
                 }
                 self.next_field();
             }
             InputField::CollimatorRot => {
                 // Auto-copy if empty
                 if self.collimator_rot_input.is_empty() {
-                    let s = self.state.read();
-                    self.collimator_rot_input = s.reference_params.collimator_angle.to_string();
+                    let Some(angle) = copy_from_prescription(&self.state, |s| s.reference_params.collimator_angle) else {
+                        return;
+                    };
+                    self.collimator_rot_input = angle.to_string();
                 }
                 self.next_field();
             }
             InputField::CollimatorX => {
                 // Auto-copy if empty
                 if self.collimator_x_input.is_empty() {
-                    let s = self.state.read();
-                    self.collimator_x_input = format!("{:.1}", s.reference_params.field_size_x);
+                    let Some(size) = copy_from_prescription(&self.state, |s| s.reference_params.field_size_x) else {
+                        return;
+                    };
+                    self.collimator_x_input = format!("{:.1}", size);
                 }
                 self.next_field();
             }
             InputField::CollimatorY => {
                 // Auto-copy if empty
                 if self.collimator_y_input.is_empty() {
-                    let s = self.state.read();
-                    self.collimator_y_input = format!("{:.1}", s.reference_params.field_size_y);
+                    let Some(size) = copy_from_prescription(&self.state, |s| s.reference_params.field_size_y) else {
+                        return;
+                    };
+                    self.collimator_y_input = format!("{:.1}", size);
                 }
                 self.next_field();
             }
@@ -306,6 +689,7 @@ impl AuthenticTuiApp {
                 self.handle_command();
             }
             InputField::PatientName => {
+                self.send(ControlMessage::PatientName(self.patient_name.clone()));
                 self.next_field();
             }
         }
@@ -354,83 +738,50 @@ impl AuthenticTuiApp {
     }
 
     fn handle_command(&mut self) {
-        let cmd = self.command_input.trim().to_lowercase();
-        match cmd.as_str() {
-            "p" | "proceed" => {
-                // Set prescribed values from inputs
-                self.apply_prescription();
-                crate::input::complete_data_entry(self.state.clone());
-                self.command_input.clear();
-            }
-            "t" | "treat" => {
-                self.apply_prescription();
-                crate::input::complete_data_entry(self.state.clone());
-                crate::simulator::start_treatment(self.state.clone());
-                self.command_input.clear();
-            }
-            "r" | "reset" => {
-                let mut s = self.state.write();
-                s.reset();
-                s.generate_new_reference();
-                // Pre-seed console with new reference (for auto-copy convenience)
+        let Ok(command) = self.command_input.parse::<Command>() else {
+            return;
+        };
+        // The prescription typed on screen goes to the console before data entry completes
+        if matches!(command, Command::Proceed | Command::Treat) {
+            self.apply_prescription();
+        }
+        self.send(command.into());
+        match command {
+            Command::Reset => {
+                // Pre-seed console with the reset's new reference (for auto-copy convenience)
                 // Hardware will sync via housekeeper naturally
-                s.console_meos = s.reference_meos;
-                s.console_params = s.reference_params;
-                drop(s);
+                self.send(ControlMessage::CopyPrescription);
                 self.clear_all_inputs();
-                self.command_input.clear();
-            }
-            "s" | "stop" => {
-                crate::simulator::stop_treatment(self.state.clone());
-                self.command_input.clear();
-            }
-            "c" | "continue" => {
-                crate::simulator::resume_treatment(self.state.clone());
-                self.command_input.clear();
             }
+            // Through the channel the acknowledgment has not been applied yet, so the
+            // cursor moves whether or not there was anything to clear
+            Command::Acknowledge => self.current_field = InputField::Mode,
+            // Q on the command line quits before a command is typed
+            Command::Quit => return,
             _ => {}
         }
+        self.command_input.clear();
     }
 
     fn apply_prescription(&mut self) {
-        let mut s = self.state.write();
-
-        // Parse and apply all prescribed values
-        if let Ok(rate) = self.unit_rate_input.parse::<f32>() {
-            s.console_params.dose_rate = rate;
-        }
-        if let Ok(gantry) = self.gantry_rot_input.parse::<u16>() {
-            s.console_params.gantry_angle = gantry;
-        }
-        if let Ok(coll_rot) = self.collimator_rot_input.parse::<u16>() {
-            s.console_params.collimator_angle = coll_rot;
-        }
-        if let Ok(coll_x) = self.collimator_x_input.parse::<f32>() {
-            s.console_params.field_size_x = coll_x;
-        }
-        if let Ok(coll_y) = self.collimator_y_input.parse::<f32>() {
-            s.console_params.field_size_y = coll_y;
-        }
-        if let Ok(time) = self.time_input.parse::<f32>() {
-            // Calculate dose from time and rate
-            if let Ok(rate) = self.unit_rate_input.parse::<f32>() {
-                s.dose_target = (time * rate) as f64;
-            }
-        }
-        if let Ok(energy_kev) = self.energy_input.parse::<u32>() {
-            // Convert KeV to MeV and set energy
-            let energy_mev = energy_kev / 1000;
-            s.console_meos.beam_energy = match energy_mev {
-                5 => BeamEnergy::E5,
-                10 => BeamEnergy::E10,
-                15 => BeamEnergy::E15,
-                20 => BeamEnergy::E20,
-                25 => BeamEnergy::E25,
-                _ => s.console_meos.beam_energy,
-            };
-        }
-
-        s.editing_taking_place = true;
+        // Parse all prescribed values; one that does not parse keeps the last entry
+        let rate = self.unit_rate_input.parse::<f32>().ok();
+        // Calculate dose from time and rate; only a changed one goes to the setter
+        let dose = self.time_input.parse::<f32>().ok().zip(rate).map(|(time, rate)| (time * rate) as f64);
+        let dose_target = dose.filter(|&dose| self.synced_dose != Some(dose));
+        self.synced_dose = dose_target.or(self.synced_dose);
+        let entries = ConsoleEntries {
+            dose_rate: rate,
+            gantry_angle: self.gantry_rot_input.parse().ok(),
+            collimator_angle: self.collimator_rot_input.parse().ok(),
+            field_size_x: self.collimator_x_input.parse().ok(),
+            field_size_y: self.collimator_y_input.parse().ok(),
+            monitor_units: self.monitor_units_input.parse().ok(),
+            dose_target,
+            // Convert KeV to MeV; out of range keeps the last one
+            energy: self.energy_input.parse::<u32>().ok().and_then(|kev| BeamEnergy::from_mev(kev as f32 / 1000.0)),
+        };
+        self.send(ControlMessage::Entries(entries));
     }
 
     fn clear_all_inputs(&mut self) {
@@ -446,9 +797,15 @@ impl AuthenticTuiApp {
         self.collimator_y_input.clear();
         self.wedge_num_input.clear();
         self.accessory_num_input.clear();
+        self.synced_dose = None;
     }
 
     fn render(&self, f: &mut Frame) {
+        if f.area().width < MIN_WIDTH || f.area().height < MIN_HEIGHT {
+            render_too_small(f, self.color);
+            return;
+        }
+
         if self.show_malfunction {
             self.render_main_screen(f);
             self.render_malfunction_popup(f);
@@ -460,11 +817,12 @@ impl AuthenticTuiApp {
     fn render_main_screen(&self, f: &mut Frame) {
         let state = self.state.read();
 
-        // VT100-style monochrome interface with green text on black
+        // VT100-style interface: green text on black, or attributes only in monochrome
         let area = f.area();
+        let color = self.color;
 
         let block = Block::default()
-            .style(Style::default().bg(Color::Black).fg(Color::Green));
+            .style(color.screen());
         f.render_widget(block, area);
 
         // Build the screen content
@@ -496,28 +854,20 @@ impl AuthenticTuiApp {
             Span::raw(format!("{:>LEFT$}Mode: ", "")),
             Span::styled(
                 format!("{:<10}", mode_display),
-                if self.current_field == InputField::Mode {
-                    Style::default().fg(Color::Black).bg(Color::Green)
-                } else {
-                    Style::default().fg(Color::Green)
-                }
+                color.field(self.current_field == InputField::Mode)
             ),
             if self.current_field == InputField::Mode {
-                Span::styled(" ◀", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                Span::styled(" ◀", color.bold())
             } else {
                 Span::raw("")
             },
             Span::raw("    Energy (KeV): "),
             Span::styled(
                 format!("{:<10}", self.energy_input),
-                if self.current_field == InputField::Energy {
-                    Style::default().fg(Color::Black).bg(Color::Green)
-                } else {
-                    Style::default().fg(Color::Green)
-                }
+                color.field(self.current_field == InputField::Energy)
             ),
             if self.current_field == InputField::Energy {
-                Span::styled(" ◀", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                Span::styled(" ◀", color.bold())
             } else {
                 Span::raw("")
             },
@@ -539,47 +889,56 @@ impl AuthenticTuiApp {
         lines.push(self.render_param_line("Unit rate/min:",
             &format!("{:.1}", state.console_params.dose_rate),
             &self.unit_rate_input,
-            self.current_field == InputField::UnitRate));
+            self.current_field == InputField::UnitRate,
+            self.entry_mismatched(&state, InputField::UnitRate)));
 
         lines.push(self.render_param_line("Monitor units:",
-            "0", // Not implemented in our simulator
+            &format!("{:.1}", state.monitor_units_delivered),
             &self.monitor_units_input,
-            self.current_field == InputField::MonitorUnits));
+            self.current_field == InputField::MonitorUnits,
+            false));
 
         lines.push(self.render_param_line("Time (minutes):",
             &format!("{:.1}", if state.dose_delivered > 0.0 { state.dose_delivered / (state.console_params.dose_rate as f64) } else { 0.0 }),
             &self.time_input,
-            self.current_field == InputField::Time));
+            self.current_field == InputField::Time,
+            false));
 
         lines.push(self.render_param_line("Gantry rotation (deg):",
             &format!("{}", state.console_params.gantry_angle),
             &self.gantry_rot_input,
-            self.current_field == InputField::GantryRot));
+            self.current_field == InputField::GantryRot,
+            self.entry_mismatched(&state, InputField::GantryRot)));
 
         lines.push(self.render_param_line("Collimator rotation (deg):",
             &format!("{}", state.console_params.collimator_angle),
             &self.collimator_rot_input,
-            self.current_field == InputField::CollimatorRot));
+            self.current_field == InputField::CollimatorRot,
+            false));
 
         lines.push(self.render_param_line("Collimator x (cm):",
             &format!("{:.1}", state.console_params.field_size_x),
             &self.collimator_x_input,
-            self.current_field == InputField::CollimatorX));
+            self.current_field == InputField::CollimatorX,
+            self.entry_mismatched(&state, InputField::CollimatorX)));
 
         lines.push(self.render_param_line("Collimator y (cm):",
             &format!("{:.1}", state.console_params.field_size_y),
             &self.collimator_y_input,
-            self.current_field == InputField::CollimatorY));
+            self.current_field == InputField::CollimatorY,
+            self.entry_mismatched(&state, InputField::CollimatorY)));
 
         lines.push(self.render_param_line("Wedge number:",
             "0",
             &self.wedge_num_input,
-            self.current_field == InputField::WedgeNum));
+            self.current_field == InputField::WedgeNum,
+            false));
 
         lines.push(self.render_param_line("Accessory number:",
             "0",
             &self.accessory_num_input,
-            self.current_field == InputField::AccessoryNum));
+            self.current_field == InputField::AccessoryNum,
+            false));
 
         // Line 16: Blank
         lines.push(Line::from(""));
@@ -590,7 +949,7 @@ impl AuthenticTuiApp {
             Span::raw(format!("{:>RIGHT$}", "")),
             Span::styled(
                 if verified { "VERIFIED" } else { "" },
-                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+                color.bold()
             ),
         ]));
 
@@ -603,131 +962,175 @@ impl AuthenticTuiApp {
             Span::raw("Command: "),
             Span::styled(
                 format!("{:<30}", &self.command_input),
-                if self.current_field == InputField::Command {
-                    Style::default().fg(Color::Black).bg(Color::Green)
-                } else {
-                    Style::default().fg(Color::Green)
-                }
+                color.field(self.current_field == InputField::Command)
             ),
             if self.current_field == InputField::Command {
-                Span::styled(" ◀", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                Span::styled(" ◀", color.bold())
             } else {
                 Span::raw("")
             },
         ]));
 
         // Line 21: Status
-        let phase_msg = format!("Phase: {:?}  |  Safety: {}",
+        let mut phase_msg = format!("Phase: {:?}  |  Safety: {}  |  MU: {:.1}/{:.1}",
             state.phase,
-            if state.hardware_meos.is_safe() { "SAFE" } else { "UNSAFE" }
+            if state.hardware_meos.is_safe() { "SAFE" } else { "UNSAFE" },
+            state.monitor_units_delivered,
+            state.monitor_units
         );
+        if state.phase == TPhase::BeamReady {
+            phase_msg.push_str(&format!("  |  BEAM ON IN {:.1} SEC - S TO ABORT",
+                state.beam_hold_remaining_ms as f32 / 1000.0));
+        }
+        if matches!(state.phase, TPhase::PatientTreatment | TPhase::PauseTreatment) {
+            if let Some(left) = state.estimated_time_remaining() {
+                phase_msg.push_str(&format!("  |  TIME LEFT: {:.1} MIN", left.as_secs_f64() / 60.0));
+            }
+        }
         lines.push(Line::from(Span::styled(
             phase_msg,
-            Style::default().fg(Color::DarkGray)
+            color.status()
         )));
 
         let paragraph = Paragraph::new(lines)
-            .style(Style::default().bg(Color::Black).fg(Color::Green));
+            .style(color.screen());
 
         f.render_widget(paragraph, area);
     }
 
     fn render_field_line(&self, label: &str, value: &str, active: bool) -> Line {
+        let color = self.color;
         Line::from(vec![
             Span::raw(format!("{:>LEFT$}{} ", "", label)),
             Span::styled(
                 format!("{:<40}", value),
-                if active {
-                    Style::default().fg(Color::Black).bg(Color::Green)
-                } else {
-                    Style::default().fg(Color::Green)
-                }
+                color.field(active)
             ),
             if active {
-                Span::styled(" ◀", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                Span::styled(" ◀", color.bold())
             } else {
                 Span::raw("")
             },
         ])
     }
 
-    fn render_param_line(&self, label: &str, actual: &str, prescribed: &str, active: bool) -> Line {
+    fn render_param_line(&self, label: &str, actual: &str, prescribed: &str, active: bool, mismatched: bool) -> Line {
+        let color = self.color;
         Line::from(vec![
             Span::raw(format!("{:>LEFT$}{:<20}", "", label)),
             Span::raw(format!("{:>13}", actual)),
             Span::raw("      "),
             Span::styled(
                 format!("{:<10}", prescribed),
-                if active {
-                    Style::default().fg(Color::Black).bg(Color::Green)
-                } else {
-                    Style::default().fg(Color::Green)
-                }
+                if mismatched { color.mismatch(active) } else { color.field(active) }
             ),
             if active {
-                Span::styled(" ◀", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+                Span::styled(" ◀", color.bold())
             } else {
                 Span::raw("")
             },
         ])
     }
 
-    fn check_verification(&self, _state: &TheracState) -> bool {
-        // In the real Therac-25, this checked if actual == prescribed
-        // For our simulator, we'll show VERIFIED when data entry is complete
-        !self.unit_rate_input.is_empty() &&
-        !self.gantry_rot_input.is_empty() &&
-        !self.collimator_x_input.is_empty() &&
-        !self.collimator_y_input.is_empty()
+    /// The entries VERIFIED depends on, each with whether it matches the prescription
+    fn verified_entries(&self, state: &TheracState) -> [(InputField, bool); 4] {
+        let params = &state.reference_params;
+        [
+            (InputField::UnitRate, fields_match(&self.unit_rate_input, params.dose_rate as f64, VERIFY_TOLERANCE)),
+            (InputField::GantryRot, fields_match(&self.gantry_rot_input, params.gantry_angle as f64, VERIFY_TOLERANCE)),
+            (InputField::CollimatorX, fields_match(&self.collimator_x_input, params.field_size_x as f64, VERIFY_TOLERANCE)),
+            (InputField::CollimatorY, fields_match(&self.collimator_y_input, params.field_size_y as f64, VERIFY_TOLERANCE)),
+        ]
+    }
+
+    fn check_verification(&self, state: &TheracState) -> bool {
+        // As on the real Therac-25: VERIFIED only when every entry matches the prescription
+        self.verified_entries(state).iter().all(|&(_, matches)| matches)
+    }
+
+    /// Whether `field` has been entered and disagrees with the prescription
+    fn entry_mismatched(&self, state: &TheracState, field: InputField) -> bool {
+        let entered = match field {
+            InputField::UnitRate => &self.unit_rate_input,
+            InputField::GantryRot => &self.gantry_rot_input,
+            InputField::CollimatorX => &self.collimator_x_input,
+            InputField::CollimatorY => &self.collimator_y_input,
+            _ => return false,
+        };
+        !entered.is_empty()
+            && self.verified_entries(state).iter().any(|&(checked, matches)| checked == field && !matches)
     }
 
     fn render_malfunction_popup(&self, f: &mut Frame) {
         let area = centered_rect(60, 40, f.area());
+        let color = self.color;
 
-        // Parse malfunction number if present
-        let malfunction_num = if self.malfunction_message.contains("MALFUNCTION") {
-            if self.malfunction_message.contains("54") {
-                "54"
-            } else if self.malfunction_message.contains("CRITICAL") {
-                "26"
-            } else {
-                "13"
-            }
-        } else {
-            "54"
+        let Some(malfunction) = &self.malfunction else {
+            return;
         };
 
-        let text = vec![
+        let mut text = vec![
             Line::from(""),
             Line::from(Span::styled(
-                format!("MALFUNCTION {}", malfunction_num),
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD)
+                format!("MALFUNCTION {}", malfunction.code()),
+                color.alarm()
             )),
-            Line::from(""),
             Line::from(Span::styled(
-                &self.malfunction_message,
-                Style::default().fg(Color::Yellow)
+                malfunction.description().unwrap_or_default(),
+                color.alert()
             )),
             Line::from(""),
             Line::from(Span::styled(
-                "Press any key to continue",
-                Style::default().fg(Color::DarkGray).add_modifier(Modifier::ITALIC)
+                malfunction.text.as_str(),
+                color.alert()
             )),
+            Line::from(""),
         ];
+        if self.state.read().config.harm_reporting_only {
+            text.push(Line::from(Span::styled(
+                HARM_REPORTING_BANNER,
+                color.banner()
+            )));
+            text.push(Line::from(""));
+        }
+        text.push(Line::from(Span::styled(
+            "Press any key to continue",
+            color.hint()
+        )));
 
         let block = Paragraph::new(text)
             .block(Block::default()
                 .borders(Borders::ALL)
                 .border_type(BorderType::Double)
-                .border_style(Style::default().fg(Color::Red)))
+                .border_style(color.popup_border()))
             .alignment(Alignment::Center)
-            .style(Style::default().bg(Color::Black));
+            .style(color.popup());
 
         f.render_widget(Clear, area);
         f.render_widget(block, area);
     }
 }
 
+/// Shown instead of the form when the terminal is smaller than a VT100 screen
+/// Whether an entry matches the prescribed value to within `tol`
+/// An empty or unreadable entry never matches.
+fn fields_match(entered: &str, reference: f64, tol: f64) -> bool {
+    entered.trim().parse::<f64>().is_ok_and(|value| (value - reference).abs() <= tol)
+}
+
+fn render_too_small(f: &mut Frame, color: ColorCapability) {
+    let area = f.area();
+    let text = vec![
+        Line::from("TERMINAL TOO SMALL"),
+        Line::from(format!("NEED {}x{}, HAVE {}x{}", MIN_WIDTH, MIN_HEIGHT, area.width, area.height)),
+    ];
+    let message = Paragraph::new(text)
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: true })
+        .style(color.screen());
+    f.render_widget(message, area);
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -747,3 +1150,239 @@ fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
         ])
         .split(popup_layout[1])[1]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    #[test]
+    fn test_monochrome_renders_with_attributes_only() {
+        let mut app = AuthenticTuiApp::new(create_therac_state())
+            .with_color_capability(ColorCapability::Monochrome);
+        app.show_malfunction = true;
+        app.malfunction = Some(Malfunction {
+            kind: MalfunctionKind::ParameterMismatch,
+            text: "MALFUNCTION 54 - Parameter mismatch".to_string(),
+        });
+
+        let mut terminal = Terminal::new(TestBackend::new(MIN_WIDTH, MIN_HEIGHT)).unwrap();
+        let buffer = terminal.draw(|f| app.render(f)).unwrap().buffer.clone();
+
+        assert!(
+            buffer.content.iter().all(|cell| cell.fg == Color::Reset && cell.bg == Color::Reset),
+            "monochrome rendering used a colour"
+        );
+        // The active field (patient name, on the first line) stands out in reverse video
+        assert!((0..MIN_WIDTH).any(|x| buffer[(x, 0)].modifier.contains(Modifier::REVERSED)));
+        // The popup's malfunction number is underlined instead of red
+        assert!(buffer.content.iter().any(|cell| cell.modifier.contains(Modifier::UNDERLINED)));
+    }
+
+    #[test]
+    fn test_too_small_terminal_gets_the_fallback() {
+        let app = AuthenticTuiApp::new(create_therac_state());
+        let draw = |width, height| {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            let buffer = terminal.draw(|f| app.render(f)).unwrap().buffer.clone();
+            buffer.content.iter().map(|cell| cell.symbol()).collect::<String>()
+        };
+
+        assert!(draw(MIN_WIDTH, MIN_HEIGHT).contains("Command:"));
+        for (width, height) in [(MIN_WIDTH - 1, MIN_HEIGHT), (MIN_WIDTH, MIN_HEIGHT - 1)] {
+            let text = draw(width, height);
+            assert!(text.contains("TERMINAL TOO SMALL"), "{}x{}", width, height);
+            assert!(!text.contains("Command:"));
+        }
+    }
+
+    #[test]
+    fn test_keystrokes_keep_edits_and_navigation() {
+        let mut app = AuthenticTuiApp::new(create_therac_state());
+        for key in [KeyCode::Down, KeyCode::Char('x'), KeyCode::Backspace, KeyCode::Char('e')] {
+            assert!(!app.handle_key(key));
+        }
+
+        let mut out = Vec::new();
+        app.write_keystrokes(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<(f64, &str)> = text
+            .lines()
+            .map(|line| {
+                let (at, key) = line.trim_start().split_once("  ").unwrap();
+                (at.parse().unwrap(), key)
+            })
+            .collect();
+        let keys: Vec<&str> = lines.iter().map(|&(_, key)| key).collect();
+        assert_eq!(keys, ["Down", "Char('x')", "Backspace", "Char('e')"]);
+        assert!(text.starts_with("   0.000  Down"));
+        assert!(lines.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    #[test]
+    fn test_keystroke_log_is_written_on_save() {
+        let path = std::env::temp_dir().join(format!("rstherac25-keys-{}.txt", std::process::id()));
+        let mut app = AuthenticTuiApp::new(create_therac_state()).with_keystroke_log(&path);
+        app.handle_key(KeyCode::Down);
+        app.handle_key(KeyCode::Char('x'));
+        app.save_keystrokes().unwrap();
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written.lines().count(), 2);
+        assert!(written.ends_with("  Char('x')\n"));
+
+        // A file that cannot be created is an error, not a silent loss
+        let app = app.with_keystroke_log(std::env::temp_dir().join("no-such-dir").join("keys.txt"));
+        assert!(app.save_keystrokes().is_err());
+    }
+
+    #[test]
+    fn test_keystroke_file_reads_back() {
+        let mut app = AuthenticTuiApp::new(create_therac_state());
+        let keys = [KeyCode::Char('x'), KeyCode::Backspace, KeyCode::Char('\''), KeyCode::F(12), KeyCode::BackTab];
+        for key in keys {
+            app.handle_key(key);
+        }
+        let mut out = Vec::new();
+        app.write_keystrokes(&mut out).unwrap();
+
+        let replay = KeystrokeReplay::parse(&String::from_utf8(out).unwrap(), 1.0).unwrap();
+        let read: Vec<KeyCode> = replay.keys.iter().map(|&(_, key)| key).collect();
+        assert_eq!(read, keys);
+        assert_eq!(replay.keys[0].0, Duration::ZERO);
+
+        let err = KeystrokeReplay::parse("   0.000  Down\n   0.100  Wiggle\n", 1.0).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_replay_keeps_the_schedule_at_speed() {
+        let keys = vec![
+            (Duration::ZERO, KeyCode::Char('x')),
+            (Duration::from_millis(400), KeyCode::Backspace),
+            (Duration::from_millis(600), KeyCode::Char('e')),
+        ];
+        let mut replay = KeystrokeReplay::new(keys, 2.0);
+
+        assert_eq!(replay.due(Duration::ZERO), [KeyCode::Char('x')]);
+        assert_eq!(replay.until_next(Duration::from_millis(50)), Some(Duration::from_millis(150)));
+        assert!(replay.due(Duration::from_millis(199)).is_empty());
+        // Twice as fast: the backspace at 0.4 s and the E at 0.6 s are due by 0.3 s
+        assert_eq!(replay.due(Duration::from_millis(300)), [KeyCode::Backspace, KeyCode::Char('e')]);
+        assert!(replay.is_finished());
+        assert_eq!(replay.until_next(Duration::from_millis(300)), None);
+    }
+
+    #[test]
+    fn test_replay_survives_extreme_times_and_speeds() {
+        let err = KeystrokeReplay::parse("   1e300  Down
+", 1.0).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(KeystrokeReplay::parse("   -1.0  Down
+", 1.0).is_err());
+        assert!(KeystrokeReplay::parse("   NaN  Down
+", 1.0).is_err());
+
+        // A tiny speed puts the second key beyond any Duration: it is never due
+        let keys = vec![(Duration::ZERO, KeyCode::Down), (Duration::from_secs(1), KeyCode::Up)];
+        let mut replay = KeystrokeReplay::new(keys, 1e-30);
+        assert_eq!(replay.due(Duration::from_secs(3600)), [KeyCode::Down]);
+        assert_eq!(replay.until_next(Duration::from_secs(3600)), Some(Duration::MAX - Duration::from_secs(3600)));
+        assert!(!replay.is_finished());
+    }
+
+    #[test]
+    fn test_replayed_keys_fill_the_form() {
+        let mut app = AuthenticTuiApp::new(create_therac_state());
+        let mut replay = KeystrokeReplay::parse("   0.000  Down\n   0.120  Char('x')\n   0.300  Backspace\n   0.420  Char('e')\n", 1.0).unwrap();
+        for key in replay.due(Duration::from_secs(1)) {
+            assert!(!app.handle_key(key));
+        }
+        assert_eq!(app.current_field, InputField::Mode);
+        assert_eq!(app.mode_input, "E");
+        assert_eq!(app.keystrokes.len(), 4);
+    }
+
+    #[test]
+    fn test_fields_match_within_tolerance() {
+        assert!(fields_match("120", 120.0, VERIFY_TOLERANCE));
+        assert!(fields_match("120.0", 120.0, VERIFY_TOLERANCE));
+        assert!(fields_match(" 12.04", 12.0, VERIFY_TOLERANCE));
+        assert!(!fields_match("12.5", 12.0, VERIFY_TOLERANCE));
+        assert!(!fields_match("90", 270.0, VERIFY_TOLERANCE));
+        assert!(!fields_match("", 0.0, VERIFY_TOLERANCE));
+        assert!(!fields_match("abc", 0.0, VERIFY_TOLERANCE));
+    }
+
+    #[test]
+    fn test_verified_only_when_entries_match_the_prescription() {
+        let state = create_therac_state();
+        let mut app = AuthenticTuiApp::new(state.clone());
+        let params = state.read().reference_params;
+        app.unit_rate_input = format!("{:.1}", params.dose_rate);
+        app.gantry_rot_input = params.gantry_angle.to_string();
+        app.collimator_x_input = format!("{:.1}", params.field_size_x);
+        app.collimator_y_input = format!("{:.1}", params.field_size_y);
+        assert!(app.check_verification(&state.read()));
+        assert!(!app.entry_mismatched(&state.read(), InputField::CollimatorX));
+
+        app.collimator_x_input = format!("{:.1}", params.field_size_x + 2.0);
+        assert!(!app.check_verification(&state.read()));
+        assert!(app.entry_mismatched(&state.read(), InputField::CollimatorX));
+        assert!(!app.entry_mismatched(&state.read(), InputField::UnitRate));
+    }
+
+    #[test]
+    fn test_unchanged_screen_is_not_an_edit() {
+        let state = create_therac_state();
+        let mut app = AuthenticTuiApp::new(state.clone());
+        app.unit_rate_input = "300".to_string();
+        app.time_input = "0.5".to_string();
+        app.gantry_rot_input = "90".to_string();
+        app.energy_input = "10000".to_string();
+        app.apply_prescription();
+        {
+            let mut s = state.write();
+            assert_eq!(s.console_params.gantry_angle, 90);
+            assert_eq!(s.console_meos.beam_energy, BeamEnergy::E10);
+            assert_eq!(s.dose_target, 150.0);
+            assert!(s.editing_taking_place);
+            s.editing_taking_place = false;
+        }
+
+        let logged = state.read().log.len();
+        app.apply_prescription();
+        let s = state.read();
+        assert!(!s.editing_taking_place);
+        assert_eq!(s.log.len(), logged);
+    }
+
+    #[test]
+    fn test_rejected_dose_is_not_refused_again_on_every_sync() {
+        let state = create_therac_state();
+        let mut app = AuthenticTuiApp::new(state.clone());
+        app.unit_rate_input = "300".to_string();
+        app.time_input = "0".to_string();
+        let refusals = |state: &SharedTheracState| {
+            state.read().log.iter().filter(|e| e.message.contains("Dose target 0 cGy rejected")).count()
+        };
+        app.apply_prescription();
+        assert_eq!(refusals(&state), 1);
+        app.apply_prescription();
+        assert_eq!(refusals(&state), 1);
+
+        // A new entry goes through again
+        app.time_input = "0.5".to_string();
+        app.apply_prescription();
+        assert_eq!(state.read().dose_target, 150.0);
+    }
+
+    #[test]
+    fn test_detect_color_capability() {
+        assert_eq!(ColorCapability::from_env(None, Some("xterm-256color")), ColorCapability::Color);
+        assert_eq!(ColorCapability::from_env(Some("1"), Some("xterm-256color")), ColorCapability::Monochrome);
+        assert_eq!(ColorCapability::from_env(Some(""), None), ColorCapability::Color);
+        assert_eq!(ColorCapability::from_env(None, Some("vt100")), ColorCapability::Monochrome);
+        assert_eq!(ColorCapability::from_env(None, Some("xterm-mono")), ColorCapability::Monochrome);
+    }
+}
@@ -9,6 +9,7 @@ use crate::*;
 use crate::simulator::*;
 use std::sync::Arc;
 use parking_lot::RwLock;
+use std::time::Duration;
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global allocator
 #[cfg(feature = "wee_alloc")]
@@ -19,13 +20,16 @@ static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 #[wasm_bindgen]
 pub struct WasmTherac25 {
     state: SharedTheracState,
+    /// Snapshot taken by the last `getChanges` call
+    last_snapshot: Option<TheracState>,
 }
 
-#[wasm_bindgen]
+/// Logical time one `tick` advances the simulator by: one frame at 60 fps
+pub const TICK_DT: Duration = Duration::from_micros(16_667);
+
 impl WasmTherac25 {
-    /// Create new simulator instance
-    #[wasm_bindgen(constructor)]
-    pub fn new() -> Result<WasmTherac25, JsValue> {
+    /// Fresh state with nothing running yet
+    fn initialize() -> WasmTherac25 {
         // Set panic hook for better error messages
         console_error_panic_hook::set_once();
 
@@ -34,6 +38,19 @@ impl WasmTherac25 {
         let state = Arc::new(RwLock::new(TheracState::new()));
         state.write().add_log("System initialized".to_string());
 
+        WasmTherac25 { state, last_snapshot: None }
+    }
+}
+
+#[wasm_bindgen]
+impl WasmTherac25 {
+    /// Create new simulator instance, with the background tasks running on the
+    /// browser's event loop
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<WasmTherac25, JsValue> {
+        let simulator = Self::initialize();
+        let state = &simulator.state;
+
         // Start concurrent tasks
         let state_clone1 = state.clone();
         let state_clone2 = state.clone();
@@ -46,7 +63,41 @@ impl WasmTherac25 {
             housekeeper(state_clone2).await;
         });
 
-        Ok(WasmTherac25 { state })
+        let state_clone3 = state.clone();
+        spawn_local(async move {
+            phantom_operator(state_clone3).await;
+        });
+
+        let state_clone4 = state.clone();
+        spawn_local(async move {
+            hardware_watchdog(state_clone4).await;
+        });
+
+        let state_clone5 = state.clone();
+        spawn_local(async move {
+            bending_magnet_settler(state_clone5).await;
+        });
+
+        Ok(simulator)
+    }
+
+    /// Create a simulator with no background tasks: nothing moves until `tick` is
+    /// called, so a requestAnimationFrame loop sets the pace and the race replays
+    /// the same way every time
+    #[wasm_bindgen(js_name = newManual)]
+    pub fn new_manual() -> WasmTherac25 {
+        Self::initialize()
+    }
+
+    /// Advance the housekeeper, treatment monitor and bending magnets by one tick of
+    /// `TICK_DT` (`tick::step`); returns whether anything `getChanges` reports changed.
+    /// Meant for a simulator from `newManual`; the phantom operator and hardware
+    /// watchdog are not stepped.
+    #[wasm_bindgen]
+    pub fn tick(&mut self) -> bool {
+        let before = self.state.read().clone();
+        step(self.state.clone(), TICK_DT);
+        !before.diff(&self.state.read()).is_empty()
     }
 
     /// Get current state as JSON
@@ -56,6 +107,18 @@ impl WasmTherac25 {
         serde_wasm_bindgen::to_value(&*state).unwrap_or(JsValue::NULL)
     }
 
+    /// Changes since the previous call as a `StateDiff`, or the full state on the first call
+    #[wasm_bindgen(js_name = getChanges)]
+    pub fn get_changes(&mut self) -> JsValue {
+        let current = self.state.read().clone();
+        let value = match &self.last_snapshot {
+            Some(previous) => serde_wasm_bindgen::to_value(&previous.diff(&current)),
+            None => serde_wasm_bindgen::to_value(&current),
+        };
+        self.last_snapshot = Some(current);
+        value.unwrap_or(JsValue::NULL)
+    }
+
     /// Reset the system
     #[wasm_bindgen]
     pub fn reset(&mut self) {
@@ -73,26 +136,249 @@ impl WasmTherac25 {
                 1 => BeamType::Electron,
                 _ => BeamType::Undefined,
             };
+            if state.console_meos.beam_type == BeamType::XRay && state.config.auto_max_energy_on_xray {
+                state.set_console_energy(BeamEnergy::E25);
+            }
             let beam_type_val = state.console_meos.beam_type;
-            state.add_log(format!("Beam type set to {}", beam_type_val));
+            state.add_log(format!("[CONSOLE] Beam type set to {}", beam_type_val));
+        }
+    }
+
+    /// Force 25 MeV when X-ray is selected, as on the real console (default: true)
+    #[wasm_bindgen(js_name = setAutoMaxEnergyOnXray)]
+    pub fn set_auto_max_energy_on_xray(&mut self, enabled: bool) {
+        self.state.write().config.auto_max_energy_on_xray = enabled;
+    }
+
+    /// Hold between beam-on and the first pulse in ms, during which stopping aborts (default: 0)
+    #[wasm_bindgen(js_name = setBeamHoldMs)]
+    pub fn set_beam_hold_ms(&mut self, hold_ms: u32) {
+        self.state.write().config.beam_hold_ms = hold_ms;
+    }
+
+    /// Pulses per second at the reference dose rate, scaled by the dose rate; 0 fires one every monitor pass (default: 0)
+    #[wasm_bindgen(js_name = setPulseRepetitionHz)]
+    pub fn set_pulse_repetition_hz(&mut self, hz: f64) {
+        self.state.write().config.pulse_repetition_hz = hz.max(0.0);
+    }
+
+    /// Simulated beam-on time of the current or last treatment (s)
+    #[wasm_bindgen(js_name = getBeamOnSeconds)]
+    pub fn get_beam_on_seconds(&self) -> f64 {
+        self.state.read().beam_on_seconds
+    }
+
+    /// Require differences from the prescription to be acknowledged before data entry completes (default: false)
+    #[wasm_bindgen(js_name = setRequireReadback)]
+    pub fn set_require_readback(&mut self, required: bool) {
+        self.state.write().config.require_readback = required;
+    }
+
+    /// Let a simulated colleague nudge console entries at random intervals (default: false)
+    #[wasm_bindgen(js_name = setPhantomOperator)]
+    pub fn set_phantom_operator(&mut self, enabled: bool) {
+        self.state.write().config.phantom_operator = enabled;
+    }
+
+    /// Time (ms) of one turntable move: how long the race window stays open (default: 800).
+    /// Refused during treatment; returns whether it was accepted
+    #[wasm_bindgen(js_name = setCollimatorMoveMs)]
+    pub fn set_collimator_move_ms(&mut self, ms: u64) -> bool {
+        self.state.write().set_collimator_move_ms(ms)
+    }
+
+    /// `setCollimatorMoveMs` for a `u32` delay from a slider or number input
+    #[wasm_bindgen(js_name = setCollimatorDelayMs)]
+    pub fn set_collimator_delay_ms(&mut self, ms: u32) -> bool {
+        self.state.write().set_collimator_move_ms(ms as u64)
+    }
+
+    /// Time (ms) the housekeeper takes to sync gantry, field size and the other params (default: 200).
+    /// Refused during treatment; returns whether it was accepted
+    #[wasm_bindgen(js_name = setParamSyncMs)]
+    pub fn set_param_sync_ms(&mut self, ms: u64) -> bool {
+        self.state.write().set_param_sync_ms(ms)
+    }
+
+    /// Concentrate the pulse dose in small fields, inversely to the field area (default: false)
+    #[wasm_bindgen(js_name = setFieldSizeScalesDose)]
+    pub fn set_field_size_scales_dose(&mut self, enabled: bool) {
+        self.state.write().config.field_size_scales_dose = enabled;
+    }
+
+    /// Fire through the race-free `zap_the_specimen_safe` instead of the original (default: false).
+    /// Refused during treatment; returns whether it was accepted
+    #[wasm_bindgen(js_name = setSafeMode)]
+    pub fn set_safe_mode(&mut self, enabled: bool) -> bool {
+        self.state.write().set_safe_mode(enabled)
+    }
+
+    /// Whether pulses fire through the race-free `zap_the_specimen_safe`
+    #[wasm_bindgen(js_name = isSafeMode)]
+    pub fn is_safe_mode(&self) -> bool {
+        self.state.read().config.safe_mode
+    }
+
+    /// Run the hardware watchdog, which trips COLLIMATOR TIMEOUT on a stuck turntable (default: false)
+    #[wasm_bindgen(js_name = setHardwareWatchdog)]
+    pub fn set_hardware_watchdog(&mut self, enabled: bool) {
+        self.state.write().config.hardware_watchdog = enabled;
+    }
+
+    /// Time (ms) the bending magnets take to settle after a beam type switch; a pulse before then raises BENDING MAGNET NOT SETTLED (default: 0)
+    #[wasm_bindgen(js_name = setBendingMagnetSettleMs)]
+    pub fn set_bending_magnet_settle_ms(&mut self, settle_ms: u64) {
+        self.state.write().config.bending_magnet_settle_ms = settle_ms;
+    }
+
+    /// Time (ms) the energy system takes to settle after the console energy goes up; a pulse before then raises ENERGY NOT SETTLED (default: 250)
+    #[wasm_bindgen(js_name = setEnergySettleMs)]
+    pub fn set_energy_settle_ms(&mut self, settle_ms: u64) {
+        self.state.write().config.energy_settle_ms = settle_ms;
+    }
+
+    /// Jam (or free) the collimator turntable: a fault injection for the watchdog
+    #[wasm_bindgen(js_name = setCollimatorJammed)]
+    pub fn set_collimator_jammed(&mut self, jammed: bool) {
+        self.state.write().collimator_jammed = jammed;
+    }
+
+    /// Chance (0.0-1.0) of a random hardware fault per pulse; 0 disables them (default: 31/42)
+    #[wasm_bindgen(js_name = setRandomFaultProbability)]
+    pub fn set_random_fault_probability(&mut self, probability: f64) {
+        self.state.write().config.random_fault_probability = probability.clamp(0.0, 1.0);
+    }
+
+    /// Freeze the background tasks where they stand; false if already paused
+    #[wasm_bindgen(js_name = pauseSimulation)]
+    pub fn pause_simulation(&mut self) -> bool {
+        pause_simulation(self.state.clone())
+    }
+
+    /// Let the background tasks carry on; false if not paused
+    #[wasm_bindgen(js_name = resumeSimulation)]
+    pub fn resume_simulation(&mut self) -> bool {
+        resume_simulation(self.state.clone())
+    }
+
+    #[wasm_bindgen(js_name = isSimulationPaused)]
+    pub fn is_simulation_paused(&self) -> bool {
+        is_simulation_paused(&self.state)
+    }
+
+    /// Chance (0.0-1.0) that a turntable move jams mid-rotation, leaving it stuck (default: 0)
+    /// Returns false, leaving the setting unchanged, for a value that is not a finite number
+    #[wasm_bindgen(js_name = setCollimatorStickProbability)]
+    pub fn set_collimator_stick_probability(&mut self, probability: f32) -> bool {
+        if !probability.is_finite() {
+            return false;
+        }
+        self.state.write().config.collimator_stick_probability = probability.clamp(0.0, 1.0);
+        true
+    }
+
+    /// Free a turntable stuck mid-rotation; false if it was not stuck
+    #[wasm_bindgen(js_name = freeStuckCollimator)]
+    pub fn free_stuck_collimator(&mut self) -> bool {
+        self.state.write().free_stuck_collimator()
+    }
+
+    /// Log LOCK WAIT when a beam pulse or collimator sync waits this long (ms) for the write lock; 0 disables (default: 0)
+    #[wasm_bindgen(js_name = setLockWaitLogMs)]
+    pub fn set_lock_wait_log_ms(&mut self, threshold_ms: u32) {
+        self.state.write().config.lock_wait_log_ms = threshold_ms;
+    }
+
+    /// Average wear added per treatment, 0.02 = 2% slower mechanics each time (default: 0)
+    #[wasm_bindgen(js_name = setWearPerTreatment)]
+    pub fn set_wear_per_treatment(&mut self, wear: f32) {
+        self.state.write().config.wear_per_treatment = wear;
+    }
+
+    /// Service the machine: wear back to new
+    #[wasm_bindgen(js_name = resetWear)]
+    pub fn reset_wear(&mut self) {
+        self.state.write().reset_wear();
+    }
+
+    /// Treatments allowed before RECALIBRATION REQUIRED refuses the next; 0 never asks (default: 0)
+    #[wasm_bindgen(js_name = setRecalibrationInterval)]
+    pub fn set_recalibration_interval(&mut self, treatments: u32) {
+        self.state.write().config.recalibration_interval = treatments;
+    }
+
+    /// Recalibrate the machine: the treatment count starts over and wear is reset
+    #[wasm_bindgen]
+    pub fn recalibrate(&mut self) {
+        self.state.write().recalibrate();
+    }
+
+    /// Whether treatment is refused until the machine is recalibrated
+    #[wasm_bindgen(js_name = isRecalibrationRequired)]
+    pub fn is_recalibration_required(&self) -> bool {
+        self.state.read().recalibration_required()
+    }
+
+    /// Label outcomes "NO ACTUAL HARM — SIMULATION" with hypothetical doses; the model is unchanged (default: false)
+    #[wasm_bindgen(js_name = setHarmReportingOnly)]
+    pub fn set_harm_reporting_only(&mut self, enabled: bool) {
+        self.state.write().config.harm_reporting_only = enabled;
+    }
+
+    /// Housekeeper sync order, e.g. "energy,collimator"; false (and unchanged) on an unknown aspect
+    #[wasm_bindgen(js_name = setSyncOrder)]
+    pub fn set_sync_order(&mut self, order: &str) -> bool {
+        match SyncOrder::parse(order) {
+            Some(order) => {
+                self.state.write().config.sync_order = order;
+                true
+            }
+            None => false,
         }
     }
 
+    /// Cap displayed overdoses at `cgy` cGy; the model is unchanged (default: 0, raw figures)
+    #[wasm_bindgen(js_name = setReportedDoseCap)]
+    pub fn set_reported_dose_cap(&mut self, cgy: f64) {
+        self.state.write().config.reported_dose_cap = cgy.max(0.0);
+    }
+
+    /// Terminate treatment before any pulse takes the delivered dose past `cgy` cGy
+    /// (default: 0, no interlock as on the original)
+    #[wasm_bindgen(js_name = setMaxSafeDoseCgy)]
+    pub fn set_max_safe_dose_cgy(&mut self, cgy: f64) {
+        self.state.write().config.max_safe_dose_cgy = cgy.max(0.0);
+    }
+
     /// Set beam energy (0-4 for E5-E25)
     #[wasm_bindgen(js_name = setBeamEnergy)]
     pub fn set_beam_energy(&mut self, energy: u8) {
         let mut state = self.state.write();
         if state.phase == TPhase::DataEntry {
-            state.console_meos.beam_energy = match energy {
+            let energy = match energy {
                 0 => BeamEnergy::E5,
                 1 => BeamEnergy::E10,
                 2 => BeamEnergy::E15,
                 3 => BeamEnergy::E20,
                 _ => BeamEnergy::E25,
             };
-            let beam_energy_val = state.console_meos.beam_energy;
-            state.add_log(format!("Beam energy set to {}", beam_energy_val));
+            state.set_console_energy(energy);
+            state.add_log(format!("[CONSOLE] Beam energy set to {}", energy));
+        }
+    }
+
+    /// Set beam energy in MeV, presets or anything in between (5-25); false if out of range
+    #[wasm_bindgen(js_name = setBeamEnergyMev)]
+    pub fn set_beam_energy_mev(&mut self, mev: f32) -> bool {
+        let mut state = self.state.write();
+        let Some(energy) = BeamEnergy::from_mev(mev) else {
+            return false;
+        };
+        if state.phase == TPhase::DataEntry {
+            state.set_console_energy(energy);
+            state.add_log(format!("[CONSOLE] Beam energy set to {}", energy));
         }
+        true
     }
 
     /// Toggle collimator position
@@ -102,10 +388,10 @@ impl WasmTherac25 {
         state.console_meos.collimator = match state.console_meos.collimator {
             CollimatorPosition::InPosition => CollimatorPosition::OutOfPosition,
             CollimatorPosition::OutOfPosition => CollimatorPosition::InPosition,
-            CollimatorPosition::Transitioning => CollimatorPosition::InPosition,
+            CollimatorPosition::Transitioning | CollimatorPosition::Stuck => CollimatorPosition::InPosition,
         };
         let collimator_val = state.console_meos.collimator;
-        state.add_log(format!("Collimator set to {}", collimator_val));
+        state.add_log(format!("[CONSOLE] Collimator set to {}", collimator_val));
     }
 
     /// Complete data entry
@@ -114,6 +400,53 @@ impl WasmTherac25 {
         complete_data_entry(self.state.clone());
     }
 
+    /// Read the console entries back against the prescription: an array of
+    /// {field, entered, prescribed} for every entry that differs
+    #[wasm_bindgen(js_name = verifyEntry)]
+    pub fn verify_entry(&mut self) -> JsValue {
+        let differences = verify_entry(self.state.clone());
+        serde_wasm_bindgen::to_value(&differences).unwrap_or(JsValue::NULL)
+    }
+
+    /// Names of the prescriptions in the reference library
+    #[wasm_bindgen(js_name = listPrescriptions)]
+    pub fn list_prescriptions(&self) -> Vec<String> {
+        list_prescriptions().into_iter().map(str::to_string).collect()
+    }
+
+    /// Make a library prescription the reference, by name; false if there is none by
+    /// that name or a treatment is running
+    #[wasm_bindgen(js_name = loadPrescription)]
+    pub fn load_prescription(&mut self, name: &str) -> bool {
+        load_prescription(self.state.clone(), name)
+    }
+
+    /// How the console, hardware and reference MEOS differ, as a JSON object:
+    /// {console_matches_hardware, hardware_matches_reference, console_matches_reference,
+    /// differing_fields, console_hardware_fields, sync_pending}
+    #[wasm_bindgen(js_name = getMeosDiff)]
+    pub fn get_meos_diff(&self) -> String {
+        serde_json::to_string(&self.state.read().meos_diff()).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Acknowledge the current differences from the prescription; false if there are none
+    #[wasm_bindgen(js_name = acknowledgeDifferences)]
+    pub fn acknowledge_differences(&mut self) -> bool {
+        acknowledge_differences(self.state.clone())
+    }
+
+    /// Log an operator in; false if the ID is empty or contains whitespace or brackets
+    #[wasm_bindgen(js_name = login)]
+    pub fn login(&mut self, id: &str) -> bool {
+        login_operator(self.state.clone(), id)
+    }
+
+    /// Log the current operator out; false if nobody was logged in
+    #[wasm_bindgen(js_name = logout)]
+    pub fn logout(&mut self) -> bool {
+        logout_operator(self.state.clone())
+    }
+
     /// Start treatment
     #[wasm_bindgen(js_name = startTreatment)]
     pub fn start_treatment(&mut self) {
@@ -126,6 +459,12 @@ impl WasmTherac25 {
         stop_treatment(self.state.clone());
     }
 
+    /// Emergency-off: cut the beam in any phase; only a reset recovers
+    #[wasm_bindgen(js_name = emergencyOff)]
+    pub fn emergency_off(&mut self) {
+        emergency_off(self.state.clone());
+    }
+
     /// Resume treatment
     #[wasm_bindgen(js_name = resumeTreatment)]
     pub fn resume_treatment(&mut self) {
@@ -135,7 +474,7 @@ impl WasmTherac25 {
     /// Generate random safe parameters
     #[wasm_bindgen(js_name = generateRandomParameters)]
     pub fn generate_random_parameters(&mut self) {
-        let params = generate_random_parameters();
+        let params = generate_random_parameters_with(&mut self.state.write().rng);
         update_console_meos(self.state.clone(), params);
     }
 
@@ -147,6 +486,12 @@ impl WasmTherac25 {
         update_console_meos(self.state.clone(), params);
     }
 
+    /// Open the race window and turn the beam on: the next pulse fires unflattened
+    #[wasm_bindgen(js_name = forceRaceWindow)]
+    pub fn force_race_window(&mut self) {
+        force_race_window(self.state.clone());
+    }
+
     /// Get log messages
     #[wasm_bindgen(js_name = getLog)]
     pub fn get_log(&self) -> Vec<JsValue> {
@@ -154,15 +499,24 @@ impl WasmTherac25 {
         state.log.iter()
             .rev()
             .take(50)
-            .map(|s| JsValue::from_str(s))
+            .map(|entry| JsValue::from_str(&entry.to_string()))
             .collect()
     }
 
+    /// The same log entries as {timestamp, operator_id, level, message} objects, to
+    /// colour by `level` ("Info", "Warn" or "Critical")
+    #[wasm_bindgen(js_name = getLogEntries)]
+    pub fn get_log_entries(&self) -> JsValue {
+        let state = self.state.read();
+        let entries: Vec<&LogEntry> = state.log.iter().rev().take(50).collect();
+        serde_wasm_bindgen::to_value(&entries).unwrap_or(JsValue::NULL)
+    }
+
     /// Get current phase as string
     #[wasm_bindgen(js_name = getPhase)]
     pub fn get_phase(&self) -> String {
         let state = self.state.read();
-        format!("{}", state.phase)
+        state.messages.phase(state.phase).to_string()
     }
 
     /// Check if hardware is safe
@@ -186,12 +540,10 @@ impl WasmTherac25 {
         state.dose_target
     }
 
-    /// Set dose target
+    /// Set dose target (cGy); false, and logged, unless it is positive
     #[wasm_bindgen(js_name = setDoseTarget)]
-    pub fn set_dose_target(&mut self, target: f64) {
-        let mut state = self.state.write();
-        state.dose_target = target;
-        state.add_log(format!("Dose target set to {:.1} cGy", target));
+    pub fn set_dose_target(&mut self, target: f64) -> bool {
+        self.state.write().set_dose_target(target)
     }
 
     /// Set gantry angle (0-360 degrees)
@@ -201,7 +553,7 @@ impl WasmTherac25 {
         if state.phase == TPhase::DataEntry {
             state.console_params.gantry_angle = angle.min(360);
             let angle_val = state.console_params.gantry_angle;
-            state.add_log(format!("Gantry angle set to {}°", angle_val));
+            state.add_log(format!("[CONSOLE] Gantry angle set to {}°", angle_val));
         }
     }
 
@@ -212,7 +564,7 @@ impl WasmTherac25 {
         if state.phase == TPhase::DataEntry {
             state.console_params.field_size_x = size.max(0.0).min(40.0);
             let size_val = state.console_params.field_size_x;
-            state.add_log(format!("Field size X set to {:.1} cm", size_val));
+            state.add_log(format!("[CONSOLE] Field size X set to {:.1} cm", size_val));
         }
     }
 
@@ -223,7 +575,7 @@ impl WasmTherac25 {
         if state.phase == TPhase::DataEntry {
             state.console_params.field_size_y = size.max(0.0).min(40.0);
             let size_val = state.console_params.field_size_y;
-            state.add_log(format!("Field size Y set to {:.1} cm", size_val));
+            state.add_log(format!("[CONSOLE] Field size Y set to {:.1} cm", size_val));
         }
     }
 
@@ -234,7 +586,7 @@ impl WasmTherac25 {
         if state.phase == TPhase::DataEntry {
             state.console_params.dose_rate = rate.max(0.0);
             let rate_val = state.console_params.dose_rate;
-            state.add_log(format!("Dose rate set to {:.0} cGy/min", rate_val));
+            state.add_log(format!("[CONSOLE] Dose rate set to {:.0} cGy/min", rate_val));
         }
     }
 
@@ -289,11 +641,119 @@ impl WasmTherac25 {
         state.malfunction_count
     }
 
+    /// Mismatches caught before the beam fired this session
+    #[wasm_bindgen(js_name = getNearMisses)]
+    pub fn get_near_misses(&self) -> u32 {
+        self.state.read().near_misses
+    }
+
+    /// Session near-miss report, or null before the first near miss
+    #[wasm_bindgen(js_name = getNearMissReport)]
+    pub fn get_near_miss_report(&self) -> Option<String> {
+        self.state.read().near_miss_report()
+    }
+
     /// Get last malfunction message
     #[wasm_bindgen(js_name = getLastMalfunction)]
     pub fn get_last_malfunction(&self) -> Option<String> {
         let state = self.state.read();
-        state.last_malfunction.clone()
+        state.last_malfunction.as_ref().map(|m| m.message())
+    }
+
+    /// Number of the last malfunction ("MALFUNCTION n"), or null
+    #[wasm_bindgen(js_name = getLastMalfunctionCode)]
+    pub fn get_last_malfunction_code(&self) -> Option<u32> {
+        self.state.read().last_malfunction.as_ref().map(|m| m.code())
+    }
+
+    /// Operator currently logged in, or null
+    #[wasm_bindgen(js_name = getOperatorId)]
+    pub fn get_operator_id(&self) -> Option<String> {
+        self.state.read().operator_id.clone()
+    }
+
+    /// Per-operator statistics for the session, as a JSON object keyed by operator ID
+    #[wasm_bindgen(js_name = getOperatorStats)]
+    pub fn get_operator_stats(&self) -> String {
+        serde_json::to_string(&self.state.read().operator_stats).unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// Summaries of every completed or terminated treatment, as a JSON array
+    #[wasm_bindgen(js_name = exportTreatmentSummaries)]
+    pub fn export_treatment_summaries(&self) -> String {
+        let state = self.state.read();
+        state.export_treatment_summaries().unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Draw every later prescription from `seed`, so a class sees the same sequence
+    #[wasm_bindgen]
+    pub fn reseed(&mut self, seed: u64) {
+        self.state.write().reseed(seed);
+    }
+
+    /// Detailed multi-section debug report of the current state
+    #[wasm_bindgen]
+    pub fn inspect(&self) -> String {
+        self.state.read().inspect()
+    }
+
+    /// Every malfunction of the session with its time, kind, phase and console/hardware
+    /// MEOS, as a JSON array oldest first
+    #[wasm_bindgen(js_name = getMalfunctionHistory)]
+    pub fn get_malfunction_history(&self) -> String {
+        self.state.read().export_malfunction_history().unwrap_or_else(|_| "[]".to_string())
+    }
+
+    /// Outcome of the session so far (dose against target, malfunctions by kind, final
+    /// phase, any unsafe beam) as a JSON object
+    #[wasm_bindgen(js_name = getSessionSummary)]
+    pub fn get_session_summary(&self) -> String {
+        self.state.read().summary().to_json().unwrap_or_else(|_| "{}".to_string())
+    }
+
+    /// The event log as CSV rows of timestamp,level,message under a header row
+    #[wasm_bindgen(js_name = exportLogCsv)]
+    pub fn export_log_csv(&self) -> String {
+        self.state.read().export_log_csv()
+    }
+
+    /// Every malfunction of the session as Common Event Format lines, for a SIEM
+    #[wasm_bindgen(js_name = exportIncidentsCef)]
+    pub fn export_incidents_cef(&self) -> String {
+        self.state.read().export_incidents_cef()
+    }
+
+    /// Dose trace of the current or last treatment as a Float64Array of
+    /// [seconds, cumulative cGy] pairs: [t0, dose0, t1, dose1, ...]
+    #[wasm_bindgen(js_name = getDoseSamples)]
+    pub fn get_dose_samples(&self) -> Vec<f64> {
+        self.state.read().dose_samples_flat()
+    }
+
+    /// The whole session as JSON, for `importSession` to resume later
+    #[wasm_bindgen(js_name = exportSession)]
+    pub fn export_session(&self) -> Result<String, JsValue> {
+        self.state.read().to_json().map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Resume a session exported with `exportSession`, replacing the current one;
+    /// a loaded translation is kept. A session with an invalid configuration is refused.
+    #[wasm_bindgen(js_name = importSession)]
+    pub fn import_session(&mut self, json: &str) -> Result<(), JsValue> {
+        let mut session = TheracState::from_json(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let mut state = self.state.write();
+        session.messages = state.messages.clone();
+        *state = session;
+        Ok(())
+    }
+
+    /// Load a message translation (JSON object from `MessageKey` names to text);
+    /// keys it leaves out stay in English
+    #[wasm_bindgen(js_name = loadMessages)]
+    pub fn load_messages(&mut self, json: &str) -> Result<(), JsValue> {
+        let messages = Messages::from_json(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        self.state.write().messages = Arc::new(messages);
+        Ok(())
     }
 }
 